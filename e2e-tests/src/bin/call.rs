@@ -176,6 +176,57 @@ async fn call_echo() {
     assert_eq!(res, bytes);
 }
 
+#[update]
+async fn call_typed() {
+    use ic_cdk::call::Error;
+    let n = 1u32;
+
+    let res: u32 = Call::bounded_wait(canister_self(), "echo")
+        .with_arg(n)
+        .candid_result()
+        .await
+        .unwrap();
+    assert_eq!(res, n);
+    let res: (u32,) = Call::unbounded_wait(canister_self(), "echo")
+        .with_arg(n)
+        .candid_tuple_result()
+        .await
+        .unwrap();
+    assert_eq!(res.0, n);
+    let res: u32 = Call::bounded_wait(canister_self(), "echo")
+        .with_arg(n)
+        .into_future()
+        .candid_result()
+        .await
+        .unwrap();
+    assert_eq!(res, n);
+    let (res,) = Call::bounded_wait(canister_self(), "echo")
+        .typed::<(u32,), (u32,)>(&(n,))
+        .with_cycles(1000)
+        .change_timeout(5)
+        .await
+        .unwrap();
+    assert_eq!(res, n);
+    let (res,) = Call::unbounded_wait(canister_self(), "foo")
+        .typed::<(), (u32,)>(&())
+        .await
+        .unwrap();
+    assert_eq!(res, 0);
+
+    // Decoding and call failures are both reported as `Error`.
+    let err = Call::bounded_wait(canister_self(), "echo")
+        .with_arg(n)
+        .candid_result::<String>()
+        .await
+        .unwrap_err();
+    assert!(matches!(err, Error::CandidDecodeFailed(_)));
+    let err = Call::bounded_wait(Principal::anonymous(), "foobar")
+        .candid_result::<u32>()
+        .await
+        .unwrap_err();
+    assert!(matches!(err, Error::CallRejected(_)));
+}
+
 /// Retries the call until it succeeds.
 ///
 /// Returns the number of retries.
@@ -206,6 +257,17 @@ async fn retry_calls() {
     let raw_args = Encode!(&n).unwrap();
     let call_with_raw_args = Call::bounded_wait(canister_self(), "echo").with_raw_args(&raw_args);
     assert_eq!(retry(call_with_raw_args).await, 0);
+
+    let policy =
+        ic_cdk::call::RetryPolicy::new(3).with_deadline(ic_cdk::api::time() + 60_000_000_000);
+    let res: u32 = Call::bounded_wait(canister_self(), "echo")
+        .with_raw_args(&raw_args)
+        .with_retry(policy)
+        .await
+        .unwrap()
+        .candid()
+        .unwrap();
+    assert_eq!(res, n);
 }
 
 #[update]
@@ -231,6 +293,22 @@ async fn join_calls() {
         vec![Box::pin(future1), Box::pin(future2)];
     let results = join_all(futures).await;
     assert_eq!(results, vec![0, 1]);
+
+    let calls = (0..4u32).map(|n| Call::bounded_wait(canister_self(), "echo").with_arg(n));
+    let results = ic_cdk::call::join_all(calls, 2).await.unwrap();
+    let results = results
+        .into_iter()
+        .map(|result| result.unwrap().candid::<u32>().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(results, vec![0, 1, 2, 3]);
+
+    let calls = [
+        Call::bounded_wait(canister_self(), "foo"),
+        Call::bounded_wait(canister_self(), "echo").with_arg(1u32),
+    ];
+    let (index, result) = ic_cdk::call::select(calls, 60).await.unwrap();
+    assert!(index < 2);
+    assert!(result.is_ok());
 }
 
 #[update]
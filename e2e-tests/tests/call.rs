@@ -10,6 +10,7 @@ fn call() {
     pic.install_canister(canister_id, wasm, vec![], None);
     let _: () = update(&pic, canister_id, "call_foo", ()).unwrap();
     let _: () = update(&pic, canister_id, "call_echo", ()).unwrap();
+    let _: () = update(&pic, canister_id, "call_typed", ()).unwrap();
     let _: () = update(&pic, canister_id, "retry_calls", ()).unwrap();
     let _: () = update(&pic, canister_id, "join_calls", ()).unwrap();
     let _: () = update(
@@ -4,6 +4,7 @@ pub use ic_btc_interface::*;
 
 use candid::Principal;
 use ic_cdk::call::{Call, CallResult};
+use ic_cdk::cycles::{CostEstimate, CostEstimateError};
 
 const MAINNET_ID: Principal = Principal::from_slice(&[0, 0, 0, 0, 1, 160, 0, 4, 1, 1]); // "ghsi2-tqaaa-aaaan-aaaca-cai"
 const TESTNET_ID: Principal = Principal::from_slice(&[0, 0, 0, 0, 1, 160, 0, 1, 1, 1]); // "g4xu7-jiaaa-aaaan-aaaaq-cai"
@@ -44,13 +45,7 @@ pub fn get_bitcoin_canister_id(network: Network) -> Principal {
 ///
 /// Check the [Bitcoin Canisters Interface Specification](https://github.com/dfinity/bitcoin-canister/blob/master/INTERFACE_SPECIFICATION.md#bitcoin_get_utxos) for more details.
 pub async fn bitcoin_get_utxos(arg: &GetUtxosRequest) -> CallResult<GetUtxosResponse> {
-    let canister_id = get_bitcoin_canister_id(arg.network.into());
-    let cycles = cost_get_utxos(arg);
-    Ok(Call::bounded_wait(canister_id, "bitcoin_get_utxos")
-        .with_arg(arg)
-        .with_cycles(cycles)
-        .await?
-        .candid()?)
+    Ok(BitcoinCall::GetUtxos(arg).call().await?.candid()?)
 }
 
 /// Gets the cycles cost for the [`bitcoin_get_utxos`] function.
@@ -73,13 +68,7 @@ pub fn cost_get_utxos(arg: &GetUtxosRequest) -> u128 {
 ///
 /// Check the [Bitcoin Canisters Interface Specification](https://github.com/dfinity/bitcoin-canister/blob/master/INTERFACE_SPECIFICATION.md#bitcoin_get_balance) for more details.
 pub async fn bitcoin_get_balance(arg: &GetBalanceRequest) -> CallResult<Satoshi> {
-    let canister_id = get_bitcoin_canister_id(arg.network.into());
-    let cycles = cost_get_balance(arg);
-    Ok(Call::bounded_wait(canister_id, "bitcoin_get_balance")
-        .with_arg(arg)
-        .with_cycles(cycles)
-        .await?
-        .candid()?)
+    Ok(BitcoinCall::GetBalance(arg).call().await?.candid()?)
 }
 
 /// Gets the cycles cost for the [`bitcoin_get_balance`] function.
@@ -106,15 +95,10 @@ pub fn cost_get_balance(arg: &GetBalanceRequest) -> u128 {
 pub async fn bitcoin_get_current_fee_percentiles(
     arg: &GetCurrentFeePercentilesRequest,
 ) -> CallResult<Vec<MillisatoshiPerByte>> {
-    let canister_id = get_bitcoin_canister_id(arg.network.into());
-    let cycles = cost_get_current_fee_percentiles(arg);
-    Ok(
-        Call::bounded_wait(canister_id, "bitcoin_get_current_fee_percentiles")
-            .with_arg(arg)
-            .with_cycles(cycles)
-            .await?
-            .candid()?,
-    )
+    Ok(BitcoinCall::GetCurrentFeePercentiles(arg)
+        .call()
+        .await?
+        .candid()?)
 }
 
 /// Gets the cycles cost for the [`bitcoin_get_current_fee_percentiles`] function.
@@ -139,13 +123,7 @@ pub fn cost_get_current_fee_percentiles(arg: &GetCurrentFeePercentilesRequest) -
 pub async fn bitcoin_get_block_headers(
     arg: &GetBlockHeadersRequest,
 ) -> CallResult<GetBlockHeadersResponse> {
-    let canister_id = get_bitcoin_canister_id(arg.network.into());
-    let cycles = cost_get_block_headers(arg);
-    Ok(Call::bounded_wait(canister_id, "bitcoin_get_block_headers")
-        .with_arg(arg)
-        .with_cycles(cycles)
-        .await?
-        .candid()?)
+    Ok(BitcoinCall::GetBlockHeaders(arg).call().await?.candid()?)
 }
 
 /// Gets the cycles cost for the [`bitcoin_get_block_headers`] function.
@@ -184,15 +162,7 @@ pub async fn get_blockchain_info(network: Network) -> CallResult<BlockchainInfo>
 ///
 /// Check the [Bitcoin Canisters Interface Specification](https://github.com/dfinity/bitcoin-canister/blob/master/INTERFACE_SPECIFICATION.md#bitcoin_send_transaction) for more details.
 pub async fn bitcoin_send_transaction(arg: &SendTransactionRequest) -> CallResult<()> {
-    let canister_id = get_bitcoin_canister_id(arg.network.into());
-    let cycles = cost_send_transaction(arg);
-    Ok(
-        Call::unbounded_wait(canister_id, "bitcoin_send_transaction")
-            .with_arg(arg)
-            .with_cycles(cycles)
-            .await?
-            .candid()?,
-    )
+    Ok(BitcoinCall::SendTransaction(arg).call().await?.candid()?)
 }
 
 /// Gets the cycles cost for the [`bitcoin_send_transaction`] function.
@@ -218,3 +188,114 @@ pub fn cost_send_transaction(arg: &SendTransactionRequest) -> u128 {
     };
     submission + payload * arg.transaction.len() as u128
 }
+
+/// A call to the Bitcoin canister, for estimating the cost of a batch of calls with
+/// [`CostPlan`](ic_cdk::cycles::CostPlan) before making them.
+///
+/// The estimate covers the cycles attached by the function making the call, such as [`cost_get_utxos`] for
+/// [`bitcoin_get_utxos`], and the cost of the call itself (see [`Call::get_cost`]).
+///
+/// ```rust, no_run
+/// use ic_cdk::cycles::CostPlan;
+/// use ic_cdk_bitcoin_canister::{BitcoinCall, GetUtxosRequest};
+///
+/// # async fn get_all_utxos(requests: Vec<GetUtxosRequest>) -> Result<(), String> {
+/// let calls: Vec<_> = requests.iter().map(BitcoinCall::from).collect();
+/// CostPlan::new()
+///     .with(&calls[..])
+///     .check_balance()
+///     .map_err(|e| e.to_string())?;
+/// for request in &requests {
+///     let utxos = ic_cdk_bitcoin_canister::bitcoin_get_utxos(request).await;
+/// #   let _ = utxos;
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub enum BitcoinCall<'a> {
+    /// A call made by [`bitcoin_get_utxos`].
+    GetUtxos(&'a GetUtxosRequest),
+    /// A call made by [`bitcoin_get_balance`].
+    GetBalance(&'a GetBalanceRequest),
+    /// A call made by [`bitcoin_get_current_fee_percentiles`].
+    GetCurrentFeePercentiles(&'a GetCurrentFeePercentilesRequest),
+    /// A call made by [`bitcoin_get_block_headers`].
+    GetBlockHeaders(&'a GetBlockHeadersRequest),
+    /// A call made by [`bitcoin_send_transaction`].
+    SendTransaction(&'a SendTransactionRequest),
+}
+
+impl<'a> From<&'a GetUtxosRequest> for BitcoinCall<'a> {
+    fn from(arg: &'a GetUtxosRequest) -> Self {
+        Self::GetUtxos(arg)
+    }
+}
+
+impl<'a> From<&'a GetBalanceRequest> for BitcoinCall<'a> {
+    fn from(arg: &'a GetBalanceRequest) -> Self {
+        Self::GetBalance(arg)
+    }
+}
+
+impl<'a> From<&'a GetCurrentFeePercentilesRequest> for BitcoinCall<'a> {
+    fn from(arg: &'a GetCurrentFeePercentilesRequest) -> Self {
+        Self::GetCurrentFeePercentiles(arg)
+    }
+}
+
+impl<'a> From<&'a GetBlockHeadersRequest> for BitcoinCall<'a> {
+    fn from(arg: &'a GetBlockHeadersRequest) -> Self {
+        Self::GetBlockHeaders(arg)
+    }
+}
+
+impl<'a> From<&'a SendTransactionRequest> for BitcoinCall<'a> {
+    fn from(arg: &'a SendTransactionRequest) -> Self {
+        Self::SendTransaction(arg)
+    }
+}
+
+impl BitcoinCall<'_> {
+    /// The call made by the function, with the cycles it attaches.
+    fn call(self) -> Call<'static, 'static> {
+        match self {
+            Self::GetUtxos(arg) => Call::bounded_wait(
+                get_bitcoin_canister_id(arg.network.into()),
+                "bitcoin_get_utxos",
+            )
+            .with_arg(arg)
+            .with_cycles(cost_get_utxos(arg)),
+            Self::GetBalance(arg) => Call::bounded_wait(
+                get_bitcoin_canister_id(arg.network.into()),
+                "bitcoin_get_balance",
+            )
+            .with_arg(arg)
+            .with_cycles(cost_get_balance(arg)),
+            Self::GetCurrentFeePercentiles(arg) => Call::bounded_wait(
+                get_bitcoin_canister_id(arg.network.into()),
+                "bitcoin_get_current_fee_percentiles",
+            )
+            .with_arg(arg)
+            .with_cycles(cost_get_current_fee_percentiles(arg)),
+            Self::GetBlockHeaders(arg) => Call::bounded_wait(
+                get_bitcoin_canister_id(arg.network.into()),
+                "bitcoin_get_block_headers",
+            )
+            .with_arg(arg)
+            .with_cycles(cost_get_block_headers(arg)),
+            Self::SendTransaction(arg) => Call::unbounded_wait(
+                get_bitcoin_canister_id(arg.network.into()),
+                "bitcoin_send_transaction",
+            )
+            .with_arg(arg)
+            .with_cycles(cost_send_transaction(arg)),
+        }
+    }
+}
+
+impl CostEstimate for BitcoinCall<'_> {
+    fn estimate_cost(&self) -> Result<u128, CostEstimateError> {
+        Ok(self.call().get_cost())
+    }
+}
@@ -0,0 +1,104 @@
+//! Estimates the cost of Bitcoin canister calls against `ic0::host::InMemoryHost`.
+
+use ic_cdk::cycles::CostEstimate;
+use ic_cdk::futures::internals::in_executor_context;
+use ic_cdk::futures::spawn;
+use ic_cdk_bitcoin_canister::{
+    BitcoinCall, GetBalanceRequest, GetBlockHeadersRequest, GetCurrentFeePercentilesRequest,
+    GetUtxosRequest, Network, NetworkInRequest, SendTransactionRequest, get_bitcoin_canister_id,
+};
+use ic0::host::{CallResponse, InMemoryHost, set_host};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+const CALL_BASE_FEE: u128 = 1_000;
+const CALL_FEE_PER_BYTE: u128 = 10;
+
+#[test]
+fn estimates_match_the_calls_made() {
+    let host = InMemoryHost::new();
+    set_host(host.clone());
+    host.set_cycle_balance(1_000_000_000_000);
+    host.set_call_cost(CALL_BASE_FEE, CALL_FEE_PER_BYTE);
+    let calls = Rc::new(RefCell::new(vec![]));
+    for method in [
+        "bitcoin_get_utxos",
+        "bitcoin_get_balance",
+        "bitcoin_get_current_fee_percentiles",
+        "bitcoin_get_block_headers",
+        "bitcoin_send_transaction",
+    ] {
+        let calls = calls.clone();
+        host.on_call(
+            get_bitcoin_canister_id(Network::Testnet),
+            method,
+            move |request| {
+                let size = (request.method.len() + request.arg.len()) as u128;
+                let cost = CALL_BASE_FEE + CALL_FEE_PER_BYTE * size + request.cycles;
+                calls.borrow_mut().push((cost, request.deadline == 0));
+                CallResponse::reject(5, "not implemented")
+            },
+        );
+    }
+
+    let address = "tb1q8yj0pqq0sjexfmnph0c8m0cjl4jyh9asmvl8dy".to_string();
+    let utxos = GetUtxosRequest {
+        address: address.clone(),
+        network: NetworkInRequest::Testnet,
+        filter: None,
+    };
+    let balance = GetBalanceRequest {
+        address,
+        network: NetworkInRequest::Testnet,
+        min_confirmations: Some(6),
+    };
+    let fees = GetCurrentFeePercentilesRequest {
+        network: NetworkInRequest::Testnet,
+    };
+    let headers = GetBlockHeadersRequest {
+        start_height: 100,
+        end_height: None,
+        network: NetworkInRequest::Testnet,
+    };
+    let transaction = SendTransactionRequest {
+        transaction: vec![0; 250],
+        network: NetworkInRequest::Testnet,
+    };
+    let estimates = [
+        BitcoinCall::from(&utxos),
+        BitcoinCall::from(&balance),
+        BitcoinCall::from(&fees),
+        BitcoinCall::from(&headers),
+        BitcoinCall::from(&transaction),
+    ]
+    .map(|call| call.estimate_cost().unwrap());
+
+    in_executor_context(|| {
+        spawn(async move {
+            use ic_cdk_bitcoin_canister as btc;
+            let _ = btc::bitcoin_get_utxos(&utxos).await;
+            let _ = btc::bitcoin_get_balance(&balance).await;
+            let _ = btc::bitcoin_get_current_fee_percentiles(&fees).await;
+            let _ = btc::bitcoin_get_block_headers(&headers).await;
+            let _ = btc::bitcoin_send_transaction(&transaction).await;
+        });
+    });
+    host.run_until_idle();
+
+    let calls = calls.borrow();
+    assert_eq!(
+        calls.iter().map(|(cost, _)| *cost).collect::<Vec<_>>(),
+        estimates
+    );
+    // Only sending a transaction is an unbounded-wait call.
+    assert_eq!(
+        calls
+            .iter()
+            .map(|(_, unbounded)| *unbounded)
+            .collect::<Vec<_>>(),
+        [false, false, false, false, true]
+    );
+    // The estimates include the fees of the Bitcoin canister, not only the cost of the calls.
+    assert!(estimates[0] > 4_000_000_000);
+    assert!(estimates[4] > 2_000_000_000 + 250 * 8_000_000);
+}
@@ -148,6 +148,7 @@ pub fn in_trap_recovery_context_for<R>(method: MethodHandle, f: impl FnOnce() ->
     setup_panic_hook();
     enter_current_method(method, || {
         RECOVERING.set(true);
+        let _unwind = OnUnwind(|| RECOVERING.set(false));
         let res = f();
         RECOVERING.set(false);
         res
@@ -241,7 +242,7 @@ pub(crate) fn poll_all() {
     }
     while let Some(task_id) = pop_wakeup(method_id, kind == ContextKind::Update) {
         // Temporarily remove the task from the table. We need to execute it while `TASKS` is not borrowed, because it may schedule more tasks.
-        let Some(mut task) = TASKS.with_borrow_mut(|tasks| tasks.get_mut(task_id).map(take)) else {
+        let Some(task) = TASKS.with_borrow_mut(|tasks| tasks.get_mut(task_id).map(take)) else {
             // This waker handle appears to be dead. The most likely cause is that the method returned before
             // a canceled call came back.
             continue;
@@ -251,7 +252,18 @@ pub(crate) fn poll_all() {
         let waker = Waker::from(Arc::new(TaskWaker { task_id }));
         let prev_current_method_var = CURRENT_METHOD.replace(Some(task.set_current_method_var));
         CURRENT_TASK_ID.set(Some(task_id));
-        let poll = task.future.as_mut().poll(&mut Context::from_waker(&waker));
+        let mut taken = TakenTask {
+            task_id,
+            task,
+            prev_current_method_var,
+        };
+        let poll = taken
+            .task
+            .future
+            .as_mut()
+            .poll(&mut Context::from_waker(&waker));
+        let task = take(&mut taken.task);
+        drop(taken);
         CURRENT_TASK_ID.set(None);
         CURRENT_METHOD.set(prev_current_method_var);
         match poll {
@@ -280,8 +292,10 @@ pub(crate) fn enter_current_method<R>(method_guard: MethodHandle, f: impl FnOnce
         );
         context_var.set(Some(method_guard.method_id));
     });
+    let unwind = OnUnwind(|| CURRENT_METHOD.set(None));
     let r = f();
     drop(method_guard); // drop the guard *before* the method freeing logic, but *after* the in-context code
+    drop(unwind);
     let method_id = CURRENT_METHOD.replace(None);
     if let Some(method_id) = method_id {
         let handles = METHODS.with_borrow_mut(|methods| methods.get(method_id).map(|m| m.handles));
@@ -293,6 +307,43 @@ pub(crate) fn enter_current_method<R>(method_guard: MethodHandle, f: impl FnOnce
     r
 }
 
+/// Runs a closure when dropped during a panic.
+///
+/// A trap rolls back all changes made during the message, including the executor's own bookkeeping. Outside of Wasm,
+/// traps are panics, which roll nothing back, so the executor restores its bookkeeping by hand when unwinding. This
+/// keeps it usable by a native host that catches the panic and runs the cleanup callback, as the IC would.
+struct OnUnwind<F: FnMut()>(F);
+
+impl<F: FnMut()> Drop for OnUnwind<F> {
+    fn drop(&mut self) {
+        if std::thread::panicking() {
+            (self.0)();
+        }
+    }
+}
+
+/// A task temporarily removed from [`TASKS`] to be polled. If the poll panics, it is put back, as a trap would.
+struct TakenTask {
+    task_id: TaskId,
+    task: Task,
+    prev_current_method_var: Option<MethodId>,
+}
+
+impl Drop for TakenTask {
+    fn drop(&mut self) {
+        if std::thread::panicking() {
+            CURRENT_TASK_ID.set(None);
+            CURRENT_METHOD.set(self.prev_current_method_var);
+            let task = take(&mut self.task);
+            TASKS.with_borrow_mut(|tasks| {
+                if let Some(t) = tasks.get_mut(self.task_id) {
+                    *t = task;
+                }
+            });
+        }
+    }
+}
+
 /// A handle to a method context. If the function returns and all handles have been dropped, the method is considered returned.
 ///
 /// This should be created before performing an inter-canister call via [`extend_current_method_context`],
@@ -319,6 +370,33 @@ impl MethodHandle {
     }
 }
 
+impl MethodHandle {
+    /// Makes a handle to the same method that does not keep it active.
+    pub fn downgrade(&self) -> WeakMethodHandle {
+        WeakMethodHandle {
+            method_id: self.method_id,
+        }
+    }
+}
+
+/// A handle to a method context that does not keep it active, made with [`MethodHandle::downgrade`].
+#[derive(Debug, Clone)]
+pub struct WeakMethodHandle {
+    method_id: MethodId,
+}
+
+impl WeakMethodHandle {
+    /// Makes a live handle to the method, or returns `None` if it has been freed.
+    pub fn upgrade(&self) -> Option<MethodHandle> {
+        if !self.method_id.is_null()
+            && METHODS.with_borrow(|methods| methods.get(self.method_id).is_none())
+        {
+            return None;
+        }
+        Some(MethodHandle::for_method(self.method_id))
+    }
+}
+
 impl Drop for MethodHandle {
     fn drop(&mut self) {
         METHODS.with_borrow_mut(|methods| {
@@ -330,7 +408,7 @@ impl Drop for MethodHandle {
 }
 
 /// A handle to a spawned task.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct TaskHandle {
     task_id: TaskId,
 }
@@ -341,6 +419,11 @@ impl TaskHandle {
         let task_id = CURRENT_TASK_ID.get()?;
         Some(Self { task_id })
     }
+
+    /// Checks whether the task still exists, i.e. it has neither completed nor been canceled.
+    pub fn is_alive(&self) -> bool {
+        TASKS.with_borrow(|tasks| tasks.contains_key(self.task_id))
+    }
 }
 
 pub(crate) struct TaskWaker {
@@ -433,6 +516,11 @@ pub fn spawn_protected(f: impl Future<Output = ()> + 'static) -> TaskHandle {
 }
 
 fn setup_panic_hook() {
+    // Outside of Wasm, `ic0::trap` panics, which would abort the process from inside the hook.
+    // The default hook already prints the message.
+    if cfg!(not(target_family = "wasm")) {
+        return;
+    }
     static SETUP: Once = Once::new();
     SETUP.call_once(|| {
         std::panic::set_hook(Box::new(|info| {
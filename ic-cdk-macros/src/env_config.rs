@@ -0,0 +1,195 @@
+use darling::util::Override;
+use darling::{FromDeriveInput, FromField, FromVariant};
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::spanned::Spanned;
+use syn::{DeriveInput, Error, Path};
+
+#[derive(FromDeriveInput)]
+#[darling(attributes(env), supports(struct_named))]
+struct EnvConfigInput {
+    ident: syn::Ident,
+    generics: syn::Generics,
+    data: darling::ast::Data<(), EnvConfigField>,
+    /// Prepended to the names of the variables derived from field names.
+    prefix: Option<String>,
+    #[darling(rename = "crate")]
+    cratename: Option<String>,
+}
+
+#[derive(FromField)]
+#[darling(attributes(env))]
+struct EnvConfigField {
+    ident: Option<syn::Ident>,
+    ty: syn::Type,
+    /// The name of the variable, overriding the one derived from the field name.
+    name: Option<String>,
+    /// `default` for `Default::default()`, or `default = "..."` for a value parsed like the variable.
+    default: Option<Override<String>>,
+}
+
+#[derive(FromDeriveInput)]
+#[darling(attributes(env), supports(enum_unit))]
+struct FromEnvVarInput {
+    ident: syn::Ident,
+    generics: syn::Generics,
+    data: darling::ast::Data<FromEnvVarVariant, ()>,
+    #[darling(rename = "crate")]
+    cratename: Option<String>,
+}
+
+#[derive(FromVariant)]
+#[darling(attributes(env))]
+struct FromEnvVarVariant {
+    ident: syn::Ident,
+    /// The value selecting the variant, matched case-insensitively, overriding the variant name.
+    rename: Option<String>,
+}
+
+fn no_generics(generics: &syn::Generics, derive: &str) -> Result<(), Error> {
+    if generics.params.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::new(
+            generics.span(),
+            format!("#[derive({derive})] does not support generic parameters."),
+        ))
+    }
+}
+
+pub(crate) fn derive_env_config(item: TokenStream) -> Result<TokenStream, Error> {
+    let input = EnvConfigInput::from_derive_input(&syn::parse2::<DeriveInput>(item)?)?;
+    no_generics(&input.generics, "EnvConfig")?;
+    let cratename: Path = syn::parse_str(input.cratename.as_deref().unwrap_or("::ic_cdk"))?;
+    let ident = &input.ident;
+    let prefix = input.prefix.unwrap_or_default();
+    let fields = input
+        .data
+        .take_struct()
+        .expect("darling only accepts structs with named fields")
+        .fields
+        .into_iter()
+        .map(|field| {
+            let field_ident = field.ident.expect("named fields have identifiers");
+            let ty = &field.ty;
+            let name = field.name.unwrap_or_else(|| {
+                format!(
+                    "{prefix}{}",
+                    field_ident
+                        .to_string()
+                        .trim_start_matches("r#")
+                        .to_uppercase()
+                )
+            });
+            let value = match field.default {
+                None => quote! { #cratename::env_config::var::<#ty>(#name)? },
+                Some(Override::Inherit) => quote! {
+                    #cratename::env_config::try_var::<#ty>(#name)?.unwrap_or_default()
+                },
+                Some(Override::Explicit(default)) => quote! {
+                    match #cratename::env_config::try_var::<#ty>(#name)? {
+                        Some(value) => value,
+                        None => #cratename::env_config::parse::<#ty>(#name, #default)?,
+                    }
+                },
+            };
+            quote! { #field_ident: #value }
+        });
+    Ok(quote! {
+        impl #cratename::env_config::EnvConfig for #ident {
+            fn load() -> ::std::result::Result<Self, #cratename::env_config::EnvConfigError> {
+                ::std::result::Result::Ok(Self {
+                    #(#fields,)*
+                })
+            }
+
+            #[doc(hidden)]
+            fn __cache() -> &'static ::std::thread::LocalKey<
+                ::std::cell::RefCell<::std::option::Option<::std::rc::Rc<Self>>>,
+            > {
+                ::std::thread_local! {
+                    static CACHE: ::std::cell::RefCell<::std::option::Option<::std::rc::Rc<#ident>>> =
+                        const { ::std::cell::RefCell::new(::std::option::Option::None) };
+                }
+                &CACHE
+            }
+        }
+    })
+}
+
+pub(crate) fn derive_from_env_var(item: TokenStream) -> Result<TokenStream, Error> {
+    let input = FromEnvVarInput::from_derive_input(&syn::parse2::<DeriveInput>(item)?)?;
+    no_generics(&input.generics, "FromEnvVar")?;
+    let cratename: Path = syn::parse_str(input.cratename.as_deref().unwrap_or("::ic_cdk"))?;
+    let ident = &input.ident;
+    let variants = input
+        .data
+        .take_enum()
+        .expect("darling only accepts enums with unit variants");
+    let (names, idents): (Vec<_>, Vec<_>) = variants
+        .into_iter()
+        .map(|variant| {
+            let name = variant
+                .rename
+                .unwrap_or_else(|| variant.ident.to_string())
+                .to_lowercase();
+            (name, variant.ident)
+        })
+        .unzip();
+    let expected = names.join(", ");
+    Ok(quote! {
+        impl #cratename::env_config::FromEnvVar for #ident {
+            fn from_env_var(value: &str) -> ::std::result::Result<Self, ::std::string::String> {
+                match value.to_lowercase().as_str() {
+                    #(#names => ::std::result::Result::Ok(Self::#idents),)*
+                    _ => ::std::result::Result::Err(::std::format!("expected one of: {}", #expected)),
+                }
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn env_config_fields() {
+        let generated = derive_env_config(quote! {
+            #[env(prefix = "APP_")]
+            struct Config {
+                ledger: Principal,
+                #[env(name = "FEE")]
+                fee: u64,
+                #[env(default)]
+                verbose: bool,
+                #[env(default = "30s")]
+                timeout: Duration,
+            }
+        })
+        .unwrap();
+        let parsed = syn::parse2::<syn::ItemImpl>(generated).unwrap();
+        let syn::ImplItem::Fn(load) = &parsed.items[0] else {
+            panic!("not a function");
+        };
+        let expected: syn::Block = syn::parse_quote! {{
+            ::std::result::Result::Ok(Self {
+                ledger: ::ic_cdk::env_config::var::<Principal>("APP_LEDGER")?,
+                fee: ::ic_cdk::env_config::var::<u64>("FEE")?,
+                verbose: ::ic_cdk::env_config::try_var::<bool>("APP_VERBOSE")?.unwrap_or_default(),
+                timeout: match ::ic_cdk::env_config::try_var::<Duration>("APP_TIMEOUT")? {
+                    Some(value) => value,
+                    None => ::ic_cdk::env_config::parse::<Duration>("APP_TIMEOUT", "30s")?,
+                },
+            })
+        }};
+        assert_eq!(load.block, expected);
+    }
+
+    #[test]
+    fn env_config_rejects_unsupported_items() {
+        assert!(derive_env_config(quote! { struct Config(u64); }).is_err());
+        assert!(derive_env_config(quote! { struct Config<T> { t: T } }).is_err());
+        assert!(derive_from_env_var(quote! { enum Network { Named { id: u64 } } }).is_err());
+    }
+}
@@ -12,8 +12,10 @@ use syn::{
 #[derive(Default, FromMeta)]
 struct ExportAttributes {
     pub name: Option<String>,
+    /// The guard functions, as paths such as `"is_admin"`, or as expressions evaluating to a guard function, such as
+    /// `"has_role(\"minter\")"`.
     #[darling(multiple)]
-    pub guard: Vec<String>,
+    pub guard: Vec<syn::Expr>,
     /// The name of the function to use for decoding arguments.
     /// If not provided, the arguments are decoded as Candid.
     ///
@@ -32,6 +34,22 @@ struct ExportAttributes {
     pub hidden: bool,
     #[darling(rename = "crate")]
     pub cratename: Option<String>,
+    /// The cycles charged to the caller before the method runs.
+    pub cycles: Option<CyclesAttributes>,
+    /// Run the update method while the canister is draining, instead of rejecting its calls.
+    #[darling(default)]
+    pub allow_while_draining: bool,
+}
+
+#[derive(FromMeta)]
+struct CyclesAttributes {
+    /// Accept exactly this many cycles, which must be attached.
+    pub accept: Option<syn::Expr>,
+    /// Accept all cycles attached, which must be at least this many.
+    pub min: Option<syn::Expr>,
+    /// With `min`, accept only that many cycles.
+    #[darling(default)]
+    pub refund_excess: bool,
 }
 
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
@@ -138,10 +156,37 @@ fn get_args(method: MethodType, signature: &Signature) -> Result<Vec<(Ident, Box
     Ok(args)
 }
 
+/// How the exported methods are instrumented, chosen by the features of this crate.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum Instrumentation {
+    None,
+    /// Record the instructions of each method in `ic_cdk::metrics`.
+    Metrics,
+    /// Wrap each method for `ic_cdk::profiling`, which records the instructions itself.
+    Profiling,
+}
+
 fn dfn_macro(
     method: MethodType,
     attr: TokenStream,
     item: TokenStream,
+) -> Result<TokenStream, Error> {
+    let instrumentation = if cfg!(feature = "profiling") {
+        Instrumentation::Profiling
+    } else if cfg!(feature = "metrics") {
+        Instrumentation::Metrics
+    } else {
+        Instrumentation::None
+    };
+    export_method(method, attr, item, instrumentation)
+}
+
+/// Exports a method, instrumented as `instrumentation` says.
+fn export_method(
+    method: MethodType,
+    attr: TokenStream,
+    item: TokenStream,
+    instrumentation: Instrumentation,
 ) -> Result<TokenStream, Error> {
     let attr_span = attr.span();
     let attr_args = NestedMeta::parse_meta_list(attr)?;
@@ -201,22 +246,73 @@ fn dfn_macro(
             format!("#[{method}] cannot have guard function(s)."),
         ));
     }
+    if attrs.allow_while_draining && method != MethodType::Update {
+        return Err(Error::new(
+            attr_span,
+            format!("#[{method}] cannot have the allow_while_draining attribute."),
+        ));
+    }
+    let drain_guard = if method == MethodType::Update && !attrs.allow_while_draining {
+        quote! {
+            let r: Result<(), String> = #cratename::lifecycle::reject_while_draining();
+            if let Err(e) = r {
+                #cratename::api::msg_reject(&e);
+                return;
+            }
+        }
+    } else {
+        quote! {}
+    };
     let guards = attrs
         .guard
         .iter()
-        .map(|guard_name| -> Result<_, Error> {
-            let guard_path = parse_str::<Path>(guard_name)?;
-            Ok(quote! {
-                let r: Result<(), String> = #guard_path ();
+        .map(|guard| {
+            // Any expression other than a path, such as `has_role("minter")`, evaluates to the guard function.
+            let guard = match guard {
+                syn::Expr::Path(_) => quote!(#guard),
+                _ => quote!((#guard)),
+            };
+            quote! {
+                let r: Result<(), String> = #guard ();
                 if let Err(e) = r {
                     #cratename::api::msg_reject(&e);
                     return;
                 }
-            })
+            }
         })
-        .collect::<Result<Vec<_>, Error>>()?;
+        .collect::<Vec<_>>();
+    let charge = match &attrs.cycles {
+        None => quote! {},
+        Some(_) if method != MethodType::Update => {
+            return Err(Error::new(
+                attr_span,
+                format!("#[{method}] cannot have a cycles attribute."),
+            ));
+        }
+        Some(cycles) => {
+            let (required, max) = match (&cycles.accept, &cycles.min, cycles.refund_excess) {
+                (Some(n), None, false) | (None, Some(n), true) => (n, quote! { cycles }),
+                (None, Some(n), false) => (n, quote! { u128::MAX }),
+                _ => {
+                    return Err(Error::new(
+                        attr_span,
+                        "The cycles attribute must be `cycles(accept = N)`, `cycles(min = N)` or `cycles(min = N, refund_excess)`.",
+                    ));
+                }
+            };
+            quote! {
+                let cycles: u128 = #required;
+                if let Err(e) = #cratename::cycles::charge(cycles, #max) {
+                    #cratename::api::msg_reject(&e.to_string());
+                    return;
+                }
+            }
+        }
+    };
     let guard = quote! {
+        #drain_guard
         #(#guards)*
+        #charge
     };
 
     // 3. decode arguments
@@ -349,31 +445,68 @@ fn dfn_macro(
         }
     };
 
-    // 7. exported function body
+    // 7. if the `metrics` feature is enabled, record the instructions of the method in `ic_cdk::metrics`, unless its
+    // changes to the state are discarded, or profile the method if the `profiling` feature is enabled
+    let profiling = instrumentation == Instrumentation::Profiling;
+    let label = if method.is_lifecycle() {
+        &export_name
+    } else {
+        &function_name
+    };
+    let record_instructions =
+        if method.is_state_persistent() && instrumentation == Instrumentation::Metrics {
+            quote! { #cratename::metrics::internals::record_method_instructions(#label); }
+        } else {
+            quote! {}
+        };
+
+    // 8. exported function body
     let async_context_name = if method.is_state_persistent() {
         format_ident!("in_executor_context")
     } else {
         format_ident!("in_query_executor_context")
     };
     let body = if signature.asyncness.is_some() {
+        let mut future = quote! {
+            async {
+                #arg_decode
+                let result = #function_call;
+                #record_instructions
+                #return_encode
+            }
+        };
+        if profiling {
+            future = quote! { #cratename::profiling::internals::profile(#label, #future) };
+        }
+        // `ic0.msg_deadline` is not available in the lifecycle methods.
+        if !method.is_lifecycle() {
+            future = quote! { #cratename::futures::internals::with_message_deadline(#future) };
+        }
         quote! {
             #cratename::futures::internals::#async_context_name(|| {
                 #guard
                 #[allow(clippy::disallowed_methods)]
-                #cratename::futures::spawn(async {
-                    #arg_decode
-                    let result = #function_call;
-                    #return_encode
-                });
+                #cratename::futures::spawn(#future);
             });
         }
     } else {
+        let mut call = quote! {
+            #arg_decode
+            let result = #function_call;
+            #record_instructions
+            #return_encode
+        };
+        if profiling {
+            call = quote! {
+                #cratename::profiling::internals::profile_sync(#label, || {
+                    #call
+                });
+            };
+        }
         quote! {
             #guard
             #cratename::futures::internals::#async_context_name(|| {
-                #arg_decode
-                let result = #function_call;
-                #return_encode
+                #call
             });
         }
     };
@@ -435,11 +568,13 @@ mod test {
 
     #[test]
     fn ic_query_empty() {
-        let generated = ic_query(
+        let generated = export_method(
+            MethodType::Query,
             quote!(),
             quote! {
                 fn query() {}
             },
+            Instrumentation::None,
         )
         .unwrap();
         let parsed = syn::parse2::<syn::File>(generated).unwrap();
@@ -485,11 +620,13 @@ mod test {
 
     #[test]
     fn ic_query_return_one_value() {
-        let generated = ic_query(
+        let generated = export_method(
+            MethodType::Query,
             quote!(),
             quote! {
                 fn query() -> u32 {}
             },
+            Instrumentation::None,
         )
         .unwrap();
         let parsed = syn::parse2::<syn::File>(generated).unwrap();
@@ -521,11 +658,13 @@ mod test {
 
     #[test]
     fn ic_query_return_tuple() {
-        let generated = ic_query(
+        let generated = export_method(
+            MethodType::Query,
             quote!(),
             quote! {
                 fn query() -> (u32, u32) {}
             },
+            Instrumentation::None,
         )
         .unwrap();
         let parsed = syn::parse2::<syn::File>(generated).unwrap();
@@ -557,11 +696,13 @@ mod test {
 
     #[test]
     fn ic_query_one_arg() {
-        let generated = ic_query(
+        let generated = export_method(
+            MethodType::Query,
             quote!(),
             quote! {
                 fn query(a: u32) {}
             },
+            Instrumentation::None,
         )
         .unwrap();
         let parsed = syn::parse2::<syn::File>(generated).unwrap();
@@ -596,11 +737,13 @@ mod test {
 
     #[test]
     fn ic_query_two_args() {
-        let generated = ic_query(
+        let generated = export_method(
+            MethodType::Query,
             quote!(),
             quote! {
                 fn query(a: u32, b: u32) {}
             },
+            Instrumentation::None,
         )
         .unwrap();
         let parsed = syn::parse2::<syn::File>(generated).unwrap();
@@ -635,11 +778,13 @@ mod test {
 
     #[test]
     fn ic_query_two_args_return_value() {
-        let generated = ic_query(
+        let generated = export_method(
+            MethodType::Query,
             quote!(),
             quote! {
                 fn query(a: u32, b: u32) -> u64 {}
             },
+            Instrumentation::None,
         )
         .unwrap();
         let parsed = syn::parse2::<syn::File>(generated).unwrap();
@@ -674,11 +819,13 @@ mod test {
 
     #[test]
     fn ic_query_export_name() {
-        let generated = ic_query(
+        let generated = export_method(
+            MethodType::Query,
             quote!(name = "custom_query"),
             quote! {
                 fn query() {}
             },
+            Instrumentation::None,
         )
         .unwrap();
         let parsed = syn::parse2::<syn::File>(generated).unwrap();
@@ -709,11 +856,13 @@ mod test {
 
     #[test]
     fn ic_query_custom_decoder() {
-        let generated = ic_query(
+        let generated = export_method(
+            MethodType::Query,
             quote!(decode_with = "custom_decoder"),
             quote! {
                 fn query(a: u32) {}
             },
+            Instrumentation::None,
         )
         .unwrap();
         let parsed = syn::parse2::<syn::File>(generated).unwrap();
@@ -760,11 +909,13 @@ mod test {
 
     #[test]
     fn ic_query_custom_encoder() {
-        let generated = ic_query(
+        let generated = export_method(
+            MethodType::Query,
             quote!(encode_with = "custom_encoder"),
             quote! {
                 fn query() -> u32 {}
             },
+            Instrumentation::None,
         )
         .unwrap();
         let parsed = syn::parse2::<syn::File>(generated).unwrap();
@@ -809,11 +960,13 @@ mod test {
 
     #[test]
     fn ic_guards() {
-        let generated = ic_query(
+        let generated = export_method(
+            MethodType::Query,
             quote!(guard = "guard1", guard = "guard2"),
             quote! {
                 fn query() {}
             },
+            Instrumentation::None,
         )
         .unwrap();
         let parsed = syn::parse2::<syn::File>(generated).unwrap();
@@ -852,13 +1005,62 @@ mod test {
         };
     }
 
+    #[test]
+    fn ic_guard_expressions() {
+        let generated = export_method(
+            MethodType::Query,
+            quote!(guard = "has_role(\"minter\")", guard = access::admins_only),
+            quote! {
+                fn query() {}
+            },
+            Instrumentation::None,
+        )
+        .unwrap();
+        let parsed = syn::parse2::<syn::File>(generated).unwrap();
+        assert!(parsed.items.len() == 3);
+        let fn_name = match parsed.items[0] {
+            syn::Item::Fn(ref f) => &f.sig.ident,
+            _ => panic!("Incorrect parsed AST."),
+        };
+        let expected = quote! {
+            #[cfg_attr(target_family = "wasm", unsafe(export_name = "canister_query query"))]
+            #[cfg_attr(not(target_family = "wasm"), unsafe(export_name = "canister_query.query"))]
+            fn #fn_name() {
+                let r: Result<(), String> = (has_role("minter")) ();
+                if let Err(e) = r {
+                    ::ic_cdk::api::msg_reject(&e);
+                    return;
+                }
+                let r: Result<(), String> = access::admins_only ();
+                if let Err(e) = r {
+                    ::ic_cdk::api::msg_reject(&e);
+                    return;
+                }
+                ::ic_cdk::futures::internals::in_query_executor_context(|| {
+                    let result = query();
+                    let bytes: Vec<u8> = ::candid::utils::encode_one(()).unwrap();
+                    ::ic_cdk::api::msg_reply(bytes);
+                });
+            }
+        };
+        let expected = syn::parse2::<syn::ItemFn>(expected).unwrap();
+        match &parsed.items[0] {
+            syn::Item::Fn(f) => {
+                assert_eq!(*f, expected);
+            }
+            _ => panic!("not a function"),
+        };
+    }
+
     #[test]
     fn alternate_crate() {
-        let generated = ic_query(
+        let generated = export_method(
+            MethodType::Query,
             quote!(crate = "ic_cdk_old"),
             quote! {
                 fn query() -> u32 {}
             },
+            Instrumentation::None,
         )
         .unwrap();
         let parsed = syn::parse2::<syn::File>(generated).unwrap();
@@ -887,4 +1089,229 @@ mod test {
             _ => panic!("not a function"),
         };
     }
+
+    #[test]
+    fn ic_update_records_instructions() {
+        let generated = export_method(
+            MethodType::Update,
+            quote!(name = "custom"),
+            quote! {
+                async fn update() {}
+            },
+            Instrumentation::Metrics,
+        )
+        .unwrap();
+        let parsed = syn::parse2::<syn::File>(generated).unwrap();
+        let fn_name = match parsed.items[0] {
+            syn::Item::Fn(ref f) => &f.sig.ident,
+            _ => panic!("Incorrect parsed AST."),
+        };
+        let expected = quote! {
+            #[cfg_attr(target_family = "wasm", unsafe(export_name = "canister_update custom"))]
+            #[cfg_attr(not(target_family = "wasm"), unsafe(export_name = "canister_update.custom"))]
+            fn #fn_name() {
+                ::ic_cdk::futures::internals::in_executor_context(|| {
+                    let r: Result<(), String> = ::ic_cdk::lifecycle::reject_while_draining();
+                    if let Err(e) = r {
+                        ::ic_cdk::api::msg_reject(&e);
+                        return;
+                    }
+                    #[allow(clippy::disallowed_methods)]
+                    ::ic_cdk::futures::spawn(::ic_cdk::futures::internals::with_message_deadline(async {
+                        let result = update().await;
+                        ::ic_cdk::metrics::internals::record_method_instructions("custom");
+                        let bytes: Vec<u8> = ::candid::utils::encode_one(()).unwrap();
+                        ::ic_cdk::api::msg_reply(bytes);
+                    }));
+                });
+            }
+        };
+        let expected = syn::parse2::<syn::ItemFn>(expected).unwrap();
+        match &parsed.items[0] {
+            syn::Item::Fn(f) => {
+                assert_eq!(*f, expected);
+            }
+            _ => panic!("not a function"),
+        };
+    }
+
+    #[test]
+    fn ic_init_records_instructions() {
+        let generated = export_method(
+            MethodType::Init,
+            quote!(),
+            quote! {
+                fn init() {}
+            },
+            Instrumentation::Metrics,
+        )
+        .unwrap();
+        let parsed = syn::parse2::<syn::File>(generated).unwrap();
+        let fn_name = match parsed.items[0] {
+            syn::Item::Fn(ref f) => &f.sig.ident,
+            _ => panic!("Incorrect parsed AST."),
+        };
+        let expected = quote! {
+            #[cfg_attr(target_family = "wasm", unsafe(export_name = "canister_init"))]
+            #[cfg_attr(not(target_family = "wasm"), unsafe(export_name = "canister_init"))]
+            fn #fn_name() {
+                ::ic_cdk::futures::internals::in_executor_context(|| {
+                    let result = init();
+                    ::ic_cdk::metrics::internals::record_method_instructions("canister_init");
+                });
+            }
+        };
+        let expected = syn::parse2::<syn::ItemFn>(expected).unwrap();
+        match &parsed.items[0] {
+            syn::Item::Fn(f) => {
+                assert_eq!(*f, expected);
+            }
+            _ => panic!("not a function"),
+        };
+    }
+
+    #[test]
+    fn profiled_methods() {
+        let generated = export_method(
+            MethodType::Update,
+            quote!(allow_while_draining),
+            quote! {
+                async fn update() {}
+            },
+            Instrumentation::Profiling,
+        )
+        .unwrap();
+        let parsed = syn::parse2::<syn::File>(generated).unwrap();
+        let fn_name = match parsed.items[0] {
+            syn::Item::Fn(ref f) => &f.sig.ident,
+            _ => panic!("Incorrect parsed AST."),
+        };
+        let expected = quote! {
+            #[cfg_attr(target_family = "wasm", unsafe(export_name = "canister_update update"))]
+            #[cfg_attr(not(target_family = "wasm"), unsafe(export_name = "canister_update.update"))]
+            fn #fn_name() {
+                ::ic_cdk::futures::internals::in_executor_context(|| {
+                    #[allow(clippy::disallowed_methods)]
+                    ::ic_cdk::futures::spawn(::ic_cdk::futures::internals::with_message_deadline(
+                        ::ic_cdk::profiling::internals::profile("update", async {
+                            let result = update().await;
+                            let bytes: Vec<u8> = ::candid::utils::encode_one(()).unwrap();
+                            ::ic_cdk::api::msg_reply(bytes);
+                        })
+                    ));
+                });
+            }
+        };
+        let expected = syn::parse2::<syn::ItemFn>(expected).unwrap();
+        match &parsed.items[0] {
+            syn::Item::Fn(f) => {
+                assert_eq!(*f, expected);
+            }
+            _ => panic!("not a function"),
+        };
+
+        let generated = export_method(
+            MethodType::Query,
+            quote!(),
+            quote! {
+                fn query() {}
+            },
+            Instrumentation::Profiling,
+        )
+        .unwrap();
+        let parsed = syn::parse2::<syn::File>(generated).unwrap();
+        let fn_name = match parsed.items[0] {
+            syn::Item::Fn(ref f) => &f.sig.ident,
+            _ => panic!("Incorrect parsed AST."),
+        };
+        let expected = quote! {
+            #[cfg_attr(target_family = "wasm", unsafe(export_name = "canister_query query"))]
+            #[cfg_attr(not(target_family = "wasm"), unsafe(export_name = "canister_query.query"))]
+            fn #fn_name() {
+                ::ic_cdk::futures::internals::in_query_executor_context(|| {
+                    ::ic_cdk::profiling::internals::profile_sync("query", || {
+                        let result = query();
+                        let bytes: Vec<u8> = ::candid::utils::encode_one(()).unwrap();
+                        ::ic_cdk::api::msg_reply(bytes);
+                    });
+                });
+            }
+        };
+        let expected = syn::parse2::<syn::ItemFn>(expected).unwrap();
+        match &parsed.items[0] {
+            syn::Item::Fn(f) => {
+                assert_eq!(*f, expected);
+            }
+            _ => panic!("not a function"),
+        };
+    }
+
+    #[test]
+    fn ic_update_charges_cycles() {
+        let generated = export_method(
+            MethodType::Update,
+            quote!(guard = "guard", cycles(min = FEE, refund_excess)),
+            quote! {
+                fn update() {}
+            },
+            Instrumentation::None,
+        )
+        .unwrap();
+        let parsed = syn::parse2::<syn::File>(generated).unwrap();
+        let fn_name = match parsed.items[0] {
+            syn::Item::Fn(ref f) => &f.sig.ident,
+            _ => panic!("Incorrect parsed AST."),
+        };
+        let expected = quote! {
+            #[cfg_attr(target_family = "wasm", unsafe(export_name = "canister_update update"))]
+            #[cfg_attr(not(target_family = "wasm"), unsafe(export_name = "canister_update.update"))]
+            fn #fn_name() {
+                let r: Result<(), String> = ::ic_cdk::lifecycle::reject_while_draining();
+                if let Err(e) = r {
+                    ::ic_cdk::api::msg_reject(&e);
+                    return;
+                }
+                let r: Result<(), String> = guard();
+                if let Err(e) = r {
+                    ::ic_cdk::api::msg_reject(&e);
+                    return;
+                }
+                let cycles: u128 = FEE;
+                if let Err(e) = ::ic_cdk::cycles::charge(cycles, cycles) {
+                    ::ic_cdk::api::msg_reject(&e.to_string());
+                    return;
+                }
+                ::ic_cdk::futures::internals::in_executor_context(|| {
+                    let result = update();
+                    let bytes: Vec<u8> = ::candid::utils::encode_one(()).unwrap();
+                    ::ic_cdk::api::msg_reply(bytes);
+                });
+            }
+        };
+        let expected = syn::parse2::<syn::ItemFn>(expected).unwrap();
+        match &parsed.items[0] {
+            syn::Item::Fn(f) => {
+                assert_eq!(*f, expected);
+            }
+            _ => panic!("not a function"),
+        };
+    }
+
+    #[test]
+    fn cycles_attribute_errors() {
+        let item = quote! { fn method() {} };
+        assert!(ic_query(quote!(cycles(accept = 1)), item.clone()).is_err());
+        assert!(ic_update(quote!(cycles(accept = 1, min = 1)), item.clone()).is_err());
+        assert!(ic_update(quote!(cycles(accept = 1, refund_excess)), item.clone()).is_err());
+        assert!(ic_update(quote!(cycles()), item.clone()).is_err());
+        assert!(ic_update(quote!(cycles(min = 1_000 * FEE)), item).is_ok());
+    }
+
+    #[test]
+    fn allow_while_draining_is_update_only() {
+        let item = quote! { fn method() {} };
+        assert!(ic_query(quote!(allow_while_draining), item.clone()).is_err());
+        assert!(ic_init(quote!(allow_while_draining), item.clone()).is_err());
+        assert!(ic_update(quote!(allow_while_draining), item).is_ok());
+    }
 }
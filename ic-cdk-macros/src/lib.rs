@@ -10,6 +10,7 @@
 use proc_macro::TokenStream;
 use syn::Error;
 
+mod env_config;
 mod export;
 
 fn handle_debug_and_errors<F>(
@@ -101,3 +102,23 @@ pub fn on_low_wasm_memory(attr: TokenStream, item: TokenStream) -> TokenStream {
         item,
     )
 }
+
+#[proc_macro_derive(EnvConfig, attributes(env))]
+pub fn derive_env_config(item: TokenStream) -> TokenStream {
+    handle_debug_and_errors(
+        |_, item| env_config::derive_env_config(item),
+        "derive_env_config",
+        TokenStream::new(),
+        item,
+    )
+}
+
+#[proc_macro_derive(FromEnvVar, attributes(env))]
+pub fn derive_from_env_var(item: TokenStream) -> TokenStream {
+    handle_debug_and_errors(
+        |_, item| env_config::derive_from_env_var(item),
+        "derive_from_env_var",
+        TokenStream::new(),
+        item,
+    )
+}
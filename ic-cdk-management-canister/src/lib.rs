@@ -9,6 +9,7 @@ use ic_cdk::api::{
     cost_vetkd_derive_key as ic0_cost_vetkd_derive_key,
 };
 use ic_cdk::call::{Call, CallFailed, CallResult, CandidDecodeFailed};
+use ic_cdk::cycles::{CostEstimate, CostEstimateError};
 use serde::{Deserialize, Serialize};
 
 // Re-export types from the `ic-management-canister-types` crate.
@@ -82,18 +83,7 @@ pub enum SignCallError {
 ///
 /// Check [Gas and cycles cost](https://internetcomputer.org/docs/current/developer-docs/gas-cost#canister-creation) for more details.
 pub async fn create_canister(arg: &CreateCanisterArgs) -> CallResult<CreateCanisterResult> {
-    let complete_arg = CreateCanisterArgsComplete {
-        settings: arg.settings.clone(),
-        sender_canister_version: Some(canister_version()),
-    };
-    let cycles = cost_create_canister();
-    Ok(
-        Call::unbounded_wait(Principal::management_canister(), "create_canister")
-            .with_arg(&complete_arg)
-            .with_cycles(cycles)
-            .await?
-            .candid()?,
-    )
+    Ok(create_canister_call(arg, 0).await?.candid()?)
 }
 
 /// Creates a new canister with extra cycles.
@@ -115,18 +105,19 @@ pub async fn create_canister_with_extra_cycles(
     arg: &CreateCanisterArgs,
     extra_cycles: u128,
 ) -> CallResult<CreateCanisterResult> {
+    Ok(create_canister_call(arg, extra_cycles).await?.candid()?)
+}
+
+/// The call made by [`create_canister`] and [`create_canister_with_extra_cycles`].
+fn create_canister_call(arg: &CreateCanisterArgs, extra_cycles: u128) -> Call<'static, 'static> {
     let complete_arg = CreateCanisterArgsComplete {
         settings: arg.settings.clone(),
         sender_canister_version: Some(canister_version()),
     };
     let cycles = cost_create_canister() + extra_cycles;
-    Ok(
-        Call::unbounded_wait(Principal::management_canister(), "create_canister")
-            .with_arg(&complete_arg)
-            .with_cycles(cycles)
-            .await?
-            .candid()?,
-    )
+    Call::unbounded_wait(Principal::management_canister(), "create_canister")
+        .with_arg(&complete_arg)
+        .with_cycles(cycles)
 }
 
 /// Argument type of [`create_canister`] and [`create_canister_with_extra_cycles`].
@@ -437,13 +428,14 @@ pub async fn delete_canister(arg: &DeleteCanisterArgs) -> CallResult<()> {
 ///
 /// See [IC method `deposit_cycles`](https://internetcomputer.org/docs/current/references/ic-interface-spec/#ic-deposit_cycles).
 pub async fn deposit_cycles(arg: &DepositCyclesArgs, cycles: u128) -> CallResult<()> {
-    Ok(
-        Call::unbounded_wait(Principal::management_canister(), "deposit_cycles")
-            .with_arg(arg)
-            .with_cycles(cycles)
-            .await?
-            .candid()?,
-    )
+    Ok(deposit_cycles_call(arg, cycles).await?.candid()?)
+}
+
+/// The call made by [`deposit_cycles`].
+fn deposit_cycles_call(arg: &DepositCyclesArgs, cycles: u128) -> Call<'static, 'static> {
+    Call::unbounded_wait(Principal::management_canister(), "deposit_cycles")
+        .with_arg(arg)
+        .with_cycles(cycles)
 }
 
 /// Gets 32 pseudo-random bytes.
@@ -499,14 +491,15 @@ pub fn cost_http_request(arg: &HttpRequestArgs) -> u128 {
 ///
 /// Check [HTTPS outcalls cycles cost](https://internetcomputer.org/docs/current/developer-docs/gas-cost#https-outcalls) for more details.
 pub async fn http_request(arg: &HttpRequestArgs) -> CallResult<HttpRequestResult> {
+    Ok(http_request_call(arg).await?.candid()?)
+}
+
+/// The call made by [`http_request`].
+fn http_request_call(arg: &HttpRequestArgs) -> Call<'static, 'static> {
     let cycles = cost_http_request(arg);
-    Ok(
-        Call::unbounded_wait(Principal::management_canister(), "http_request")
-            .with_arg(arg)
-            .with_cycles(cycles)
-            .await?
-            .candid()?,
-    )
+    Call::unbounded_wait(Principal::management_canister(), "http_request")
+        .with_arg(arg)
+        .with_cycles(cycles)
 }
 
 /// Constructs a [`TransformContext`] from a query method name and context.
@@ -673,13 +666,16 @@ pub fn cost_sign_with_ecdsa(arg: &SignWithEcdsaArgs) -> Result<u128, SignCostErr
 pub async fn sign_with_ecdsa(
     arg: &SignWithEcdsaArgs,
 ) -> Result<SignWithEcdsaResult, SignCallError> {
+    Ok(sign_with_ecdsa_call(arg)?.await?.candid()?)
+}
+
+/// The call made by [`sign_with_ecdsa`].
+fn sign_with_ecdsa_call(arg: &SignWithEcdsaArgs) -> Result<Call<'static, 'static>, SignCostError> {
     let cycles = cost_sign_with_ecdsa(arg)?;
     Ok(
         Call::unbounded_wait(Principal::management_canister(), "sign_with_ecdsa")
             .with_arg(arg)
-            .with_cycles(cycles)
-            .await?
-            .candid()?,
+            .with_cycles(cycles),
     )
 }
 
@@ -734,13 +730,18 @@ pub fn cost_sign_with_schnorr(arg: &SignWithSchnorrArgs) -> Result<u128, SignCos
 pub async fn sign_with_schnorr(
     arg: &SignWithSchnorrArgs,
 ) -> Result<SignWithSchnorrResult, SignCallError> {
+    Ok(sign_with_schnorr_call(arg)?.await?.candid()?)
+}
+
+/// The call made by [`sign_with_schnorr`].
+fn sign_with_schnorr_call(
+    arg: &SignWithSchnorrArgs,
+) -> Result<Call<'static, 'static>, SignCostError> {
     let cycles = cost_sign_with_schnorr(arg)?;
     Ok(
         Call::unbounded_wait(Principal::management_canister(), "sign_with_schnorr")
             .with_arg(arg)
-            .with_cycles(cycles)
-            .await?
-            .candid()?,
+            .with_cycles(cycles),
     )
 }
 
@@ -805,13 +806,18 @@ pub fn cost_vetkd_derive_key(arg: &VetKDDeriveKeyArgs) -> Result<u128, SignCostE
 pub async fn vetkd_derive_key(
     arg: &VetKDDeriveKeyArgs,
 ) -> Result<VetKDDeriveKeyResult, SignCallError> {
+    Ok(vetkd_derive_key_call(arg)?.await?.candid()?)
+}
+
+/// The call made by [`vetkd_derive_key`].
+fn vetkd_derive_key_call(
+    arg: &VetKDDeriveKeyArgs,
+) -> Result<Call<'static, 'static>, SignCostError> {
     let cycles = cost_vetkd_derive_key(arg)?;
     Ok(
         Call::unbounded_wait(Principal::management_canister(), "vetkd_derive_key")
             .with_arg(arg)
-            .with_cycles(cycles)
-            .await?
-            .candid()?,
+            .with_cycles(cycles),
     )
 }
 
@@ -1063,3 +1069,99 @@ pub async fn delete_canister_snapshot(arg: &DeleteCanisterSnapshotArgs) -> CallR
             .candid()?,
     )
 }
+
+/// A call to the management canister that costs cycles, for estimating the cost of a batch of calls with
+/// [`CostPlan`](ic_cdk::cycles::CostPlan) before making them.
+///
+/// The estimate covers the cycles attached by the function making the call, such as [`cost_http_request`] for
+/// [`http_request`], and the cost of the call itself (see [`Call::get_cost`]).
+///
+/// ```rust, no_run
+/// use ic_cdk::cycles::CostPlan;
+/// use ic_cdk_management_canister::{HttpRequestArgs, ManagementCall, SignWithEcdsaArgs};
+///
+/// # async fn fetch_and_sign(request: HttpRequestArgs, sign: SignWithEcdsaArgs) -> Result<(), String> {
+/// CostPlan::new()
+///     .with(&ManagementCall::from(&request))
+///     .with(&ManagementCall::from(&sign))
+///     .check_balance()
+///     .map_err(|e| e.to_string())?;
+/// let response = ic_cdk_management_canister::http_request(&request).await;
+/// let signature = ic_cdk_management_canister::sign_with_ecdsa(&sign).await;
+/// # let _ = (response, signature);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub enum ManagementCall<'a> {
+    /// A call made by [`create_canister`] or [`create_canister_with_extra_cycles`].
+    CreateCanister {
+        /// The argument of the call.
+        arg: &'a CreateCanisterArgs,
+        /// The cycles attached on top of the cost of creating the canister.
+        extra_cycles: u128,
+    },
+    /// A call made by [`deposit_cycles`].
+    DepositCycles {
+        /// The argument of the call.
+        arg: &'a DepositCyclesArgs,
+        /// The cycles deposited.
+        cycles: u128,
+    },
+    /// A call made by [`http_request`].
+    HttpRequest(&'a HttpRequestArgs),
+    /// A call made by [`sign_with_ecdsa`].
+    SignWithEcdsa(&'a SignWithEcdsaArgs),
+    /// A call made by [`sign_with_schnorr`].
+    SignWithSchnorr(&'a SignWithSchnorrArgs),
+    /// A call made by [`vetkd_derive_key`].
+    VetKDDeriveKey(&'a VetKDDeriveKeyArgs),
+}
+
+impl<'a> From<&'a CreateCanisterArgs> for ManagementCall<'a> {
+    /// A call made by [`create_canister`], without extra cycles.
+    fn from(arg: &'a CreateCanisterArgs) -> Self {
+        Self::CreateCanister {
+            arg,
+            extra_cycles: 0,
+        }
+    }
+}
+
+impl<'a> From<&'a HttpRequestArgs> for ManagementCall<'a> {
+    fn from(arg: &'a HttpRequestArgs) -> Self {
+        Self::HttpRequest(arg)
+    }
+}
+
+impl<'a> From<&'a SignWithEcdsaArgs> for ManagementCall<'a> {
+    fn from(arg: &'a SignWithEcdsaArgs) -> Self {
+        Self::SignWithEcdsa(arg)
+    }
+}
+
+impl<'a> From<&'a SignWithSchnorrArgs> for ManagementCall<'a> {
+    fn from(arg: &'a SignWithSchnorrArgs) -> Self {
+        Self::SignWithSchnorr(arg)
+    }
+}
+
+impl<'a> From<&'a VetKDDeriveKeyArgs> for ManagementCall<'a> {
+    fn from(arg: &'a VetKDDeriveKeyArgs) -> Self {
+        Self::VetKDDeriveKey(arg)
+    }
+}
+
+impl CostEstimate for ManagementCall<'_> {
+    fn estimate_cost(&self) -> Result<u128, CostEstimateError> {
+        let call = match *self {
+            Self::CreateCanister { arg, extra_cycles } => create_canister_call(arg, extra_cycles),
+            Self::DepositCycles { arg, cycles } => deposit_cycles_call(arg, cycles),
+            Self::HttpRequest(arg) => http_request_call(arg),
+            Self::SignWithEcdsa(arg) => sign_with_ecdsa_call(arg)?,
+            Self::SignWithSchnorr(arg) => sign_with_schnorr_call(arg)?,
+            Self::VetKDDeriveKey(arg) => vetkd_derive_key_call(arg)?,
+        };
+        Ok(call.get_cost())
+    }
+}
@@ -0,0 +1,165 @@
+//! Estimates the cost of management canister calls against `ic0::host::InMemoryHost`.
+
+use candid::Principal;
+use ic_cdk::api::SignCostError;
+use ic_cdk::cycles::{CostEstimate, CostEstimateError};
+use ic_cdk::futures::internals::in_executor_context;
+use ic_cdk::futures::spawn;
+use ic_cdk_management_canister::{
+    CreateCanisterArgs, DepositCyclesArgs, EcdsaCurve, EcdsaKeyId, HttpRequestArgs, ManagementCall,
+    SchnorrAlgorithm, SchnorrKeyId, SignWithEcdsaArgs, SignWithSchnorrArgs, VetKDCurve,
+    VetKDDeriveKeyArgs, VetKDKeyId,
+};
+use ic0::host::{CallResponse, InMemoryHost, set_host};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+const CALL_BASE_FEE: u128 = 1_000;
+const CALL_FEE_PER_BYTE: u128 = 10;
+
+/// The method, the cost and whether it is an unbounded-wait call, of each call made.
+type Calls = Rc<RefCell<Vec<(String, u128, bool)>>>;
+
+/// Installs a host that records the calls to the management canister.
+fn install() -> (InMemoryHost, Calls) {
+    let host = InMemoryHost::new();
+    set_host(host.clone());
+    host.set_cycle_balance(1_000_000_000);
+    host.set_call_cost(CALL_BASE_FEE, CALL_FEE_PER_BYTE);
+    host.set_create_canister_cost(500_000);
+    host.set_http_request_cost(400, 2, 1);
+    host.set_sign_with_ecdsa_cost("key_1", 0, 26_000);
+    host.set_sign_with_schnorr_cost("key_1", 1, 27_000);
+    host.set_vetkd_derive_key_cost("key_1", 0, 28_000);
+    let calls = Rc::new(RefCell::new(vec![]));
+    for method in [
+        "create_canister",
+        "deposit_cycles",
+        "http_request",
+        "sign_with_ecdsa",
+        "sign_with_schnorr",
+        "vetkd_derive_key",
+    ] {
+        let calls = calls.clone();
+        host.on_call(Principal::management_canister(), method, move |request| {
+            let size = (request.method.len() + request.arg.len()) as u128;
+            let cost = CALL_BASE_FEE + CALL_FEE_PER_BYTE * size + request.cycles;
+            calls
+                .borrow_mut()
+                .push((request.method.clone(), cost, request.deadline == 0));
+            CallResponse::reject(5, "not implemented")
+        });
+    }
+    (host, calls)
+}
+
+#[test]
+fn estimates_match_the_calls_made() {
+    let (host, calls) = install();
+    let create = CreateCanisterArgs::default();
+    let deposit = DepositCyclesArgs {
+        canister_id: Principal::from_slice(&[1]),
+    };
+    let http = HttpRequestArgs {
+        url: "https://example.com".to_string(),
+        max_response_bytes: Some(1_000),
+        ..Default::default()
+    };
+    let ecdsa = SignWithEcdsaArgs {
+        message_hash: vec![0; 32],
+        derivation_path: vec![],
+        key_id: EcdsaKeyId {
+            curve: EcdsaCurve::Secp256k1,
+            name: "key_1".to_string(),
+        },
+    };
+    let schnorr = SignWithSchnorrArgs {
+        message: b"message".to_vec(),
+        derivation_path: vec![],
+        key_id: SchnorrKeyId {
+            algorithm: SchnorrAlgorithm::Ed25519,
+            name: "key_1".to_string(),
+        },
+        aux: None,
+    };
+    let vetkd = VetKDDeriveKeyArgs {
+        input: b"input".to_vec(),
+        context: b"context".to_vec(),
+        transport_public_key: vec![0; 48],
+        key_id: VetKDKeyId {
+            curve: VetKDCurve::Bls12_381_G2,
+            name: "key_1".to_string(),
+        },
+    };
+    let estimates = [
+        ManagementCall::CreateCanister {
+            arg: &create,
+            extra_cycles: 1_000,
+        },
+        ManagementCall::DepositCycles {
+            arg: &deposit,
+            cycles: 2_000,
+        },
+        ManagementCall::from(&http),
+        ManagementCall::from(&ecdsa),
+        ManagementCall::from(&schnorr),
+        ManagementCall::from(&vetkd),
+    ]
+    .map(|call| call.estimate_cost().unwrap());
+
+    in_executor_context(|| {
+        spawn(async move {
+            use ic_cdk_management_canister as mgmt;
+            let _ = mgmt::create_canister_with_extra_cycles(&create, 1_000).await;
+            let _ = mgmt::deposit_cycles(&deposit, 2_000).await;
+            let _ = mgmt::http_request(&http).await;
+            let _ = mgmt::sign_with_ecdsa(&ecdsa).await;
+            let _ = mgmt::sign_with_schnorr(&schnorr).await;
+            let _ = mgmt::vetkd_derive_key(&vetkd).await;
+        });
+    });
+    host.run_until_idle();
+
+    let calls = calls.borrow();
+    assert_eq!(
+        calls
+            .iter()
+            .map(|(method, ..)| &method[..])
+            .collect::<Vec<_>>(),
+        [
+            "create_canister",
+            "deposit_cycles",
+            "http_request",
+            "sign_with_ecdsa",
+            "sign_with_schnorr",
+            "vetkd_derive_key"
+        ]
+    );
+    assert_eq!(
+        calls.iter().map(|(_, cost, _)| *cost).collect::<Vec<_>>(),
+        estimates
+    );
+    // All of these are unbounded-wait calls.
+    assert!(calls.iter().all(|(_, _, unbounded)| *unbounded));
+    // The estimates include the fees of the operations, not only the cost of the calls.
+    assert!(estimates[0] > 501_000);
+    assert!(estimates[3] > 26_000);
+}
+
+#[test]
+fn unknown_keys_cannot_be_estimated() {
+    let _ = install();
+    let ecdsa = SignWithEcdsaArgs {
+        key_id: EcdsaKeyId {
+            curve: EcdsaCurve::Secp256k1,
+            name: "unknown".to_string(),
+        },
+        ..Default::default()
+    };
+    assert!(matches!(
+        ManagementCall::from(&ecdsa).estimate_cost(),
+        Err(CostEstimateError::SignCostError(
+            SignCostError::InvalidKeyName
+        ))
+    ));
+}
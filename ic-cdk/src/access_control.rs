@@ -0,0 +1,293 @@
+//! Role-based access control for the guards of exported methods.
+//!
+//! A table of roles, each with the principals it was granted to, is kept on the heap. Methods are gated with the
+//! guards of this module: [`controllers_only`], [`admins_only`], and [`has_role`], which takes the role to check:
+//!
+//! ```rust, no_run
+//! use candid::Principal;
+//! use ic_cdk::access_control::{self, AccessControlState};
+//! use ic_cdk::{init, post_upgrade, pre_upgrade, storage, update};
+//!
+//! #[init]
+//! fn init(minter: Principal) {
+//!     access_control::grant_role("minter", minter);
+//! }
+//!
+//! #[update(guard = "access_control::has_role(\"minter\")")]
+//! fn mint(amount: u64) {
+//!     // ...
+//! # let _ = amount;
+//! }
+//!
+//! #[update(guard = "access_control::controllers_only")]
+//! fn configure() {}
+//!
+//! // Lets admins grant and revoke roles, and read the roles and their audit log.
+//! ic_cdk::export_role_management!();
+//!
+//! #[pre_upgrade]
+//! fn pre_upgrade() {
+//!     storage::stable_save((access_control::save(),)).unwrap();
+//! }
+//!
+//! #[post_upgrade]
+//! fn post_upgrade() {
+//!     let (state,): (AccessControlState,) = storage::stable_restore().unwrap();
+//!     access_control::restore(state);
+//! }
+//! ```
+//!
+//! Every grant and revoke is recorded in an audit log, with the time and the caller, which keeps the most recent
+//! [`AUDIT_LOG_CAPACITY`] changes. The table and the log are reset when the canister is upgraded, unless they are
+//! [saved](save) and [restored](restore) as above.
+
+use crate::api::{is_controller, msg_caller, time};
+use candid::{CandidType, Principal};
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// The role of the principals allowed to manage roles, along with the controllers of the canister.
+pub const ADMIN: &str = "admin";
+
+/// The number of role changes kept in the audit log.
+pub const AUDIT_LOG_CAPACITY: usize = 1_000;
+
+thread_local! {
+    static STATE: RefCell<AccessControlState> = RefCell::default();
+}
+
+/// The roles and their audit log, as saved across upgrades by [`save`] and [`restore`].
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct AccessControlState {
+    /// The principals each role is granted to.
+    pub roles: BTreeMap<String, BTreeSet<Principal>>,
+    /// The most recent role changes, oldest first.
+    pub audit_log: Vec<RoleChange>,
+}
+
+/// A change of the roles, as recorded in the audit log.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct RoleChange {
+    /// The time of the change, in nanoseconds since the epoch.
+    pub timestamp: u64,
+    /// The caller of the method that made the change.
+    pub caller: Principal,
+    /// Whether the role was granted or revoked.
+    pub action: RoleAction,
+    /// The role.
+    pub role: String,
+    /// The principal the role was granted to or revoked from.
+    pub principal: Principal,
+}
+
+/// The kind of a [`RoleChange`].
+#[derive(CandidType, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RoleAction {
+    /// The role was granted.
+    Grant,
+    /// The role was revoked.
+    Revoke,
+}
+
+/// The argument of the methods exported by [`export_role_management!`](crate::export_role_management).
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct RoleAssignment {
+    /// The role.
+    pub role: String,
+    /// The principal to grant the role to or revoke it from.
+    pub principal: Principal,
+}
+
+/// The principals a role is granted to, as returned by [`roles`].
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct RoleMembers {
+    /// The role.
+    pub role: String,
+    /// The principals the role is granted to, sorted.
+    pub members: Vec<Principal>,
+}
+
+/// A guard accepting the controllers of the canister.
+///
+/// Use it as `#[update(guard = "ic_cdk::access_control::controllers_only")]`.
+pub fn controllers_only() -> Result<(), String> {
+    if is_controller(&msg_caller()) {
+        Ok(())
+    } else {
+        Err("only controllers can call this method".to_string())
+    }
+}
+
+/// A guard accepting the controllers of the canister and the principals granted the [`ADMIN`] role.
+///
+/// Use it as `#[update(guard = "ic_cdk::access_control::admins_only")]`.
+pub fn admins_only() -> Result<(), String> {
+    let caller = msg_caller();
+    if is_controller(&caller) || holds_role(&caller, ADMIN) {
+        Ok(())
+    } else {
+        Err("only admins can call this method".to_string())
+    }
+}
+
+/// Returns a guard accepting the principals granted `role`.
+///
+/// Use it as `#[update(guard = "ic_cdk::access_control::has_role(\"minter\")")]`. Controllers are not accepted unless
+/// they are granted the role.
+pub fn has_role(role: &str) -> impl Fn() -> Result<(), String> + '_ {
+    move || require_role(role)
+}
+
+/// Checks that the caller is granted `role`, for use in guards.
+///
+/// # Errors
+///
+/// Fails with a message for the caller if the caller is not granted the role.
+pub fn require_role(role: &str) -> Result<(), String> {
+    if holds_role(&msg_caller(), role) {
+        Ok(())
+    } else {
+        Err(format!("caller does not have the `{role}` role"))
+    }
+}
+
+/// Checks whether `principal` is granted `role`.
+pub fn holds_role(principal: &Principal, role: &str) -> bool {
+    STATE.with_borrow(|state| {
+        state
+            .roles
+            .get(role)
+            .is_some_and(|members| members.contains(principal))
+    })
+}
+
+/// Grants `role` to `principal`, recording the change in the audit log.
+///
+/// Returns `false`, without recording a change, if the principal already has the role.
+pub fn grant_role(role: &str, principal: Principal) -> bool {
+    let granted = STATE.with_borrow_mut(|state| {
+        state
+            .roles
+            .entry(role.to_string())
+            .or_default()
+            .insert(principal)
+    });
+    if granted {
+        record(RoleAction::Grant, role, principal);
+    }
+    granted
+}
+
+/// Revokes `role` from `principal`, recording the change in the audit log.
+///
+/// Returns `false`, without recording a change, if the principal does not have the role.
+pub fn revoke_role(role: &str, principal: Principal) -> bool {
+    let revoked = STATE.with_borrow_mut(|state| {
+        let Some(members) = state.roles.get_mut(role) else {
+            return false;
+        };
+        let revoked = members.remove(&principal);
+        if members.is_empty() {
+            state.roles.remove(role);
+        }
+        revoked
+    });
+    if revoked {
+        record(RoleAction::Revoke, role, principal);
+    }
+    revoked
+}
+
+/// Gets the principals granted `role`, sorted.
+pub fn role_members(role: &str) -> Vec<Principal> {
+    STATE.with_borrow(|state| {
+        state
+            .roles
+            .get(role)
+            .map(|members| members.iter().copied().collect())
+            .unwrap_or_default()
+    })
+}
+
+/// Gets the roles granted to at least one principal, sorted by name.
+pub fn roles() -> Vec<RoleMembers> {
+    STATE.with_borrow(|state| {
+        state
+            .roles
+            .iter()
+            .map(|(role, members)| RoleMembers {
+                role: role.clone(),
+                members: members.iter().copied().collect(),
+            })
+            .collect()
+    })
+}
+
+/// Gets the most recent role changes, oldest first.
+pub fn audit_log() -> Vec<RoleChange> {
+    STATE.with_borrow(|state| state.audit_log.clone())
+}
+
+/// Gets the roles and their audit log, to be saved in stable memory before an upgrade.
+pub fn save() -> AccessControlState {
+    STATE.with_borrow(Clone::clone)
+}
+
+/// Replaces the roles and their audit log with the ones [saved](save) before an upgrade.
+pub fn restore(state: AccessControlState) {
+    STATE.set(state);
+}
+
+fn record(action: RoleAction, role: &str, principal: Principal) {
+    let change = RoleChange {
+        timestamp: time(),
+        caller: msg_caller(),
+        action,
+        role: role.to_string(),
+        principal,
+    };
+    STATE.with_borrow_mut(|state| {
+        if state.audit_log.len() >= AUDIT_LOG_CAPACITY {
+            let excess = state.audit_log.len() + 1 - AUDIT_LOG_CAPACITY;
+            state.audit_log.drain(..excess);
+        }
+        state.audit_log.push(change);
+    });
+}
+
+/// Exports methods managing the roles of [`access_control`](crate::access_control), guarded by
+/// [`admins_only`](crate::access_control::admins_only):
+///
+/// - `grant_role` and `revoke_role`, updates taking a
+///   [`RoleAssignment`](crate::access_control::RoleAssignment) and returning whether the roles changed.
+/// - `list_roles`, a query returning the [`roles`](crate::access_control::roles).
+/// - `get_role_audit_log`, a query returning the [`audit_log`](crate::access_control::audit_log).
+///
+/// ```rust, no_run
+/// ic_cdk::export_role_management!();
+/// ```
+#[macro_export]
+macro_rules! export_role_management {
+    () => {
+        #[$crate::update(guard = $crate::access_control::admins_only)]
+        fn grant_role(assignment: $crate::access_control::RoleAssignment) -> bool {
+            $crate::access_control::grant_role(&assignment.role, assignment.principal)
+        }
+
+        #[$crate::update(guard = $crate::access_control::admins_only)]
+        fn revoke_role(assignment: $crate::access_control::RoleAssignment) -> bool {
+            $crate::access_control::revoke_role(&assignment.role, assignment.principal)
+        }
+
+        #[$crate::query(guard = $crate::access_control::admins_only)]
+        fn list_roles() -> Vec<$crate::access_control::RoleMembers> {
+            $crate::access_control::roles()
+        }
+
+        #[$crate::query(guard = $crate::access_control::admins_only)]
+        fn get_role_audit_log() -> Vec<$crate::access_control::RoleChange> {
+            $crate::access_control::audit_log()
+        }
+    };
+}
@@ -19,7 +19,7 @@
 //! For example, [`msg_arg_data`] wraps both `ic0::msg_arg_data_size` and `ic0::msg_arg_data_copy`.
 
 use candid::Principal;
-use std::{convert::TryFrom, num::NonZeroU64};
+use std::{cell::Cell, convert::TryFrom, future::Future, num::NonZeroU64, pin::pin};
 
 /// Gets the message argument data.
 pub fn msg_arg_data() -> Vec<u8> {
@@ -57,6 +57,9 @@ pub fn msg_caller() -> Principal {
 ///     // Decode per the signer's documented format (e.g. identity attributes).
 /// }
 /// ```
+///
+/// To decode the data into typed attributes and check the signer against an allowlist, see
+/// [`CallerInfo`](crate::caller_info::CallerInfo).
 pub fn msg_caller_info_data() -> Vec<u8> {
     let len = ic0::msg_caller_info_data_size();
     let mut buf = vec![0u8; len];
@@ -135,6 +138,98 @@ pub fn msg_deadline() -> Option<NonZeroU64> {
     }
 }
 
+/// The deadline after which the caller of the current message might stop waiting for a response.
+///
+/// This is a higher-level view of [`msg_deadline`]. Within a callback, [`msg_deadline`] is the one of the call being
+/// responded to, so the task of an `async` update or query method keeps the deadline of the message that started it,
+/// and [`Deadline::current`] returns it across the awaits of the task. The tasks spawned within a task with
+/// [`futures::spawn`](crate::futures::spawn) inherit its deadline. Bounded-wait calls made within a task are made
+/// within its deadline by default, which propagates the deadline of the original caller into nested calls:
+///
+/// ```rust,no_run
+/// # use ic_cdk::api::Deadline;
+/// # use ic_cdk::call::Call;
+/// # async fn bar() {
+/// # let canister_id = ic_cdk::api::canister_self();
+/// let first = Call::bounded_wait(canister_id, "first").await;
+/// // The same deadline as before the first call, although this runs in its callback.
+/// let deadline = Deadline::current();
+/// let second = Call::bounded_wait(canister_id, "second").await;
+/// // Opting out of the deadline.
+/// let third = Call::bounded_wait(canister_id, "third").within_deadline(Deadline::new(None)).await;
+/// # }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Deadline(Option<NonZeroU64>);
+
+thread_local! {
+    /// The deadline of the task being polled, if it was spawned with a deadline.
+    static TASK_DEADLINE: Cell<Option<Deadline>> = const { Cell::new(None) };
+}
+
+impl Deadline {
+    /// Gets the deadline of the current task, or that of the current message outside of a task.
+    pub fn current() -> Self {
+        TASK_DEADLINE.get().unwrap_or_else(|| Self(msg_deadline()))
+    }
+
+    /// Constructs a deadline from nanoseconds since 1970-01-01, with `None` standing for no deadline.
+    pub fn new(deadline: Option<NonZeroU64>) -> Self {
+        Self(deadline)
+    }
+
+    /// Checks whether the caller waits for a response only until the deadline.
+    ///
+    /// This is the case for bounded-wait calls to update methods and their callbacks.
+    pub fn is_bounded_wait(&self) -> bool {
+        self.0.is_some()
+    }
+
+    /// Gets the deadline in nanoseconds since 1970-01-01, or `None` if the caller waits for a response indefinitely.
+    pub fn get(&self) -> Option<NonZeroU64> {
+        self.0
+    }
+
+    /// Gets the time left until the deadline according to [`time`], or `None` if the caller waits for a response
+    /// indefinitely.
+    ///
+    /// Once the deadline has passed, this is zero.
+    pub fn remaining(&self) -> Option<std::time::Duration> {
+        self.0
+            .map(|deadline| std::time::Duration::from_nanos(deadline.get().saturating_sub(time())))
+    }
+
+    /// Checks whether the deadline has passed, in which case the caller may no longer be waiting for a response.
+    pub fn has_passed(&self) -> bool {
+        self.remaining()
+            .is_some_and(|remaining| remaining.is_zero())
+    }
+
+    /// Gets the deadline of the current task, if it has one.
+    ///
+    /// Unlike [`Deadline::current`], this does not call `ic0.msg_deadline`, which is not available in every context.
+    pub(crate) fn task() -> Option<Self> {
+        TASK_DEADLINE.get()
+    }
+
+    /// Makes `deadline` the deadline of the task while `future` is polled.
+    pub(crate) async fn scope<F: Future>(deadline: Option<Self>, future: F) -> F::Output {
+        /// Restores the deadline of the outer task, even if the future panics.
+        struct Restore(Option<Deadline>);
+        impl Drop for Restore {
+            fn drop(&mut self) {
+                TASK_DEADLINE.set(self.0);
+            }
+        }
+        let mut future = pin!(future);
+        std::future::poll_fn(|context| {
+            let _restore = Restore(TASK_DEADLINE.replace(deadline));
+            future.as_mut().poll(context)
+        })
+        .await
+    }
+}
+
 /// Replies to the sender with the data.
 pub fn msg_reply<T: AsRef<[u8]>>(data: T) {
     let buf = data.as_ref();
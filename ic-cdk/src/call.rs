@@ -32,25 +32,63 @@
 //!   - [`CallFailed`]: Errors related to the execution of the call itself, i.e. all the errors except for the Candid decoding failure.
 //!   - [`OnewayError`]: The error type for when sending a [`oneway`](Call::oneway) call.
 //!
+//! # Testing
+//!
+//! Outside of Wasm, calls are delivered by the host installed with [`ic0::host::set_host`]. With
+//! [`ic0::host::InMemoryHost`], calls can be answered by closures or by other canister methods in the same process, with
+//! simulated reject codes, latency, refunds and timeouts:
+//!
+//! ```rust, no_run
+//! # use ic_cdk::call::Call;
+//! # use candid::{Encode, Principal};
+//! use ic0::host::{CallResponse, InMemoryHost, set_host};
+//! use std::time::Duration;
+//!
+//! let callee = Principal::from_slice(&[1]);
+//! let host = InMemoryHost::new();
+//! set_host(host.clone());
+//! host.on_call(callee, "greet", |_| {
+//!     CallResponse::reply(Encode!(&"hello").unwrap()).with_latency(Duration::from_secs(1))
+//! });
+//!
+//! ic_cdk::futures::internals::in_executor_context(|| {
+//!     ic_cdk::futures::spawn(async move {
+//!         let greeting: String = Call::bounded_wait(callee, "greet").await.unwrap().candid().unwrap();
+//!         ic_cdk::api::msg_reply(Encode!(&greeting).unwrap());
+//!     });
+//! });
+//! host.run_until_idle();
+//! assert!(host.reply().is_some());
+//! ```
+//!
 //! # Internal Details
 //!
 //! The module also includes internal types and functions to manage the state and execution of inter-canister calls,
 //! such as [`CallFuture`] and its associated state management.
 
-use crate::api::{cost_call, msg_arg_data, msg_reject_code, msg_reject_msg};
+use crate::api::{
+    Deadline, cost_call, msg_arg_data, msg_cycles_refunded, msg_reject_code, msg_reject_msg,
+};
 use crate::{futures::is_recovering_from_trap, trap};
 use candid::utils::{ArgumentDecoder, ArgumentEncoder, encode_args_ref};
 use candid::{CandidType, Deserialize, Principal, decode_args, decode_one, encode_one};
 use ic_cdk_executor::{MethodHandle, TaskHandle};
 use std::borrow::Cow;
-use std::future::IntoFuture;
+use std::future::{Future, IntoFuture};
+use std::marker::PhantomData;
 use std::mem;
 use std::pin::Pin;
-use std::sync::{Arc, RwLock};
+use std::rc::Rc;
+use std::sync::{Arc, RwLock, Weak};
 use std::task::{Context, Poll, Waker};
+use std::time::Duration;
 use thiserror::Error;
 
-pub use ic_error_types::RejectCode;
+pub use ic_error_types::{ErrorCode, RejectCode};
+
+#[cfg(feature = "paging")]
+#[cfg_attr(docsrs, doc(cfg(feature = "paging")))]
+pub mod paging;
 
 /// Inter-canister Call.
 ///
@@ -75,6 +113,8 @@ pub use ic_error_types::RejectCode;
 ///   - [`with_cycles`][Self::with_cycles]: set the cycles attached in this call.
 /// - Response waiting timeout:
 ///   - [`change_timeout`][Self::change_timeout]: change the timeout for **`bounded_wait`** call.
+///   - [`within_deadline`][Self::within_deadline]: cap the timeout for **`bounded_wait`** call by another
+///     [`Deadline`] than that of the current task.
 ///
 /// Please note that all the configuration methods are chainable and can be called multiple times.
 /// For each **aspect** of the call, the **last** configuration takes effect.
@@ -107,6 +147,9 @@ pub use ic_error_types::RejectCode;
 ///   - Direct approach: Use `.await` on the call (e.g., `call.await`).
 ///   - Collective approach: Use [`IntoFuture::into_future`] to obtain futures explicitly,
 ///     then combine them with `join!`, `select!`, or other combinators.
+/// - **With retries**: Use [`with_retry`][Self::with_retry] to retry failed attempts according to a [`RetryPolicy`].
+/// - **In batches**: Use [`join_all`] to execute many calls with a concurrency limit, or [`select`] to wait for the first
+///   of several calls to complete.
 /// - **One-way**: Send a call with [`oneway`][Self::oneway] when you don't need a response.
 ///
 /// ## Example
@@ -150,6 +193,27 @@ pub use ic_error_types::RejectCode;
 /// # }
 /// ```
 ///
+/// To get the decoded response directly as a [`CallResult`], use one of the adapters:
+/// - [`candid_result`][Self::candid_result]: decode the response as a single Candid type.
+/// - [`candid_tuple_result`][Self::candid_tuple_result]: decode the response as a tuple of Candid types.
+/// - [`typed`][Self::typed]: check the arguments against the method signature at compile time, and decode the response
+///   as its result tuple.
+///
+/// ## Example
+///
+/// ```rust, no_run
+/// # use ic_cdk::call::{Call, CallResult};
+/// # async fn bar() -> CallResult<()> {
+/// # let canister_id = ic_cdk::api::canister_self();
+/// # let method = "foo";
+/// let result: u32 = Call::bounded_wait(canister_id, method).candid_result().await?;
+/// let (sum,) = Call::bounded_wait(canister_id, method)
+///     .typed::<(u32, u32), (u64,)>(&(1, 2))
+///     .await?;
+/// # Ok(())
+/// # }
+/// ```
+///
 /// <div class="warning">
 ///
 /// Using an inter-canister call creates the possibility that your async function will be canceled partway through.
@@ -162,6 +226,8 @@ pub struct Call<'m, 'a> {
     method: &'m str,
     cycles: u128,
     timeout_seconds: Option<u32>,
+    /// The deadline capping the timeout of a bounded-wait call when it is made.
+    deadline: Option<Deadline>,
     encoded_args: Cow<'a, [u8]>,
 }
 
@@ -175,6 +241,9 @@ impl<'m> Call<'m, '_> {
     /// It aligns with the `MAX_CALL_TIMEOUT` constant in the current IC implementation.
     /// The timeout can be changed using the [`change_timeout`][Self::change_timeout] method.
     ///
+    /// Within a task with a [`Deadline`], such as that of an `async` update method, the call does not outlive the
+    /// deadline: see [`within_deadline`][Self::within_deadline].
+    ///
     /// To unboundedly wait for response, use the [`Call::unbounded_wait`] constructor instead.
     #[must_use]
     pub fn bounded_wait(canister_id: Principal, method: &'m str) -> Self {
@@ -184,6 +253,7 @@ impl<'m> Call<'m, '_> {
             cycles: 0,
             // Default to 300-second timeout.
             timeout_seconds: Some(300),
+            deadline: Deadline::task(),
             // Bytes for empty arguments.
             // `candid::Encode!(&()).unwrap()`
             encoded_args: Cow::Owned(vec![0x44, 0x49, 0x44, 0x4c, 0x00, 0x00]),
@@ -200,6 +270,7 @@ impl<'m> Call<'m, '_> {
             method,
             cycles: 0,
             timeout_seconds: None,
+            deadline: None,
             // Bytes for empty arguments.
             // `candid::Encode!(&()).unwrap()`
             encoded_args: Cow::Owned(vec![0x44, 0x49, 0x44, 0x4c, 0x00, 0x00]),
@@ -311,6 +382,38 @@ impl<'a> Call<'_, 'a> {
         self
     }
 
+    /// Caps the timeout so that the call does not outlive the given [`Deadline`], instead of that of the current task.
+    ///
+    /// When the call is made, the timeout is reduced to the whole seconds [remaining](Deadline::remaining) until the
+    /// deadline, if that is shorter. By default, a call constructed by [`Call::bounded_wait`] within a task is made
+    /// within the [deadline of the task](Deadline::current), which propagates the deadline of the caller into nested
+    /// calls. Pass `Deadline::new(None)` to only wait for the timeout.
+    ///
+    /// Calls constructed by [`Call::unbounded_wait`], and calls within a deadline that is not
+    /// [bounded](Deadline::is_bounded_wait), are left unchanged.
+    ///
+    /// # Note
+    ///
+    /// If less than a second remains, the timeout is 0, and the call will most likely time out.
+    /// See [`change_timeout`](Self::change_timeout).
+    #[must_use]
+    pub fn within_deadline(mut self, deadline: Deadline) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// Gets the timeout of a bounded-wait call, capped by its deadline.
+    fn effective_timeout(&self) -> Option<u32> {
+        let timeout_seconds = self.timeout_seconds?;
+        match self.deadline.and_then(|deadline| deadline.remaining()) {
+            Some(remaining) => {
+                let remaining_seconds = u32::try_from(remaining.as_secs()).unwrap_or(u32::MAX);
+                Some(timeout_seconds.min(remaining_seconds))
+            }
+            None => Some(timeout_seconds),
+        }
+    }
+
     /// Returns the amount of cycles a canister needs to be above the freezing threshold in order to
     /// successfully perform this call. Takes into account the attached cycles ([`with_cycles`](Self::with_cycles))
     /// as well as
@@ -502,6 +605,13 @@ pub struct InsufficientLiquidCycleBalance {
     pub required: u128,
 }
 
+impl InsufficientLiquidCycleBalance {
+    /// Gets the cycles missing from the liquid cycle balance.
+    pub fn shortfall(&self) -> u128 {
+        self.required.saturating_sub(self.available)
+    }
+}
+
 /// Represents an error that occurs when the `ic0.call_perform` operation fails.
 ///
 /// This error type indicates that the underlying `ic0.call_perform` operation
@@ -513,17 +623,19 @@ pub struct CallPerformFailed;
 /// Represents an error that occurs when an inter-canister call is rejected.
 ///
 /// The [`reject_code`][`Self::reject_code`] and [`reject_message`][`Self::reject_message`]
-/// are exposed to provide details of the rejection.
+/// are exposed to provide details of the rejection, along with the finer-grained [`error_code`][`Self::error_code`]
+/// when it is known.
 ///
 /// This is wrapped by the [`CallFailed::CallRejected`] variant.
 #[derive(Error, Debug, Clone)]
 #[error("call rejected: {raw_reject_code} - {reject_message}")]
 pub struct CallRejected {
     /// All fields are private so we will be able to change the implementation without breaking the API.
-    /// Once we have `ic0.msg_error_code` system API, we will only store the `error_code` in this struct.
-    /// It will still be possible to get the [`RejectCode`] using the public getter,
+    /// Once we have `ic0.msg_error_code` system API, `raw_error_code` will be set for every rejection, and we will only
+    /// store it in this struct. It will still be possible to get the [`RejectCode`] using the public getter,
     /// because every `error_code` can map to a [`RejectCode`].
     raw_reject_code: u32,
+    raw_error_code: Option<u32>,
     reject_message: String,
 }
 
@@ -544,6 +656,24 @@ impl CallRejected {
     pub fn with_rejection(raw_reject_code: u32, reject_message: String) -> Self {
         Self {
             raw_reject_code,
+            raw_error_code: None,
+            reject_message,
+        }
+    }
+
+    /// Constructs a [`CallRejected`] instance with the reject code, the finer-grained [`ErrorCode`] and the message.
+    ///
+    /// # Note
+    ///
+    /// Like [`with_rejection`](Self::with_rejection), this constructor is primarily intended for testing scenarios.
+    pub fn with_error_code(
+        raw_reject_code: u32,
+        raw_error_code: u32,
+        reject_message: String,
+    ) -> Self {
+        Self {
+            raw_reject_code,
+            raw_error_code: Some(raw_error_code),
             reject_message,
         }
     }
@@ -570,12 +700,109 @@ impl CallRejected {
         self.raw_reject_code
     }
 
+    /// Gets the finer-grained [`ErrorCode`], which tells apart e.g. a callee that is out of cycles from one that is
+    /// stopped.
+    ///
+    /// Returns `None` if the error code is not known, or not recognized by [`ic_error_types::ErrorCode`].
+    ///
+    /// # Note
+    ///
+    /// The system API does not yet expose the error code of a rejection, so it is currently only known for instances
+    /// constructed with [`with_error_code`](Self::with_error_code).
+    pub fn error_code(&self) -> Option<ErrorCode> {
+        self.raw_error_code.and_then(error_code_from_raw)
+    }
+
+    /// Gets the raw numeric [`ErrorCode`] value, if known.
+    pub fn raw_error_code(&self) -> Option<u32> {
+        self.raw_error_code
+    }
+
     /// Retrieves the reject message associated with the call.
     ///
     /// This message is obtained from [`api::msg_reject_msg`](`msg_reject_msg`).
     pub fn reject_message(&self) -> &str {
         &self.reject_message
     }
+
+    /// The rejection yielded by a [`CallFuture`] that was cancelled through a [`CancelHandle`].
+    fn cancelled() -> Self {
+        Self {
+            raw_reject_code: RejectCode::SysUnknown as u32,
+            raw_error_code: None,
+            reject_message: "call cancelled by the caller".into(),
+        }
+    }
+}
+
+/// Maps a raw error code to the [`ErrorCode`] it stands for.
+///
+/// `ic-error-types` does not provide this conversion, so the codes are listed explicitly.
+fn error_code_from_raw(code: u32) -> Option<ErrorCode> {
+    const CODES: &[ErrorCode] = &[
+        ErrorCode::SubnetOversubscribed,
+        ErrorCode::MaxNumberOfCanistersReached,
+        ErrorCode::CanisterQueueFull,
+        ErrorCode::IngressMessageTimeout,
+        ErrorCode::CanisterQueueNotEmpty,
+        ErrorCode::IngressHistoryFull,
+        ErrorCode::CanisterIdAlreadyExists,
+        ErrorCode::StopCanisterRequestTimeout,
+        ErrorCode::CanisterOutOfCycles,
+        ErrorCode::CertifiedStateUnavailable,
+        ErrorCode::CanisterInstallCodeRateLimited,
+        ErrorCode::CanisterHeapDeltaRateLimited,
+        ErrorCode::CanisterNotFound,
+        ErrorCode::CanisterSnapshotNotFound,
+        ErrorCode::InsufficientMemoryAllocation,
+        ErrorCode::InsufficientCyclesForCreateCanister,
+        ErrorCode::SubnetNotFound,
+        ErrorCode::CanisterNotHostedBySubnet,
+        ErrorCode::CanisterRejectedMessage,
+        ErrorCode::UnknownManagementMessage,
+        ErrorCode::InvalidManagementPayload,
+        ErrorCode::CanisterSnapshotImmutable,
+        ErrorCode::CanisterTrapped,
+        ErrorCode::CanisterCalledTrap,
+        ErrorCode::CanisterContractViolation,
+        ErrorCode::CanisterInvalidWasm,
+        ErrorCode::CanisterDidNotReply,
+        ErrorCode::CanisterOutOfMemory,
+        ErrorCode::CanisterStopped,
+        ErrorCode::CanisterStopping,
+        ErrorCode::CanisterNotStopped,
+        ErrorCode::CanisterStoppingCancelled,
+        ErrorCode::CanisterInvalidController,
+        ErrorCode::CanisterFunctionNotFound,
+        ErrorCode::CanisterNonEmpty,
+        ErrorCode::QueryCallGraphLoopDetected,
+        ErrorCode::InsufficientCyclesInCall,
+        ErrorCode::CanisterWasmEngineError,
+        ErrorCode::CanisterInstructionLimitExceeded,
+        ErrorCode::CanisterMemoryAccessLimitExceeded,
+        ErrorCode::QueryCallGraphTooDeep,
+        ErrorCode::QueryCallGraphTotalInstructionLimitExceeded,
+        ErrorCode::CompositeQueryCalledInReplicatedMode,
+        ErrorCode::QueryTimeLimitExceeded,
+        ErrorCode::QueryCallGraphInternal,
+        ErrorCode::InsufficientCyclesInComputeAllocation,
+        ErrorCode::InsufficientCyclesInMemoryAllocation,
+        ErrorCode::InsufficientCyclesInMemoryGrow,
+        ErrorCode::ReservedCyclesLimitExceededInMemoryAllocation,
+        ErrorCode::ReservedCyclesLimitExceededInMemoryGrow,
+        ErrorCode::InsufficientCyclesInMessageMemoryGrow,
+        ErrorCode::CanisterMethodNotFound,
+        ErrorCode::CanisterWasmModuleNotFound,
+        ErrorCode::CanisterAlreadyInstalled,
+        ErrorCode::CanisterWasmMemoryLimitExceeded,
+        ErrorCode::ReservedCyclesLimitIsTooLow,
+        ErrorCode::DeadlineExpired,
+        ErrorCode::ResponseDropped,
+    ];
+    CODES
+        .iter()
+        .copied()
+        .find(|error_code| *error_code as u32 == code)
 }
 
 /// Represents an error that occurs when the response from an inter-canister call
@@ -633,24 +860,44 @@ impl CallErrorExt for CallPerformFailed {
 
 impl CallErrorExt for CallRejected {
     fn is_clean_reject(&self) -> bool {
-        // Here we apply a conservative whitelist of reject codes that are considered clean.
-        // Once finer `error_code` is available, we can allow more cases to be clean.
-        let clean_reject_codes: Vec<u32> = vec![
-            RejectCode::SysFatal as u32,
-            RejectCode::SysTransient as u32,
-            RejectCode::DestinationInvalid as u32,
-        ];
-        clean_reject_codes.contains(&self.raw_reject_code)
+        match self.error_code() {
+            // The callee could not execute the call, so its state is unchanged.
+            Some(
+                ErrorCode::CanisterOutOfCycles
+                | ErrorCode::CanisterStopped
+                | ErrorCode::CanisterStopping,
+            ) => true,
+            // Other error codes are classified by the reject code they map to.
+            _ => {
+                // Here we apply a conservative whitelist of reject codes that are considered clean.
+                let clean_reject_codes: Vec<u32> = vec![
+                    RejectCode::SysFatal as u32,
+                    RejectCode::SysTransient as u32,
+                    RejectCode::DestinationInvalid as u32,
+                ];
+                clean_reject_codes.contains(&self.raw_reject_code)
+            }
+        }
     }
 
     fn is_immediately_retryable(&self) -> bool {
-        // Here we apply a conservative whitelist of reject codes that are considered immediately retryable.
-        // Once finer `error_code` is available, we can allow more cases to be immediately retryable.
-        let immediately_retryable_codes: Vec<u32> = vec![
-            RejectCode::SysTransient as u32,
-            RejectCode::SysUnknown as u32,
-        ];
-        immediately_retryable_codes.contains(&self.raw_reject_code)
+        match self.error_code() {
+            // The callee has to be topped up or restarted first, which an immediate retry does not wait for.
+            Some(
+                ErrorCode::CanisterOutOfCycles
+                | ErrorCode::CanisterStopped
+                | ErrorCode::CanisterStopping,
+            ) => false,
+            // Other error codes are classified by the reject code they map to.
+            _ => {
+                // Here we apply a conservative whitelist of reject codes that are considered immediately retryable.
+                let immediately_retryable_codes: Vec<u32> = vec![
+                    RejectCode::SysTransient as u32,
+                    RejectCode::SysUnknown as u32,
+                ];
+                immediately_retryable_codes.contains(&self.raw_reject_code)
+            }
+        }
     }
 }
 
@@ -727,6 +974,168 @@ impl CallErrorExt for OnewayError {
 /// Result of a inter-canister call.
 pub type CallResult<R> = Result<R, Error>;
 
+/// Policy for retrying a failed call with [`Call::with_retry`].
+///
+/// A failed attempt is retried only if its error [`is_immediately_retryable`](CallErrorExt::is_immediately_retryable)
+/// and [`is_clean_reject`](CallErrorExt::is_clean_reject), unless the method is declared
+/// [idempotent](Self::with_idempotent), and only while none of the limits of the policy is reached.
+///
+/// Failed attempts are retried right away, unless the policy [backs off](Self::with_backoff) between attempts.
+///
+/// ## Example
+///
+/// ```rust, no_run
+/// # use ic_cdk::call::{Call, RetryPolicy};
+/// # async fn bar() {
+/// # let canister_id = ic_cdk::api::canister_self();
+/// let policy = RetryPolicy::new(5)
+///     .with_deadline(ic_cdk::api::time() + 60_000_000_000)
+///     .with_cycle_budget(10_000_000_000);
+/// let response = Call::bounded_wait(canister_id, "get_balance")
+///     .with_arg(42)
+///     .with_retry(policy)
+///     .await
+///     .unwrap();
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    deadline: Option<u64>,
+    idempotent: bool,
+    cycle_budget: Option<u128>,
+    backoff: Option<Backoff>,
+}
+
+/// The delays between the attempts of a [`RetryPolicy`], and the function waiting for them.
+#[derive(Clone)]
+struct Backoff {
+    initial_delay: Duration,
+    max_delay: Duration,
+    sleep: Sleep,
+}
+
+/// A function returning a future that waits for the given delay.
+type Sleep = Rc<dyn Fn(Duration) -> Pin<Box<dyn Future<Output = ()>>>>;
+
+impl std::fmt::Debug for RetryPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RetryPolicy")
+            .field("max_attempts", &self.max_attempts)
+            .field("deadline", &self.deadline)
+            .field("idempotent", &self.idempotent)
+            .field("cycle_budget", &self.cycle_budget)
+            .field(
+                "backoff",
+                &self
+                    .backoff
+                    .as_ref()
+                    .map(|backoff| (backoff.initial_delay, backoff.max_delay)),
+            )
+            .finish()
+    }
+}
+
+impl RetryPolicy {
+    /// Constructs a [`RetryPolicy`] that makes at most `max_attempts` attempts, including the first one.
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if `max_attempts` is zero, as the first attempt is always made.
+    #[must_use]
+    pub fn new(max_attempts: u32) -> Self {
+        assert!(
+            max_attempts > 0,
+            "a retry policy makes at least one attempt"
+        );
+        Self {
+            max_attempts,
+            deadline: None,
+            idempotent: false,
+            cycle_budget: None,
+            backoff: None,
+        }
+    }
+
+    /// Makes no new attempt once [`api::time`](crate::api::time) reaches `deadline`, in nanoseconds since the epoch.
+    ///
+    /// With a [backoff](Self::with_backoff), no new attempt is made if the deadline is reached by the end of the delay.
+    #[must_use]
+    pub fn with_deadline(mut self, deadline: u64) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// Declares whether the called method is idempotent.
+    ///
+    /// If it is, errors that are not [clean rejects](CallErrorExt::is_clean_reject) are retried too, such as a
+    /// [`SysUnknown`](RejectCode::SysUnknown) reject of a bounded-wait call, after which the callee may or may not have
+    /// executed the call. Declaring a non-idempotent method idempotent can make it execute more than once.
+    #[must_use]
+    pub fn with_idempotent(mut self, idempotent: bool) -> Self {
+        self.idempotent = idempotent;
+        self
+    }
+
+    /// Makes no new attempt if the summed [`Call::get_cost`] of all attempts would exceed `cycles`.
+    ///
+    /// The first attempt is always made.
+    #[must_use]
+    pub fn with_cycle_budget(mut self, cycles: u128) -> Self {
+        self.cycle_budget = Some(cycles);
+        self
+    }
+
+    /// Waits before each new attempt, `initial_delay` before the second one, and twice as long as the previous time
+    /// before each of the next ones, up to `max_delay`.
+    ///
+    /// The CDK cannot wait by itself, so the future returned by `sleep` waits for the given delay, e.g. until a timer
+    /// of `ic-cdk-timers` fires. A task spawned with [`spawn`](crate::futures::spawn), such as the body of an async
+    /// canister method, only resumes within its own method, so it has to retry in a task spawned with
+    /// [`spawn_migratory`](crate::futures::spawn_migratory) to resume from a timer.
+    #[must_use]
+    pub fn with_backoff<F>(
+        mut self,
+        initial_delay: Duration,
+        max_delay: Duration,
+        sleep: impl Fn(Duration) -> F + 'static,
+    ) -> Self
+    where
+        F: Future<Output = ()> + 'static,
+    {
+        self.backoff = Some(Backoff {
+            initial_delay,
+            max_delay,
+            sleep: Rc::new(move |delay| Box::pin(sleep(delay))),
+        });
+        self
+    }
+
+    /// Gets the delay before the attempt following `attempts` attempts.
+    fn delay(&self, attempts: u32) -> Duration {
+        self.backoff.as_ref().map_or(Duration::ZERO, |backoff| {
+            let doublings = attempts.saturating_sub(1).min(u32::BITS - 1);
+            backoff
+                .initial_delay
+                .saturating_mul(1 << doublings)
+                .min(backoff.max_delay)
+        })
+    }
+
+    /// Checks if a call that failed with `error` may be attempted again after `delay`, after `attempts` attempts and
+    /// with a summed cost of `cost` cycles including the next attempt.
+    fn allows_retry(&self, error: &CallFailed, attempts: u32, cost: u128, delay: Duration) -> bool {
+        let delay_nanos = u64::try_from(delay.as_nanos()).unwrap_or(u64::MAX);
+        error.is_immediately_retryable()
+            && (self.idempotent || error.is_clean_reject())
+            && attempts < self.max_attempts
+            && self
+                .deadline
+                .is_none_or(|deadline| crate::api::time().saturating_add(delay_nanos) < deadline)
+            && self.cycle_budget.is_none_or(|budget| cost <= budget)
+    }
+}
+
 impl<'m, 'a> IntoFuture for Call<'m, 'a> {
     type Output = Result<Response, CallFailed>;
     type IntoFuture = CallFuture<'m, 'a>;
@@ -738,6 +1147,156 @@ impl<'m, 'a> IntoFuture for Call<'m, 'a> {
     }
 }
 
+// Decoding
+impl<'m, 'a> Call<'m, 'a> {
+    /// Executes the call and decodes the response as a single Candid type.
+    ///
+    /// This is a shorthand for [`into_future().candid_result()`](CallFuture::candid_result).
+    pub fn candid_result<R>(self) -> DecodedCallFuture<'m, 'a, R>
+    where
+        R: CandidType + for<'de> Deserialize<'de>,
+    {
+        self.into_future().candid_result()
+    }
+
+    /// Executes the call and decodes the response as a tuple of Candid types.
+    ///
+    /// This is a shorthand for [`into_future().candid_tuple_result()`](CallFuture::candid_tuple_result).
+    pub fn candid_tuple_result<R>(self) -> DecodedCallFuture<'m, 'a, R>
+    where
+        R: for<'de> ArgumentDecoder<'de>,
+    {
+        self.into_future().candid_tuple_result()
+    }
+
+    /// Sets the arguments of the call to `args` and types the call with the signature of the called method.
+    ///
+    /// `Args` and `Ret` are the argument and result tuples of the method, e.g. `(Principal, u64)` and `(Nat,)`, so the
+    /// compiler checks the arguments at each call site, and awaiting the [`TypedCall`] yields the decoded results.
+    #[must_use]
+    pub fn typed<Args, Ret>(self, args: &Args) -> TypedCall<'m, 'a, Args, Ret>
+    where
+        Args: ArgumentEncoder,
+        Ret: for<'de> ArgumentDecoder<'de>,
+    {
+        TypedCall {
+            call: self.with_args(args),
+            signature: PhantomData,
+        }
+    }
+}
+
+/// A [`Call`] typed with the signature of the called method.
+///
+/// It is constructed with [`Call::typed`], and awaiting it yields the result tuple `Ret` of the method, decoded from
+/// Candid.
+///
+/// ## Example
+///
+/// ```rust, no_run
+/// # use ic_cdk::call::{Call, CallResult, TypedCall};
+/// # use candid::Principal;
+/// /// `transfer : (to : principal, amount : nat64) -> (nat64)`
+/// fn transfer<'m>(ledger: Principal, to: Principal, amount: u64) -> TypedCall<'m, 'static, (Principal, u64), (u64,)> {
+///     Call::unbounded_wait(ledger, "transfer").typed(&(to, amount))
+/// }
+///
+/// # async fn bar(ledger: Principal, to: Principal) -> CallResult<()> {
+/// let (block_index,) = transfer(ledger, to, 100).with_cycles(1_000).await?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct TypedCall<'m, 'a, Args, Ret> {
+    call: Call<'m, 'a>,
+    signature: PhantomData<fn(Args) -> Ret>,
+}
+
+impl<Args, Ret> std::fmt::Debug for TypedCall<'_, '_, Args, Ret> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TypedCall")
+            .field("call", &self.call)
+            .field("args", &std::any::type_name::<Args>())
+            .field("ret", &std::any::type_name::<Ret>())
+            .finish()
+    }
+}
+
+impl<Args, Ret> Clone for TypedCall<'_, '_, Args, Ret> {
+    fn clone(&self) -> Self {
+        Self {
+            call: self.call.clone(),
+            signature: PhantomData,
+        }
+    }
+}
+
+impl<'m, 'a, Args, Ret> TypedCall<'m, 'a, Args, Ret> {
+    /// Sets the cycles payment for the call.
+    ///
+    /// See [`Call::with_cycles`].
+    #[must_use]
+    pub fn with_cycles(self, cycles: u128) -> Self {
+        Self {
+            call: self.call.with_cycles(cycles),
+            ..self
+        }
+    }
+
+    /// Changes the timeout for bounded response waiting.
+    ///
+    /// See [`Call::change_timeout`].
+    ///
+    /// # Panics
+    ///
+    /// This method will panic if invoked on an unbounded response waiting call constructed by [`Call::unbounded_wait`].
+    #[must_use]
+    pub fn change_timeout(self, timeout_seconds: u32) -> Self {
+        Self {
+            call: self.call.change_timeout(timeout_seconds),
+            ..self
+        }
+    }
+
+    /// Caps the timeout so that the call does not outlive the given [`Deadline`].
+    ///
+    /// See [`Call::within_deadline`].
+    #[must_use]
+    pub fn within_deadline(self, deadline: Deadline) -> Self {
+        Self {
+            call: self.call.within_deadline(deadline),
+            ..self
+        }
+    }
+
+    /// Returns the amount of cycles needed to perform the call. See [`Call::get_cost`].
+    #[must_use]
+    pub fn get_cost(&self) -> u128 {
+        self.call.get_cost()
+    }
+
+    /// Sends the call and ignores the reply.
+    pub fn oneway(&self) -> Result<(), OnewayError> {
+        self.call.oneway()
+    }
+
+    /// Gets the untyped [`Call`].
+    pub fn into_call(self) -> Call<'m, 'a> {
+        self.call
+    }
+}
+
+impl<'m, 'a, Args, Ret> IntoFuture for TypedCall<'m, 'a, Args, Ret>
+where
+    Ret: for<'de> ArgumentDecoder<'de>,
+{
+    type Output = CallResult<Ret>;
+    type IntoFuture = DecodedCallFuture<'m, 'a, Ret>;
+
+    fn into_future(self) -> Self::IntoFuture {
+        self.call.candid_tuple_result()
+    }
+}
+
 // Execution
 impl Call<'_, '_> {
     /// Sends the call and ignores the reply.
@@ -749,6 +1308,38 @@ impl Call<'_, '_> {
         }
     }
 
+    /// Executes the call, and retries failed attempts according to `policy`.
+    ///
+    /// Every attempt sends the same argument bytes, which are encoded only once, when the call is configured.
+    ///
+    /// Returns the response of the first successful attempt, or the error of the last attempt.
+    pub async fn with_retry(&self, policy: RetryPolicy) -> Result<Response, CallFailed> {
+        let cost = self.get_cost();
+        let mut attempts = 0;
+        let mut spent: u128 = 0;
+        loop {
+            let attempt = Call {
+                canister_id: self.canister_id,
+                method: self.method,
+                cycles: self.cycles,
+                timeout_seconds: self.timeout_seconds,
+                deadline: self.deadline,
+                encoded_args: Cow::Borrowed(&self.encoded_args),
+            };
+            attempts += 1;
+            spent = spent.saturating_add(cost);
+            let delay = policy.delay(attempts);
+            match attempt.await {
+                Err(e) if policy.allows_retry(&e, attempts, spent.saturating_add(cost), delay) => {
+                    if let Some(backoff) = &policy.backoff {
+                        (backoff.sleep)(delay).await;
+                    }
+                }
+                result => return result,
+            }
+        }
+    }
+
     /// Checks if the liquid cycle balance is sufficient to perform the call.
     fn check_liquid_cycle_balance_sufficient(&self) -> Result<(), InsufficientLiquidCycleBalance> {
         let required = self.get_cost();
@@ -807,6 +1398,7 @@ impl Call<'_, '_> {
                 //   and the cleanup callback runs afterwards. Inside the runtime, there is no difference between
                 //   'state is rolled back to before the callback was called' and 'the callback was never called'.
                 //   So from the code's perspective, exactly one function is called.
+                #[cfg(target_family = "wasm")]
                 unsafe {
                     ic0::call_new(
                         callee,
@@ -818,6 +1410,12 @@ impl Call<'_, '_> {
                     );
                     ic0::call_on_cleanup(cleanup, state_ptr as usize);
                 }
+                // Outside of Wasm, the callbacks can trap without aborting, see `unwind`.
+                #[cfg(not(target_family = "wasm"))]
+                // SAFETY: as above.
+                unsafe {
+                    unwind::call_new(callee, method, state_ptr as usize);
+                }
             }
 
             None => {
@@ -834,10 +1432,17 @@ impl Call<'_, '_> {
         if self.cycles > 0 {
             ic0::call_cycles_add128(self.cycles);
         }
-        if let Some(timeout_seconds) = self.timeout_seconds {
+        if let Some(timeout_seconds) = self.effective_timeout() {
             ic0::call_with_best_effort_response(timeout_seconds);
         }
         let res = ic0::call_perform();
+        if res == 0 && state_ptr_opt.is_some() {
+            crate::lifecycle::call_started();
+        }
+        #[cfg(feature = "profiling")]
+        if res == 0 {
+            crate::profiling::spend_call_cycles(|| self.get_cost());
+        }
         if res != 0
             && let Some(state_ptr) = state_ptr_opt
         {
@@ -854,6 +1459,148 @@ impl Call<'_, '_> {
     }
 }
 
+// Combinators
+
+/// Executes `calls` concurrently, with at most `max_concurrency` of them in flight at any time.
+///
+/// Before any call is made, the liquid cycle balance is checked against the summed [`Call::get_cost`] of all the calls,
+/// so that the batch does not run out of cycles partway through. The next call is made as soon as one in flight
+/// completes, which keeps the number of outstanding calls of the canister bounded. A `max_concurrency` of zero is
+/// treated as one.
+///
+/// Returns the result of each call, in the order of `calls`.
+///
+/// # Errors
+///
+/// Returns [`InsufficientLiquidCycleBalance`] without making any call if the liquid cycle balance does not cover all
+/// of them.
+///
+/// ## Example
+///
+/// ```rust, no_run
+/// # use ic_cdk::call::{Call, join_all};
+/// # use candid::Principal;
+/// # async fn bar(canisters: Vec<Principal>) {
+/// let calls = canisters.iter().map(|id| Call::bounded_wait(*id, "get_balance"));
+/// for result in join_all(calls, 10).await.unwrap() {
+///     let balance: u64 = result.unwrap().candid().unwrap();
+/// }
+/// # }
+/// ```
+pub async fn join_all<'m, 'a>(
+    calls: impl IntoIterator<Item = Call<'m, 'a>>,
+    max_concurrency: usize,
+) -> Result<Vec<Result<Response, CallFailed>>, InsufficientLiquidCycleBalance> {
+    let calls = calls.into_iter().collect::<Vec<_>>();
+    check_liquid_cycle_balance_sufficient_for_all(&calls)?;
+    let max_concurrency = max_concurrency.max(1);
+    let mut results = calls.iter().map(|_| None).collect::<Vec<_>>();
+    let mut waiting = calls.into_iter().enumerate();
+    let mut in_flight = Vec::<(usize, CallFuture<'m, 'a>)>::new();
+    std::future::poll_fn(|context| {
+        loop {
+            while in_flight.len() < max_concurrency
+                && let Some((index, call)) = waiting.next()
+            {
+                in_flight.push((index, call.into_future()));
+            }
+            if in_flight.is_empty() {
+                return Poll::Ready(());
+            }
+            let before = in_flight.len();
+            in_flight.retain_mut(|(index, future)| match Pin::new(future).poll(context) {
+                Poll::Ready(result) => {
+                    results[*index] = Some(result);
+                    false
+                }
+                Poll::Pending => true,
+            });
+            // Calls that completed make room for more, possibly completing immediately, so keep going until all the
+            // calls in flight are pending.
+            if in_flight.len() == before {
+                return Poll::Pending;
+            }
+        }
+    })
+    .await;
+    Ok(results
+        .into_iter()
+        .map(|result| result.expect("every call has completed"))
+        .collect())
+}
+
+/// Executes `calls` concurrently, and returns the index and the result of the first one to complete.
+///
+/// Every call waits at most `timeout_seconds` for its response: those with a longer timeout are made with that timeout
+/// instead. If no call completes earlier, the first call therefore completes with a
+/// [`SysUnknown`](RejectCode::SysUnknown) reject once the timeout expires.
+///
+/// The calls must be bounded-wait calls. An unbounded-wait call cannot time out, and turning it into a bounded-wait
+/// call would give up the guarantee of getting the actual response, which its caller may rely on.
+///
+/// The other calls are [cancelled](CallFuture::cancel): those in flight may still execute, but their responses are
+/// ignored. Calls that have not been made by the time one completes, e.g. because an earlier one failed immediately,
+/// are not made.
+///
+/// Before any call is made, the liquid cycle balance is checked against the summed [`Call::get_cost`] of all the calls.
+///
+/// # Errors
+///
+/// Returns [`InsufficientLiquidCycleBalance`] without making any call if the liquid cycle balance does not cover all
+/// of them.
+///
+/// # Panics
+///
+/// This function will panic if `calls` is empty, or if any of them is an unbounded-wait call.
+pub async fn select<'m, 'a>(
+    calls: impl IntoIterator<Item = Call<'m, 'a>>,
+    timeout_seconds: u32,
+) -> Result<(usize, Result<Response, CallFailed>), InsufficientLiquidCycleBalance> {
+    let calls = calls
+        .into_iter()
+        .map(|call| match call.timeout_seconds {
+            Some(timeout) if timeout > timeout_seconds => call.change_timeout(timeout_seconds),
+            Some(_) => call,
+            None => panic!("select requires bounded-wait calls"),
+        })
+        .collect::<Vec<_>>();
+    assert!(!calls.is_empty(), "select requires at least one call");
+    check_liquid_cycle_balance_sufficient_for_all(&calls)?;
+    let mut futures = calls
+        .into_iter()
+        .map(IntoFuture::into_future)
+        .collect::<Vec<_>>();
+    let first = std::future::poll_fn(|context| {
+        for (index, future) in futures.iter_mut().enumerate() {
+            if let Poll::Ready(result) = Pin::new(future).poll(context) {
+                return Poll::Ready((index, result));
+            }
+        }
+        Poll::Pending
+    })
+    .await;
+    Ok(first)
+}
+
+/// Checks if the liquid cycle balance is sufficient to perform all of `calls`.
+fn check_liquid_cycle_balance_sufficient_for_all(
+    calls: &[Call<'_, '_>],
+) -> Result<(), InsufficientLiquidCycleBalance> {
+    let required = calls
+        .iter()
+        .map(Call::get_cost)
+        .fold(0, u128::saturating_add);
+    let available = crate::api::canister_liquid_cycle_balance();
+    if available >= required {
+        Ok(())
+    } else {
+        Err(InsufficientLiquidCycleBalance {
+            available,
+            required,
+        })
+    }
+}
+
 // # Internal =================================================================
 
 /// Internal state for the Future when sending a call.
@@ -875,12 +1622,88 @@ enum CallFutureState<'m, 'a> {
     /// Polling will return `Ready` and transition to `PostComplete`.
     Complete {
         result: Result<Response, CallFailed>,
+        cycles_refunded: u128,
     },
     /// The completion state of `Complete` has been returned from `poll` as `Poll::Ready`. Polling again will trap.
     #[default]
     PostComplete,
     /// The future (*not* the state) was canceled because of a trap in another future during `Executing`. Polling will trap.
     Trapped,
+    /// The call was [cancelled](CallFuture::cancel) during `Executing`. Neither callback has been called.
+    /// If the cancellation came from a [`CancelHandle`] and has not been returned from `poll` yet, polling will return
+    /// the cancellation error; otherwise polling will trap. `callback` passes the late response to the hook, if any.
+    Abandoned {
+        method: MethodHandle,
+        on_late_response: Option<LateResponseHook>,
+        unobserved: bool,
+    },
+}
+
+/// The hook passed to [`CallFuture::cancel_with`] or [`CancelHandle::cancel_with`].
+struct LateResponseHook(Box<dyn FnOnce(LateResponse) + Send + Sync>);
+
+impl std::fmt::Debug for LateResponseHook {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("LateResponseHook")
+    }
+}
+
+impl<'m, 'a> CallFutureState<'m, 'a> {
+    /// Cancels the call in place, returning whether it was cancelled.
+    ///
+    /// `notify` is set when the future may still be polled, so that it yields the cancellation error.
+    fn cancel(
+        state: &RwLock<CallFutureState<'m, 'a>>,
+        on_late_response: Option<LateResponseHook>,
+        notify: bool,
+    ) -> bool {
+        let mut guard = state.write().unwrap();
+        match mem::take(&mut *guard) {
+            CallFutureState::Prepared { .. } => {
+                // The call has not been performed, and now never will be.
+                *guard = if notify {
+                    CallFutureState::Complete {
+                        result: Err(CallFailed::CallRejected(CallRejected::cancelled())),
+                        cycles_refunded: 0,
+                    }
+                } else {
+                    CallFutureState::PostComplete
+                };
+                true
+            }
+            CallFutureState::Executing { waker, method, .. } => {
+                *guard = CallFutureState::Abandoned {
+                    method,
+                    on_late_response,
+                    unobserved: notify,
+                };
+                drop(guard);
+                if notify {
+                    waker.wake();
+                }
+                true
+            }
+            CallFutureState::Complete {
+                result,
+                cycles_refunded,
+            } if !notify => {
+                // The response arrived before it was read, so it is late all the same.
+                *guard = CallFutureState::PostComplete;
+                drop(guard);
+                if let Some(LateResponseHook(hook)) = on_late_response {
+                    hook(LateResponse {
+                        result,
+                        cycles_refunded,
+                    });
+                }
+                true
+            }
+            other => {
+                *guard = other;
+                false
+            }
+        }
+    }
 }
 
 /// Represents a future that resolves to the result of an inter-canister call.
@@ -888,6 +1711,35 @@ enum CallFutureState<'m, 'a> {
 /// This type is returned by [`IntoFuture::into_future`] when called on a [`Call`].
 /// The [`Call`] type implements the [`IntoFuture`] trait, allowing it to be converted
 /// into a [`CallFuture`]. The future can be awaited to retrieve the result of the call.
+///
+/// # Cancellation
+///
+/// Once sent, a call cannot be recalled: the callee may execute it and a response will arrive regardless. Cancelling
+/// the future with [`cancel`](Self::cancel) or [`cancel_with`](Self::cancel_with), or simply dropping it, marks the
+/// call as abandoned so that the response is ignored when it arrives. The refunded cycles are added back to the
+/// canister's balance either way, and [`cancel_with`](Self::cancel_with) passes them along with the response to a
+/// hook, e.g. to undo the effects of a transfer that went through after all.
+///
+/// A [`CancelHandle`] from [`cancel_handle`](Self::cancel_handle) cancels the call while it is being awaited, making the
+/// future yield a [`RejectCode::SysUnknown`] rejection. Together with a timer, this gives a caller-side timeout for
+/// calls with unbounded wait:
+///
+/// ```rust, ignore
+/// # use ic_cdk::call::Call;
+/// # use std::{future::IntoFuture, time::Duration};
+/// # async fn bar() {
+/// # let canister_id = ic_cdk::api::canister_self();
+/// let future = Call::unbounded_wait(canister_id, "transfer").into_future();
+/// let handle = future.cancel_handle();
+/// let timer = ic_cdk_timers::set_timer(Duration::from_secs(60), async move {
+///     handle.cancel();
+/// });
+/// let result = future.await;
+/// ic_cdk_timers::clear_timer(timer);
+/// # }
+/// ```
+///
+/// See [`CancelHandle::cancel`] for when the awaiting task resumes.
 #[derive(Debug)]
 pub struct CallFuture<'m, 'a> {
     state: Arc<RwLock<CallFutureState<'m, 'a>>>,
@@ -930,12 +1782,90 @@ impl std::future::Future for CallFuture<'_, '_> {
                 };
                 Poll::Pending
             }
-            CallFutureState::Complete { result } => {
+            CallFutureState::Complete {
+                result,
+                cycles_refunded,
+            } => {
                 *state = CallFutureState::PostComplete;
+                crate::cycles::record_refund(cycles_refunded);
+                #[cfg(feature = "profiling")]
+                crate::profiling::refund_call_cycles(cycles_refunded);
                 Poll::Ready(result)
             }
+            CallFutureState::Abandoned {
+                method,
+                on_late_response,
+                unobserved: true,
+            } => {
+                *state = CallFutureState::Abandoned {
+                    method,
+                    on_late_response,
+                    unobserved: false,
+                };
+                Poll::Ready(Err(CallFailed::CallRejected(CallRejected::cancelled())))
+            }
             CallFutureState::Trapped => trap("Call already trapped"),
-            CallFutureState::PostComplete => trap("CallFuture polled after completing"),
+            CallFutureState::PostComplete | CallFutureState::Abandoned { .. } => {
+                trap("CallFuture polled after completing")
+            }
+        }
+    }
+}
+
+impl<'m, 'a> CallFuture<'m, 'a> {
+    /// Cancels the call.
+    ///
+    /// If the call has not been sent yet, it never will be. Otherwise its response, once it arrives, is ignored; the
+    /// refunded cycles are added back to the canister's balance as usual. Dropping the future has the same effect.
+    pub fn cancel(self) {
+        CallFutureState::cancel(&self.state, None, false);
+    }
+
+    /// Like [`cancel`](Self::cancel), and calls `on_late_response` with the response to the call once it arrives.
+    ///
+    /// The hook runs in the callback of the call, so it can account for the [refunded
+    /// cycles](LateResponse::cycles_refunded) or undo the effects of a call that succeeded after all. If the response
+    /// has already arrived, the hook is called right away. It is never called if the call was not sent.
+    pub fn cancel_with(self, on_late_response: impl FnOnce(LateResponse) + Send + Sync + 'static) {
+        CallFutureState::cancel(
+            &self.state,
+            Some(LateResponseHook(Box::new(on_late_response))),
+            false,
+        );
+    }
+
+    /// Returns a handle that cancels the call while the future is being awaited.
+    pub fn cancel_handle(&self) -> CancelHandle<'m, 'a> {
+        CancelHandle {
+            state: Arc::downgrade(&self.state),
+        }
+    }
+
+    /// Decodes the response as a single Candid type once the call completes.
+    ///
+    /// Awaiting the returned future yields a [`CallResult`], with the [`CallFailed`] or [`CandidDecodeFailed`] error
+    /// converted into an [`enum@Error`].
+    pub fn candid_result<R>(self) -> DecodedCallFuture<'m, 'a, R>
+    where
+        R: CandidType + for<'de> Deserialize<'de>,
+    {
+        DecodedCallFuture {
+            inner: self,
+            decode: Response::candid,
+        }
+    }
+
+    /// Decodes the response as a tuple of Candid types once the call completes.
+    ///
+    /// Awaiting the returned future yields a [`CallResult`], with the [`CallFailed`] or [`CandidDecodeFailed`] error
+    /// converted into an [`enum@Error`].
+    pub fn candid_tuple_result<R>(self) -> DecodedCallFuture<'m, 'a, R>
+    where
+        R: for<'de> ArgumentDecoder<'de>,
+    {
+        DecodedCallFuture {
+            inner: self,
+            decode: Response::candid_tuple,
         }
     }
 }
@@ -946,10 +1876,94 @@ impl Drop for CallFuture<'_, '_> {
         // then it has been canceled due to a trap in another future.
         if is_recovering_from_trap() {
             *self.state.write().unwrap() = CallFutureState::Trapped;
+        } else {
+            CallFutureState::cancel(&self.state, None, false);
         }
     }
 }
 
+/// A handle that cancels an in-flight [`CallFuture`] from elsewhere, e.g. from a timer.
+///
+/// Obtained from [`CallFuture::cancel_handle`]. Unlike [`CallFuture::cancel`], the future remains in use: once
+/// cancelled, awaiting it yields a [`CallRejected`] with [`RejectCode::SysUnknown`], as the callee may or may not
+/// have executed the call.
+#[derive(Debug, Clone)]
+pub struct CancelHandle<'m, 'a> {
+    state: Weak<RwLock<CallFutureState<'m, 'a>>>,
+}
+
+impl CancelHandle<'_, '_> {
+    /// Cancels the call, returning whether it was still pending.
+    ///
+    /// Returns `false` if the future has already completed or been cancelled, or if the response has already arrived.
+    /// The task awaiting the future is woken. A task spawned with [`spawn`](crate::futures::spawn), such as the body of
+    /// an async canister method, only resumes within its own method, so it observes a cancellation made from another
+    /// method once the late response arrives; a task spawned with
+    /// [`spawn_migratory`](crate::futures::spawn_migratory) resumes right away.
+    pub fn cancel(&self) -> bool {
+        self.state
+            .upgrade()
+            .is_some_and(|state| CallFutureState::cancel(&state, None, true))
+    }
+
+    /// Like [`cancel`](Self::cancel), and calls `on_late_response` when the response to the cancelled call arrives.
+    ///
+    /// The hook is not called if this returns `false`.
+    pub fn cancel_with(
+        &self,
+        on_late_response: impl FnOnce(LateResponse) + Send + Sync + 'static,
+    ) -> bool {
+        self.state.upgrade().is_some_and(|state| {
+            CallFutureState::cancel(
+                &state,
+                Some(LateResponseHook(Box::new(on_late_response))),
+                true,
+            )
+        })
+    }
+}
+
+/// The response to a call that was cancelled after it was sent.
+///
+/// Passed to the hook given to [`CallFuture::cancel_with`] or [`CancelHandle::cancel_with`].
+#[derive(Debug)]
+pub struct LateResponse {
+    /// The result the call would have had, had it not been cancelled.
+    pub result: Result<Response, CallFailed>,
+    /// The cycles refunded by the callee, which have already been added back to the canister's balance.
+    pub cycles_refunded: u128,
+}
+
+/// A [`CallFuture`] whose response is decoded as Candid.
+///
+/// This type is returned by [`CallFuture::candid_result`], [`CallFuture::candid_tuple_result`] and the shorthands on
+/// [`Call`] and [`TypedCall`]. Dropping it drops the underlying [`CallFuture`].
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct DecodedCallFuture<'m, 'a, R> {
+    inner: CallFuture<'m, 'a>,
+    decode: fn(&Response) -> Result<R, CandidDecodeFailed>,
+}
+
+impl<R> std::fmt::Debug for DecodedCallFuture<'_, '_, R> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DecodedCallFuture")
+            .field("inner", &self.inner)
+            .field("type_name", &std::any::type_name::<R>())
+            .finish()
+    }
+}
+
+impl<R> Future for DecodedCallFuture<'_, '_, R> {
+    type Output = CallResult<R>;
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context<'_>) -> Poll<Self::Output> {
+        let self_ref = Pin::into_inner(self);
+        Pin::new(&mut self_ref.inner)
+            .poll(context)
+            .map(|result| -> CallResult<R> { Ok((self_ref.decode)(&result?)?) })
+    }
+}
+
 /// The reply/reject callback for `ic0.call_new`.
 ///
 /// It dereferences the future from a raw pointer, assigns the result and calls the waker.
@@ -958,37 +1972,79 @@ impl Drop for CallFuture<'_, '_> {
 /// # Safety
 ///
 /// This function must only be passed to the IC with a pointer from `Arc::<RwLock<CallFutureState>>::into_raw` as userdata.
+#[cfg(target_family = "wasm")]
 unsafe extern "C" fn callback(env: usize) {
+    // SAFETY: guaranteed by the caller.
+    unsafe { on_response(env) }
+}
+
+/// The body of [`callback`].
+///
+/// # Safety
+///
+/// Same as [`callback`].
+unsafe fn on_response(env: usize) {
     let state_ptr = env as *const RwLock<CallFutureState<'_, '_>>;
     // SAFETY: This function is only ever called by the IC, and we only ever pass an Arc as userdata.
     let state = unsafe { Arc::from_raw(state_ptr) };
-    let completed_state = CallFutureState::Complete {
-        result: match msg_reject_code() {
-            0 => Ok(Response(msg_arg_data())),
-            code => {
-                // The conversion is safe because the code is not 0.
-                Err(CallFailed::CallRejected(CallRejected {
-                    raw_reject_code: code,
-                    reject_message: msg_reject_msg(),
-                }))
-            }
-        },
+    let result = match msg_reject_code() {
+        0 => Ok(Response(msg_arg_data())),
+        code => {
+            // The conversion is safe because the code is not 0.
+            Err(CallFailed::CallRejected(CallRejected {
+                raw_reject_code: code,
+                raw_error_code: None,
+                reject_message: msg_reject_msg(),
+            }))
+        }
     };
-    let (waker, method) = match mem::replace(&mut *state.write().unwrap(), completed_state) {
-        CallFutureState::Executing { waker, method, .. } => (waker, method),
+    let cycles_refunded = msg_cycles_refunded();
+    let previous_state = mem::take(&mut *state.write().unwrap());
+    match previous_state {
+        CallFutureState::Executing { waker, method, .. } => {
+            *state.write().unwrap() = CallFutureState::Complete {
+                result,
+                cycles_refunded,
+            };
+            crate::lifecycle::call_finished();
+            ic_cdk_executor::in_callback_executor_context_for(method, || {
+                waker.wake();
+            });
+        }
+        CallFutureState::Abandoned {
+            method,
+            on_late_response,
+            unobserved,
+        } => {
+            // The future may still yield the cancellation, but never this result.
+            *state.write().unwrap() = if unobserved {
+                CallFutureState::Complete {
+                    result: Err(CallFailed::CallRejected(CallRejected::cancelled())),
+                    cycles_refunded: 0,
+                }
+            } else {
+                CallFutureState::PostComplete
+            };
+            crate::lifecycle::call_finished();
+            ic_cdk_executor::in_callback_executor_context_for(method, || {
+                if let Some(LateResponseHook(hook)) = on_late_response {
+                    hook(LateResponse {
+                        result,
+                        cycles_refunded,
+                    });
+                }
+            });
+        }
         // This future has already been cancelled and waking it will do nothing.
         // All that's left is to explicitly trap in case this is the last call being multiplexed,
         // to replace an automatic trap from not replying.
         CallFutureState::Trapped => trap("Call already trapped"),
         _ => {
             unreachable!(
-                "CallFutureState for in-flight calls should only be Executing or Trapped (callback)"
+                "CallFutureState for in-flight calls should only be Executing, Abandoned or Trapped (callback)"
             )
         }
-    };
-    ic_cdk_executor::in_callback_executor_context_for(method, || {
-        waker.wake();
-    });
+    }
 }
 
 /// The cleanup callback for `ic0.call_on_cleanup`.
@@ -1000,10 +2056,22 @@ unsafe extern "C" fn callback(env: usize) {
 /// # Safety
 ///
 /// This function must only be passed to the IC with a pointer from `Arc::<RwLock<CallFutureState>>::into_raw` as userdata.
+#[cfg(target_family = "wasm")]
 unsafe extern "C" fn cleanup(env: usize) {
+    // SAFETY: guaranteed by the caller.
+    unsafe { on_cleanup(env) }
+}
+
+/// The body of [`cleanup`].
+///
+/// # Safety
+///
+/// Same as [`cleanup`].
+unsafe fn on_cleanup(env: usize) {
     let state_ptr = env as *const RwLock<CallFutureState<'_, '_>>;
     // SAFETY: This function is only ever called by the IC, and we only ever pass a Arc as userdata.
     let state = unsafe { Arc::from_raw(state_ptr) };
+    crate::lifecycle::call_finished();
     // We set the call result, even though it won't be read on the
     // default executor, because we can't guarantee it was called on
     // our executor. However, we are not allowed to inspect
@@ -1015,11 +2083,15 @@ unsafe extern "C" fn cleanup(env: usize) {
     let err_state = CallFutureState::Complete {
         result: Err(CallFailed::CallRejected(CallRejected {
             raw_reject_code: RejectCode::CanisterReject as u32,
+            raw_error_code: None,
             reject_message: "cleanup".into(),
         })),
+        cycles_refunded: 0,
     };
     let (method, task) = match mem::replace(&mut *state.write().unwrap(), err_state) {
         CallFutureState::Executing { method, task, .. } => (method, task),
+        // The hook for the late response trapped. The future, if still around, reads the cleanup reject.
+        CallFutureState::Abandoned { method, .. } => (method, None),
         CallFutureState::Trapped => {
             // The future has already been canceled and dropped. There is nothing
             // more to clean up except for the CallFutureState.
@@ -1027,7 +2099,7 @@ unsafe extern "C" fn cleanup(env: usize) {
         }
         _ => {
             unreachable!(
-                "CallFutureState for in-flight calls should only be Executing or Trapped (cleanup)"
+                "CallFutureState for in-flight calls should only be Executing, Abandoned or Trapped (cleanup)"
             )
         }
     };
@@ -1039,6 +2111,158 @@ unsafe extern "C" fn cleanup(env: usize) {
     });
 }
 
+/// The callbacks outside of Wasm, where a trap is a panic.
+///
+/// On the IC, a trap in [`callback`] rolls back to before it was called, and then [`cleanup`] is called with the same
+/// state. A panic rolls nothing back, so these callbacks are declared `extern "C-unwind"`, letting a native host such
+/// as `ic0::host::InMemoryHost` catch the panic, and restore the in-flight call while unwinding, so that `cleanup`
+/// finds it as it would on the IC.
+#[cfg(not(target_family = "wasm"))]
+mod unwind {
+    use super::{CallFutureState, on_cleanup, on_response};
+    use ic_cdk_executor::{MethodHandle, TaskHandle, WeakMethodHandle};
+    use std::mem;
+    use std::sync::{Arc, PoisonError, RwLock};
+    use std::task::Waker;
+
+    /// Registers the callbacks of a call being made, like `ic0.call_new` and `ic0.call_on_cleanup`.
+    ///
+    /// # Safety
+    ///
+    /// `env` must be a pointer from `Arc::<RwLock<CallFutureState>>::into_raw`.
+    pub(super) unsafe fn call_new(callee: &[u8], method: &str, env: usize) {
+        let callback = callback as unsafe extern "C-unwind" fn(usize) as usize;
+        let cleanup = cleanup as unsafe extern "C-unwind" fn(usize) as usize;
+        // SAFETY: `callee` and `method` are passed as ptr and len, and the callbacks can be called with `env`, as in
+        // `Call::perform`.
+        unsafe {
+            ic0::sys::call_new(
+                callee.as_ptr() as usize,
+                callee.len(),
+                method.as_ptr() as usize,
+                method.len(),
+                callback,
+                env,
+                callback,
+                env,
+            );
+            ic0::sys::call_on_cleanup(cleanup, env);
+        }
+    }
+
+    /// [`super::callback`], restoring the in-flight call if it panics.
+    ///
+    /// # Safety
+    ///
+    /// Same as [`super::callback`].
+    unsafe extern "C-unwind" fn callback(env: usize) {
+        let state_ptr = env as *const RwLock<CallFutureState<'_, '_>>;
+        // SAFETY: `env` is an Arc, whose reference is consumed by `on_response`. A second one is kept for `cleanup`.
+        let state = unsafe {
+            Arc::increment_strong_count(state_ptr);
+            Arc::from_raw(state_ptr)
+        };
+        let in_flight = InFlight::of(&state.read().unwrap());
+        let rollback = Rollback { state, in_flight };
+        // SAFETY: guaranteed by the caller.
+        unsafe { on_response(env) };
+        drop(rollback);
+    }
+
+    /// [`super::cleanup`], declared `extern "C-unwind"` so that it can trap too.
+    ///
+    /// # Safety
+    ///
+    /// Same as [`super::cleanup`].
+    unsafe extern "C-unwind" fn cleanup(env: usize) {
+        // SAFETY: guaranteed by the caller.
+        unsafe { on_cleanup(env) }
+    }
+
+    /// The parts of an in-flight call that `callback` consumes.
+    ///
+    /// The method is held by a weak handle, so that the method is freed as usual if the callback returns.
+    enum InFlight {
+        Executing {
+            waker: Waker,
+            method: WeakMethodHandle,
+            task: Option<TaskHandle>,
+        },
+        Abandoned {
+            method: WeakMethodHandle,
+            unobserved: bool,
+        },
+        Trapped,
+    }
+
+    impl InFlight {
+        fn of(state: &CallFutureState<'_, '_>) -> Option<Self> {
+            match state {
+                CallFutureState::Executing {
+                    waker,
+                    method,
+                    task,
+                } => Some(Self::Executing {
+                    waker: waker.clone(),
+                    method: method.downgrade(),
+                    task: task.clone(),
+                }),
+                CallFutureState::Abandoned {
+                    method, unobserved, ..
+                } => Some(Self::Abandoned {
+                    method: method.downgrade(),
+                    unobserved: *unobserved,
+                }),
+                CallFutureState::Trapped => Some(Self::Trapped),
+                _ => None,
+            }
+        }
+    }
+
+    /// Restores the in-flight call, and keeps the reference to its state for `cleanup`, if dropped while unwinding.
+    struct Rollback<'m, 'a> {
+        state: Arc<RwLock<CallFutureState<'m, 'a>>>,
+        in_flight: Option<InFlight>,
+    }
+
+    impl Drop for Rollback<'_, '_> {
+        fn drop(&mut self) {
+            let Some(in_flight) = self.in_flight.take().filter(|_| std::thread::panicking()) else {
+                return;
+            };
+            // The method handle was dropped while unwinding, but the method is only freed when its context returns.
+            let upgrade = |method: WeakMethodHandle| -> MethodHandle {
+                method
+                    .upgrade()
+                    .expect("the method of a trapped callback is alive")
+            };
+            let restored = match in_flight {
+                InFlight::Executing {
+                    waker,
+                    method,
+                    task,
+                } => CallFutureState::Executing {
+                    waker,
+                    method: upgrade(method),
+                    task,
+                },
+                // The hook was consumed, so `cleanup` does not call it again.
+                InFlight::Abandoned { method, unobserved } => CallFutureState::Abandoned {
+                    method: upgrade(method),
+                    on_late_response: None,
+                    unobserved,
+                },
+                InFlight::Trapped => CallFutureState::Trapped,
+            };
+            if !matches!(restored, CallFutureState::Trapped) {
+                crate::lifecycle::call_started();
+            }
+            *self.state.write().unwrap_or_else(PoisonError::into_inner) = restored;
+            mem::forget(Arc::clone(&self.state));
+        }
+    }
+}
+
 // # Internal END =============================================================
 
 /// Panics with an informative message when argument encoding fails.
@@ -0,0 +1,300 @@
+//! Paging of payloads too large for a single response.
+//!
+//! A response to an inter-canister call is limited to about 2 MiB. To transfer a larger payload, the callee serves it
+//! in pages from a method taking a [`PageRequest`] and returning a [`Page`], and the caller fetches the pages one after
+//! the other with [`Call::paged`], passing along the [`ContinuationToken`] of each page to get the next one.
+//!
+//! Every page carries the SHA-256 digest of the entire payload. The caller checks that the digest does not change
+//! between pages, and that the assembled payload matches it.
+//!
+//! This module is available with the `paging` feature.
+//!
+//! # Example
+//!
+//! The callee keeps the payload in a [`PagedPayload`], which computes its digest once, made of raw bytes or of a
+//! Candid value, such as a sequence, and serves its pages with [`PagedPayload::serve`]:
+//!
+//! ```rust, no_run
+//! # use ic_cdk::{query, update};
+//! use ic_cdk::call::paging::{Page, PageRequest, PagedPayload};
+//! use std::cell::RefCell;
+//! # fn accounts() -> Vec<(String, u64)> { vec![] }
+//!
+//! thread_local! {
+//!     static ACCOUNTS: RefCell<PagedPayload> = RefCell::new(PagedPayload::candid(&accounts()));
+//! }
+//!
+//! #[update]
+//! fn refresh_accounts() {
+//!     ACCOUNTS.set(PagedPayload::candid(&accounts()));
+//! }
+//!
+//! #[query]
+//! fn get_accounts(request: PageRequest) -> Page {
+//!     ACCOUNTS.with_borrow(|accounts| accounts.serve(&request))
+//! }
+//! ```
+//!
+//! The caller assembles the pages into a [`Response`]:
+//!
+//! ```rust, no_run
+//! # use ic_cdk::call::Call;
+//! # async fn bar() -> Result<(), ic_cdk::call::paging::PagingError> {
+//! # let canister_id = ic_cdk::api::canister_self();
+//! let accounts: Vec<(String, u64)> = Call::bounded_wait(canister_id, "get_accounts")
+//!     .paged()
+//!     .await?
+//!     .candid()?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Each page is cut from the payload as it is when the page is requested. If the payload is replaced while it is being
+//! fetched, the callee traps when it gets a token for the previous payload.
+
+use super::{Call, CallFailed, CandidDecodeFailed, Response, panic_when_encode_fails};
+use crate::trap;
+use candid::{CandidType, Deserialize, encode_one};
+use sha2::{Digest, Sha256};
+use std::borrow::Cow;
+use thiserror::Error;
+
+/// The largest page [`PagedPayload::serve`] returns, leaving room for the rest of the [`Page`] within the response size limit.
+pub const MAX_PAGE_SIZE: usize = 2_000_000;
+
+/// The argument of a method serving a payload in pages.
+#[derive(CandidType, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct PageRequest {
+    /// The token of the previous page, or `None` to get the first page.
+    pub token: Option<ContinuationToken>,
+}
+
+/// Identifies the next page of a payload.
+///
+/// Tokens are issued by [`PagedPayload::serve`] and should be passed back as they are.
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct ContinuationToken {
+    /// The offset of the next page in the payload.
+    pub offset: u64,
+    /// The SHA-256 digest of the payload.
+    #[serde(with = "serde_bytes")]
+    pub sha256: Vec<u8>,
+}
+
+/// A page of a payload, returned by a method serving the payload in pages.
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Page {
+    /// The bytes of the payload in this page.
+    #[serde(with = "serde_bytes")]
+    pub data: Vec<u8>,
+    /// The length of the entire payload.
+    pub total_len: u64,
+    /// The SHA-256 digest of the entire payload.
+    #[serde(with = "serde_bytes")]
+    pub sha256: Vec<u8>,
+    /// The token to get the next page with, or `None` if this is the last page.
+    pub next: Option<ContinuationToken>,
+}
+
+/// A payload served in pages, with its SHA-256 digest computed once.
+///
+/// Keep it in the state of the canister, and replace it when the payload changes.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PagedPayload {
+    bytes: Vec<u8>,
+    sha256: Vec<u8>,
+}
+
+impl PagedPayload {
+    /// Creates a payload of raw bytes, which the caller gets with [`Response::into_bytes`].
+    pub fn new(bytes: Vec<u8>) -> Self {
+        let sha256 = Sha256::digest(&bytes).to_vec();
+        Self { bytes, sha256 }
+    }
+
+    /// Creates a payload holding a single Candid value, which the caller decodes with [`Response::candid`].
+    pub fn candid<T: CandidType>(value: &T) -> Self {
+        Self::new(encode_one(value).unwrap_or_else(panic_when_encode_fails))
+    }
+
+    /// Gets the bytes of the payload.
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// Serves the page requested by `request`, at most [`MAX_PAGE_SIZE`] bytes long.
+    ///
+    /// # Traps
+    ///
+    /// This function traps if the token of the request was not issued for this payload, e.g. because the payload has
+    /// been replaced since.
+    pub fn serve(&self, request: &PageRequest) -> Page {
+        self.serve_with_page_size(request, MAX_PAGE_SIZE)
+    }
+
+    /// Like [`serve`](Self::serve), with pages of at most `page_size` bytes.
+    ///
+    /// # Panics
+    ///
+    /// This function panics if `page_size` is zero.
+    ///
+    /// # Traps
+    ///
+    /// See [`serve`](Self::serve).
+    pub fn serve_with_page_size(&self, request: &PageRequest, page_size: usize) -> Page {
+        assert!(page_size > 0, "page size must not be zero");
+        let payload = &self.bytes;
+        let start = match &request.token {
+            None => 0,
+            Some(token) => {
+                if token.sha256 != self.sha256 {
+                    trap("continuation token was issued for a different payload");
+                }
+                match usize::try_from(token.offset) {
+                    Ok(offset) if offset < payload.len() => offset,
+                    _ => trap("continuation token is out of range"),
+                }
+            }
+        };
+        let end = payload.len().min(start.saturating_add(page_size));
+        Page {
+            data: payload[start..end].to_vec(),
+            total_len: payload.len() as u64,
+            next: (end < payload.len()).then(|| ContinuationToken {
+                offset: end as u64,
+                sha256: self.sha256.clone(),
+            }),
+            sha256: self.sha256.clone(),
+        }
+    }
+}
+
+impl From<Vec<u8>> for PagedPayload {
+    fn from(bytes: Vec<u8>) -> Self {
+        Self::new(bytes)
+    }
+}
+
+/// The error type of [`Call::paged`].
+#[derive(Error, Debug, Clone)]
+pub enum PagingError {
+    /// The call for a page failed.
+    #[error(transparent)]
+    CallFailed(#[from] CallFailed),
+
+    /// A page could not be decoded as a [`Page`].
+    #[error(transparent)]
+    CandidDecodeFailed(#[from] CandidDecodeFailed),
+
+    /// The pages do not add up to a payload matching their length and SHA-256 digest.
+    #[error("paged payload failed the integrity check")]
+    IntegrityCheckFailed,
+}
+
+impl Call<'_, '_> {
+    /// Fetches a payload served in pages by the method of the call.
+    ///
+    /// The method must take a single [`PageRequest`] and return a [`Page`], like a method using
+    /// [`PagedPayload::serve`]. The call is
+    /// made once for each page, with the configured cycles and timeout; the configured arguments are replaced by the
+    /// [`PageRequest`] of the page.
+    ///
+    /// # Errors
+    ///
+    /// Fails with the error of the first page that could not be fetched or decoded, or with
+    /// [`PagingError::IntegrityCheckFailed`] if the pages do not add up to the payload they describe.
+    pub async fn paged(&self) -> Result<Response, PagingError> {
+        let mut payload = Vec::new();
+        let mut expected: Option<(u64, Vec<u8>)> = None;
+        let mut token = None;
+        loop {
+            let request = PageRequest { token };
+            let call = Call {
+                canister_id: self.canister_id,
+                method: self.method,
+                cycles: self.cycles,
+                timeout_seconds: self.timeout_seconds,
+                deadline: self.deadline,
+                encoded_args: Cow::Owned(
+                    encode_one(&request).unwrap_or_else(panic_when_encode_fails),
+                ),
+            };
+            let page: Page = call.await?.candid()?;
+            let (total_len, sha256) =
+                expected.get_or_insert_with(|| (page.total_len, page.sha256.clone()));
+            if page.total_len != *total_len || page.sha256 != *sha256 {
+                return Err(PagingError::IntegrityCheckFailed);
+            }
+            payload.extend_from_slice(&page.data);
+            if payload.len() as u64 > *total_len {
+                return Err(PagingError::IntegrityCheckFailed);
+            }
+            match page.next {
+                // An empty page that is not the last would never make progress.
+                Some(next) if next.offset == payload.len() as u64 && !page.data.is_empty() => {
+                    token = Some(next);
+                }
+                Some(_) => return Err(PagingError::IntegrityCheckFailed),
+                None => {
+                    if payload.len() as u64 != *total_len
+                        || Sha256::digest(&payload).as_slice() != sha256.as_slice()
+                    {
+                        return Err(PagingError::IntegrityCheckFailed);
+                    }
+                    return Ok(Response(payload));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fetch(payload: &[u8], page_size: usize) -> Vec<Page> {
+        let payload = PagedPayload::new(payload.to_vec());
+        let mut pages = vec![];
+        let mut request = PageRequest::default();
+        loop {
+            let page = payload.serve_with_page_size(&request, page_size);
+            request.token = page.next.clone();
+            pages.push(page);
+            if request.token.is_none() {
+                return pages;
+            }
+        }
+    }
+
+    #[test]
+    fn pages_cover_the_payload() {
+        let payload: Vec<u8> = (0..=255).collect();
+        let pages = fetch(&payload, 100);
+        assert_eq!(
+            pages.iter().map(|page| page.data.len()).collect::<Vec<_>>(),
+            [100, 100, 56]
+        );
+        assert_eq!(
+            pages
+                .iter()
+                .flat_map(|page| page.data.clone())
+                .collect::<Vec<_>>(),
+            payload
+        );
+        let sha256 = Sha256::digest(&payload).to_vec();
+        assert!(
+            pages
+                .iter()
+                .all(|page| page.total_len == 256 && page.sha256 == sha256)
+        );
+        assert_eq!(pages[0].next.as_ref().unwrap().offset, 100);
+    }
+
+    #[test]
+    fn empty_payload_is_a_single_page() {
+        let pages = fetch(&[], 100);
+        assert_eq!(pages.len(), 1);
+        assert!(pages[0].data.is_empty());
+        assert_eq!(pages[0].sha256, Sha256::digest(b"").to_vec());
+    }
+}
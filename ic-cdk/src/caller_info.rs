@@ -0,0 +1,272 @@
+//! Typed access to the identity attributes of callers authenticated by canister signatures.
+//!
+//! A caller authenticated by a canister signature, e.g. through Internet Identity, may come with attributes about
+//! its identity, signed by the issuing canister. [`api::msg_caller_info_data`] returns them as raw bytes and
+//! [`api::msg_caller_info_signer`] returns the issuer. This module decodes the attributes into a [`CallerInfo`],
+//! after checking the issuer against an allowlist of [`TrustedSigner`]s, each specifying the format it encodes the
+//! attributes in.
+//!
+//! # Example
+//!
+//! Register the trusted signers when the canister is installed or upgraded, and gate endpoints with guards:
+//!
+//! ```rust, no_run
+//! use candid::Principal;
+//! use ic_cdk::caller_info::{self, AttributeFormat, AttributeValue, TrustedSigner};
+//! use ic_cdk::{init, update};
+//!
+//! #[init]
+//! fn init() {
+//!     let issuer = Principal::from_text("rdmx6-jaaaa-aaaaa-aaadq-cai").unwrap();
+//!     caller_info::set_trusted_signers([TrustedSigner::new(issuer, AttributeFormat::Candid)]);
+//! }
+//!
+//! fn is_verified() -> Result<(), String> {
+//!     caller_info::require_attribute("verified", &AttributeValue::Bool(true))
+//! }
+//!
+//! #[update(guard = "is_verified")]
+//! fn verified_only() {}
+//!
+//! #[update(guard = "caller_info::is_trusted_caller")]
+//! fn with_attributes() -> Option<String> {
+//!     let info = caller_info::CallerInfo::current().unwrap();
+//!     match info.get("email") {
+//!         Some(AttributeValue::Text(email)) => Some(email.clone()),
+//!         _ => None,
+//!     }
+//! }
+//! ```
+//!
+//! [`api::msg_caller_info_data`]: crate::api::msg_caller_info_data
+//! [`api::msg_caller_info_signer`]: crate::api::msg_caller_info_signer
+
+use crate::api::{msg_caller_info_data, msg_caller_info_signer};
+use candid::{CandidType, Deserialize, Principal, decode_one};
+#[cfg(feature = "cbor")]
+use serde_cbor::Value;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use thiserror::Error;
+
+thread_local! {
+    static TRUSTED_SIGNERS: RefCell<Vec<TrustedSigner>> = const { RefCell::new(Vec::new()) };
+}
+
+/// The encoding of the attributes provided by a signer, as documented by the signer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum AttributeFormat {
+    /// A single Candid value of type `vec record { text; AttributeValue }`.
+    Candid,
+    /// A CBOR map with text keys, and text, integer, boolean or byte string values.
+    #[cfg(feature = "cbor")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "cbor")))]
+    Cbor,
+}
+
+/// A canister whose attributes about callers are trusted.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct TrustedSigner {
+    canister_id: Principal,
+    format: AttributeFormat,
+}
+
+impl TrustedSigner {
+    /// Trusts the attributes signed by `canister_id`, decoding them according to `format`.
+    pub fn new(canister_id: Principal, format: AttributeFormat) -> Self {
+        Self {
+            canister_id,
+            format,
+        }
+    }
+
+    /// Gets the ID of the signing canister.
+    pub fn canister_id(&self) -> Principal {
+        self.canister_id
+    }
+
+    /// Gets the format of the attributes.
+    pub fn format(&self) -> AttributeFormat {
+        self.format
+    }
+}
+
+/// Sets the signers whose attributes are trusted, replacing those set before.
+///
+/// The allowlist lives on the heap, so it should be set in both `#[init]` and `#[post_upgrade]`.
+pub fn set_trusted_signers(signers: impl IntoIterator<Item = TrustedSigner>) {
+    TRUSTED_SIGNERS.set(signers.into_iter().collect());
+}
+
+/// The value of an identity attribute.
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub enum AttributeValue {
+    /// A text value.
+    Text(String),
+    /// A non-negative integer.
+    Nat(u64),
+    /// A negative integer.
+    Int(i64),
+    /// A boolean value.
+    Bool(bool),
+    /// A byte string.
+    Bytes(Vec<u8>),
+}
+
+/// The error type of [`CallerInfo::current`] and [`CallerInfo::decode`].
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum CallerInfoError {
+    /// The caller is not authenticated by a canister signature, so it has no attributes.
+    #[error("the caller is not authenticated by a canister signature")]
+    NoCallerInfo,
+    /// The attributes are signed by a canister that is not a [`TrustedSigner`].
+    #[error("caller info is signed by untrusted canister {0}")]
+    UntrustedSigner(Principal),
+    /// The attributes are not encoded in the format of the signer.
+    #[error("caller info could not be decoded: {0}")]
+    DecodeFailed(String),
+}
+
+/// The identity attributes of the caller, signed by a trusted canister.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CallerInfo {
+    signer: Principal,
+    attributes: BTreeMap<String, AttributeValue>,
+}
+
+impl CallerInfo {
+    /// Gets the attributes of the caller of the current message.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the caller has no attributes, if their signer is not among those set with [`set_trusted_signers`],
+    /// or if they are not encoded in the format of the signer.
+    pub fn current() -> Result<Self, CallerInfoError> {
+        let signer = msg_caller_info_signer().ok_or(CallerInfoError::NoCallerInfo)?;
+        let format = TRUSTED_SIGNERS
+            .with_borrow(|signers| {
+                signers
+                    .iter()
+                    .find(|trusted| trusted.canister_id == signer)
+                    .map(|trusted| trusted.format)
+            })
+            .ok_or(CallerInfoError::UntrustedSigner(signer))?;
+        Self::decode(signer, &msg_caller_info_data(), format)
+    }
+
+    /// Decodes attributes signed by `signer` in the given format, without checking whether the signer is trusted.
+    ///
+    /// # Errors
+    ///
+    /// Fails if `data` is not encoded in `format`.
+    pub fn decode(
+        signer: Principal,
+        data: &[u8],
+        format: AttributeFormat,
+    ) -> Result<Self, CallerInfoError> {
+        let attributes = match format {
+            AttributeFormat::Candid => decode_one::<Vec<(String, AttributeValue)>>(data)
+                .map_err(|e| CallerInfoError::DecodeFailed(e.to_string()))?
+                .into_iter()
+                .collect(),
+            #[cfg(feature = "cbor")]
+            AttributeFormat::Cbor => decode_cbor_attributes(data)?,
+        };
+        Ok(Self { signer, attributes })
+    }
+
+    /// Gets the canister that signed the attributes.
+    pub fn signer(&self) -> Principal {
+        self.signer
+    }
+
+    /// Gets the value of an attribute.
+    pub fn get(&self, name: &str) -> Option<&AttributeValue> {
+        self.attributes.get(name)
+    }
+
+    /// Gets all attributes by name.
+    pub fn attributes(&self) -> &BTreeMap<String, AttributeValue> {
+        &self.attributes
+    }
+}
+
+#[cfg(feature = "cbor")]
+fn decode_cbor_attributes(
+    data: &[u8],
+) -> Result<BTreeMap<String, AttributeValue>, CallerInfoError> {
+    let invalid = |message: &str| CallerInfoError::DecodeFailed(message.to_string());
+    serde_cbor::from_slice::<BTreeMap<String, Value>>(data)
+        .map_err(|e| CallerInfoError::DecodeFailed(e.to_string()))?
+        .into_iter()
+        .map(|(key, value)| {
+            let value = match value {
+                Value::Text(text) => AttributeValue::Text(text),
+                Value::Integer(n) if n >= 0 => AttributeValue::Nat(
+                    u64::try_from(n).map_err(|_| invalid("attribute value is out of range"))?,
+                ),
+                Value::Integer(n) => AttributeValue::Int(
+                    i64::try_from(n).map_err(|_| invalid("attribute value is out of range"))?,
+                ),
+                Value::Bool(b) => AttributeValue::Bool(b),
+                Value::Bytes(bytes) => AttributeValue::Bytes(bytes),
+                _ => return Err(invalid("unsupported attribute value")),
+            };
+            Ok((key, value))
+        })
+        .collect()
+}
+
+/// A guard accepting callers whose attributes are signed by a trusted signer and can be decoded.
+///
+/// Use it as `#[update(guard = "ic_cdk::caller_info::is_trusted_caller")]`.
+pub fn is_trusted_caller() -> Result<(), String> {
+    CallerInfo::current().map(|_| ()).map_err(|e| e.to_string())
+}
+
+/// Checks that the caller has a trusted attribute with the given value, for use in guards.
+///
+/// # Errors
+///
+/// Fails with a message for the caller if [`CallerInfo::current`] fails or the attribute does not have the value.
+pub fn require_attribute(name: &str, value: &AttributeValue) -> Result<(), String> {
+    let info = CallerInfo::current().map_err(|e| e.to_string())?;
+    if info.get(name) == Some(value) {
+        Ok(())
+    } else {
+        Err(format!(
+            "caller attribute `{name}` does not have the required value"
+        ))
+    }
+}
+
+#[cfg(all(test, feature = "cbor"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_cbor_attributes() {
+        // {"email": "a@b", "age": 42, "delta": -3, "verified": true}
+        let data = [
+            0xa4, 0x65, b'e', b'm', b'a', b'i', b'l', 0x63, b'a', b'@', b'b', 0x63, b'a', b'g',
+            b'e', 0x18, 42, 0x65, b'd', b'e', b'l', b't', b'a', 0x22, 0x68, b'v', b'e', b'r', b'i',
+            b'f', b'i', b'e', b'd', 0xf5,
+        ];
+        let signer = Principal::from_slice(&[1]);
+        let info = CallerInfo::decode(signer, &data, AttributeFormat::Cbor).unwrap();
+        assert_eq!(info.signer(), signer);
+        assert_eq!(info.get("email"), Some(&AttributeValue::Text("a@b".into())));
+        assert_eq!(info.get("age"), Some(&AttributeValue::Nat(42)));
+        assert_eq!(info.get("delta"), Some(&AttributeValue::Int(-3)));
+        assert_eq!(info.get("verified"), Some(&AttributeValue::Bool(true)));
+        assert_eq!(info.attributes().len(), 4);
+        // The same attributes, self-described.
+        let tagged = [&[0xd9, 0xd9, 0xf7][..], &data].concat();
+        assert_eq!(
+            CallerInfo::decode(signer, &tagged, AttributeFormat::Cbor).unwrap(),
+            info
+        );
+        // An array is not a map.
+        assert!(CallerInfo::decode(signer, &[0x80], AttributeFormat::Cbor).is_err());
+    }
+}
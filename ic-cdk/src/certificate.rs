@@ -0,0 +1,676 @@
+//! Parsing and verification of the certificates of the Internet Computer.
+//!
+//! In a query call, [`api::data_certificate`] returns a certificate signed by the subnet, authenticating the data set
+//! with [`api::certified_data_set`]. Canisters usually set the root hash of a hash tree as their certified data,
+//! e.g. with `ic-certified-map`, and hand the certificate to clients along with the pruned tree. This module parses
+//! such certificates into a [`Certificate`], whose state tree is the [`HashTree`] of `ic-certified-map`, so that
+//! canisters and tests can check them without a replica:
+//!
+//! - [`lookup`] looks up a path in a hash tree, such as the state tree of a certificate.
+//! - [`Certificate::certified_data`] looks up the certified data of a canister.
+//! - `Certificate::verify` checks the BLS signature, following the delegation of the subnet if there is one.
+//!
+//! Verifying a BLS signature takes pairing-based cryptography, which adds considerably to the size of a canister.
+//! It is provided by the `verify-bls` feature, with `Certificate::verify` and `verify_data_certificate`. Either way,
+//! [`Certificate::verify_with`] and [`verify_data_certificate_with`] take a function checking the signatures
+//! instead, which native tests can use to certify data with fake signatures.
+//!
+//! # Example
+//!
+//! [`api::root_key`] is only available in replicated mode, while [`api::data_certificate`] is only available in
+//! queries, so the root key is captured when the canister is installed or upgraded:
+//!
+//! ```rust, ignore
+//! use ic_cdk::certificate;
+//! use ic_certified_map::RbTree;
+//! use std::cell::RefCell;
+//!
+//! thread_local! {
+//!     static ROOT_KEY: RefCell<Vec<u8>> = const { RefCell::new(Vec::new()) };
+//!     static TREE: RefCell<RbTree<Vec<u8>, Vec<u8>>> = RefCell::new(RbTree::new());
+//! }
+//!
+//! #[ic_cdk::init]
+//! fn init() {
+//!     ROOT_KEY.set(ic_cdk::api::root_key());
+//! }
+//!
+//! #[ic_cdk::query]
+//! fn data_certificate_is_valid() -> bool {
+//!     let expected = TREE.with_borrow(|tree| tree.root_hash());
+//!     ROOT_KEY.with_borrow(|root_key| certificate::verify_data_certificate(&expected, root_key).is_ok())
+//! }
+//! ```
+//!
+//! [`api::data_certificate`]: crate::api::data_certificate
+//! [`api::certified_data_set`]: crate::api::certified_data_set
+//! [`api::root_key`]: crate::api::root_key
+
+use crate::api::{canister_self, data_certificate};
+use candid::Principal;
+pub use ic_certified_map::{Hash, HashTree};
+use serde::{Deserialize, Deserializer, Serialize};
+use thiserror::Error;
+
+/// The domain separator and domain of the signature of a certificate.
+const STATE_ROOT_DOMAIN: &[u8] = b"\x0dic-state-root";
+
+/// A function verifying a BLS signature, given the signature, the signed message and the public key.
+type VerifyBls<'a> = &'a dyn Fn(&[u8], &[u8], &[u8]) -> bool;
+
+/// The DER prefix of a BLS public key on curve BLS12-381, as returned by [`api::root_key`](crate::api::root_key).
+const DER_PREFIX: [u8; 37] = [
+    0x30, 0x81, 0x82, 0x30, 0x1d, 0x06, 0x0d, 0x2b, 0x06, 0x01, 0x04, 0x01, 0x82, 0xdc, 0x7c, 0x05,
+    0x03, 0x01, 0x02, 0x01, 0x06, 0x0c, 0x2b, 0x06, 0x01, 0x04, 0x01, 0x82, 0xdc, 0x7c, 0x05, 0x03,
+    0x02, 0x01, 0x03, 0x61, 0x00,
+];
+
+/// The length of a BLS public key.
+const KEY_LENGTH: usize = 96;
+
+/// The result of [`lookup`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LookupResult<'a> {
+    /// The path leads to a leaf with this value.
+    Found(&'a [u8]),
+    /// The tree proves that the path does not exist.
+    Absent,
+    /// The path may exist in a part of the tree that is pruned.
+    Unknown,
+    /// The path leads to a node that is not a leaf, or through a leaf.
+    Error,
+}
+
+/// Looks up the value at a path of labels in a hash tree.
+pub fn lookup<'t, L: AsRef<[u8]>>(tree: &'t HashTree<'_>, path: &[L]) -> LookupResult<'t> {
+    let Some((label, rest)) = path.split_first() else {
+        return match tree {
+            HashTree::Leaf(value) => LookupResult::Found(value),
+            HashTree::Pruned(_) => LookupResult::Unknown,
+            HashTree::Empty => LookupResult::Absent,
+            _ => LookupResult::Error,
+        };
+    };
+    let mut pruned = false;
+    let mut subtrees = vec![tree];
+    while let Some(node) = subtrees.pop() {
+        match node {
+            HashTree::Fork(children) => subtrees.extend([&children.1, &children.0]),
+            HashTree::Labeled(l, subtree) if *l == label.as_ref() => return lookup(subtree, rest),
+            HashTree::Leaf(_) => return LookupResult::Error,
+            HashTree::Pruned(_) => pruned = true,
+            HashTree::Empty | HashTree::Labeled(..) => {}
+        }
+    }
+    if pruned {
+        LookupResult::Unknown
+    } else {
+        LookupResult::Absent
+    }
+}
+
+/// The delegation of the signing authority from the root subnet to another subnet.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Delegation {
+    /// The ID of the subnet the authority is delegated to.
+    pub subnet_id: Principal,
+    /// The certificate of the root subnet, holding the public key and canister ranges of the subnet.
+    #[serde(with = "bytes")]
+    pub certificate: Vec<u8>,
+}
+
+/// A certificate of the Internet Computer.
+///
+/// The state tree borrows its labels from the bytes the certificate is decoded from.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Certificate<'a> {
+    #[serde(borrow, deserialize_with = "deserialize_tree")]
+    tree: HashTree<'a>,
+    #[serde(with = "bytes")]
+    signature: Vec<u8>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    delegation: Option<Delegation>,
+}
+
+/// Deserializes a tree borrowing from the deserializer, as `HashTree` only does for the lifetime of the deserializer.
+fn deserialize_tree<'de: 'a, 'a, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<HashTree<'a>, D::Error> {
+    HashTree::deserialize(deserializer)
+}
+
+/// Encodes bytes as a CBOR byte string, rather than as an array of numbers.
+mod bytes {
+    use serde::de::{Error, Visitor};
+    use serde::{Deserializer, Serializer};
+    use std::fmt;
+
+    pub(super) fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_bytes(bytes)
+    }
+
+    pub(super) fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<u8>, D::Error> {
+        struct BytesVisitor;
+
+        impl Visitor<'_> for BytesVisitor {
+            type Value = Vec<u8>;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str("a byte string")
+            }
+
+            fn visit_bytes<E: Error>(self, bytes: &[u8]) -> Result<Vec<u8>, E> {
+                Ok(bytes.to_vec())
+            }
+
+            fn visit_byte_buf<E: Error>(self, bytes: Vec<u8>) -> Result<Vec<u8>, E> {
+                Ok(bytes)
+            }
+        }
+
+        deserializer.deserialize_byte_buf(BytesVisitor)
+    }
+}
+
+/// The error type of parsing and verifying a [`Certificate`].
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum CertificateError {
+    /// There is no data certificate, because the current call is not a query.
+    #[error("no data certificate is available outside of queries")]
+    NoDataCertificate,
+    /// The certificate is not well-formed.
+    #[error("malformed certificate: {0}")]
+    Malformed(String),
+    /// The root key is not a DER-encoded BLS public key.
+    #[error("the root key is not a DER-encoded BLS public key")]
+    InvalidRootKey,
+    /// The delegation is not valid for the certificate.
+    #[error("invalid delegation: {0}")]
+    InvalidDelegation(String),
+    /// The canister is not among those of the subnet the authority is delegated to.
+    #[error("canister {0} is not in the ranges of the delegated subnet")]
+    CanisterNotInRange(Principal),
+    /// The signature does not match the tree and the public key.
+    #[error("the certificate signature is invalid")]
+    InvalidSignature,
+    /// The certificate holds no certified data for the canister.
+    #[error("the certificate holds no certified data for canister {0}")]
+    MissingCertifiedData(Principal),
+    /// The certified data differs from the expected data.
+    #[error("the certified data does not match the expected data")]
+    CertifiedDataMismatch,
+}
+
+fn malformed(message: impl ToString) -> CertificateError {
+    CertificateError::Malformed(message.to_string())
+}
+
+impl<'a> Certificate<'a> {
+    /// Creates a certificate from its parts, e.g. to test how a canister handles certificates.
+    pub fn new(tree: HashTree<'a>, signature: Vec<u8>, delegation: Option<Delegation>) -> Self {
+        Self {
+            tree,
+            signature,
+            delegation,
+        }
+    }
+
+    /// Encodes the certificate in CBOR, as [`api::data_certificate`] returns it.
+    ///
+    /// [`api::data_certificate`]: crate::api::data_certificate
+    pub fn encode(&self) -> Vec<u8> {
+        serde_cbor::to_vec(self).expect("a certificate can be encoded in CBOR")
+    }
+
+    /// Decodes a certificate from its CBOR encoding, as returned by [`api::data_certificate`].
+    ///
+    /// # Errors
+    ///
+    /// Fails if `bytes` is not the CBOR encoding of a certificate.
+    ///
+    /// [`api::data_certificate`]: crate::api::data_certificate
+    pub fn decode(bytes: &'a [u8]) -> Result<Self, CertificateError> {
+        serde_cbor::from_slice(bytes).map_err(malformed)
+    }
+
+    /// Gets the certified state tree.
+    pub fn tree(&self) -> &HashTree<'a> {
+        &self.tree
+    }
+
+    /// Gets the BLS signature of the root hash of the tree.
+    pub fn signature(&self) -> &[u8] {
+        &self.signature
+    }
+
+    /// Gets the delegation of the signing authority, if the certificate is not signed by the root subnet.
+    pub fn delegation(&self) -> Option<&Delegation> {
+        self.delegation.as_ref()
+    }
+
+    /// Gets the time of the certificate, in nanoseconds since the epoch (1970-01-01).
+    ///
+    /// # Errors
+    ///
+    /// Fails if the tree holds no time, or not one encoded as a LEB128 number.
+    pub fn time(&self) -> Result<u64, CertificateError> {
+        let LookupResult::Found(bytes) = lookup(&self.tree, &["time"]) else {
+            return Err(malformed("certificate has no time"));
+        };
+        decode_leb128(bytes).ok_or_else(|| malformed("certificate time is not a LEB128 number"))
+    }
+
+    /// Gets the certified data of a canister, at the path `/canister/<canister_id>/certified_data` of the tree.
+    pub fn certified_data(&self, canister_id: Principal) -> Option<&[u8]> {
+        match lookup(
+            &self.tree,
+            &[
+                b"canister".as_slice(),
+                canister_id.as_slice(),
+                b"certified_data",
+            ],
+        ) {
+            LookupResult::Found(data) => Some(data),
+            _ => None,
+        }
+    }
+
+    /// Verifies that the certificate is signed by the Internet Computer with the given root key, and that it may
+    /// certify the state of `canister_id`.
+    ///
+    /// If the certificate has a delegation, the delegation certificate is verified against the root key, the
+    /// canister must be in the canister ranges of the delegated subnet, and the certificate is verified against the
+    /// public key of the subnet.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the root key is not a DER-encoded BLS key, if the delegation is invalid or does not cover the
+    /// canister, or if the signature is invalid.
+    #[cfg(feature = "verify-bls")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "verify-bls")))]
+    pub fn verify(&self, canister_id: Principal, root_key: &[u8]) -> Result<(), CertificateError> {
+        self.verify_with(canister_id, root_key, verify_bls_signature)
+    }
+
+    /// Verifies the certificate as with `verify`, checking the BLS signatures with `verify_bls` instead.
+    ///
+    /// `verify_bls` takes the signature, the signed message and the 96-byte public key, and returns whether the
+    /// signature is valid.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the root key is not a DER-encoded BLS key, if the delegation is invalid or does not cover the
+    /// canister, or if the signature is invalid.
+    pub fn verify_with(
+        &self,
+        canister_id: Principal,
+        root_key: &[u8],
+        verify_bls: impl Fn(&[u8], &[u8], &[u8]) -> bool,
+    ) -> Result<(), CertificateError> {
+        let key = match &self.delegation {
+            None => extract_der_key(root_key).ok_or(CertificateError::InvalidRootKey)?,
+            Some(delegation) => &delegation.subnet_key(canister_id, root_key, &verify_bls)?,
+        };
+        self.verify_signature(key, &verify_bls)
+    }
+
+    fn verify_signature(
+        &self,
+        key: &[u8],
+        verify_bls: VerifyBls<'_>,
+    ) -> Result<(), CertificateError> {
+        let message = [STATE_ROOT_DOMAIN, &self.tree.reconstruct()].concat();
+        if verify_bls(&self.signature, &message, key) {
+            Ok(())
+        } else {
+            Err(CertificateError::InvalidSignature)
+        }
+    }
+}
+
+impl Delegation {
+    /// Verifies the delegation and gets the public key of the subnet, if the subnet hosts `canister_id`.
+    fn subnet_key(
+        &self,
+        canister_id: Principal,
+        root_key: &[u8],
+        verify_bls: VerifyBls<'_>,
+    ) -> Result<Vec<u8>, CertificateError> {
+        let invalid = |message: &str| CertificateError::InvalidDelegation(message.to_string());
+        let certificate = Certificate::decode(&self.certificate)?;
+        if certificate.delegation.is_some() {
+            return Err(invalid(
+                "the delegation certificate has a delegation of its own",
+            ));
+        }
+        let root_key = extract_der_key(root_key).ok_or(CertificateError::InvalidRootKey)?;
+        certificate
+            .verify_signature(root_key, verify_bls)
+            .map_err(|_| invalid("the delegation certificate signature is invalid"))?;
+
+        let subnet_id = self.subnet_id.as_slice();
+        let LookupResult::Found(ranges) = lookup(
+            &certificate.tree,
+            &[b"subnet".as_slice(), subnet_id, b"canister_ranges"],
+        ) else {
+            return Err(invalid("the subnet has no canister ranges"));
+        };
+        let ranges: Vec<(Principal, Principal)> = serde_cbor::from_slice(ranges)
+            .map_err(|_| invalid("the canister ranges are not a list of pairs of principals"))?;
+        if !ranges
+            .iter()
+            .any(|(low, high)| (low.as_slice()..=high.as_slice()).contains(&canister_id.as_slice()))
+        {
+            return Err(CertificateError::CanisterNotInRange(canister_id));
+        }
+
+        let LookupResult::Found(key) = lookup(
+            &certificate.tree,
+            &[b"subnet".as_slice(), subnet_id, b"public_key"],
+        ) else {
+            return Err(invalid("the subnet has no public key"));
+        };
+        extract_der_key(key)
+            .map(<[u8]>::to_vec)
+            .ok_or_else(|| invalid("the subnet public key is not a DER-encoded BLS key"))
+    }
+}
+
+/// Gets the BLS public key out of its DER encoding.
+fn extract_der_key(der: &[u8]) -> Option<&[u8]> {
+    der.strip_prefix(&DER_PREFIX)
+        .filter(|key| key.len() == KEY_LENGTH)
+}
+
+#[cfg(feature = "verify-bls")]
+fn verify_bls_signature(signature: &[u8], message: &[u8], key: &[u8]) -> bool {
+    ic_verify_bls_signature::verify_bls_signature(signature, message, key).is_ok()
+}
+
+fn decode_leb128(bytes: &[u8]) -> Option<u64> {
+    let mut value = 0u64;
+    for (i, byte) in bytes.iter().enumerate() {
+        let bits = u64::from(byte & 0x7f);
+        let shift = 7 * i as u32;
+        if shift >= 64 || (shift > 0 && bits >> (64 - shift) != 0) {
+            return None;
+        }
+        value |= bits << shift;
+        if byte & 0x80 == 0 {
+            return (i + 1 == bytes.len()).then_some(value);
+        }
+    }
+    None
+}
+
+/// Verifies the data certificate of the current query for this canister, with its certified data equal to
+/// `expected`.
+///
+/// The root key must be captured with [`api::root_key`](crate::api::root_key) beforehand, in a replicated call such
+/// as `#[init]`, since it is not available in queries.
+///
+/// # Errors
+///
+/// Fails with [`CertificateError::NoDataCertificate`] if the current call is not a query, if the certificate cannot be
+/// decoded or verified, or if its certified data is missing or different.
+#[cfg(feature = "verify-bls")]
+#[cfg_attr(docsrs, doc(cfg(feature = "verify-bls")))]
+pub fn verify_data_certificate(expected: &[u8], root_key: &[u8]) -> Result<(), CertificateError> {
+    verify_data_certificate_with(expected, root_key, verify_bls_signature)
+}
+
+/// Verifies the data certificate of the current query as with `verify_data_certificate`, checking the BLS signatures
+/// with `verify_bls` instead, as [`Certificate::verify_with`] does.
+///
+/// # Errors
+///
+/// Fails with [`CertificateError::NoDataCertificate`] if the current call is not a query, if the certificate cannot be
+/// decoded or verified, or if its certified data is missing or different.
+pub fn verify_data_certificate_with(
+    expected: &[u8],
+    root_key: &[u8],
+    verify_bls: impl Fn(&[u8], &[u8], &[u8]) -> bool,
+) -> Result<(), CertificateError> {
+    let bytes = data_certificate().ok_or(CertificateError::NoDataCertificate)?;
+    let certificate = Certificate::decode(&bytes)?;
+    let canister_id = canister_self();
+    certificate.verify_with(canister_id, root_key, verify_bls)?;
+    let data = certificate
+        .certified_data(canister_id)
+        .ok_or(CertificateError::MissingCertifiedData(canister_id))?;
+    if data == expected {
+        Ok(())
+    } else {
+        Err(CertificateError::CertifiedDataMismatch)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ic_certified_map::{fork, labeled};
+    use sha2::{Digest, Sha256};
+
+    fn leaf(value: &[u8]) -> HashTree<'_> {
+        HashTree::Leaf(value.into())
+    }
+
+    /// The example tree of the interface specification.
+    fn example_tree() -> HashTree<'static> {
+        fork(
+            fork(
+                labeled(
+                    b"a",
+                    fork(
+                        fork(labeled(b"x", leaf(b"hello")), HashTree::Empty),
+                        labeled(b"y", leaf(b"world")),
+                    ),
+                ),
+                labeled(b"b", leaf(b"good")),
+            ),
+            fork(
+                labeled(b"c", HashTree::Empty),
+                labeled(b"d", leaf(b"morning")),
+            ),
+        )
+    }
+
+    /// Signs by hashing the message with the key, which the fake verifier checks.
+    fn sign(message: &[u8], key: &[u8]) -> Vec<u8> {
+        Sha256::new()
+            .chain_update(message)
+            .chain_update(key)
+            .finalize()
+            .to_vec()
+    }
+
+    fn fake_verifier(signature: &[u8], message: &[u8], key: &[u8]) -> bool {
+        key.len() == KEY_LENGTH && signature == sign(message, key)
+    }
+
+    fn der(key: &[u8]) -> Vec<u8> {
+        [&DER_PREFIX, key].concat()
+    }
+
+    fn signed<'a>(
+        tree: HashTree<'a>,
+        key: &[u8],
+        delegation: Option<Delegation>,
+    ) -> Certificate<'a> {
+        let signature = sign(&[STATE_ROOT_DOMAIN, &tree.reconstruct()].concat(), key);
+        Certificate::new(tree, signature, delegation)
+    }
+
+    fn state<'a>(canister_id: &'a Principal, certified_data: &'static [u8]) -> HashTree<'a> {
+        fork(
+            labeled(
+                b"canister",
+                labeled(
+                    canister_id.as_slice(),
+                    labeled(b"certified_data", leaf(certified_data)),
+                ),
+            ),
+            labeled(b"time", leaf(&[0xe5, 0x8e, 0x26])),
+        )
+    }
+
+    #[test]
+    fn looks_up_trees() {
+        let tree = example_tree();
+        assert_eq!(
+            hex::encode(tree.reconstruct()),
+            "eb5c5b2195e62d996b84c9bcc8259d19a83786a2f59e0878cec84c811f669aa0"
+        );
+        assert_eq!(lookup(&tree, &["a", "x"]), LookupResult::Found(b"hello"));
+        assert_eq!(lookup(&tree, &["a", "y"]), LookupResult::Found(b"world"));
+        assert_eq!(lookup(&tree, &["d"]), LookupResult::Found(b"morning"));
+        assert_eq!(lookup(&tree, &["c"]), LookupResult::Absent);
+        assert_eq!(lookup(&tree, &["e"]), LookupResult::Absent);
+        assert_eq!(lookup(&tree, &["a"]), LookupResult::Error);
+        assert_eq!(lookup(&tree, &["b", "x"]), LookupResult::Error);
+
+        let HashTree::Fork(children) = tree else {
+            unreachable!()
+        };
+        let (left, right) = *children;
+        let pruned = fork(left, HashTree::Pruned(right.reconstruct()));
+        assert_eq!(pruned.reconstruct(), example_tree().reconstruct());
+        assert_eq!(lookup(&pruned, &["b"]), LookupResult::Found(b"good"));
+        assert_eq!(lookup(&pruned, &["d"]), LookupResult::Unknown);
+    }
+
+    #[test]
+    fn verifies_certificates_signed_by_the_root_key() {
+        let root_key = [1; KEY_LENGTH];
+        let canister_id = Principal::from_slice(&[1, 2, 3]);
+        let bytes = signed(state(&canister_id, b"data"), &root_key, None).encode();
+        // The system prefixes the certificate with the tag of self-described CBOR.
+        let bytes = [[0xd9, 0xd9, 0xf7].as_slice(), &bytes].concat();
+        let certificate = Certificate::decode(&bytes).unwrap();
+        assert_eq!(certificate.time(), Ok(624_485));
+        assert_eq!(certificate.certified_data(canister_id), Some(&b"data"[..]));
+        assert_eq!(
+            certificate.verify_with(canister_id, &der(&root_key), fake_verifier),
+            Ok(())
+        );
+        let other = Principal::from_slice(&[4]);
+        assert_eq!(certificate.certified_data(other), None);
+        assert_eq!(
+            certificate.verify_with(canister_id, &der(&[2; KEY_LENGTH]), fake_verifier),
+            Err(CertificateError::InvalidSignature)
+        );
+        assert_eq!(
+            certificate.verify_with(canister_id, &root_key, fake_verifier),
+            Err(CertificateError::InvalidRootKey)
+        );
+    }
+
+    #[test]
+    fn verifies_delegated_certificates() {
+        let root_key = [1; KEY_LENGTH];
+        let subnet_key = [2; KEY_LENGTH];
+        let subnet_der = der(&subnet_key);
+        let subnet_id = Principal::from_slice(&[9]);
+        let canister_id = Principal::from_slice(&[1, 5]);
+        let ranges = serde_cbor::to_vec(&[(
+            Principal::from_slice(&[1, 0]),
+            Principal::from_slice(&[1, 9]),
+        )])
+        .unwrap();
+        let subnet = labeled(
+            b"subnet",
+            labeled(
+                subnet_id.as_slice(),
+                fork(
+                    labeled(b"canister_ranges", leaf(&ranges)),
+                    labeled(b"public_key", leaf(&subnet_der)),
+                ),
+            ),
+        );
+        let delegation = |key: &[u8]| Delegation {
+            subnet_id,
+            certificate: signed(subnet.clone(), key, None).encode(),
+        };
+
+        let certificate = signed(
+            state(&canister_id, b"data"),
+            &subnet_key,
+            Some(delegation(&root_key)),
+        );
+        let bytes = certificate.encode();
+        let certificate = Certificate::decode(&bytes).unwrap();
+        assert_eq!(certificate.delegation().unwrap().subnet_id, subnet_id);
+        assert_eq!(
+            certificate.verify_with(canister_id, &der(&root_key), fake_verifier),
+            Ok(())
+        );
+        // The subnet does not host the canister.
+        let outside = Principal::from_slice(&[2]);
+        let certificate = signed(
+            state(&outside, b"data"),
+            &subnet_key,
+            Some(delegation(&root_key)),
+        );
+        assert_eq!(
+            certificate.verify_with(outside, &der(&root_key), fake_verifier),
+            Err(CertificateError::CanisterNotInRange(outside))
+        );
+        // The delegation is not signed by the root key.
+        let certificate = signed(
+            state(&canister_id, b"data"),
+            &subnet_key,
+            Some(delegation(&subnet_key)),
+        );
+        assert!(matches!(
+            certificate.verify_with(canister_id, &der(&root_key), fake_verifier),
+            Err(CertificateError::InvalidDelegation(_))
+        ));
+        // The certificate is signed by the root key rather than the subnet key.
+        let certificate = signed(
+            state(&canister_id, b"data"),
+            &root_key,
+            Some(delegation(&root_key)),
+        );
+        assert_eq!(
+            certificate.verify_with(canister_id, &der(&root_key), fake_verifier),
+            Err(CertificateError::InvalidSignature)
+        );
+    }
+
+    #[cfg(feature = "verify-bls")]
+    #[test]
+    fn verifies_bls_signatures() {
+        use ic_verify_bls_signature::PrivateKey;
+
+        let root_key = PrivateKey::deserialize(&[[0; 31].as_slice(), &[7]].concat()).unwrap();
+        let canister_id = Principal::from_slice(&[1, 2, 3]);
+        let tree = state(&canister_id, b"data");
+        let message = [STATE_ROOT_DOMAIN, &tree.reconstruct()].concat();
+        let signature = root_key.sign(&message).serialize().to_vec();
+        let certificate = Certificate::new(tree, signature, None);
+        let public_key = der(&root_key.public_key().serialize());
+        assert_eq!(certificate.verify(canister_id, &public_key), Ok(()));
+
+        let other_key = PrivateKey::deserialize(&[[0; 31].as_slice(), &[8]].concat()).unwrap();
+        assert_eq!(
+            certificate.verify(canister_id, &der(&other_key.public_key().serialize())),
+            Err(CertificateError::InvalidSignature)
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_certificates() {
+        assert!(matches!(
+            Certificate::decode(&[0xa0]),
+            Err(CertificateError::Malformed(_))
+        ));
+        // A pruned hash of a single byte.
+        let tree = serde_cbor::from_slice::<HashTree<'_>>(&[0x82, 0x04, 0x41, 0x00]);
+        assert!(tree.is_err());
+        assert_eq!(decode_leb128(&[0x80]), None);
+        assert_eq!(decode_leb128(&[0x7f, 0x00]), None);
+        assert_eq!(decode_leb128(&[0xff; 10]), None);
+    }
+}
@@ -0,0 +1,337 @@
+//! Charging callers for the cycles a method costs, and budgeting the cycles spent on calls.
+//!
+//! An `#[update]` method charges its callers with the `cycles` attribute, such as `cycles(accept = N)`, which accepts
+//! the cycles attached to the call before the method runs, or rejects the call if too few are attached. See
+//! [`update`](crate::update) for its forms.
+//!
+//! ```rust, no_run
+//! use ic_cdk::cycles::CyclesBudget;
+//! use ic_cdk::update;
+//!
+//! const FEE: u128 = 1_000_000_000;
+//!
+//! #[update(cycles(accept = FEE))]
+//! fn paid() {}
+//!
+//! #[update]
+//! async fn forward() -> Result<(), String> {
+//!     // Keep the fee, and spend the rest of what the caller paid on calls to other canisters.
+//!     let paid = ic_cdk::cycles::charge(2 * FEE, u128::MAX).map_err(|e| e.to_string())?;
+//!     let budget = CyclesBudget::new(paid - FEE);
+//! # let call = ic_cdk::call::Call::bounded_wait(candid::Principal::anonymous(), "work");
+//!     budget.call(call.with_cycles(FEE)).await.map_err(|e| e.to_string())?;
+//!     Ok(())
+//! }
+//! ```
+//!
+//! [`charge`] does the same as the attribute, for methods that decide what to charge while they run or need to know
+//! how many cycles they accepted.
+//!
+//! Before starting a batch of operations that cost cycles, a [`CostPlan`] sums their [estimated costs](CostEstimate)
+//! and checks the total against the liquid cycle balance, so that none of them is started if the canister cannot
+//! afford all of them:
+//!
+//! ```rust, no_run
+//! use ic_cdk::call::Call;
+//! use ic_cdk::cycles::CostPlan;
+//!
+//! # async fn notify_all(subscribers: Vec<candid::Principal>) -> Result<(), String> {
+//! let calls: Vec<_> = subscribers
+//!     .iter()
+//!     .map(|subscriber| Call::bounded_wait(*subscriber, "notify").with_cycles(1_000_000))
+//!     .collect();
+//! if let Err(e) = CostPlan::new().with(&calls[..]).check_balance() {
+//!     return Err(e.to_string());
+//! }
+//! for call in calls {
+//!     call.oneway().map_err(|e| e.to_string())?;
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::api::{
+    SignCostError, canister_liquid_cycle_balance, msg_cycles_accept, msg_cycles_available,
+};
+use crate::call::{Call, CallFailed, InsufficientLiquidCycleBalance, Response, TypedCall};
+use std::cell::Cell;
+use std::future::{Future, IntoFuture};
+use std::pin::pin;
+use thiserror::Error;
+
+thread_local! {
+    /// The cycles refunded to the call being polled by [`CyclesBudget::call`], if any.
+    static REFUNDED: Cell<Option<u128>> = const { Cell::new(None) };
+}
+
+/// The error type of [`charge`] when too few cycles are attached to the call.
+#[derive(Error, Debug, Clone, Copy, PartialEq, Eq)]
+#[error("the call requires {required} cycles, but only {available} are attached")]
+pub struct InsufficientCyclesAttached {
+    /// The cycles attached to the call.
+    pub available: u128,
+    /// The cycles required by the method.
+    pub required: u128,
+}
+
+/// Accepts between `min` and `max` of the cycles attached to the call, as many as are available, and returns how
+/// many were accepted.
+///
+/// # Errors
+///
+/// Fails without accepting any cycles if fewer than `min` are attached.
+pub fn charge(min: u128, max: u128) -> Result<u128, InsufficientCyclesAttached> {
+    let available = msg_cycles_available();
+    if available < min {
+        return Err(InsufficientCyclesAttached {
+            available,
+            required: min,
+        });
+    }
+    Ok(msg_cycles_accept(available.min(max)))
+}
+
+/// The error type of [`CyclesBudget::call`].
+#[derive(Error, Debug, Clone)]
+pub enum BudgetedCallError {
+    /// The call would cost more cycles than remain in the budget. It was not made.
+    #[error("the call costs up to {cost} cycles, but only {remaining} remain in the budget")]
+    BudgetExceeded {
+        /// The cycles attached to the call and its cost.
+        cost: u128,
+        /// The cycles remaining in the budget.
+        remaining: u128,
+    },
+    /// The call failed.
+    #[error(transparent)]
+    CallFailed(#[from] CallFailed),
+}
+
+/// A limit on the cycles spent on calls, such as the cycles a caller paid to be forwarded.
+///
+/// [`call`](Self::call) makes a call if the cycles attached to it and [its cost](Call::get_cost) fit in the budget,
+/// and credits the budget with the cycles refunded with its response. Part of the cost of a call is refunded without
+/// the canister being told, so the cycles counted as spent are an upper bound.
+#[derive(Debug)]
+pub struct CyclesBudget {
+    limit: u128,
+    spent: Cell<u128>,
+}
+
+impl CyclesBudget {
+    /// Creates a budget of `limit` cycles.
+    pub fn new(limit: u128) -> Self {
+        Self {
+            limit,
+            spent: Cell::new(0),
+        }
+    }
+
+    /// Gets the cycles the budget was created with.
+    pub fn limit(&self) -> u128 {
+        self.limit
+    }
+
+    /// Gets the cycles spent on calls so far, including those of calls still in flight.
+    pub fn spent(&self) -> u128 {
+        self.spent.get()
+    }
+
+    /// Gets the cycles remaining in the budget.
+    pub fn remaining(&self) -> u128 {
+        self.limit.saturating_sub(self.spent.get())
+    }
+
+    /// Makes a call within the budget.
+    ///
+    /// Calls can be made concurrently, each reserving its cost in the budget until its response arrives.
+    ///
+    /// # Errors
+    ///
+    /// Fails with [`BudgetedCallError::BudgetExceeded`] without making the call if its cost exceeds the remaining
+    /// budget, or with [`BudgetedCallError::CallFailed`] if the call fails.
+    pub async fn call(&self, call: Call<'_, '_>) -> Result<Response, BudgetedCallError> {
+        let cost = call.get_cost();
+        let remaining = self.remaining();
+        if cost > remaining {
+            return Err(BudgetedCallError::BudgetExceeded { cost, remaining });
+        }
+        self.spent.set(self.spent.get() + cost);
+        let mut future = pin!(call.into_future());
+        let result = std::future::poll_fn(|context| {
+            let outer = REFUNDED.replace(Some(0));
+            let poll = future.as_mut().poll(context);
+            let refunded = REFUNDED.replace(outer).unwrap_or_default();
+            self.spent.set(self.spent.get().saturating_sub(refunded));
+            poll
+        })
+        .await;
+        if let Err(
+            CallFailed::InsufficientLiquidCycleBalance(_) | CallFailed::CallPerformFailed(_),
+        ) = result
+        {
+            // The call was not made, so none of its cost was spent.
+            self.spent.set(self.spent.get().saturating_sub(cost));
+        }
+        Ok(result?)
+    }
+}
+
+/// An operation that costs cycles, such as a call, whose cost can be estimated before it starts.
+///
+/// It is implemented by [`Call`] and [`TypedCall`], by an amount of cycles, and by the result of the `cost_*` functions
+/// of [`api`](crate::api), such as [`cost_sign_with_ecdsa`](crate::api::cost_sign_with_ecdsa). The
+/// `ic-cdk-management-canister` and `ic-cdk-bitcoin-canister` crates implement it for the requests they make.
+pub trait CostEstimate {
+    /// Estimates the cycles the operation costs, including the cycles it attaches to calls.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the cost cannot be determined, e.g. because a signature uses an unknown key.
+    fn estimate_cost(&self) -> Result<u128, CostEstimateError>;
+}
+
+/// The error type of [`CostEstimate::estimate_cost`].
+#[derive(Error, Debug, Clone)]
+pub enum CostEstimateError {
+    /// The cost of a signature or a key derivation cannot be determined.
+    #[error(transparent)]
+    SignCostError(#[from] SignCostError),
+}
+
+impl CostEstimate for Call<'_, '_> {
+    /// The cycles attached to the call and [its cost](Call::get_cost).
+    fn estimate_cost(&self) -> Result<u128, CostEstimateError> {
+        Ok(self.get_cost())
+    }
+}
+
+impl<Args, Ret> CostEstimate for TypedCall<'_, '_, Args, Ret> {
+    /// The cycles attached to the call and [its cost](TypedCall::get_cost).
+    fn estimate_cost(&self) -> Result<u128, CostEstimateError> {
+        Ok(self.get_cost())
+    }
+}
+
+impl CostEstimate for u128 {
+    fn estimate_cost(&self) -> Result<u128, CostEstimateError> {
+        Ok(*self)
+    }
+}
+
+impl CostEstimate for Result<u128, SignCostError> {
+    fn estimate_cost(&self) -> Result<u128, CostEstimateError> {
+        Ok(self.clone()?)
+    }
+}
+
+impl<T: CostEstimate + ?Sized> CostEstimate for &T {
+    fn estimate_cost(&self) -> Result<u128, CostEstimateError> {
+        (**self).estimate_cost()
+    }
+}
+
+impl<T: CostEstimate> CostEstimate for [T] {
+    /// The summed costs of the operations.
+    fn estimate_cost(&self) -> Result<u128, CostEstimateError> {
+        self.iter().try_fold(0, |total: u128, operation| {
+            Ok(total.saturating_add(operation.estimate_cost()?))
+        })
+    }
+}
+
+/// The error type of [`CostPlan::total`] and [`CostPlan::check_balance`].
+#[derive(Error, Debug, Clone)]
+pub enum CostPlanError {
+    /// The cost of an operation of the plan cannot be estimated.
+    #[error("the cost of operation {index} of the plan cannot be estimated: {source}")]
+    CostUnavailable {
+        /// The position of the operation in the plan.
+        index: usize,
+        /// The reason the cost cannot be estimated.
+        source: CostEstimateError,
+    },
+    /// The liquid cycle balance is less than the total cost of the plan.
+    #[error(transparent)]
+    InsufficientLiquidCycleBalance(#[from] InsufficientLiquidCycleBalance),
+}
+
+/// The operations planned by a method, to be checked against the liquid cycle balance before any of them starts.
+///
+/// Operations are added with [`with`](Self::with) or [`add`](Self::add), in the order they are planned, and their
+/// costs are estimated as they are added. The estimates are an upper bound: part of the cost of a call is refunded
+/// with its response.
+#[derive(Debug, Clone, Default)]
+pub struct CostPlan {
+    costs: Vec<Result<u128, CostEstimateError>>,
+}
+
+impl CostPlan {
+    /// Creates an empty plan.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds an operation to the plan.
+    #[must_use]
+    pub fn with(mut self, operation: &(impl CostEstimate + ?Sized)) -> Self {
+        self.add(operation);
+        self
+    }
+
+    /// Adds an operation to the plan.
+    pub fn add(&mut self, operation: &(impl CostEstimate + ?Sized)) -> &mut Self {
+        self.costs.push(operation.estimate_cost());
+        self
+    }
+
+    /// Gets the estimated cost of each operation, in the order they were added.
+    pub fn costs(&self) -> &[Result<u128, CostEstimateError>] {
+        &self.costs
+    }
+
+    /// Gets the total cost of the plan.
+    ///
+    /// # Errors
+    ///
+    /// Fails with [`CostPlanError::CostUnavailable`] if the cost of an operation cannot be estimated.
+    pub fn total(&self) -> Result<u128, CostPlanError> {
+        self.costs
+            .iter()
+            .enumerate()
+            .try_fold(0, |total: u128, (index, cost)| match cost {
+                Ok(cost) => Ok(total.saturating_add(*cost)),
+                Err(source) => Err(CostPlanError::CostUnavailable {
+                    index,
+                    source: source.clone(),
+                }),
+            })
+    }
+
+    /// Checks that the liquid cycle balance covers the total cost of the plan, and returns the total.
+    ///
+    /// # Errors
+    ///
+    /// Fails with [`CostPlanError::CostUnavailable`] if the cost of an operation cannot be estimated, or with
+    /// [`CostPlanError::InsufficientLiquidCycleBalance`], which tells how many cycles are missing, if the canister
+    /// cannot afford the plan.
+    pub fn check_balance(&self) -> Result<u128, CostPlanError> {
+        let required = self.total()?;
+        let available = canister_liquid_cycle_balance();
+        if available < required {
+            return Err(InsufficientLiquidCycleBalance {
+                available,
+                required,
+            }
+            .into());
+        }
+        Ok(required)
+    }
+}
+
+/// Records the cycles refunded with the response to a call, for the budget polling it.
+pub(crate) fn record_refund(cycles: u128) {
+    if let Some(refunded) = REFUNDED.get() {
+        REFUNDED.set(Some(refunded.saturating_add(cycles)));
+    }
+}
@@ -0,0 +1,317 @@
+//! Typed configuration loaded from the environment variables of the canister.
+//!
+//! The controllers of a canister can set environment variables for it, which [`api::env_var_value`] returns as
+//! strings. Deriving [`EnvConfig`](trait@EnvConfig) for a struct loads each field from a variable, parsed with
+//! [`FromEnvVar`](trait@FromEnvVar):
+//!
+//! ```rust, no_run
+//! use candid::Principal;
+//! use ic_cdk::env_config::{EnvConfig, FromEnvVar};
+//! use std::time::Duration;
+//!
+//! #[derive(FromEnvVar)]
+//! enum Network {
+//!     Mainnet,
+//!     #[env(rename = "local")]
+//!     Testnet,
+//! }
+//!
+//! #[derive(EnvConfig)]
+//! #[env(prefix = "APP_")]
+//! struct Config {
+//!     /// Loaded from `APP_LEDGER`, e.g. `ryjl3-tyaaa-aaaaa-aaaba-cai`.
+//!     ledger: Principal,
+//!     /// Loaded from `NETWORK`, e.g. `mainnet`.
+//!     #[env(name = "NETWORK")]
+//!     network: Network,
+//!     /// Loaded from `APP_MAX_BATCH`, or 0 if it is not set.
+//!     #[env(default)]
+//!     max_batch: u32,
+//!     /// Loaded from `APP_TIMEOUT`, e.g. `1500ms`, or 30 seconds if it is not set.
+//!     #[env(default = "30s")]
+//!     timeout: Duration,
+//!     /// Loaded from `APP_ADMIN`, or `None` if it is not set.
+//!     admin: Option<Principal>,
+//! }
+//!
+//! # fn f() {
+//! let timeout = Config::get().timeout;
+//! # }
+//! ```
+//!
+//! The name of a variable is the name of the field in uppercase, after the `prefix` of the struct if it has one,
+//! unless it is set with `#[env(name = "...")]`. A variable must be set, unless the field has a default or an
+//! `Option` type. The variants of an enum deriving [`FromEnvVar`](derive@FromEnvVar) are selected by their name or
+//! their `rename`, case-insensitively.
+//!
+//! [`EnvConfig::get`] loads the configuration on first use and caches it on the heap.
+//!
+//! [`api::env_var_value`]: crate::api::env_var_value
+
+use crate::api::{env_var_name_exists, env_var_value};
+use crate::trap;
+use candid::Principal;
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::thread::LocalKey;
+use std::time::Duration;
+use thiserror::Error;
+
+/// Derives [`trait@EnvConfig`] for a struct with named fields, each implementing [`trait@FromEnvVar`].
+///
+/// See the [module documentation](self) for the attributes.
+pub use ic_cdk_macros::EnvConfig;
+
+/// Derives [`trait@FromEnvVar`] for an enum with unit variants.
+///
+/// See the [module documentation](self) for the attributes.
+pub use ic_cdk_macros::FromEnvVar;
+
+/// The error type of loading an [`EnvConfig`](trait@EnvConfig).
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum EnvConfigError {
+    /// A required variable is not set.
+    #[error("environment variable `{0}` is not set")]
+    Missing(String),
+    /// A variable could not be parsed.
+    #[error("environment variable `{name}` has an invalid value `{value}`: {reason}")]
+    Invalid {
+        /// The name of the variable.
+        name: String,
+        /// The value of the variable.
+        value: String,
+        /// Why the value is invalid.
+        reason: String,
+    },
+}
+
+/// A configuration loaded from the environment variables of the canister, usually derived.
+pub trait EnvConfig: Sized + 'static {
+    /// Loads the configuration from the environment variables.
+    ///
+    /// # Errors
+    ///
+    /// Fails if a required variable is not set or a variable cannot be parsed.
+    fn load() -> Result<Self, EnvConfigError>;
+
+    /// Gets the cached configuration, loading it on first use.
+    ///
+    /// # Errors
+    ///
+    /// Fails as [`load`](Self::load) does, in which case nothing is cached and the next call loads it again.
+    fn try_get() -> Result<Rc<Self>, EnvConfigError> {
+        if let Some(config) = Self::__cache().with_borrow(Option::clone) {
+            return Ok(config);
+        }
+        let config = Rc::new(Self::load()?);
+        Self::__cache().set(Some(config.clone()));
+        Ok(config)
+    }
+
+    /// Gets the cached configuration, loading it on first use.
+    ///
+    /// # Traps
+    ///
+    /// This function traps if the configuration cannot be loaded, naming the variable at fault.
+    fn get() -> Rc<Self> {
+        Self::try_get().unwrap_or_else(|e| trap(e.to_string()))
+    }
+
+    /// Loads the configuration again, replacing the cached one if it succeeds.
+    ///
+    /// The cache lives on the heap, so it is cleared when the canister is upgraded. A canister whose variables are
+    /// changed without an upgrade picks up the change only when it calls this function.
+    ///
+    /// # Errors
+    ///
+    /// Fails as [`load`](Self::load) does, in which case the cached configuration is kept.
+    fn reload() -> Result<Rc<Self>, EnvConfigError> {
+        let config = Rc::new(Self::load()?);
+        Self::__cache().set(Some(config.clone()));
+        Ok(config)
+    }
+
+    #[doc(hidden)]
+    fn __cache() -> &'static LocalKey<RefCell<Option<Rc<Self>>>>;
+}
+
+/// A type that can be parsed from the value of an environment variable.
+pub trait FromEnvVar: Sized {
+    /// Parses the value of a variable, or describes why it is invalid.
+    fn from_env_var(value: &str) -> Result<Self, String>;
+
+    /// The value of a variable that is not set, or `None` if it must be set.
+    fn missing() -> Option<Self> {
+        None
+    }
+}
+
+/// Gets and parses a variable, or `None` if it is not set.
+///
+/// # Errors
+///
+/// Fails if the variable cannot be parsed.
+pub fn try_var<T: FromEnvVar>(name: &str) -> Result<Option<T>, EnvConfigError> {
+    if !env_var_name_exists(name) {
+        return Ok(None);
+    }
+    parse(name, &env_var_value(name)).map(Some)
+}
+
+/// Gets and parses a variable, which must be set unless `T` has a value for variables that are not set, like
+/// `Option`.
+///
+/// # Errors
+///
+/// Fails if the variable is required but not set, or cannot be parsed.
+pub fn var<T: FromEnvVar>(name: &str) -> Result<T, EnvConfigError> {
+    match try_var(name)? {
+        Some(value) => Ok(value),
+        None => T::missing().ok_or_else(|| EnvConfigError::Missing(name.to_string())),
+    }
+}
+
+/// Parses `value` as the value of the variable `name`.
+///
+/// # Errors
+///
+/// Fails with [`EnvConfigError::Invalid`] if the value cannot be parsed.
+pub fn parse<T: FromEnvVar>(name: &str, value: &str) -> Result<T, EnvConfigError> {
+    T::from_env_var(value).map_err(|reason| EnvConfigError::Invalid {
+        name: name.to_string(),
+        value: value.to_string(),
+        reason,
+    })
+}
+
+impl<T: FromEnvVar> FromEnvVar for Option<T> {
+    fn from_env_var(value: &str) -> Result<Self, String> {
+        T::from_env_var(value).map(Some)
+    }
+
+    fn missing() -> Option<Self> {
+        Some(None)
+    }
+}
+
+/// A comma-separated list. An empty value is an empty list.
+impl<T: FromEnvVar> FromEnvVar for Vec<T> {
+    fn from_env_var(value: &str) -> Result<Self, String> {
+        if value.trim().is_empty() {
+            return Ok(Vec::new());
+        }
+        value
+            .split(',')
+            .map(|item| T::from_env_var(item.trim()))
+            .collect()
+    }
+}
+
+impl FromEnvVar for String {
+    fn from_env_var(value: &str) -> Result<Self, String> {
+        Ok(value.to_string())
+    }
+}
+
+/// `true`, `false`, `1` or `0`, case-insensitively.
+impl FromEnvVar for bool {
+    fn from_env_var(value: &str) -> Result<Self, String> {
+        match value.to_lowercase().as_str() {
+            "true" | "1" => Ok(true),
+            "false" | "0" => Ok(false),
+            _ => Err("expected `true` or `false`".to_string()),
+        }
+    }
+}
+
+macro_rules! impl_from_env_var_for_integers {
+    ($($t:ty),*) => {$(
+        /// A decimal integer, optionally with `_` separators, e.g. `1_000_000`.
+        impl FromEnvVar for $t {
+            fn from_env_var(value: &str) -> Result<Self, String> {
+                value.replace('_', "").parse().map_err(|e| format!("{e}"))
+            }
+        }
+    )*};
+}
+
+impl_from_env_var_for_integers!(
+    u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize
+);
+
+/// The textual representation of a principal, e.g. `aaaaa-aa`.
+impl FromEnvVar for Principal {
+    fn from_env_var(value: &str) -> Result<Self, String> {
+        Principal::from_text(value).map_err(|e| e.to_string())
+    }
+}
+
+/// An integer followed by a unit: `ns`, `us`, `ms`, `s`, `m`, `h` or `d`, e.g. `1500ms` or `30s`.
+impl FromEnvVar for Duration {
+    fn from_env_var(value: &str) -> Result<Self, String> {
+        let split = value
+            .find(|c: char| !c.is_ascii_digit() && c != '_')
+            .ok_or("expected a unit: ns, us, ms, s, m, h or d")?;
+        let (amount, unit) = value.split_at(split);
+        let amount = u64::from_env_var(amount)?;
+        let seconds = |factor: u64| {
+            amount
+                .checked_mul(factor)
+                .map(Duration::from_secs)
+                .ok_or_else(|| "duration is too long".to_string())
+        };
+        match unit.trim() {
+            "ns" => Ok(Duration::from_nanos(amount)),
+            "us" => Ok(Duration::from_micros(amount)),
+            "ms" => Ok(Duration::from_millis(amount)),
+            "s" => seconds(1),
+            "m" => seconds(60),
+            "h" => seconds(60 * 60),
+            "d" => seconds(24 * 60 * 60),
+            unit => Err(format!(
+                "unknown unit `{unit}`, expected ns, us, ms, s, m, h or d"
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_values() {
+        assert_eq!(u64::from_env_var("1_000_000"), Ok(1_000_000));
+        assert!(u8::from_env_var("256").is_err());
+        assert_eq!(i32::from_env_var("-5"), Ok(-5));
+        assert_eq!(bool::from_env_var("TRUE"), Ok(true));
+        assert_eq!(bool::from_env_var("0"), Ok(false));
+        assert!(bool::from_env_var("yes").is_err());
+        assert_eq!(
+            Principal::from_env_var("aaaaa-aa"),
+            Ok(Principal::management_canister())
+        );
+        assert_eq!(Vec::<u32>::from_env_var("1, 2,3"), Ok(vec![1, 2, 3]));
+        assert_eq!(Vec::<u32>::from_env_var(""), Ok(vec![]));
+        assert_eq!(Option::<u32>::missing(), Some(None));
+        assert_eq!(u32::missing(), None);
+    }
+
+    #[test]
+    fn parses_durations() {
+        assert_eq!(
+            Duration::from_env_var("1500ms"),
+            Ok(Duration::from_millis(1500))
+        );
+        assert_eq!(Duration::from_env_var("30s"), Ok(Duration::from_secs(30)));
+        assert_eq!(Duration::from_env_var("2h"), Ok(Duration::from_secs(7200)));
+        assert_eq!(
+            Duration::from_env_var("1d"),
+            Ok(Duration::from_secs(86_400))
+        );
+        assert!(Duration::from_env_var("30").is_err());
+        assert!(Duration::from_env_var("s").is_err());
+        assert!(Duration::from_env_var("3w").is_err());
+        assert!(Duration::from_env_var(&format!("{}d", u64::MAX)).is_err());
+    }
+}
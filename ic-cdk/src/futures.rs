@@ -80,6 +80,7 @@
 //! [`in_replicated_execution`]: crate::api::in_replicated_execution
 //! [`canister_self`]: crate::api::canister_self
 
+use crate::api::Deadline;
 use std::{
     future::Future,
     pin::Pin,
@@ -95,6 +96,9 @@ pub mod internals;
 /// Spawn a protected asynchronous task to run during the current canister method.
 ///
 /// The task will panic if it outlives the canister method. To cancel it instead, use [`spawn_weak`].
+///
+/// Within a task, the spawned task inherits its [`Deadline`], as do the tasks spawned by [`spawn_weak`] and
+/// [`spawn_migratory`].
 pub fn spawn<F: 'static + Future<Output = ()>>(future: F) {
     pin_project_lite::pin_project! {
         struct ProtectedTask<F> {
@@ -127,7 +131,7 @@ pub fn spawn<F: 'static + Future<Output = ()>>(future: F) {
         }
     }
     ic_cdk_executor::spawn_protected(ProtectedTask {
-        future,
+        future: Deadline::scope(Deadline::task(), future),
         completed: false,
     });
 }
@@ -136,12 +140,12 @@ pub fn spawn<F: 'static + Future<Output = ()>>(future: F) {
 ///
 /// If the task outlives the canister method, it will be dropped.
 pub fn spawn_weak<F: 'static + Future<Output = ()>>(future: F) {
-    ic_cdk_executor::spawn_protected(future);
+    ic_cdk_executor::spawn_protected(Deadline::scope(Deadline::task(), future));
 }
 
 /// Spawn an asynchronous task that can outlive the current canister method.
 pub fn spawn_migratory<F: 'static + Future<Output = ()>>(future: F) {
-    ic_cdk_executor::spawn_migratory(future);
+    ic_cdk_executor::spawn_migratory(Deadline::scope(Deadline::task(), future));
 }
 
 /// Tells you whether the current async fn is being canceled due to a trap/panic.
@@ -2,6 +2,8 @@
 //!
 //! You do not need to use this module unless you are deliberately avoiding the attribute macros.
 
+use std::future::Future;
+
 /// Execute an update function in a context that allows calling [`spawn`](super::spawn).
 ///
 /// You do not need to worry about this function unless you are avoiding the attribute macros.
@@ -21,3 +23,13 @@ pub fn in_executor_context<R>(f: impl FnOnce() -> R) -> R {
 pub fn in_query_executor_context<R>(f: impl FnOnce() -> R) -> R {
     ic_cdk_executor::in_tracking_query_executor_context(f)
 }
+
+/// Makes the deadline of the current message the deadline of the task running `future`, across its awaits.
+///
+/// You do not need to worry about this function unless you are avoiding the attribute macros, which call it for
+/// `async` update and query methods. It calls [`msg_deadline`](crate::api::msg_deadline), so it must not be called in
+/// the lifecycle methods.
+pub fn with_message_deadline<F: Future>(future: F) -> impl Future<Output = F::Output> {
+    let deadline = crate::api::Deadline::current();
+    crate::api::Deadline::scope(Some(deadline), future)
+}
@@ -0,0 +1,129 @@
+//! Graceful shutdown: observing when the canister is stopping, and draining its outstanding calls.
+//!
+//! A canister cannot be stopped, and thus upgraded safely, while it has open call contexts, which includes calls it
+//! made that have not received a response yet. A canister that keeps making calls, from a long-running task or an
+//! interval timer, can keep `stop_canister` waiting indefinitely. This module lets such code notice that the canister
+//! is shutting down and stop making new calls:
+//!
+//! - [`is_draining`] is true while the canister is [`Stopping`](CanisterStatusCode::Stopping), or after
+//!   [`begin_drain`] was called, e.g. by an admin preparing an upgrade, until [`end_drain`] is called or the canister is
+//!   upgraded.
+//! - While the canister is draining, every `#[update]` method rejects its calls before running its guards, unless it
+//!   has the `allow_while_draining` attribute. [`reject_while_draining`] is the same check, to be used as a guard of
+//!   queries and other entry points.
+//! - [`outstanding_calls`] counts the calls made by the canister that are waiting for their response, and [`drained`]
+//!   waits until there are none.
+//!
+//! ```rust, no_run
+//! use ic_cdk::{lifecycle, update};
+//!
+//! fn poll_prices() {
+//!     // Do not start new calls once the canister is draining.
+//!     if lifecycle::is_draining() {
+//!         return;
+//!     }
+//!     // ...
+//! }
+//!
+//! #[update(guard = "ic_cdk::access_control::controllers_only", allow_while_draining)]
+//! fn prepare_upgrade() {
+//!     lifecycle::begin_drain();
+//!     ic_cdk::futures::spawn_migratory(async {
+//!         lifecycle::drained().await;
+//!         ic_cdk::println!("all outstanding calls completed, ready to stop");
+//!     });
+//! }
+//! ```
+//!
+//! One-way calls do not wait for a response, and are not counted.
+
+use crate::api::{CanisterStatusCode, canister_status};
+use std::cell::{Cell, RefCell};
+use std::future::poll_fn;
+use std::task::{Poll, Waker};
+
+thread_local! {
+    static DRAINING: Cell<bool> = const { Cell::new(false) };
+    static OUTSTANDING_CALLS: Cell<usize> = const { Cell::new(0) };
+    /// The tasks waiting in [`drained`].
+    static DRAIN_WAITERS: RefCell<Vec<Waker>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Checks whether the canister is stopping, as reported by [`canister_status`].
+pub fn is_stopping() -> bool {
+    canister_status() == CanisterStatusCode::Stopping
+}
+
+/// Checks whether the canister is stopping or [`begin_drain`] was called.
+///
+/// Long-running tasks and timers should not make new calls while the canister is draining.
+pub fn is_draining() -> bool {
+    DRAINING.get() || is_stopping()
+}
+
+/// Starts draining the canister: `#[update]` methods reject new calls, and [`is_draining`] is true.
+///
+/// The canister drains until [`end_drain`] is called or it is upgraded.
+pub fn begin_drain() {
+    DRAINING.set(true);
+}
+
+/// Stops draining the canister, unless it is stopping.
+pub fn end_drain() {
+    DRAINING.set(false);
+}
+
+/// Gets the number of calls made by the canister that are waiting for their response.
+pub fn outstanding_calls() -> usize {
+    OUTSTANDING_CALLS.get()
+}
+
+/// Waits until the canister has no [outstanding calls](outstanding_calls).
+///
+/// The future completes in the callback of the last outstanding call, which is not a call made by the task awaiting
+/// it, so it should be awaited by a task spawned with [`spawn_migratory`](crate::futures::spawn_migratory).
+pub async fn drained() {
+    poll_fn(|context| {
+        if OUTSTANDING_CALLS.get() == 0 {
+            Poll::Ready(())
+        } else {
+            DRAIN_WAITERS.with_borrow_mut(|waiters| {
+                if !waiters
+                    .iter()
+                    .any(|waiter| waiter.will_wake(context.waker()))
+                {
+                    waiters.push(context.waker().clone());
+                }
+            });
+            Poll::Pending
+        }
+    })
+    .await
+}
+
+/// A guard rejecting calls while the canister [is draining](is_draining).
+///
+/// `#[update]` methods check this before their own guards, unless they have the `allow_while_draining` attribute.
+pub fn reject_while_draining() -> Result<(), String> {
+    if is_draining() {
+        Err("the canister is shutting down and does not accept new calls".to_string())
+    } else {
+        Ok(())
+    }
+}
+
+/// Records that a call was made and is waiting for its response.
+pub(crate) fn call_started() {
+    OUTSTANDING_CALLS.set(OUTSTANDING_CALLS.get() + 1);
+}
+
+/// Records that the response to a call was received, waking the tasks waiting in [`drained`] if it was the last.
+pub(crate) fn call_finished() {
+    let outstanding = OUTSTANDING_CALLS.get().saturating_sub(1);
+    OUTSTANDING_CALLS.set(outstanding);
+    if outstanding == 0 {
+        for waiter in DRAIN_WAITERS.take() {
+            waiter.wake();
+        }
+    }
+}
@@ -0,0 +1,662 @@
+//! A logger for the [`log`] crate, keeping recent entries in the canister.
+//!
+//! [`CanisterLogger`] routes the records of the `log` macros to [`debug_print`](crate::api::debug_print), like
+//! [`println!`](crate::println), and keeps the most recent ones in a bounded buffer on the heap, so that they can be
+//! read after the fact with [`get_logs`] or the query exported by [`export_log_query!`](crate::export_log_query).
+//! The buffer can also be mirrored to a region of stable memory, so that the entries survive upgrades.
+//!
+//! # Example
+//!
+//! ```rust, no_run
+//! use ic_cdk::logging::CanisterLogger;
+//! use ic_cdk::stable::CanisterStableMemory;
+//! use log::LevelFilter;
+//!
+//! fn init_logger() {
+//!     CanisterLogger::new()
+//!         .with_level(LevelFilter::Info)
+//!         .with_module_level("my_canister::ledger", LevelFilter::Debug)
+//!         .with_capacity(1_000)
+//!         // The first MiB of stable memory holds the log.
+//!         .with_stable_memory(CanisterStableMemory::default(), 0, 1 << 20)
+//!         .init()
+//!         .expect("failed to initialize the logger");
+//! }
+//!
+//! #[ic_cdk::init]
+//! fn init() {
+//!     init_logger();
+//!     log::info!("installed");
+//! }
+//!
+//! #[ic_cdk::post_upgrade]
+//! fn post_upgrade() {
+//!     // Restores the entries logged before the upgrade.
+//!     init_logger();
+//! }
+//!
+//! fn is_controller() -> Result<(), String> {
+//!     if ic_cdk::api::is_controller(&ic_cdk::api::msg_caller()) {
+//!         Ok(())
+//!     } else {
+//!         Err("only controllers can read the log".to_string())
+//!     }
+//! }
+//!
+//! ic_cdk::export_log_query!(guard = "is_controller");
+//! ```
+
+use crate::api::{debug_print, time};
+use crate::stable::{StableMemory, StableMemoryError, WASM_PAGE_SIZE_IN_BYTES};
+use candid::{CandidType, Deserialize};
+use log::{LevelFilter, Log, Metadata, Record};
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+use thiserror::Error;
+
+/// The number of entries [`get_logs`] returns when the request does not limit them.
+pub const DEFAULT_PAGE_SIZE: u32 = 100;
+
+/// The most bytes of messages and targets [`get_logs`] returns at once, leaving room within the response size limit.
+const MAX_PAGE_BYTES: usize = 1_500_000;
+
+thread_local! {
+    static STATE: RefCell<Option<State>> = const { RefCell::new(None) };
+}
+
+/// Whether the forwarding logger has been installed with [`log::set_logger`], which can only be done once.
+static INSTALLED: AtomicBool = AtomicBool::new(false);
+
+/// The severity of a [`LogEntry`].
+#[derive(CandidType, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum LogLevel {
+    /// See [`log::Level::Error`].
+    Error,
+    /// See [`log::Level::Warn`].
+    Warn,
+    /// See [`log::Level::Info`].
+    Info,
+    /// See [`log::Level::Debug`].
+    Debug,
+    /// See [`log::Level::Trace`].
+    Trace,
+}
+
+impl From<log::Level> for LogLevel {
+    fn from(level: log::Level) -> Self {
+        match level {
+            log::Level::Error => Self::Error,
+            log::Level::Warn => Self::Warn,
+            log::Level::Info => Self::Info,
+            log::Level::Debug => Self::Debug,
+            log::Level::Trace => Self::Trace,
+        }
+    }
+}
+
+impl From<LogLevel> for log::Level {
+    fn from(level: LogLevel) -> Self {
+        match level {
+            LogLevel::Error => Self::Error,
+            LogLevel::Warn => Self::Warn,
+            LogLevel::Info => Self::Info,
+            LogLevel::Debug => Self::Debug,
+            LogLevel::Trace => Self::Trace,
+        }
+    }
+}
+
+/// A logged record.
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct LogEntry {
+    /// The position of the entry among all entries logged, starting from 0.
+    pub index: u64,
+    /// The time of the entry, in nanoseconds since the epoch (1970-01-01).
+    pub timestamp: u64,
+    /// The severity of the entry.
+    pub level: LogLevel,
+    /// The target of the record, by default the module path of the code that logged it.
+    pub target: String,
+    /// The formatted message.
+    pub message: String,
+}
+
+/// The argument of [`get_logs`].
+#[derive(CandidType, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct LogRequest {
+    /// Only returns entries older than the entry with this index, to get the page after [`LogPage::next`].
+    pub before: Option<u64>,
+    /// The most entries to return, [`DEFAULT_PAGE_SIZE`] if `None`.
+    pub max_entries: Option<u32>,
+    /// Only returns entries at least as severe as this level.
+    pub min_level: Option<LogLevel>,
+}
+
+/// A page of entries, returned by [`get_logs`].
+#[derive(CandidType, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct LogPage {
+    /// The entries, newest first.
+    pub entries: Vec<LogEntry>,
+    /// The `before` of the request for the next page of older entries, or `None` if there are no more.
+    pub next: Option<u64>,
+}
+
+/// The error type of [`CanisterLogger::init`].
+#[derive(Error, Debug)]
+pub enum LoggerError {
+    /// Another logger has been set for the `log` crate.
+    #[error("another logger is already set")]
+    SetLoggerFailed,
+    /// The stable memory region could not be allocated.
+    #[error("failed to allocate the stable memory of the log: {0}")]
+    StableMemory(#[from] StableMemoryError),
+    /// The stable memory region is too small to hold any entry.
+    #[error("the stable memory region of the log must be larger than its 32-byte header")]
+    RegionTooSmall,
+}
+
+/// A logger for the [`log`] crate, configured with the `with_*` methods and installed with
+/// [`init`](Self::init).
+pub struct CanisterLogger {
+    level: LevelFilter,
+    module_levels: Vec<(String, LevelFilter)>,
+    capacity: usize,
+    debug_print: bool,
+    stable: Option<StableRing>,
+}
+
+impl std::fmt::Debug for CanisterLogger {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CanisterLogger")
+            .field("level", &self.level)
+            .field("module_levels", &self.module_levels)
+            .field("capacity", &self.capacity)
+            .field("debug_print", &self.debug_print)
+            .field("stable", &self.stable.is_some())
+            .finish()
+    }
+}
+
+impl Default for CanisterLogger {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CanisterLogger {
+    /// Creates a logger keeping the 1000 most recent entries at level `Info` or more severe, and printing them
+    /// with [`debug_print`](crate::api::debug_print).
+    pub fn new() -> Self {
+        Self {
+            level: LevelFilter::Info,
+            module_levels: Vec::new(),
+            capacity: 1_000,
+            debug_print: true,
+            stable: None,
+        }
+    }
+
+    /// Sets the least severe level logged, unless overridden for a module.
+    pub fn with_level(mut self, level: LevelFilter) -> Self {
+        self.level = level;
+        self
+    }
+
+    /// Sets the least severe level logged by the targets in a module, such as `my_canister::ledger`.
+    ///
+    /// The level applies to the module and its submodules. When several modules match, the most specific one wins.
+    pub fn with_module_level(mut self, module: impl Into<String>, level: LevelFilter) -> Self {
+        self.module_levels.push((module.into(), level));
+        self
+    }
+
+    /// Sets the number of entries kept on the heap, dropping the oldest ones beyond it.
+    pub fn with_capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity;
+        self
+    }
+
+    /// Sets whether entries are printed with [`debug_print`](crate::api::debug_print).
+    pub fn with_debug_print(mut self, debug_print: bool) -> Self {
+        self.debug_print = debug_print;
+        self
+    }
+
+    /// Mirrors the entries to `size` bytes of stable memory starting at `offset`, dropping the oldest ones when
+    /// it is full.
+    ///
+    /// The region must not be used for anything else. When the logger is initialized with the same region after an
+    /// upgrade, the entries it holds are restored. An entry too large for the region is only kept on the heap.
+    pub fn with_stable_memory(
+        mut self,
+        memory: impl StableMemory + 'static,
+        offset: u64,
+        size: u64,
+    ) -> Self {
+        self.stable = Some(StableRing {
+            memory: Box::new(memory),
+            start: offset,
+            capacity: size.saturating_sub(HEADER_LEN),
+            head: 0,
+            used: 0,
+        });
+        self
+    }
+
+    /// Installs the logger for the `log` crate, replacing the configuration of a previous `CanisterLogger`.
+    ///
+    /// If the logger mirrors its entries to stable memory, the region is allocated, and the entries it holds are
+    /// restored to the heap.
+    ///
+    /// # Errors
+    ///
+    /// Fails if a logger other than a `CanisterLogger` is set, or the stable memory region cannot be allocated.
+    pub fn init(mut self) -> Result<(), LoggerError> {
+        let mut entries = VecDeque::new();
+        if let Some(ring) = &mut self.stable {
+            entries = ring.open()?;
+            while entries.len() > self.capacity {
+                entries.pop_front();
+            }
+        }
+        if !INSTALLED.swap(true, Ordering::Relaxed) && log::set_logger(&Forwarder).is_err() {
+            INSTALLED.store(false, Ordering::Relaxed);
+            return Err(LoggerError::SetLoggerFailed);
+        }
+        let max_level = self
+            .module_levels
+            .iter()
+            .map(|(_, level)| *level)
+            .fold(self.level, Ord::max);
+        log::set_max_level(max_level);
+        let next_index = entries.back().map_or(0, |entry: &LogEntry| entry.index + 1);
+        STATE.set(Some(State {
+            logger: self,
+            entries,
+            next_index,
+        }));
+        Ok(())
+    }
+
+    fn enabled(&self, level: log::Level, target: &str) -> bool {
+        let filter = self
+            .module_levels
+            .iter()
+            .filter(|(module, _)| {
+                target
+                    .strip_prefix(module.as_str())
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with("::"))
+            })
+            .max_by_key(|(module, _)| module.len())
+            .map_or(self.level, |(_, level)| *level);
+        level <= filter
+    }
+}
+
+struct State {
+    logger: CanisterLogger,
+    entries: VecDeque<LogEntry>,
+    next_index: u64,
+}
+
+/// The logger set for the `log` crate, forwarding to the `CanisterLogger` of the canister.
+struct Forwarder;
+
+impl Log for Forwarder {
+    fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+        STATE.with_borrow(|state| {
+            state
+                .as_ref()
+                .is_some_and(|state| state.logger.enabled(metadata.level(), metadata.target()))
+        })
+    }
+
+    fn log(&self, record: &Record<'_>) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let message = record.args().to_string();
+        // A logger must not panic, so a record logged while the state is borrowed is dropped.
+        let _ = STATE.try_with(|state| {
+            let Ok(mut state) = state.try_borrow_mut() else {
+                return;
+            };
+            let Some(state) = state.as_mut() else {
+                return;
+            };
+            let entry = LogEntry {
+                index: state.next_index,
+                timestamp: time(),
+                level: record.level().into(),
+                target: record.target().to_string(),
+                message,
+            };
+            state.next_index += 1;
+            if state.logger.debug_print {
+                debug_print(format!(
+                    "[{} {}] {}",
+                    record.level(),
+                    entry.target,
+                    entry.message
+                ));
+            }
+            if let Some(ring) = &mut state.logger.stable {
+                ring.push(&entry);
+            }
+            state.entries.push_back(entry);
+            while state.entries.len() > state.logger.capacity {
+                state.entries.pop_front();
+            }
+        });
+    }
+
+    fn flush(&self) {}
+}
+
+/// Gets a page of the entries kept by the [`CanisterLogger`], newest first.
+///
+/// Returns no entries if the logger is not initialized.
+pub fn get_logs(request: &LogRequest) -> LogPage {
+    let max_entries = request.max_entries.unwrap_or(DEFAULT_PAGE_SIZE).max(1) as usize;
+    STATE.with_borrow(|state| {
+        let Some(state) = state else {
+            return LogPage::default();
+        };
+        let mut page = LogPage::default();
+        let mut bytes = 0;
+        let older = state
+            .entries
+            .iter()
+            .rev()
+            .filter(|entry| request.before.is_none_or(|before| entry.index < before))
+            .filter(|entry| {
+                request.min_level.is_none_or(|min_level| {
+                    log::Level::from(entry.level) <= log::Level::from(min_level)
+                })
+            });
+        for entry in older {
+            bytes += entry.message.len() + entry.target.len();
+            if page.entries.len() == max_entries
+                || (bytes > MAX_PAGE_BYTES && !page.entries.is_empty())
+            {
+                page.next = page.entries.last().map(|last| last.index);
+                break;
+            }
+            page.entries.push(entry.clone());
+        }
+        page
+    })
+}
+
+/// Exports a `get_logs` query returning the entries of the [`CanisterLogger`](crate::logging::CanisterLogger), as
+/// [`get_logs`](crate::logging::get_logs) does.
+///
+/// Logs may reveal more than the canister otherwise does, so the query should be guarded:
+///
+/// ```rust, no_run
+/// fn is_controller() -> Result<(), String> {
+///     if ic_cdk::api::is_controller(&ic_cdk::api::msg_caller()) {
+///         Ok(())
+///     } else {
+///         Err("only controllers can read the log".to_string())
+///     }
+/// }
+///
+/// ic_cdk::export_log_query!(guard = "is_controller");
+/// ```
+#[macro_export]
+macro_rules! export_log_query {
+    () => {
+        #[$crate::query]
+        fn get_logs(request: $crate::logging::LogRequest) -> $crate::logging::LogPage {
+            $crate::logging::get_logs(&request)
+        }
+    };
+    (guard = $guard:literal) => {
+        #[$crate::query(guard = $guard)]
+        fn get_logs(request: $crate::logging::LogRequest) -> $crate::logging::LogPage {
+            $crate::logging::get_logs(&request)
+        }
+    };
+}
+
+/// Identifies a stable memory region holding a log.
+const MAGIC: &[u8; 4] = b"CLOG";
+
+/// The version of the layout of the region.
+const VERSION: u32 = 1;
+
+/// The length of the header of the region: the magic bytes, the version, the capacity, the head and the used bytes.
+const HEADER_LEN: u64 = 32;
+
+/// A ring buffer of encoded entries in a region of stable memory.
+///
+/// Each entry is stored as its length in 4 bytes, followed by its index, timestamp, level, target length, target
+/// and message, with integers in little endian.
+struct StableRing {
+    memory: Box<dyn StableMemory>,
+    /// The offset of the region.
+    start: u64,
+    /// The number of bytes available for entries, after the header.
+    capacity: u64,
+    /// The position of the oldest entry among the bytes available for entries.
+    head: u64,
+    /// The number of bytes used by entries.
+    used: u64,
+}
+
+impl StableRing {
+    /// Allocates the region and reads the entries it holds, or resets it if it does not hold a log.
+    fn open(&mut self) -> Result<VecDeque<LogEntry>, LoggerError> {
+        if self.capacity == 0 {
+            return Err(LoggerError::RegionTooSmall);
+        }
+        let end = self.start + HEADER_LEN + self.capacity;
+        let pages = end.div_ceil(WASM_PAGE_SIZE_IN_BYTES);
+        let current = self.memory.stable_size();
+        if current < pages {
+            self.memory.stable_grow(pages - current)?;
+        }
+        let mut header = [0; HEADER_LEN as usize];
+        self.memory.stable_read(self.start, &mut header);
+        let field = |i: usize| u64::from_le_bytes(header[i..i + 8].try_into().unwrap());
+        if &header[..4] == MAGIC
+            && header[4..8] == VERSION.to_le_bytes()
+            && field(8) == self.capacity
+            && field(16) < self.capacity
+            && field(24) <= self.capacity
+        {
+            self.head = field(16);
+            self.used = field(24);
+            if let Some(entries) = self.read_entries() {
+                return Ok(entries);
+            }
+        }
+        self.head = 0;
+        self.used = 0;
+        self.write_header();
+        Ok(VecDeque::new())
+    }
+
+    fn read_entries(&self) -> Option<VecDeque<LogEntry>> {
+        let mut entries = VecDeque::new();
+        let mut position = 0;
+        while position < self.used {
+            let mut len = [0; 4];
+            self.read(self.head + position, &mut len);
+            let len = u64::from(u32::from_le_bytes(len));
+            if position + 4 + len > self.used {
+                return None;
+            }
+            let mut bytes = vec![0; len as usize];
+            self.read(self.head + position + 4, &mut bytes);
+            entries.push_back(decode_entry(&bytes)?);
+            position += 4 + len;
+        }
+        Some(entries)
+    }
+
+    fn push(&mut self, entry: &LogEntry) {
+        let bytes = encode_entry(entry);
+        let len = 4 + bytes.len() as u64;
+        if len > self.capacity {
+            return;
+        }
+        while self.used + len > self.capacity {
+            let mut oldest = [0; 4];
+            self.read(self.head, &mut oldest);
+            let oldest = 4 + u64::from(u32::from_le_bytes(oldest));
+            self.head = (self.head + oldest) % self.capacity;
+            self.used -= oldest;
+        }
+        let tail = self.head + self.used;
+        self.write(tail, &(bytes.len() as u32).to_le_bytes());
+        self.write(tail + 4, &bytes);
+        self.used += len;
+        self.write_header();
+    }
+
+    fn write_header(&self) {
+        let mut header = Vec::with_capacity(HEADER_LEN as usize);
+        header.extend_from_slice(MAGIC);
+        header.extend_from_slice(&VERSION.to_le_bytes());
+        header.extend_from_slice(&self.capacity.to_le_bytes());
+        header.extend_from_slice(&self.head.to_le_bytes());
+        header.extend_from_slice(&self.used.to_le_bytes());
+        self.memory.stable_write(self.start, &header);
+    }
+
+    /// Reads bytes at a position among the bytes available for entries, wrapping around at the end.
+    fn read(&self, position: u64, buf: &mut [u8]) {
+        let position = position % self.capacity;
+        let first = buf.len().min((self.capacity - position) as usize);
+        let data = self.start + HEADER_LEN;
+        self.memory.stable_read(data + position, &mut buf[..first]);
+        self.memory.stable_read(data, &mut buf[first..]);
+    }
+
+    /// Writes bytes at a position among the bytes available for entries, wrapping around at the end.
+    fn write(&self, position: u64, buf: &[u8]) {
+        let position = position % self.capacity;
+        let first = buf.len().min((self.capacity - position) as usize);
+        let data = self.start + HEADER_LEN;
+        self.memory.stable_write(data + position, &buf[..first]);
+        self.memory.stable_write(data, &buf[first..]);
+    }
+}
+
+fn encode_entry(entry: &LogEntry) -> Vec<u8> {
+    // Targets are module paths, so truncating them is harmless.
+    let target = &entry.target.as_bytes()[..entry.target.len().min(u16::MAX as usize)];
+    let mut bytes = Vec::with_capacity(19 + target.len() + entry.message.len());
+    bytes.extend_from_slice(&entry.index.to_le_bytes());
+    bytes.extend_from_slice(&entry.timestamp.to_le_bytes());
+    bytes.push(log::Level::from(entry.level) as u8);
+    bytes.extend_from_slice(&(target.len() as u16).to_le_bytes());
+    bytes.extend_from_slice(target);
+    bytes.extend_from_slice(entry.message.as_bytes());
+    bytes
+}
+
+fn decode_entry(bytes: &[u8]) -> Option<LogEntry> {
+    let (index, rest) = bytes.split_first_chunk::<8>()?;
+    let (timestamp, rest) = rest.split_first_chunk::<8>()?;
+    let (level, rest) = rest.split_first()?;
+    let (target_len, rest) = rest.split_first_chunk::<2>()?;
+    let target_len = u16::from_le_bytes(*target_len) as usize;
+    if rest.len() < target_len {
+        return None;
+    }
+    let (target, message) = rest.split_at(target_len);
+    let level = match level {
+        1 => LogLevel::Error,
+        2 => LogLevel::Warn,
+        3 => LogLevel::Info,
+        4 => LogLevel::Debug,
+        5 => LogLevel::Trace,
+        _ => return None,
+    };
+    Some(LogEntry {
+        index: u64::from_le_bytes(*index),
+        timestamp: u64::from_le_bytes(*timestamp),
+        level,
+        target: String::from_utf8_lossy(target).into_owned(),
+        message: String::from_utf8_lossy(message).into_owned(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stable::tests::TestStableMemory;
+    use std::rc::Rc;
+    use std::sync::Mutex;
+
+    fn entry(index: u64, message: &str) -> LogEntry {
+        LogEntry {
+            index,
+            timestamp: 1_000 + index,
+            level: LogLevel::Warn,
+            target: "canister::module".to_string(),
+            message: message.to_string(),
+        }
+    }
+
+    fn ring(memory: &Rc<Mutex<Vec<u8>>>, size: u64) -> StableRing {
+        let CanisterLogger { stable, .. } = CanisterLogger::new().with_stable_memory(
+            TestStableMemory::new(memory.clone()),
+            100,
+            size,
+        );
+        stable.unwrap()
+    }
+
+    #[test]
+    fn entries_round_trip() {
+        let entry = entry(7, "héllo");
+        assert_eq!(decode_entry(&encode_entry(&entry)), Some(entry));
+        assert_eq!(decode_entry(&[0; 18]), None);
+    }
+
+    #[test]
+    fn stable_ring_drops_the_oldest_entries() {
+        let memory = Rc::new(Mutex::new(Vec::new()));
+        // Each entry takes 4 + 19 + 16 + 10 = 49 bytes, so 3 of them fit.
+        let mut log = ring(&memory, HEADER_LEN + 150);
+        assert!(log.open().unwrap().is_empty());
+        for i in 0..10 {
+            log.push(&entry(i, &format!("message {i:02}")));
+        }
+        let mut reopened = ring(&memory, HEADER_LEN + 150);
+        let entries = reopened.open().unwrap();
+        assert_eq!(
+            entries.iter().map(|entry| entry.index).collect::<Vec<_>>(),
+            [7, 8, 9]
+        );
+        assert_eq!(entries[2], entry(9, "message 09"));
+        // Entries larger than the region are not persisted.
+        reopened.push(&entry(10, &"x".repeat(200)));
+        assert_eq!(ring(&memory, HEADER_LEN + 150).open().unwrap().len(), 3);
+        // A region of another size is reset.
+        assert!(ring(&memory, HEADER_LEN + 200).open().unwrap().is_empty());
+        assert!(matches!(
+            ring(&memory, HEADER_LEN).open(),
+            Err(LoggerError::RegionTooSmall)
+        ));
+    }
+
+    #[test]
+    fn module_levels_override_the_level() {
+        let logger = CanisterLogger::new()
+            .with_level(LevelFilter::Warn)
+            .with_module_level("app", LevelFilter::Debug)
+            .with_module_level("app::noisy", LevelFilter::Error);
+        assert!(logger.enabled(log::Level::Warn, "other"));
+        assert!(!logger.enabled(log::Level::Info, "other"));
+        assert!(logger.enabled(log::Level::Debug, "app"));
+        assert!(logger.enabled(log::Level::Debug, "app::ledger"));
+        assert!(!logger.enabled(log::Level::Info, "application"));
+        assert!(!logger.enabled(log::Level::Warn, "app::noisy::inner"));
+    }
+}
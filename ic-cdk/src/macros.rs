@@ -72,6 +72,19 @@ pub use ic_cdk_macros::export_candid;
 /// }
 /// ```
 ///
+/// A guard can also be an expression evaluating to a guard function, such as the guards of
+/// [`access_control`](crate::access_control) that take the role to check:
+///
+/// ```rust
+/// # use ic_cdk::query;
+/// use ic_cdk::access_control::has_role;
+/// #[query(guard = "has_role(\"minter\")")]
+/// fn query_function() {
+///     // ...
+/// # unimplemented!()
+/// }
+/// ```
+///
 /// ## Custom Argument Decoding
 ///
 /// You can specify a custom function to decode the arguments.
@@ -214,6 +227,57 @@ pub use ic_cdk_macros::query;
 /// }
 /// ```
 ///
+/// A guard can also be an expression evaluating to a guard function, such as the guards of
+/// [`access_control`](crate::access_control) that take the role to check:
+///
+/// ```rust
+/// # use ic_cdk::update;
+/// use ic_cdk::access_control::has_role;
+/// #[update(guard = "has_role(\"minter\")")]
+/// fn update_function() {
+///     // ...
+/// # unimplemented!()
+/// }
+/// ```
+///
+/// ## Charging Cycles
+///
+/// You can charge the caller cycles before the update function runs, after the guard functions.
+/// If fewer cycles are attached to the call than required, it is rejected and none are accepted.
+///
+/// - `cycles(accept = N)` accepts exactly `N` cycles and refunds the rest.
+/// - `cycles(min = N)` accepts all the cycles attached, which must be at least `N`.
+/// - `cycles(min = N, refund_excess)` is the same as `cycles(accept = N)`.
+///
+/// `N` can be any expression of type `u128`. See [`cycles`](crate::cycles) for charging while the function runs.
+///
+/// ```rust
+/// # use ic_cdk::update;
+/// const FEE: u128 = 1_000_000;
+///
+/// #[update(cycles(accept = FEE))]
+/// fn update_function() {
+///     // ...
+/// # unimplemented!()
+/// }
+/// ```
+///
+/// ## Draining
+///
+/// While the canister is stopping or draining, as reported by [`lifecycle::is_draining`](crate::lifecycle::is_draining),
+/// calls to the update function are rejected before its guard functions run.
+/// Functions that must keep working while the canister drains, such as one that ends the drain, can opt out with the
+/// `allow_while_draining` attribute.
+///
+/// ```rust
+/// # use ic_cdk::update;
+/// #[update(allow_while_draining)]
+/// fn update_function() {
+///     // ...
+/// # unimplemented!()
+/// }
+/// ```
+///
 /// ## Custom Argument Decoding
 ///
 /// You can specify a custom function to decode the arguments.
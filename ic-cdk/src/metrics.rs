@@ -0,0 +1,629 @@
+//! Metrics of the canister, rendered in the [Prometheus text exposition format].
+//!
+//! This module is only available with the `metrics` feature, which also makes the method attribute macros record the
+//! instructions of each method.
+//!
+//! Counters, gauges and histograms are registered by name in a registry kept on the heap. Registering a name again
+//! returns the same metric, so a metric can be registered where it is used:
+//!
+//! ```rust, no_run
+//! use ic_cdk::metrics;
+//!
+//! fn transfer() {
+//!     metrics::counter("transfers_total", "Number of transfers.")
+//!         .with_labels(&[("token", "ICP")])
+//!         .inc();
+//! }
+//! ```
+//!
+//! [`render`] renders all metrics, including these standard ones, updated on every render:
+//!
+//! - `canister_cycle_balance`: the cycle balance of the canister.
+//! - `canister_version`: the version of the canister.
+//! - `canister_stable_memory_bytes`: the size of the stable memory.
+//! - `canister_heap_memory_bytes`: the size of the heap, on Wasm targets only.
+//! - `canister_method_instructions`: a histogram of the instructions executed by each update and lifecycle method,
+//!   labeled by `method`, recorded by the method attribute macros when the method returns. The instructions of an
+//!   `async` method are counted across all of its awaits. Queries are not recorded, since their changes to the heap
+//!   are discarded.
+//!
+//! Metrics are usually served by an `http_request` query, to be scraped through the HTTP gateway:
+//!
+//! ```rust, no_run
+//! # use candid::CandidType;
+//! # use serde::Deserialize;
+//! # #[derive(CandidType, Deserialize)]
+//! # struct HttpRequest { url: String }
+//! # #[derive(CandidType)]
+//! # struct HttpResponse { status_code: u16, headers: Vec<(String, String)>, body: Vec<u8> }
+//! use ic_cdk::{metrics, query};
+//!
+//! #[query(hidden = true)]
+//! fn http_request(request: HttpRequest) -> HttpResponse {
+//!     if request.url != "/metrics" {
+//!         return HttpResponse { status_code: 404, headers: vec![], body: vec![] };
+//!     }
+//!     HttpResponse {
+//!         status_code: 200,
+//!         headers: vec![("Content-Type".into(), metrics::CONTENT_TYPE.into())],
+//!         body: metrics::render().into_bytes(),
+//!     }
+//! }
+//! ```
+//!
+//! The registry lives on the heap, so metrics are reset when the canister is upgraded.
+//!
+//! [Prometheus text exposition format]: https://prometheus.io/docs/instrumenting/exposition_formats/#text-based-format
+
+use candid::CandidType;
+use serde::{Deserialize, Serialize};
+use std::cell::{Cell, RefCell};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::rc::Rc;
+
+/// The content type of the text returned by [`render`].
+pub const CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+
+/// The buckets of instruction histograms: powers of ten from ten thousand to ten billion instructions.
+pub(crate) const INSTRUCTION_BUCKETS: [f64; 7] = [1e4, 1e5, 1e6, 1e7, 1e8, 1e9, 1e10];
+
+/// The label names and values of a series, sorted by name.
+type Labels = Vec<(String, String)>;
+
+thread_local! {
+    static REGISTRY: RefCell<BTreeMap<String, Family>> = const { RefCell::new(BTreeMap::new()) };
+}
+
+/// The series sharing a name, which differ by their labels.
+struct Family {
+    help: String,
+    kind: Kind,
+}
+
+enum Kind {
+    Counter(BTreeMap<Labels, Rc<Cell<u64>>>),
+    Gauge(BTreeMap<Labels, Rc<Cell<f64>>>),
+    Histogram {
+        buckets: Rc<[f64]>,
+        series: BTreeMap<Labels, Rc<RefCell<HistogramState>>>,
+    },
+}
+
+impl Kind {
+    fn name(&self) -> &'static str {
+        match self {
+            Kind::Counter(_) => "counter",
+            Kind::Gauge(_) => "gauge",
+            Kind::Histogram { .. } => "histogram",
+        }
+    }
+}
+
+/// Finds or creates the family `name` with `new`, then the series with `labels` in it with `series`.
+fn register<T>(
+    name: &str,
+    help: &str,
+    labels: &[(&str, &str)],
+    new: impl FnOnce() -> Kind,
+    series: impl FnOnce(&mut Kind, Labels) -> Option<T>,
+) -> T {
+    assert!(is_valid_name(name, true), "invalid metric name `{name}`");
+    let mut sorted: Labels = labels
+        .iter()
+        .map(|(label, value)| {
+            assert!(
+                is_valid_name(label, false) && !label.starts_with("__"),
+                "invalid label name `{label}` of metric `{name}`"
+            );
+            (label.to_string(), value.to_string())
+        })
+        .collect();
+    sorted.sort();
+    REGISTRY.with_borrow_mut(|registry| {
+        let family = registry.entry(name.to_string()).or_insert_with(|| Family {
+            help: help.to_string(),
+            kind: new(),
+        });
+        let kind = family.kind.name();
+        series(&mut family.kind, sorted)
+            .unwrap_or_else(|| panic!("metric `{name}` is already registered as a {kind}"))
+    })
+}
+
+/// Whether `name` is a valid metric name, or a valid label name if `colons` is false.
+fn is_valid_name(name: &str, colons: bool) -> bool {
+    let valid = |c: char| c.is_ascii_alphabetic() || c == '_' || (colons && c == ':');
+    let mut chars = name.chars();
+    chars.next().is_some_and(valid) && chars.all(|c| valid(c) || c.is_ascii_digit())
+}
+
+/// Registers a counter, or returns the one registered with the same name.
+///
+/// # Panics
+///
+/// Panics if the name is invalid or registered as another kind of metric.
+pub fn counter(name: &str, help: &str) -> Counter {
+    counter_with_labels(name, help, &[])
+}
+
+fn counter_with_labels(name: &str, help: &str, labels: &[(&str, &str)]) -> Counter {
+    let value = register(
+        name,
+        help,
+        labels,
+        || Kind::Counter(BTreeMap::new()),
+        |kind, labels| match kind {
+            Kind::Counter(series) => Some(series.entry(labels).or_default().clone()),
+            _ => None,
+        },
+    );
+    Counter {
+        name: name.into(),
+        value,
+    }
+}
+
+/// Registers a gauge, or returns the one registered with the same name.
+///
+/// # Panics
+///
+/// Panics if the name is invalid or registered as another kind of metric.
+pub fn gauge(name: &str, help: &str) -> Gauge {
+    gauge_with_labels(name, help, &[])
+}
+
+fn gauge_with_labels(name: &str, help: &str, labels: &[(&str, &str)]) -> Gauge {
+    let value = register(
+        name,
+        help,
+        labels,
+        || Kind::Gauge(BTreeMap::new()),
+        |kind, labels| match kind {
+            Kind::Gauge(series) => Some(series.entry(labels).or_default().clone()),
+            _ => None,
+        },
+    );
+    Gauge {
+        name: name.into(),
+        value,
+    }
+}
+
+/// Registers a histogram with the upper bounds of its buckets, or returns the one registered with the same name, in
+/// which case its buckets are kept.
+///
+/// A bucket with an infinite upper bound is always added after `buckets`.
+///
+/// # Panics
+///
+/// Panics if the name is invalid or registered as another kind of metric, or if `buckets` are not finite and strictly
+/// increasing.
+pub fn histogram(name: &str, help: &str, buckets: &[f64]) -> Histogram {
+    assert!(
+        buckets.iter().all(|b| b.is_finite()) && buckets.windows(2).all(|w| w[0] < w[1]),
+        "buckets of histogram `{name}` are not finite and strictly increasing"
+    );
+    histogram_with_labels(name, help, buckets, &[])
+}
+
+fn histogram_with_labels(
+    name: &str,
+    help: &str,
+    buckets: &[f64],
+    labels: &[(&str, &str)],
+) -> Histogram {
+    let state = register(
+        name,
+        help,
+        labels,
+        || Kind::Histogram {
+            buckets: buckets.into(),
+            series: BTreeMap::new(),
+        },
+        |kind, labels| match kind {
+            Kind::Histogram { buckets, series } => Some(
+                series
+                    .entry(labels)
+                    .or_insert_with(|| Rc::new(RefCell::new(HistogramState::new(buckets.clone()))))
+                    .clone(),
+            ),
+            _ => None,
+        },
+    );
+    Histogram {
+        name: name.into(),
+        state,
+    }
+}
+
+/// Registers the series of the histogram `name` labeled by `method`.
+pub(crate) fn method_histogram(name: &str, help: &str, buckets: &[f64], method: &str) -> Histogram {
+    histogram_with_labels(name, help, buckets, &[("method", method)])
+}
+
+/// The series of `canister_method_instructions` of `method`.
+pub(crate) fn method_instructions(method: &str) -> Histogram {
+    method_histogram(
+        "canister_method_instructions",
+        "The instructions executed by each method.",
+        &INSTRUCTION_BUCKETS,
+        method,
+    )
+}
+
+/// Upper bounds of histogram buckets, from `start` multiplied by `factor` for each of the `count` buckets.
+///
+/// # Panics
+///
+/// Panics if `start` is not positive or `factor` is not greater than 1.
+pub fn exponential_buckets(start: f64, factor: f64, count: usize) -> Vec<f64> {
+    assert!(
+        start > 0.0 && factor > 1.0,
+        "exponential buckets need a positive start and a factor greater than 1"
+    );
+    std::iter::successors(Some(start), |bound| Some(bound * factor))
+        .take(count)
+        .collect()
+}
+
+/// A value that only goes up, such as a number of requests.
+#[derive(Clone, Debug)]
+pub struct Counter {
+    name: Rc<str>,
+    value: Rc<Cell<u64>>,
+}
+
+impl Counter {
+    /// Returns the counter of the same name with the given labels, registering it on first use.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a label name is invalid.
+    pub fn with_labels(&self, labels: &[(&str, &str)]) -> Counter {
+        counter_with_labels(&self.name, "", labels)
+    }
+
+    /// Adds 1 to the counter.
+    pub fn inc(&self) {
+        self.inc_by(1);
+    }
+
+    /// Adds `n` to the counter.
+    pub fn inc_by(&self, n: u64) {
+        self.value.set(self.value.get().saturating_add(n));
+    }
+
+    /// Gets the value of the counter.
+    pub fn get(&self) -> u64 {
+        self.value.get()
+    }
+}
+
+/// A value that goes up and down, such as a queue length.
+#[derive(Clone, Debug)]
+pub struct Gauge {
+    name: Rc<str>,
+    value: Rc<Cell<f64>>,
+}
+
+impl Gauge {
+    /// Returns the gauge of the same name with the given labels, registering it on first use.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a label name is invalid.
+    pub fn with_labels(&self, labels: &[(&str, &str)]) -> Gauge {
+        gauge_with_labels(&self.name, "", labels)
+    }
+
+    /// Sets the gauge to `value`.
+    pub fn set(&self, value: f64) {
+        self.value.set(value);
+    }
+
+    /// Adds `delta` to the gauge.
+    pub fn add(&self, delta: f64) {
+        self.value.set(self.value.get() + delta);
+    }
+
+    /// Subtracts `delta` from the gauge.
+    pub fn sub(&self, delta: f64) {
+        self.add(-delta);
+    }
+
+    /// Adds 1 to the gauge.
+    pub fn inc(&self) {
+        self.add(1.0);
+    }
+
+    /// Subtracts 1 from the gauge.
+    pub fn dec(&self) {
+        self.add(-1.0);
+    }
+
+    /// Gets the value of the gauge.
+    pub fn get(&self) -> f64 {
+        self.value.get()
+    }
+}
+
+/// A distribution of observed values, such as instruction counts, counted in buckets.
+#[derive(Clone, Debug)]
+pub struct Histogram {
+    name: Rc<str>,
+    state: Rc<RefCell<HistogramState>>,
+}
+
+#[derive(Debug)]
+struct HistogramState {
+    buckets: Rc<[f64]>,
+    /// The number of observations in each bucket, not cumulative, the last one for the infinite bucket.
+    counts: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+impl HistogramState {
+    fn new(buckets: Rc<[f64]>) -> Self {
+        Self {
+            counts: vec![0; buckets.len() + 1],
+            buckets,
+            sum: 0.0,
+            count: 0,
+        }
+    }
+
+    fn snapshot(&self) -> HistogramSnapshot {
+        let mut cumulative = 0;
+        let buckets = self
+            .counts
+            .iter()
+            .enumerate()
+            .map(|(i, count)| {
+                cumulative += count;
+                let le = self.buckets.get(i).copied().unwrap_or(f64::INFINITY);
+                (le, cumulative)
+            })
+            .collect();
+        HistogramSnapshot {
+            buckets,
+            sum: self.sum,
+            count: self.count,
+        }
+    }
+}
+
+impl Histogram {
+    /// Returns the histogram of the same name with the given labels, registering it on first use with the buckets of
+    /// the histogram.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a label name is invalid.
+    pub fn with_labels(&self, labels: &[(&str, &str)]) -> Histogram {
+        let buckets = self.state.borrow().buckets.clone();
+        histogram_with_labels(&self.name, "", &buckets, labels)
+    }
+
+    /// Records an observed value.
+    pub fn observe(&self, value: f64) {
+        let mut state = self.state.borrow_mut();
+        let bucket = state.buckets.partition_point(|bound| *bound < value);
+        state.counts[bucket] += 1;
+        state.sum += value;
+        state.count += 1;
+    }
+
+    /// Gets the number of observed values.
+    pub fn count(&self) -> u64 {
+        self.state.borrow().count
+    }
+
+    /// Gets the sum of observed values.
+    pub fn sum(&self) -> f64 {
+        self.state.borrow().sum
+    }
+
+    /// Gets the observations recorded so far, to be returned by a query.
+    pub fn snapshot(&self) -> HistogramSnapshot {
+        self.state.borrow().snapshot()
+    }
+}
+
+/// The observations of a [`Histogram`].
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct HistogramSnapshot {
+    /// The upper bound of each bucket, the last one infinite, with the number of observations up to it.
+    pub buckets: Vec<(f64, u64)>,
+    /// The sum of observed values.
+    pub sum: f64,
+    /// The number of observed values.
+    pub count: u64,
+}
+
+/// Updates the standard metrics and renders all metrics in the Prometheus text exposition format, sorted by name.
+pub fn render() -> String {
+    update_standard_metrics();
+    encode()
+}
+
+fn encode() -> String {
+    REGISTRY.with_borrow(|registry| {
+        let mut out = String::new();
+        for (name, family) in registry {
+            if !family.help.is_empty() {
+                writeln!(out, "# HELP {name} {}", escape(&family.help, false)).unwrap();
+            }
+            writeln!(out, "# TYPE {name} {}", family.kind.name()).unwrap();
+            match &family.kind {
+                Kind::Counter(series) => {
+                    for (labels, value) in series {
+                        sample(&mut out, name, labels, None, value.get() as f64);
+                    }
+                }
+                Kind::Gauge(series) => {
+                    for (labels, value) in series {
+                        sample(&mut out, name, labels, None, value.get());
+                    }
+                }
+                Kind::Histogram { series, .. } => {
+                    let bucket = format!("{name}_bucket");
+                    for (labels, state) in series {
+                        let snapshot = state.borrow().snapshot();
+                        for (le, count) in snapshot.buckets {
+                            sample(&mut out, &bucket, labels, Some(le), count as f64);
+                        }
+                        sample(&mut out, &format!("{name}_sum"), labels, None, snapshot.sum);
+                        let count = snapshot.count as f64;
+                        sample(&mut out, &format!("{name}_count"), labels, None, count);
+                    }
+                }
+            }
+        }
+        out
+    })
+}
+
+fn update_standard_metrics() {
+    gauge(
+        "canister_cycle_balance",
+        "The cycle balance of the canister.",
+    )
+    .set(crate::api::canister_cycle_balance() as f64);
+    gauge("canister_version", "The version of the canister.")
+        .set(crate::api::canister_version() as f64);
+    gauge(
+        "canister_stable_memory_bytes",
+        "The size of the stable memory of the canister in bytes.",
+    )
+    .set((crate::api::stable_size() * 65536) as f64);
+    #[cfg(target_arch = "wasm32")]
+    gauge(
+        "canister_heap_memory_bytes",
+        "The size of the heap memory of the canister in bytes.",
+    )
+    .set((core::arch::wasm32::memory_size(0) * 65536) as f64);
+}
+
+/// Writes a sample line, with an `le` label after the others for histogram buckets.
+fn sample(out: &mut String, name: &str, labels: &Labels, le: Option<f64>, value: f64) {
+    out.push_str(name);
+    let le = le.map(|le| ("le", format_value(le)));
+    let labels = labels
+        .iter()
+        .map(|(label, value)| (label.as_str(), escape(value, true)))
+        .chain(le);
+    let mut separator = '{';
+    for (label, value) in labels {
+        write!(out, "{separator}{label}=\"{value}\"").unwrap();
+        separator = ',';
+    }
+    if separator == ',' {
+        out.push('}');
+    }
+    writeln!(out, " {}", format_value(value)).unwrap();
+}
+
+fn format_value(value: f64) -> String {
+    match value {
+        f64::INFINITY => "+Inf".to_string(),
+        f64::NEG_INFINITY => "-Inf".to_string(),
+        _ if value.is_nan() => "NaN".to_string(),
+        _ => value.to_string(),
+    }
+}
+
+/// Escapes backslashes and line feeds, and double quotes in label values.
+fn escape(text: &str, quotes: bool) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '"' if quotes => escaped.push_str("\\\""),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[doc(hidden)]
+pub mod internals {
+    use super::*;
+
+    thread_local! {
+        static METHODS: RefCell<BTreeMap<&'static str, Histogram>> = const { RefCell::new(BTreeMap::new()) };
+    }
+
+    /// Records the instructions executed in the call context of `method`, called by the method attribute macros when
+    /// the method returns.
+    pub fn record_method_instructions(method: &'static str) {
+        let instructions = crate::api::call_context_instruction_counter();
+        METHODS.with_borrow_mut(|methods| {
+            methods
+                .entry(method)
+                .or_insert_with(|| method_instructions(method))
+                .observe(instructions as f64);
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_metrics() {
+        let requests = counter("requests_total", "Number of requests.");
+        requests.with_labels(&[("method", "a\"b")]).inc_by(2);
+        counter("requests_total", "ignored").inc();
+        let queue = gauge("queue_length", "Length of\nthe queue.");
+        queue.set(3.0);
+        queue.dec();
+        let latency = histogram("latency", "", &[1.0, 10.0]);
+        for value in [0.5, 1.0, 5.0, 20.0] {
+            latency.observe(value);
+        }
+        assert_eq!(requests.get(), 1);
+        assert_eq!(latency.count(), 4);
+        assert_eq!(latency.sum(), 26.5);
+        assert_eq!(
+            latency.snapshot().buckets,
+            vec![(1.0, 2), (10.0, 3), (f64::INFINITY, 4)]
+        );
+        // The standard metrics need the system API, so only the registered ones are encoded.
+        assert_eq!(
+            encode(),
+            "# TYPE latency histogram\n\
+             latency_bucket{le=\"1\"} 2\n\
+             latency_bucket{le=\"10\"} 3\n\
+             latency_bucket{le=\"+Inf\"} 4\n\
+             latency_sum 26.5\n\
+             latency_count 4\n\
+             # HELP queue_length Length of\\nthe queue.\n\
+             # TYPE queue_length gauge\n\
+             queue_length 2\n\
+             # HELP requests_total Number of requests.\n\
+             # TYPE requests_total counter\n\
+             requests_total 1\n\
+             requests_total{method=\"a\\\"b\"} 2\n"
+        );
+    }
+
+    #[test]
+    fn rejects_conflicting_registrations() {
+        assert!(is_valid_name("ns:requests_total", true));
+        assert!(!is_valid_name("ns:method", false));
+        assert!(!is_valid_name("2xx", true));
+        assert!(!is_valid_name("", true));
+        counter("conflicting", "");
+        let result = std::panic::catch_unwind(|| gauge("conflicting", ""));
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn builds_exponential_buckets() {
+        assert_eq!(exponential_buckets(1.0, 10.0, 3), vec![1.0, 10.0, 100.0]);
+    }
+}
@@ -0,0 +1,212 @@
+//! Profiling of the instructions and cycles spent by each exported method.
+//!
+//! With the `profiling` feature enabled, the method attribute macros profile every method they export, queries
+//! included, recording these histograms of [`metrics`], labeled by `method`:
+//!
+//! - `canister_method_instructions`: the instructions executed by each call of the method, across all of its awaits.
+//! - `canister_method_segment_instructions`: the instructions executed by the method between two of its awaits, or
+//!   from its start to its first await, or from its last await to its end.
+//! - `canister_method_segments`: the number of such segments of each call of the method, which is one more than the
+//!   number of awaits that suspended it.
+//! - `canister_method_call_cycles`: the cycles spent on the inter-canister calls made by each call of the method: the
+//!   cycles attached to the calls and their cost, minus the cycles refunded with their responses. Part of the cost of
+//!   a call is refunded without the canister being told, so this is an upper bound. Calls made by tasks spawned by the
+//!   method are not counted.
+//!
+//! They are rendered along with the other metrics by [`metrics::render`], and [`profiles`] returns them to be served
+//! by a query. [`dump`] prints a summary with [`debug_print`], which can be read from the canister logs.
+//!
+//! Profiling costs instructions of its own on every call. Like the other metrics, profiles are reset when the canister
+//! is upgraded, and the ones recorded by queries are kept only when the query is executed as an update call.
+//!
+//! [`debug_print`]: crate::api::debug_print
+
+use crate::api::{call_context_instruction_counter, debug_print, instruction_counter};
+use crate::metrics::{self, Histogram, HistogramSnapshot, INSTRUCTION_BUCKETS};
+use candid::CandidType;
+use serde::{Deserialize, Serialize};
+use std::cell::{Cell, RefCell};
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::pin::pin;
+
+/// The buckets of `canister_method_segments`.
+const SEGMENT_BUCKETS: [f64; 7] = [1.0, 2.0, 4.0, 8.0, 16.0, 32.0, 64.0];
+
+/// The buckets of `canister_method_call_cycles`: powers of ten from a million to ten trillion cycles.
+const CYCLE_BUCKETS: [f64; 8] = [1e6, 1e7, 1e8, 1e9, 1e10, 1e11, 1e12, 1e13];
+
+thread_local! {
+    static PROFILES: RefCell<BTreeMap<&'static str, Profile>> = const { RefCell::new(BTreeMap::new()) };
+    /// The cycles spent on calls by the segment being executed, if it is profiled.
+    static CALL_CYCLES: Cell<Option<i128>> = const { Cell::new(None) };
+}
+
+/// The histograms of a method.
+#[derive(Clone)]
+struct Profile {
+    instructions: Histogram,
+    segment_instructions: Histogram,
+    segments: Histogram,
+    call_cycles: Histogram,
+}
+
+impl Profile {
+    fn get(method: &'static str) -> Profile {
+        PROFILES.with_borrow_mut(|profiles| {
+            profiles
+                .entry(method)
+                .or_insert_with(|| Profile {
+                    instructions: metrics::method_instructions(method),
+                    segment_instructions: metrics::method_histogram(
+                        "canister_method_segment_instructions",
+                        "The instructions executed by each method between two awaits.",
+                        &INSTRUCTION_BUCKETS,
+                        method,
+                    ),
+                    segments: metrics::method_histogram(
+                        "canister_method_segments",
+                        "The number of segments between awaits of each method.",
+                        &SEGMENT_BUCKETS,
+                        method,
+                    ),
+                    call_cycles: metrics::method_histogram(
+                        "canister_method_call_cycles",
+                        "The cycles spent on calls by each method.",
+                        &CYCLE_BUCKETS,
+                        method,
+                    ),
+                })
+                .clone()
+        })
+    }
+}
+
+/// The histograms of the profile of a method, as returned by [`profiles`].
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct MethodProfile {
+    /// The name of the method, or the name of its export, such as `canister_init`, for lifecycle methods.
+    pub method: String,
+    /// The instructions executed by each call.
+    pub instructions: HistogramSnapshot,
+    /// The instructions executed between two awaits.
+    pub segment_instructions: HistogramSnapshot,
+    /// The number of segments between awaits of each call.
+    pub segments: HistogramSnapshot,
+    /// The cycles spent on calls by each call.
+    pub call_cycles: HistogramSnapshot,
+}
+
+/// Gets the profiles of the methods called so far, sorted by method name.
+pub fn profiles() -> Vec<MethodProfile> {
+    PROFILES.with_borrow(|profiles| {
+        profiles
+            .iter()
+            .map(|(method, profile)| MethodProfile {
+                method: method.to_string(),
+                instructions: profile.instructions.snapshot(),
+                segment_instructions: profile.segment_instructions.snapshot(),
+                segments: profile.segments.snapshot(),
+                call_cycles: profile.call_cycles.snapshot(),
+            })
+            .collect()
+    })
+}
+
+/// Prints the average instructions, segments and cycles spent on calls of each method with
+/// [`debug_print`](crate::api::debug_print).
+pub fn dump() {
+    for profile in profiles() {
+        let calls = profile.instructions.count;
+        let average = |snapshot: &HistogramSnapshot| snapshot.sum / calls.max(1) as f64;
+        debug_print(format!(
+            "{}: {calls} calls, {:.0} instructions in {:.1} segments, {:.0} cycles on calls on average",
+            profile.method,
+            average(&profile.instructions),
+            average(&profile.segments),
+            average(&profile.call_cycles),
+        ));
+    }
+}
+
+/// Records the cycles attached to a call and its cost, if the current segment is profiled.
+pub(crate) fn spend_call_cycles(cycles: impl FnOnce() -> u128) {
+    if let Some(spent) = CALL_CYCLES.get() {
+        let cycles = i128::try_from(cycles()).unwrap_or(i128::MAX);
+        CALL_CYCLES.set(Some(spent.saturating_add(cycles)));
+    }
+}
+
+/// Records the cycles refunded with the response to a call, if the current segment is profiled.
+pub(crate) fn refund_call_cycles(cycles: u128) {
+    if let Some(spent) = CALL_CYCLES.get() {
+        let cycles = i128::try_from(cycles).unwrap_or(i128::MAX);
+        CALL_CYCLES.set(Some(spent.saturating_sub(cycles)));
+    }
+}
+
+/// A call of a method being profiled.
+struct Run {
+    profile: Profile,
+    segments: u64,
+    call_cycles: i128,
+}
+
+impl Run {
+    fn new(method: &'static str) -> Self {
+        Self {
+            profile: Profile::get(method),
+            segments: 0,
+            call_cycles: 0,
+        }
+    }
+
+    fn segment<R>(&mut self, f: impl FnOnce() -> R) -> R {
+        let outer = CALL_CYCLES.replace(Some(0));
+        let start = instruction_counter();
+        let result = f();
+        let instructions = instruction_counter().saturating_sub(start);
+        let call_cycles = CALL_CYCLES.replace(outer).unwrap_or_default();
+        self.profile
+            .segment_instructions
+            .observe(instructions as f64);
+        self.segments += 1;
+        self.call_cycles = self.call_cycles.saturating_add(call_cycles);
+        result
+    }
+
+    fn finish(&self) {
+        self.profile
+            .instructions
+            .observe(call_context_instruction_counter() as f64);
+        self.profile.segments.observe(self.segments as f64);
+        self.profile.call_cycles.observe(self.call_cycles as f64);
+    }
+}
+
+#[doc(hidden)]
+pub mod internals {
+    use super::*;
+
+    /// Profiles a method returning without awaiting, called by the method attribute macros.
+    pub fn profile_sync<R>(method: &'static str, f: impl FnOnce() -> R) -> R {
+        let mut run = Run::new(method);
+        let result = run.segment(f);
+        run.finish();
+        result
+    }
+
+    /// Profiles the future of an `async` method, called by the method attribute macros.
+    pub async fn profile<F: Future>(method: &'static str, future: F) -> F::Output {
+        let mut run = Run::new(method);
+        let mut future = pin!(future);
+        std::future::poll_fn(|context| {
+            let poll = run.segment(|| future.as_mut().poll(context));
+            if poll.is_ready() {
+                run.finish();
+            }
+            poll
+        })
+        .await
+    }
+}
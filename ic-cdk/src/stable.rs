@@ -8,6 +8,10 @@
 
 use std::{error, fmt, io};
 
+pub mod collections;
+pub mod memory_manager;
+pub mod transaction;
+
 /// WASM page size in bytes.
 pub const WASM_PAGE_SIZE_IN_BYTES: u64 = 64 * 1024; // 64KB
 
@@ -111,6 +115,16 @@ pub fn stable_read(offset: u64, buf: &mut [u8]) {
     CANISTER_STABLE_MEMORY.stable_read(offset, buf);
 }
 
+/// Grows `memory` so that it holds at least `len` bytes.
+fn grow_to<M: StableMemory>(memory: &M, len: u64) -> Result<(), StableMemoryError> {
+    let pages = len.div_ceil(WASM_PAGE_SIZE_IN_BYTES);
+    let size = memory.stable_size();
+    if size < pages {
+        memory.stable_grow(pages - size)?;
+    }
+    Ok(())
+}
+
 /// Returns a copy of the stable memory.
 ///
 /// This will map the whole memory (even if not all of it has been written to).
@@ -493,7 +507,7 @@ impl<M: StableMemory> io::Seek for BufferedStableReader<M> {
 }
 
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
     use super::*;
     use std::rc::Rc;
     use std::sync::Mutex;
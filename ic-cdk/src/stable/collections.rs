@@ -0,0 +1,380 @@
+//! Collections that live directly in stable memory.
+//!
+//! The collections of this module keep their elements in a [`StableMemory`] instead of on the heap, so they do not
+//! need to be saved in `pre_upgrade` and restored in `post_upgrade`: initializing a collection with the same memory
+//! after an upgrade finds its elements as they were.
+//!
+//! - [`StableBTreeMap`] is an ordered map supporting range queries.
+//! - [`StableVec`] is a growable array.
+//! - [`StableLog`] is an append-only list of entries of any size, with an index to look them up by position.
+//! - [`StableCell`] holds a single value.
+//!
+//! Each collection needs a memory of its own, such as a [`VirtualMemory`](super::memory_manager::VirtualMemory)
+//! of a memory manager. Elements are converted to and from bytes with the [`Storable`] trait, and the elements of a
+//! [`StableBTreeMap`] and a [`StableVec`] must have a maximum size, declared with [`BoundedStorable`].
+//!
+//! ```rust, no_run
+//! use ic_cdk::stable::collections::{StableBTreeMap, StableCell};
+//! use ic_cdk::stable::memory_manager::{MemoryId, MemoryManager, VirtualMemory};
+//! use ic_cdk::stable::CanisterStableMemory;
+//! use std::cell::RefCell;
+//!
+//! thread_local! {
+//!     static MEMORY_MANAGER: MemoryManager = MemoryManager::init(CanisterStableMemory::default())
+//!         .expect("the stable memory is not managed by a memory manager");
+//!     static BALANCES: RefCell<StableBTreeMap<u64, u64, VirtualMemory>> = RefCell::new(
+//!         StableBTreeMap::init(MEMORY_MANAGER.with(|manager| manager.get(MemoryId::new(0)))).unwrap(),
+//!     );
+//!     static NEXT_ID: RefCell<StableCell<u64, VirtualMemory>> = RefCell::new(
+//!         StableCell::init(MEMORY_MANAGER.with(|manager| manager.get(MemoryId::new(1))), 0).unwrap(),
+//!     );
+//! }
+//!
+//! #[ic_cdk::update]
+//! fn open_account(balance: u64) -> u64 {
+//!     let id = NEXT_ID.with_borrow_mut(|next_id| {
+//!         let id = *next_id.get();
+//!         next_id.set(id + 1).unwrap();
+//!         id
+//!     });
+//!     BALANCES.with_borrow_mut(|balances| balances.insert(id, balance)).unwrap();
+//!     id
+//! }
+//!
+//! #[ic_cdk::query]
+//! fn balances_between(first: u64, last: u64) -> Vec<(u64, u64)> {
+//!     BALANCES.with_borrow(|balances| balances.range(first..=last).collect())
+//! }
+//! ```
+//!
+//! Each collection starts with a header identifying it, so initializing a collection with a memory that holds
+//! another collection, or other data, fails with an [`InitError`].
+
+use super::{StableMemory, StableMemoryError, grow_to};
+use candid::Principal;
+use std::borrow::Cow;
+use thiserror::Error;
+
+pub mod btree_map;
+pub mod cell;
+pub mod log;
+pub mod vec;
+
+pub use btree_map::StableBTreeMap;
+pub use cell::StableCell;
+pub use log::StableLog;
+pub use vec::StableVec;
+
+/// The version of the layout of the collections.
+const LAYOUT_VERSION: u8 = 1;
+
+/// The length of the magic bytes, the layout version and the reserved bytes that start every header.
+const MAGIC_LEN: usize = 8;
+
+/// Converts a value to and from the bytes it is stored as.
+///
+/// ```rust
+/// use candid::{CandidType, Deserialize};
+/// use ic_cdk::stable::collections::Storable;
+/// use std::borrow::Cow;
+///
+/// #[derive(CandidType, Deserialize)]
+/// struct Profile {
+///     name: String,
+///     bio: String,
+/// }
+///
+/// impl Storable for Profile {
+///     fn to_bytes(&self) -> Cow<'_, [u8]> {
+///         Cow::Owned(candid::encode_one(self).unwrap())
+///     }
+///
+///     fn from_bytes(bytes: Cow<'_, [u8]>) -> Self {
+///         candid::decode_one(&bytes).unwrap()
+///     }
+/// }
+/// ```
+pub trait Storable {
+    /// Converts the value to bytes.
+    fn to_bytes(&self) -> Cow<'_, [u8]>;
+
+    /// Converts bytes returned by [`to_bytes`](Storable::to_bytes) back to a value.
+    ///
+    /// The bytes were written by the collection, so an implementation may panic if they cannot be converted.
+    fn from_bytes(bytes: Cow<'_, [u8]>) -> Self;
+}
+
+/// A [`Storable`] value whose bytes have a maximum size.
+///
+/// [`StableBTreeMap`] and [`StableVec`] reserve this size for each element. It is saved in the header of the
+/// collection, so it cannot change once the collection is created.
+pub trait BoundedStorable: Storable {
+    /// The maximum size of the bytes of a value.
+    const MAX_SIZE: u32;
+}
+
+/// The error type of the `init` functions of the collections.
+#[derive(Error, Debug)]
+pub enum InitError {
+    /// The memory holds data, but not the header of this kind of collection.
+    #[error("the stable memory holds data that is not a collection of this kind")]
+    UnrecognizedLayout,
+    /// The header of the collection was written by a newer version of this crate.
+    #[error("unsupported collection layout version {0}")]
+    UnsupportedVersion(u8),
+    /// The collection was created with a different maximum size of its elements.
+    #[error("the elements were stored with a maximum size of {saved} bytes, not {expected}")]
+    IncompatibleMaxSize {
+        /// The maximum size saved in the header.
+        saved: u32,
+        /// The [`MAX_SIZE`](BoundedStorable::MAX_SIZE) of the element type.
+        expected: u32,
+    },
+    /// The header or the initial value could not be allocated.
+    #[error("failed to allocate the collection: {0}")]
+    StableMemory(#[from] StableMemoryError),
+}
+
+/// Reads the magic bytes and the layout version at the start of `memory`, or writes them and allocates `header_len`
+/// bytes if it is empty.
+///
+/// Returns whether the header was written.
+fn init_header<M: StableMemory>(
+    memory: &M,
+    magic: &[u8; 4],
+    header_len: u64,
+) -> Result<bool, InitError> {
+    let mut header = [0; MAGIC_LEN];
+    if memory.stable_size() == 0 {
+        grow_to(memory, header_len)?;
+        header[..4].copy_from_slice(magic);
+        header[4] = LAYOUT_VERSION;
+        memory.stable_write(0, &header);
+        return Ok(true);
+    }
+    memory.stable_read(0, &mut header);
+    if &header[..4] != magic {
+        return Err(InitError::UnrecognizedLayout);
+    }
+    if header[4] != LAYOUT_VERSION {
+        return Err(InitError::UnsupportedVersion(header[4]));
+    }
+    Ok(false)
+}
+
+/// Writes the maximum size of the elements at `offset` in a new header, or checks it against the one in an existing
+/// header.
+fn init_max_size<M: StableMemory>(
+    memory: &M,
+    offset: u64,
+    expected: u32,
+    created: bool,
+) -> Result<(), InitError> {
+    if created {
+        memory.stable_write(offset, &expected.to_le_bytes());
+        return Ok(());
+    }
+    let mut saved = [0; 4];
+    memory.stable_read(offset, &mut saved);
+    let saved = u32::from_le_bytes(saved);
+    if saved != expected {
+        return Err(InitError::IncompatibleMaxSize { saved, expected });
+    }
+    Ok(())
+}
+
+fn read_u64<M: StableMemory>(memory: &M, offset: u64) -> u64 {
+    let mut bytes = [0; 8];
+    memory.stable_read(offset, &mut bytes);
+    u64::from_le_bytes(bytes)
+}
+
+fn write_u64<M: StableMemory>(memory: &M, offset: u64, value: u64) {
+    memory.stable_write(offset, &value.to_le_bytes());
+}
+
+/// Converts a value to bytes, checking them against its maximum size.
+///
+/// # Panics
+///
+/// Panics if the bytes are larger than [`BoundedStorable::MAX_SIZE`].
+fn to_bounded_bytes<T: BoundedStorable>(value: &T) -> Cow<'_, [u8]> {
+    let bytes = value.to_bytes();
+    assert!(
+        bytes.len() <= T::MAX_SIZE as usize,
+        "the value of {} bytes exceeds the maximum size of {} bytes",
+        bytes.len(),
+        T::MAX_SIZE
+    );
+    bytes
+}
+
+macro_rules! impl_storable_for_int {
+    ($($t:ty),*) => {
+        $(
+            impl Storable for $t {
+                fn to_bytes(&self) -> Cow<'_, [u8]> {
+                    Cow::Owned(self.to_be_bytes().to_vec())
+                }
+
+                fn from_bytes(bytes: Cow<'_, [u8]>) -> Self {
+                    Self::from_be_bytes(bytes.as_ref().try_into().expect("invalid integer bytes"))
+                }
+            }
+
+            impl BoundedStorable for $t {
+                const MAX_SIZE: u32 = size_of::<$t>() as u32;
+            }
+        )*
+    };
+}
+
+impl_storable_for_int!(u8, u16, u32, u64, u128, i8, i16, i32, i64, i128);
+
+impl Storable for bool {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(vec![u8::from(*self)])
+    }
+
+    fn from_bytes(bytes: Cow<'_, [u8]>) -> Self {
+        match bytes.as_ref() {
+            [0] => false,
+            [1] => true,
+            _ => panic!("invalid bool bytes"),
+        }
+    }
+}
+
+impl BoundedStorable for bool {
+    const MAX_SIZE: u32 = 1;
+}
+
+impl Storable for () {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Borrowed(&[])
+    }
+
+    fn from_bytes(_: Cow<'_, [u8]>) -> Self {}
+}
+
+impl BoundedStorable for () {
+    const MAX_SIZE: u32 = 0;
+}
+
+impl<const N: usize> Storable for [u8; N] {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Borrowed(self)
+    }
+
+    fn from_bytes(bytes: Cow<'_, [u8]>) -> Self {
+        bytes.as_ref().try_into().expect("invalid array bytes")
+    }
+}
+
+impl<const N: usize> BoundedStorable for [u8; N] {
+    const MAX_SIZE: u32 = N as u32;
+}
+
+impl Storable for Vec<u8> {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Borrowed(self)
+    }
+
+    fn from_bytes(bytes: Cow<'_, [u8]>) -> Self {
+        bytes.into_owned()
+    }
+}
+
+impl Storable for String {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Borrowed(self.as_bytes())
+    }
+
+    fn from_bytes(bytes: Cow<'_, [u8]>) -> Self {
+        String::from_utf8(bytes.into_owned()).expect("invalid UTF-8 bytes")
+    }
+}
+
+impl Storable for Principal {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Borrowed(self.as_slice())
+    }
+
+    fn from_bytes(bytes: Cow<'_, [u8]>) -> Self {
+        Principal::from_slice(&bytes)
+    }
+}
+
+impl BoundedStorable for Principal {
+    const MAX_SIZE: u32 = 29;
+}
+
+/// A pair is stored as the length of the bytes of its first element, followed by the bytes of both elements.
+impl<A: BoundedStorable, B: BoundedStorable> Storable for (A, B) {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        let (a, b) = (to_bounded_bytes(&self.0), to_bounded_bytes(&self.1));
+        let mut bytes = Vec::with_capacity(4 + a.len() + b.len());
+        bytes.extend_from_slice(&(a.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&a);
+        bytes.extend_from_slice(&b);
+        Cow::Owned(bytes)
+    }
+
+    fn from_bytes(bytes: Cow<'_, [u8]>) -> Self {
+        let (len, rest) = bytes.split_at(4);
+        let len = u32::from_le_bytes(len.try_into().unwrap()) as usize;
+        let (a, b) = rest.split_at(len);
+        (
+            A::from_bytes(Cow::Borrowed(a)),
+            B::from_bytes(Cow::Borrowed(b)),
+        )
+    }
+}
+
+impl<A: BoundedStorable, B: BoundedStorable> BoundedStorable for (A, B) {
+    const MAX_SIZE: u32 = 4 + A::MAX_SIZE + B::MAX_SIZE;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip<T: Storable + PartialEq + std::fmt::Debug>(value: T) {
+        assert_eq!(T::from_bytes(value.to_bytes()), value);
+    }
+
+    #[test]
+    fn values_round_trip() {
+        round_trip(u128::MAX);
+        round_trip(-5_i32);
+        round_trip(true);
+        round_trip(());
+        round_trip([1_u8, 2, 3]);
+        round_trip(vec![4_u8, 5]);
+        round_trip("hello".to_string());
+        round_trip(Principal::management_canister());
+        round_trip((7_u64, Principal::anonymous()));
+    }
+
+    #[test]
+    #[should_panic(expected = "the value of 3 bytes exceeds the maximum size of 2 bytes")]
+    fn oversized_values_panic() {
+        struct Short(Vec<u8>);
+
+        impl Storable for Short {
+            fn to_bytes(&self) -> Cow<'_, [u8]> {
+                Cow::Borrowed(&self.0)
+            }
+
+            fn from_bytes(bytes: Cow<'_, [u8]>) -> Self {
+                Self(bytes.into_owned())
+            }
+        }
+
+        impl BoundedStorable for Short {
+            const MAX_SIZE: u32 = 2;
+        }
+
+        to_bounded_bytes(&Short(vec![1, 2, 3]));
+    }
+}
@@ -0,0 +1,773 @@
+//! An ordered map in stable memory.
+//!
+//! The map is a B-tree whose nodes are stored in the memory, and read and written one at a time, so an operation on
+//! a map of `n` entries reads `O(log n)` nodes however large the map grows.
+//!
+//! The header holds the magic bytes `CBTM`, the layout version, the maximum sizes of the keys and the values, the
+//! address of the root node, the number of entries, the head of the list of free nodes, and the end of the allocated
+//! nodes. Each node is a slot of a fixed size, holding up to 11 entries, each in a slot of the maximum sizes prefixed
+//! with the lengths of the key and the value, and the addresses of up to 12 children. Nodes freed by removals are
+//! reused by later insertions.
+
+use super::{
+    BoundedStorable, InitError, grow_to, init_header, init_max_size, read_u64, to_bounded_bytes,
+    write_u64,
+};
+use crate::stable::{CanisterStableMemory, StableMemory, StableMemoryError};
+use std::borrow::Cow;
+use std::fmt;
+use std::marker::PhantomData;
+use std::ops::{Bound, RangeBounds};
+
+const MAGIC: &[u8; 4] = b"CBTM";
+
+/// The offsets of the fields of the header.
+const MAX_KEY_SIZE_OFFSET: u64 = 8;
+const MAX_VALUE_SIZE_OFFSET: u64 = 12;
+const ROOT_OFFSET: u64 = 16;
+const LEN_OFFSET: u64 = 24;
+const FREE_OFFSET: u64 = 32;
+const END_OFFSET: u64 = 40;
+
+/// The length of the header, before the first node.
+const HEADER_LEN: u64 = 48;
+
+/// The minimum degree of the B-tree: every node but the root holds at least `B - 1` entries.
+const B: usize = 6;
+
+/// The maximum number of entries of a node.
+const CAPACITY: usize = 2 * B - 1;
+
+/// The address of no node, since the header is at 0.
+const NULL: u64 = 0;
+
+/// The length of the kind and the number of entries at the start of a node.
+const NODE_HEADER_LEN: usize = 8;
+
+const LEAF: u8 = 0;
+const INTERNAL: u8 = 1;
+
+/// An ordered map of [`BoundedStorable`] keys and values in a [`StableMemory`].
+///
+/// Keys are ordered by their [`Ord`] implementation, not by their bytes.
+pub struct StableBTreeMap<K, V, M: StableMemory = CanisterStableMemory> {
+    memory: M,
+    root: u64,
+    len: u64,
+    free: u64,
+    end: u64,
+    _marker: PhantomData<(K, V)>,
+}
+
+/// A node read from the memory.
+struct Node<K> {
+    address: u64,
+    leaf: bool,
+    keys: Vec<K>,
+    values: Vec<Vec<u8>>,
+    children: Vec<u64>,
+}
+
+impl<K, V, M> StableBTreeMap<K, V, M>
+where
+    K: BoundedStorable + Ord + Clone,
+    V: BoundedStorable,
+    M: StableMemory,
+{
+    /// Initializes a map in `memory`.
+    ///
+    /// If `memory` is empty, an empty map is created. Otherwise, the map it holds is loaded.
+    ///
+    /// # Errors
+    ///
+    /// Fails if `memory` holds something other than a map, or a map of keys or values with a different maximum size.
+    pub fn init(memory: M) -> Result<Self, InitError> {
+        let created = init_header(&memory, MAGIC, HEADER_LEN)?;
+        init_max_size(&memory, MAX_KEY_SIZE_OFFSET, K::MAX_SIZE, created)?;
+        init_max_size(&memory, MAX_VALUE_SIZE_OFFSET, V::MAX_SIZE, created)?;
+        if created {
+            write_u64(&memory, END_OFFSET, HEADER_LEN);
+        }
+        Ok(Self {
+            root: read_u64(&memory, ROOT_OFFSET),
+            len: read_u64(&memory, LEN_OFFSET),
+            free: read_u64(&memory, FREE_OFFSET),
+            end: read_u64(&memory, END_OFFSET),
+            memory,
+            _marker: PhantomData,
+        })
+    }
+
+    /// Gets the number of entries.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Checks whether the map has no entries.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Gets the value of `key`.
+    pub fn get(&self, key: &K) -> Option<V> {
+        let mut address = self.root;
+        while address != NULL {
+            let node = self.load(address);
+            match node.keys.binary_search(key) {
+                Ok(i) => return Some(decode(&node.values[i])),
+                Err(_) if node.leaf => return None,
+                Err(i) => address = node.children[i],
+            }
+        }
+        None
+    }
+
+    /// Checks whether the map holds `key`.
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// Inserts an entry, and returns the previous value of `key`.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the memory cannot be grown to hold the nodes the insertion may need, in which case the map is
+    /// unchanged.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the bytes of `key` or `value` exceed their maximum size.
+    pub fn insert(&mut self, key: K, value: V) -> Result<Option<V>, StableMemoryError> {
+        // Check the sizes before anything is written.
+        let value = to_bounded_bytes(&value).into_owned();
+        to_bounded_bytes(&key);
+        // Each level may split a node, and splitting the root adds a level.
+        grow_to(
+            &self.memory,
+            self.end + (self.depth() + 1) * Self::node_size(),
+        )?;
+
+        if self.root == NULL {
+            let address = self.allocate();
+            self.save(&Node {
+                address,
+                leaf: true,
+                keys: vec![key],
+                values: vec![value],
+                children: Vec::new(),
+            });
+            self.root = address;
+            self.len = 1;
+            self.save_header();
+            return Ok(None);
+        }
+
+        let mut node = self.load(self.root);
+        if node.keys.len() == CAPACITY {
+            let mut root = Node {
+                address: self.allocate(),
+                leaf: false,
+                keys: Vec::new(),
+                values: Vec::new(),
+                children: vec![node.address],
+            };
+            self.split_child(&mut root, 0, node);
+            self.root = root.address;
+            node = root;
+        }
+        let previous = loop {
+            match node.keys.binary_search(&key) {
+                Ok(i) => {
+                    let previous = std::mem::replace(&mut node.values[i], value);
+                    self.save(&node);
+                    break Some(decode(&previous));
+                }
+                Err(i) if node.leaf => {
+                    node.keys.insert(i, key);
+                    node.values.insert(i, value);
+                    self.save(&node);
+                    self.len += 1;
+                    break None;
+                }
+                Err(i) => {
+                    let child = self.load(node.children[i]);
+                    if child.keys.len() == CAPACITY {
+                        // The median of the child moves up to `i`, so search this node again.
+                        self.split_child(&mut node, i, child);
+                    } else {
+                        node = child;
+                    }
+                }
+            }
+        };
+        self.save_header();
+        Ok(previous)
+    }
+
+    /// Removes `key`, and returns its value.
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        if self.root == NULL {
+            return None;
+        }
+        let root = self.load(self.root);
+        // The nodes may be restructured even if the key is not found, so the header is saved either way.
+        let value = self.remove_from(root, key);
+        if value.is_some() {
+            self.len -= 1;
+            if self.len == 0 {
+                self.deallocate(self.root);
+                self.root = NULL;
+            }
+        }
+        self.save_header();
+        value.map(|value| decode(&value))
+    }
+
+    /// Removes all the entries. The memory of the nodes is kept for the entries inserted afterwards.
+    pub fn clear(&mut self) {
+        self.root = NULL;
+        self.len = 0;
+        self.free = NULL;
+        self.end = HEADER_LEN;
+        self.save_header();
+    }
+
+    /// Gets the entry with the smallest key.
+    pub fn first_key_value(&self) -> Option<(K, V)> {
+        self.iter().next()
+    }
+
+    /// Gets the entry with the largest key.
+    pub fn last_key_value(&self) -> Option<(K, V)> {
+        self.iter().next_back()
+    }
+
+    /// Gets an iterator over the entries, ordered by key.
+    pub fn iter(&self) -> Iter<'_, K, V, M> {
+        self.range(..)
+    }
+
+    /// Gets an iterator over the entries whose keys are within `range`, ordered by key.
+    ///
+    /// Each step of the iteration searches the map from the root, so entries inserted or removed by the time the
+    /// iterator advances are taken into account.
+    pub fn range(&self, range: impl RangeBounds<K>) -> Iter<'_, K, V, M> {
+        Iter {
+            map: self,
+            lower: range.start_bound().cloned(),
+            upper: range.end_bound().cloned(),
+        }
+    }
+
+    /// Gets the number of levels of nodes below the root.
+    fn depth(&self) -> u64 {
+        let mut depth = 0;
+        let mut address = self.root;
+        while address != NULL {
+            let node = self.load(address);
+            address = node.children.first().copied().unwrap_or(NULL);
+            depth += 1;
+        }
+        depth
+    }
+
+    /// Moves the upper half of the full child at `i` to a new node, and its median entry up to the parent.
+    fn split_child(&mut self, parent: &mut Node<K>, i: usize, mut child: Node<K>) {
+        let right = Node {
+            address: self.allocate(),
+            leaf: child.leaf,
+            keys: child.keys.split_off(B),
+            values: child.values.split_off(B),
+            children: if child.leaf {
+                Vec::new()
+            } else {
+                child.children.split_off(B)
+            },
+        };
+        let key = child.keys.pop().unwrap();
+        let value = child.values.pop().unwrap();
+        parent.keys.insert(i, key);
+        parent.values.insert(i, value);
+        parent.children.insert(i + 1, right.address);
+        self.save(&child);
+        self.save(&right);
+        self.save(parent);
+    }
+
+    /// Removes `key` from the subtree of `node`, which is the root or holds at least `B` entries.
+    ///
+    /// Every node the search descends to is first given at least `B` entries, so that removing an entry from a leaf
+    /// never leaves it with fewer than `B - 1`.
+    fn remove_from(&mut self, mut node: Node<K>, key: &K) -> Option<Vec<u8>> {
+        loop {
+            match node.keys.binary_search(key) {
+                Ok(i) if node.leaf => {
+                    node.keys.remove(i);
+                    let value = node.values.remove(i);
+                    self.save(&node);
+                    return Some(value);
+                }
+                Ok(i) => {
+                    let left = self.load(node.children[i]);
+                    if left.keys.len() >= B {
+                        // Replace the entry with its predecessor, the largest entry of the left subtree.
+                        let predecessor = self.last_key(left.address);
+                        let value = self.remove_from(left, &predecessor).unwrap();
+                        node.keys[i] = predecessor;
+                        let removed = std::mem::replace(&mut node.values[i], value);
+                        self.save(&node);
+                        return Some(removed);
+                    }
+                    let right = self.load(node.children[i + 1]);
+                    if right.keys.len() >= B {
+                        // Replace the entry with its successor, the smallest entry of the right subtree.
+                        let successor = self.first_key(right.address);
+                        let value = self.remove_from(right, &successor).unwrap();
+                        node.keys[i] = successor;
+                        let removed = std::mem::replace(&mut node.values[i], value);
+                        self.save(&node);
+                        return Some(removed);
+                    }
+                    // Both children have `B - 1` entries: merge them around the entry, and remove it from the result.
+                    node = self.merge(node, i, left, right);
+                }
+                Err(_) if node.leaf => return None,
+                Err(i) => {
+                    let child = self.load(node.children[i]);
+                    node = if child.keys.len() >= B {
+                        child
+                    } else {
+                        self.fill(node, i, child)
+                    };
+                }
+            }
+        }
+    }
+
+    /// Gives the child at `i` of `parent`, which has `B - 1` entries, at least `B` entries, by moving an entry from a
+    /// sibling through the parent, or by merging it with a sibling. Returns the node holding the entries of the child.
+    fn fill(&mut self, mut parent: Node<K>, i: usize, mut child: Node<K>) -> Node<K> {
+        let left = (i > 0).then(|| self.load(parent.children[i - 1]));
+        match left {
+            Some(mut left) if left.keys.len() >= B => {
+                let key = std::mem::replace(&mut parent.keys[i - 1], left.keys.pop().unwrap());
+                let value =
+                    std::mem::replace(&mut parent.values[i - 1], left.values.pop().unwrap());
+                child.keys.insert(0, key);
+                child.values.insert(0, value);
+                if !child.leaf {
+                    child.children.insert(0, left.children.pop().unwrap());
+                }
+                self.save(&left);
+                self.save(&child);
+                self.save(&parent);
+                return child;
+            }
+            Some(left) if i == parent.keys.len() => return self.merge(parent, i - 1, left, child),
+            _ => {}
+        }
+        let mut right = self.load(parent.children[i + 1]);
+        if right.keys.len() < B {
+            return self.merge(parent, i, child, right);
+        }
+        let key = std::mem::replace(&mut parent.keys[i], right.keys.remove(0));
+        let value = std::mem::replace(&mut parent.values[i], right.values.remove(0));
+        child.keys.push(key);
+        child.values.push(value);
+        if !child.leaf {
+            child.children.push(right.children.remove(0));
+        }
+        self.save(&right);
+        self.save(&child);
+        self.save(&parent);
+        child
+    }
+
+    /// Merges the children at `i` and `i + 1` of `parent` and the entry between them into the left child, and returns
+    /// it. If the parent is the root and is left empty, the merged node becomes the root.
+    fn merge(
+        &mut self,
+        mut parent: Node<K>,
+        i: usize,
+        mut left: Node<K>,
+        right: Node<K>,
+    ) -> Node<K> {
+        left.keys.push(parent.keys.remove(i));
+        left.values.push(parent.values.remove(i));
+        left.keys.extend(right.keys);
+        left.values.extend(right.values);
+        left.children.extend(right.children);
+        parent.children.remove(i + 1);
+        self.deallocate(right.address);
+        self.save(&left);
+        if parent.address == self.root && parent.keys.is_empty() {
+            self.deallocate(parent.address);
+            self.root = left.address;
+        } else {
+            self.save(&parent);
+        }
+        left
+    }
+
+    fn first_key(&self, mut address: u64) -> K {
+        loop {
+            let mut node = self.load(address);
+            if node.leaf {
+                return node.keys.swap_remove(0);
+            }
+            address = node.children[0];
+        }
+    }
+
+    fn last_key(&self, mut address: u64) -> K {
+        loop {
+            let mut node = self.load(address);
+            if node.leaf {
+                return node.keys.pop().unwrap();
+            }
+            address = *node.children.last().unwrap();
+        }
+    }
+
+    /// Finds the entry with the smallest key after `lower`.
+    fn find_first(&self, lower: Bound<&K>) -> Option<(K, Vec<u8>)> {
+        let mut found = None;
+        let mut address = self.root;
+        while address != NULL {
+            let mut node = self.load(address);
+            let i = match lower {
+                Bound::Unbounded => 0,
+                Bound::Included(key) => node.keys.partition_point(|k| k < key),
+                Bound::Excluded(key) => node.keys.partition_point(|k| k <= key),
+            };
+            address = if node.leaf { NULL } else { node.children[i] };
+            // The entries of the child are smaller than this one, so a match found there takes precedence.
+            if i < node.keys.len() {
+                found = Some((node.keys.swap_remove(i), node.values.swap_remove(i)));
+            }
+        }
+        found
+    }
+
+    /// Finds the entry with the largest key before `upper`.
+    fn find_last(&self, upper: Bound<&K>) -> Option<(K, Vec<u8>)> {
+        let mut found = None;
+        let mut address = self.root;
+        while address != NULL {
+            let mut node = self.load(address);
+            let i = match upper {
+                Bound::Unbounded => node.keys.len(),
+                Bound::Included(key) => node.keys.partition_point(|k| k <= key),
+                Bound::Excluded(key) => node.keys.partition_point(|k| k < key),
+            };
+            address = if node.leaf { NULL } else { node.children[i] };
+            if i > 0 {
+                found = Some((node.keys.swap_remove(i - 1), node.values.swap_remove(i - 1)));
+            }
+        }
+        found
+    }
+
+    fn entry_size() -> usize {
+        8 + K::MAX_SIZE as usize + V::MAX_SIZE as usize
+    }
+
+    fn node_size() -> u64 {
+        (NODE_HEADER_LEN + CAPACITY * Self::entry_size() + 8 * (CAPACITY + 1)) as u64
+    }
+
+    /// Takes a node from the list of free nodes, or from the end of the allocated nodes.
+    ///
+    /// The memory must have been grown to hold it beforehand.
+    fn allocate(&mut self) -> u64 {
+        if self.free != NULL {
+            let address = self.free;
+            self.free = read_u64(&self.memory, address);
+            return address;
+        }
+        let address = self.end;
+        self.end += Self::node_size();
+        address
+    }
+
+    /// Adds a node to the list of free nodes.
+    fn deallocate(&mut self, address: u64) {
+        write_u64(&self.memory, address, self.free);
+        self.free = address;
+    }
+
+    fn load(&self, address: u64) -> Node<K> {
+        let mut bytes = vec![0; Self::node_size() as usize];
+        self.memory.stable_read(address, &mut bytes);
+        let leaf = bytes[0] == LEAF;
+        let len = usize::from(u16::from_le_bytes([bytes[2], bytes[3]]));
+        let mut node = Node {
+            address,
+            leaf,
+            keys: Vec::with_capacity(CAPACITY),
+            values: Vec::with_capacity(CAPACITY),
+            children: Vec::new(),
+        };
+        for i in 0..len {
+            let entry = &bytes[NODE_HEADER_LEN + i * Self::entry_size()..];
+            let (key, rest) = read_slot(entry, K::MAX_SIZE);
+            let (value, _) = read_slot(rest, V::MAX_SIZE);
+            node.keys.push(K::from_bytes(Cow::Borrowed(key)));
+            node.values.push(value.to_vec());
+        }
+        if !leaf {
+            let children = &bytes[NODE_HEADER_LEN + CAPACITY * Self::entry_size()..];
+            node.children = children.as_chunks::<8>().0[..=len]
+                .iter()
+                .map(|address| u64::from_le_bytes(*address))
+                .collect();
+        }
+        node
+    }
+
+    fn save(&self, node: &Node<K>) {
+        let mut bytes = vec![0; Self::node_size() as usize];
+        bytes[0] = if node.leaf { LEAF } else { INTERNAL };
+        bytes[2..4].copy_from_slice(&(node.keys.len() as u16).to_le_bytes());
+        for (i, (key, value)) in node.keys.iter().zip(&node.values).enumerate() {
+            let entry = &mut bytes[NODE_HEADER_LEN + i * Self::entry_size()..];
+            let rest = write_slot(entry, K::MAX_SIZE, &key.to_bytes());
+            write_slot(rest, V::MAX_SIZE, value);
+        }
+        let children = NODE_HEADER_LEN + CAPACITY * Self::entry_size();
+        for (i, child) in node.children.iter().enumerate() {
+            bytes[children + 8 * i..children + 8 * (i + 1)].copy_from_slice(&child.to_le_bytes());
+        }
+        self.memory.stable_write(node.address, &bytes);
+    }
+
+    fn save_header(&self) {
+        write_u64(&self.memory, ROOT_OFFSET, self.root);
+        write_u64(&self.memory, LEN_OFFSET, self.len);
+        write_u64(&self.memory, FREE_OFFSET, self.free);
+        write_u64(&self.memory, END_OFFSET, self.end);
+    }
+}
+
+/// Reads the bytes of a slot of `max_size` bytes prefixed with their length, and returns them with the bytes after
+/// the slot.
+fn read_slot(bytes: &[u8], max_size: u32) -> (&[u8], &[u8]) {
+    let len = u32::from_le_bytes(bytes[..4].try_into().unwrap()) as usize;
+    (&bytes[4..4 + len], &bytes[4 + max_size as usize..])
+}
+
+/// Writes `value` to a slot of `max_size` bytes prefixed with its length, and returns the bytes after the slot.
+fn write_slot<'a>(bytes: &'a mut [u8], max_size: u32, value: &[u8]) -> &'a mut [u8] {
+    bytes[..4].copy_from_slice(&(value.len() as u32).to_le_bytes());
+    bytes[4..4 + value.len()].copy_from_slice(value);
+    &mut bytes[4 + max_size as usize..]
+}
+
+fn decode<V: BoundedStorable>(bytes: &[u8]) -> V {
+    V::from_bytes(Cow::Borrowed(bytes))
+}
+
+impl<K, V, M: StableMemory> fmt::Debug for StableBTreeMap<K, V, M> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("StableBTreeMap")
+            .field("len", &self.len)
+            .finish()
+    }
+}
+
+impl<'a, K, V, M> IntoIterator for &'a StableBTreeMap<K, V, M>
+where
+    K: BoundedStorable + Ord + Clone,
+    V: BoundedStorable,
+    M: StableMemory,
+{
+    type Item = (K, V);
+    type IntoIter = Iter<'a, K, V, M>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// An iterator over the entries of a [`StableBTreeMap`], returned by [`StableBTreeMap::iter`] and
+/// [`StableBTreeMap::range`].
+pub struct Iter<'a, K, V, M: StableMemory = CanisterStableMemory> {
+    map: &'a StableBTreeMap<K, V, M>,
+    lower: Bound<K>,
+    upper: Bound<K>,
+}
+
+impl<K: fmt::Debug, V, M: StableMemory> fmt::Debug for Iter<'_, K, V, M> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Iter")
+            .field("lower", &self.lower)
+            .field("upper", &self.upper)
+            .finish()
+    }
+}
+
+impl<K, V, M> Iterator for Iter<'_, K, V, M>
+where
+    K: BoundedStorable + Ord + Clone,
+    V: BoundedStorable,
+    M: StableMemory,
+{
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<(K, V)> {
+        let (key, value) = self.map.find_first(self.lower.as_ref())?;
+        let within = match &self.upper {
+            Bound::Unbounded => true,
+            Bound::Included(upper) => key <= *upper,
+            Bound::Excluded(upper) => key < *upper,
+        };
+        if !within {
+            return None;
+        }
+        self.lower = Bound::Excluded(key.clone());
+        Some((key, decode(&value)))
+    }
+}
+
+impl<K, V, M> DoubleEndedIterator for Iter<'_, K, V, M>
+where
+    K: BoundedStorable + Ord + Clone,
+    V: BoundedStorable,
+    M: StableMemory,
+{
+    fn next_back(&mut self) -> Option<(K, V)> {
+        let (key, value) = self.map.find_last(self.upper.as_ref())?;
+        let within = match &self.lower {
+            Bound::Unbounded => true,
+            Bound::Included(lower) => key >= *lower,
+            Bound::Excluded(lower) => key > *lower,
+        };
+        if !within {
+            return None;
+        }
+        self.upper = Bound::Excluded(key.clone());
+        Some((key, decode(&value)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stable::tests::TestStableMemory;
+    use std::collections::BTreeMap;
+    use std::rc::Rc;
+    use std::sync::Mutex;
+
+    /// A deterministic sequence of keys in a scrambled order.
+    fn keys(n: u64) -> impl Iterator<Item = u64> {
+        (0..n).map(move |i| (i * 7_919) % n)
+    }
+
+    #[test]
+    fn entries_match_a_btree_map() {
+        let mut map = StableBTreeMap::init(TestStableMemory::default()).unwrap();
+        let mut expected = BTreeMap::new();
+        for key in keys(2_000) {
+            assert_eq!(
+                map.insert(key, key * 2).unwrap(),
+                expected.insert(key, key * 2)
+            );
+        }
+        assert_eq!(map.insert(10, 0).unwrap(), expected.insert(10, 0));
+        assert_eq!(map.len(), 2_000);
+        assert!(map.iter().eq(expected.clone()));
+
+        // Remove every third key, in a different order than they were inserted.
+        for key in keys(2_000)
+            .filter(|key| key % 3 == 0)
+            .collect::<Vec<_>>()
+            .into_iter()
+            .rev()
+        {
+            assert_eq!(map.remove(&key), expected.remove(&key));
+        }
+        assert_eq!(map.remove(&3), None);
+        assert_eq!(map.len(), expected.len() as u64);
+        assert!(map.iter().eq(expected.clone()));
+        assert!(map.iter().rev().eq(expected.clone().into_iter().rev()));
+        assert_eq!(map.get(&1), Some(2));
+        assert!(!map.contains_key(&999));
+
+        for key in keys(2_000) {
+            assert_eq!(map.remove(&key), expected.remove(&key));
+        }
+        assert!(map.is_empty());
+        assert_eq!(map.iter().next(), None);
+    }
+
+    #[test]
+    fn ranges_are_queried() {
+        let mut map = StableBTreeMap::init(TestStableMemory::default()).unwrap();
+        for key in keys(500) {
+            map.insert(key * 2, ()).unwrap();
+        }
+        let range = |range: (Bound<u64>, Bound<u64>)| {
+            map.range(range).map(|(key, ())| key).collect::<Vec<_>>()
+        };
+        assert_eq!(
+            range((Bound::Included(10), Bound::Excluded(20))),
+            [10, 12, 14, 16, 18]
+        );
+        assert_eq!(range((Bound::Excluded(11), Bound::Included(15))), [12, 14]);
+        assert_eq!(range((Bound::Included(995), Bound::Unbounded)), [996, 998]);
+        assert_eq!(
+            range((Bound::Excluded(998), Bound::Unbounded)),
+            Vec::<u64>::new()
+        );
+        assert_eq!(
+            map.range(..5)
+                .rev()
+                .map(|(key, ())| key)
+                .collect::<Vec<_>>(),
+            [4, 2, 0]
+        );
+
+        let mut iter = map.range(100..=106);
+        assert_eq!(iter.next(), Some((100, ())));
+        assert_eq!(iter.next_back(), Some((106, ())));
+        assert_eq!(iter.collect::<Vec<_>>(), [(102, ()), (104, ())]);
+        assert_eq!(map.first_key_value(), Some((0, ())));
+        assert_eq!(map.last_key_value(), Some((998, ())));
+    }
+
+    #[test]
+    fn entries_survive_reinitialization() {
+        let bytes = Rc::new(Mutex::new(Vec::new()));
+        let mut map = StableBTreeMap::init(TestStableMemory::new(bytes.clone())).unwrap();
+        for key in 0..100_u32 {
+            map.insert(key, (key, [key as u8; 3])).unwrap();
+        }
+        drop(map);
+
+        let mut map =
+            StableBTreeMap::<u32, (u32, [u8; 3]), _>::init(TestStableMemory::new(bytes.clone()))
+                .unwrap();
+        assert_eq!(map.len(), 100);
+        assert_eq!(map.get(&42), Some((42, [42; 3])));
+        assert!(matches!(
+            StableBTreeMap::<u64, (u32, [u8; 3]), _>::init(TestStableMemory::new(bytes.clone())),
+            Err(InitError::IncompatibleMaxSize {
+                saved: 4,
+                expected: 8
+            })
+        ));
+
+        // Nodes freed by removals are reused.
+        for key in 0..100 {
+            map.remove(&key);
+        }
+        let end = map.end;
+        for key in 0..100 {
+            map.insert(key, (key, [0; 3])).unwrap();
+        }
+        assert_eq!(map.end, end);
+        map.clear();
+        assert!(map.is_empty());
+        assert_eq!(map.get(&42), None);
+    }
+}
@@ -0,0 +1,157 @@
+//! A single value in stable memory.
+//!
+//! The header holds the magic bytes `CCEL`, the layout version, and the length of the bytes of the value, which
+//! follow it.
+
+use super::{InitError, Storable, grow_to, init_header, read_u64, write_u64};
+use crate::stable::{CanisterStableMemory, StableMemory, StableMemoryError};
+use std::borrow::Cow;
+use std::fmt;
+
+const MAGIC: &[u8; 4] = b"CCEL";
+
+/// The offset of the length of the value in the header.
+const LEN_OFFSET: u64 = 8;
+
+/// The length of the header, before the value.
+const HEADER_LEN: u64 = 16;
+
+/// A single [`Storable`] value in a [`StableMemory`].
+///
+/// The value is also kept on the heap, so reading it is free, and only [`set`](StableCell::set) writes to the memory.
+pub struct StableCell<T, M: StableMemory = CanisterStableMemory> {
+    memory: M,
+    value: T,
+}
+
+impl<T: Storable, M: StableMemory> StableCell<T, M> {
+    /// Initializes a cell in `memory`.
+    ///
+    /// If `memory` is empty, a cell holding `default` is created. Otherwise, the value it holds is loaded, and
+    /// `default` is dropped.
+    ///
+    /// # Errors
+    ///
+    /// Fails if `memory` holds something other than a cell, or `default` cannot be saved.
+    pub fn init(memory: M, default: T) -> Result<Self, InitError> {
+        if init_header(&memory, MAGIC, HEADER_LEN)? {
+            let cell = Self {
+                memory,
+                value: default,
+            };
+            cell.save()?;
+            return Ok(cell);
+        }
+        let len = read_u64(&memory, LEN_OFFSET);
+        let mut bytes = vec![0; len as usize];
+        memory.stable_read(HEADER_LEN, &mut bytes);
+        Ok(Self {
+            value: T::from_bytes(Cow::Owned(bytes)),
+            memory,
+        })
+    }
+
+    /// Gets the value.
+    pub fn get(&self) -> &T {
+        &self.value
+    }
+
+    /// Replaces the value, and returns the previous one.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the memory cannot be grown to hold the value, in which case the cell is unchanged.
+    pub fn set(&mut self, value: T) -> Result<T, StableMemoryError> {
+        let previous = std::mem::replace(&mut self.value, value);
+        if let Err(error) = self.save() {
+            self.value = previous;
+            return Err(error);
+        }
+        Ok(previous)
+    }
+
+    fn save(&self) -> Result<(), StableMemoryError> {
+        let bytes = self.value.to_bytes();
+        grow_to(&self.memory, HEADER_LEN + bytes.len() as u64)?;
+        self.memory.stable_write(HEADER_LEN, &bytes);
+        write_u64(&self.memory, LEN_OFFSET, bytes.len() as u64);
+        Ok(())
+    }
+}
+
+impl<T: fmt::Debug, M: StableMemory> fmt::Debug for StableCell<T, M> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("StableCell")
+            .field("value", &self.value)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stable::WASM_PAGE_SIZE_IN_BYTES;
+    use crate::stable::collections::StableVec;
+    use crate::stable::tests::TestStableMemory;
+    use std::rc::Rc;
+    use std::sync::Mutex;
+
+    #[test]
+    fn value_survives_reinitialization() {
+        let bytes = Rc::new(Mutex::new(Vec::new()));
+        let mut cell =
+            StableCell::init(TestStableMemory::new(bytes.clone()), "a".to_string()).unwrap();
+        assert_eq!(cell.get(), "a");
+        assert_eq!(cell.set("b".repeat(100_000)).unwrap(), "a");
+        assert_eq!(cell.set("c".to_string()).unwrap(), "b".repeat(100_000));
+        drop(cell);
+
+        let cell = StableCell::init(TestStableMemory::new(bytes), String::new()).unwrap();
+        assert_eq!(cell.get(), "c");
+    }
+
+    #[test]
+    fn other_collections_are_rejected() {
+        let bytes = Rc::new(Mutex::new(Vec::new()));
+        StableVec::<u8, _>::init(TestStableMemory::new(bytes.clone())).unwrap();
+        assert!(matches!(
+            StableCell::init(TestStableMemory::new(bytes), 0_u8),
+            Err(InitError::UnrecognizedLayout)
+        ));
+    }
+
+    #[test]
+    fn failed_growth_keeps_the_value() {
+        /// A memory that cannot grow beyond one page.
+        struct SmallMemory(TestStableMemory);
+
+        impl StableMemory for SmallMemory {
+            fn stable_size(&self) -> u64 {
+                self.0.stable_size()
+            }
+
+            fn stable_grow(&self, new_pages: u64) -> Result<u64, StableMemoryError> {
+                if self.stable_size() + new_pages > 1 {
+                    return Err(StableMemoryError::OutOfMemory);
+                }
+                self.0.stable_grow(new_pages)
+            }
+
+            fn stable_write(&self, offset: u64, buf: &[u8]) {
+                self.0.stable_write(offset, buf)
+            }
+
+            fn stable_read(&self, offset: u64, buf: &mut [u8]) {
+                self.0.stable_read(offset, buf)
+            }
+        }
+
+        let mut cell =
+            StableCell::init(SmallMemory(TestStableMemory::default()), vec![1_u8]).unwrap();
+        assert!(matches!(
+            cell.set(vec![0; WASM_PAGE_SIZE_IN_BYTES as usize]),
+            Err(StableMemoryError::OutOfMemory)
+        ));
+        assert_eq!(cell.get(), &[1]);
+    }
+}
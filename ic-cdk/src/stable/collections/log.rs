@@ -0,0 +1,238 @@
+//! An append-only log in stable memory.
+//!
+//! A log uses two memories. The index memory holds a header with the magic bytes `CLGI`, the layout version and the
+//! number of entries, followed by the end offset of each entry in the data memory. The data memory holds a header with
+//! the magic bytes `CLGD` and the layout version, followed by the bytes of the entries, one after the other.
+//!
+//! An entry is written to the data memory before its end offset and the number of entries are written to the index,
+//! so a log whose memory could not be grown is left as it was before the append.
+
+use super::{InitError, Storable, grow_to, init_header, read_u64, write_u64};
+use crate::stable::{CanisterStableMemory, StableMemory, StableMemoryError};
+use std::borrow::Cow;
+use std::fmt;
+use std::marker::PhantomData;
+
+const INDEX_MAGIC: &[u8; 4] = b"CLGI";
+
+const DATA_MAGIC: &[u8; 4] = b"CLGD";
+
+/// The offset of the number of entries in the header of the index memory.
+const LEN_OFFSET: u64 = 8;
+
+/// The length of the header of the index memory, before the end offsets of the entries.
+const INDEX_HEADER_LEN: u64 = 16;
+
+/// The length of the header of the data memory, before the entries.
+const DATA_HEADER_LEN: u64 = 8;
+
+/// An append-only list of [`Storable`] entries in [`StableMemory`], which can be of any size.
+///
+/// Entries are looked up by their position in the log, starting from 0.
+pub struct StableLog<
+    T,
+    INDEX: StableMemory = CanisterStableMemory,
+    DATA: StableMemory = CanisterStableMemory,
+> {
+    index: INDEX,
+    data: DATA,
+    len: u64,
+    _marker: PhantomData<T>,
+}
+
+impl<T: Storable, INDEX: StableMemory, DATA: StableMemory> StableLog<T, INDEX, DATA> {
+    /// Initializes a log in `index` and `data`.
+    ///
+    /// If both memories are empty, an empty log is created. Otherwise, the log they hold is loaded.
+    ///
+    /// # Errors
+    ///
+    /// Fails if either memory holds something other than the index or the data of a log.
+    pub fn init(index: INDEX, data: DATA) -> Result<Self, InitError> {
+        let created = init_header(&index, INDEX_MAGIC, INDEX_HEADER_LEN)?;
+        if init_header(&data, DATA_MAGIC, DATA_HEADER_LEN)? != created {
+            return Err(InitError::UnrecognizedLayout);
+        }
+        let len = read_u64(&index, LEN_OFFSET);
+        Ok(Self {
+            index,
+            data,
+            len,
+            _marker: PhantomData,
+        })
+    }
+
+    /// Gets the number of entries.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Checks whether the log has no entries.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Appends an entry to the log, and returns its position.
+    ///
+    /// # Errors
+    ///
+    /// Fails if either memory cannot be grown to hold the entry, in which case the log is unchanged.
+    pub fn append(&mut self, entry: &T) -> Result<u64, StableMemoryError> {
+        let bytes = entry.to_bytes();
+        let start = self.end_offset(self.len);
+        let end = start + bytes.len() as u64;
+        grow_to(&self.data, DATA_HEADER_LEN + end)?;
+        grow_to(&self.index, INDEX_HEADER_LEN + 8 * (self.len + 1))?;
+        self.data.stable_write(DATA_HEADER_LEN + start, &bytes);
+        write_u64(&self.index, INDEX_HEADER_LEN + 8 * self.len, end);
+        let position = self.len;
+        self.len += 1;
+        write_u64(&self.index, LEN_OFFSET, self.len);
+        Ok(position)
+    }
+
+    /// Gets the entry at `position`, or `None` if it is out of bounds.
+    pub fn get(&self, position: u64) -> Option<T> {
+        if position >= self.len {
+            return None;
+        }
+        let start = self.end_offset(position);
+        let end = self.end_offset(position + 1);
+        let mut bytes = vec![0; (end - start) as usize];
+        self.data.stable_read(DATA_HEADER_LEN + start, &mut bytes);
+        Some(T::from_bytes(Cow::Owned(bytes)))
+    }
+
+    /// Gets an iterator over the entries, from the oldest.
+    pub fn iter(&self) -> Iter<'_, T, INDEX, DATA> {
+        Iter {
+            log: self,
+            range: 0..self.len,
+        }
+    }
+
+    /// Gets the offset in the data memory at which the entries before `position` end.
+    fn end_offset(&self, position: u64) -> u64 {
+        match position.checked_sub(1) {
+            None => 0,
+            Some(last) => read_u64(&self.index, INDEX_HEADER_LEN + 8 * last),
+        }
+    }
+}
+
+impl<T, INDEX: StableMemory, DATA: StableMemory> fmt::Debug for StableLog<T, INDEX, DATA> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("StableLog").field("len", &self.len).finish()
+    }
+}
+
+impl<'a, T: Storable, INDEX: StableMemory, DATA: StableMemory> IntoIterator
+    for &'a StableLog<T, INDEX, DATA>
+{
+    type Item = T;
+    type IntoIter = Iter<'a, T, INDEX, DATA>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// An iterator over the entries of a [`StableLog`], returned by [`StableLog::iter`].
+pub struct Iter<
+    'a,
+    T,
+    INDEX: StableMemory = CanisterStableMemory,
+    DATA: StableMemory = CanisterStableMemory,
+> {
+    log: &'a StableLog<T, INDEX, DATA>,
+    range: std::ops::Range<u64>,
+}
+
+impl<T, INDEX: StableMemory, DATA: StableMemory> fmt::Debug for Iter<'_, T, INDEX, DATA> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Iter").field("range", &self.range).finish()
+    }
+}
+
+impl<T: Storable, INDEX: StableMemory, DATA: StableMemory> Iterator for Iter<'_, T, INDEX, DATA> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.range
+            .next()
+            .and_then(|position| self.log.get(position))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.range.size_hint()
+    }
+}
+
+impl<T: Storable, INDEX: StableMemory, DATA: StableMemory> DoubleEndedIterator
+    for Iter<'_, T, INDEX, DATA>
+{
+    fn next_back(&mut self) -> Option<T> {
+        self.range
+            .next_back()
+            .and_then(|position| self.log.get(position))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stable::tests::TestStableMemory;
+    use std::rc::Rc;
+    use std::sync::Mutex;
+
+    #[test]
+    fn entries_are_appended_and_looked_up() {
+        let (index, data) = (
+            Rc::new(Mutex::new(Vec::new())),
+            Rc::new(Mutex::new(Vec::new())),
+        );
+        let mut log = StableLog::init(
+            TestStableMemory::new(index.clone()),
+            TestStableMemory::new(data.clone()),
+        )
+        .unwrap();
+        assert!(log.is_empty());
+        for i in 0..1_000 {
+            assert_eq!(log.append(&"x".repeat(i)).unwrap(), i as u64);
+        }
+        assert_eq!(log.append(&String::new()).unwrap(), 1_000);
+        drop(log);
+
+        let log = StableLog::<String, _, _>::init(
+            TestStableMemory::new(index),
+            TestStableMemory::new(data),
+        )
+        .unwrap();
+        assert_eq!(log.len(), 1_001);
+        assert_eq!(log.get(0).unwrap(), "");
+        assert_eq!(log.get(999).unwrap(), "x".repeat(999));
+        assert_eq!(log.get(1_001), None);
+        assert_eq!(log.iter().rev().nth(1).unwrap().len(), 999);
+        assert_eq!(
+            log.iter().map(|entry| entry.len()).sum::<usize>(),
+            999 * 1_000 / 2
+        );
+    }
+
+    #[test]
+    fn mismatched_memories_are_rejected() {
+        let data = Rc::new(Mutex::new(Vec::new()));
+        StableLog::<String, _, _>::init(
+            TestStableMemory::default(),
+            TestStableMemory::new(data.clone()),
+        )
+        .unwrap();
+        assert!(matches!(
+            StableLog::<String, _, _>::init(
+                TestStableMemory::default(),
+                TestStableMemory::new(data)
+            ),
+            Err(InitError::UnrecognizedLayout)
+        ));
+    }
+}
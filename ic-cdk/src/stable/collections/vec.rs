@@ -0,0 +1,244 @@
+//! A growable array in stable memory.
+//!
+//! The header holds the magic bytes `CVEC`, the layout version, the maximum size of the elements, and the length of
+//! the vector. Each element follows in a slot of the maximum size, prefixed with the length of its bytes.
+
+use super::{
+    BoundedStorable, InitError, grow_to, init_header, init_max_size, read_u64, to_bounded_bytes,
+    write_u64,
+};
+use crate::stable::{CanisterStableMemory, StableMemory, StableMemoryError};
+use std::borrow::Cow;
+use std::fmt;
+use std::marker::PhantomData;
+
+const MAGIC: &[u8; 4] = b"CVEC";
+
+/// The offset of the maximum size of the elements in the header.
+const MAX_SIZE_OFFSET: u64 = 8;
+
+/// The offset of the length in the header.
+const LEN_OFFSET: u64 = 16;
+
+/// The length of the header, before the first element.
+const HEADER_LEN: u64 = 24;
+
+/// A growable array of [`BoundedStorable`] elements in a [`StableMemory`].
+pub struct StableVec<T, M: StableMemory = CanisterStableMemory> {
+    memory: M,
+    len: u64,
+    _marker: PhantomData<T>,
+}
+
+impl<T: BoundedStorable, M: StableMemory> StableVec<T, M> {
+    /// Initializes a vector in `memory`.
+    ///
+    /// If `memory` is empty, an empty vector is created. Otherwise, the vector it holds is loaded.
+    ///
+    /// # Errors
+    ///
+    /// Fails if `memory` holds something other than a vector, or a vector of elements with a different maximum size.
+    pub fn init(memory: M) -> Result<Self, InitError> {
+        let created = init_header(&memory, MAGIC, HEADER_LEN)?;
+        init_max_size(&memory, MAX_SIZE_OFFSET, T::MAX_SIZE, created)?;
+        let len = read_u64(&memory, LEN_OFFSET);
+        Ok(Self {
+            memory,
+            len,
+            _marker: PhantomData,
+        })
+    }
+
+    /// Gets the number of elements.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Checks whether the vector has no elements.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Gets the element at `index`, or `None` if it is out of bounds.
+    pub fn get(&self, index: u64) -> Option<T> {
+        if index >= self.len {
+            return None;
+        }
+        let offset = Self::slot_offset(index);
+        let len = {
+            let mut len = [0; 4];
+            self.memory.stable_read(offset, &mut len);
+            u32::from_le_bytes(len)
+        };
+        let mut bytes = vec![0; len as usize];
+        self.memory.stable_read(offset + 4, &mut bytes);
+        Some(T::from_bytes(Cow::Owned(bytes)))
+    }
+
+    /// Replaces the element at `index`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of bounds, or the bytes of `value` exceed the maximum size of the elements.
+    pub fn set(&mut self, index: u64, value: &T) {
+        assert!(
+            index < self.len,
+            "index {index} out of bounds of a vector of length {}",
+            self.len
+        );
+        self.write_slot(index, value);
+    }
+
+    /// Appends an element to the end of the vector.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the memory cannot be grown to hold the element.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the bytes of `value` exceed the maximum size of the elements.
+    pub fn push(&mut self, value: &T) -> Result<(), StableMemoryError> {
+        grow_to(&self.memory, Self::slot_offset(self.len + 1))?;
+        self.write_slot(self.len, value);
+        self.set_len(self.len + 1);
+        Ok(())
+    }
+
+    /// Removes the last element and returns it, or `None` if the vector is empty.
+    pub fn pop(&mut self) -> Option<T> {
+        let last = self.len.checked_sub(1)?;
+        let value = self.get(last);
+        self.set_len(last);
+        value
+    }
+
+    /// Removes all the elements. The memory they used is kept for the elements pushed afterwards.
+    pub fn clear(&mut self) {
+        self.set_len(0);
+    }
+
+    /// Gets an iterator over the elements.
+    pub fn iter(&self) -> Iter<'_, T, M> {
+        Iter {
+            vec: self,
+            range: 0..self.len,
+        }
+    }
+
+    fn slot_offset(index: u64) -> u64 {
+        HEADER_LEN + index * (4 + u64::from(T::MAX_SIZE))
+    }
+
+    fn write_slot(&self, index: u64, value: &T) {
+        let bytes = to_bounded_bytes(value);
+        let offset = Self::slot_offset(index);
+        self.memory
+            .stable_write(offset, &(bytes.len() as u32).to_le_bytes());
+        self.memory.stable_write(offset + 4, &bytes);
+    }
+
+    fn set_len(&mut self, len: u64) {
+        self.len = len;
+        write_u64(&self.memory, LEN_OFFSET, len);
+    }
+}
+
+impl<T, M: StableMemory> fmt::Debug for StableVec<T, M> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("StableVec").field("len", &self.len).finish()
+    }
+}
+
+impl<'a, T: BoundedStorable, M: StableMemory> IntoIterator for &'a StableVec<T, M> {
+    type Item = T;
+    type IntoIter = Iter<'a, T, M>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// An iterator over the elements of a [`StableVec`], returned by [`StableVec::iter`].
+pub struct Iter<'a, T, M: StableMemory = CanisterStableMemory> {
+    vec: &'a StableVec<T, M>,
+    range: std::ops::Range<u64>,
+}
+
+impl<T, M: StableMemory> fmt::Debug for Iter<'_, T, M> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Iter").field("range", &self.range).finish()
+    }
+}
+
+impl<T: BoundedStorable, M: StableMemory> Iterator for Iter<'_, T, M> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.range.next().and_then(|index| self.vec.get(index))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.range.size_hint()
+    }
+}
+
+impl<T: BoundedStorable, M: StableMemory> DoubleEndedIterator for Iter<'_, T, M> {
+    fn next_back(&mut self) -> Option<T> {
+        self.range.next_back().and_then(|index| self.vec.get(index))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stable::tests::TestStableMemory;
+    use std::rc::Rc;
+    use std::sync::Mutex;
+
+    #[test]
+    fn elements_are_pushed_and_popped() {
+        let mut vec = StableVec::<u64, _>::init(TestStableMemory::default()).unwrap();
+        assert!(vec.is_empty());
+        for i in 0..10_000 {
+            vec.push(&(i * 3)).unwrap();
+        }
+        assert_eq!(vec.len(), 10_000);
+        assert_eq!(vec.get(5_000), Some(15_000));
+        assert_eq!(vec.get(10_000), None);
+        vec.set(0, &42);
+        assert_eq!(vec.iter().take(2).collect::<Vec<_>>(), [42, 3]);
+        assert_eq!(vec.iter().next_back(), Some(29_997));
+        assert_eq!(vec.pop(), Some(29_997));
+        assert_eq!(vec.len(), 9_999);
+        vec.clear();
+        assert_eq!(vec.pop(), None);
+    }
+
+    #[test]
+    fn elements_survive_reinitialization() {
+        let bytes = Rc::new(Mutex::new(Vec::new()));
+        let mut vec = StableVec::init(TestStableMemory::new(bytes.clone())).unwrap();
+        vec.push(&[1_u8, 2]).unwrap();
+        vec.push(&[3, 4]).unwrap();
+        drop(vec);
+
+        let vec = StableVec::<[u8; 2], _>::init(TestStableMemory::new(bytes.clone())).unwrap();
+        assert_eq!(vec.iter().collect::<Vec<_>>(), [[1, 2], [3, 4]]);
+        assert!(matches!(
+            StableVec::<u64, _>::init(TestStableMemory::new(bytes)),
+            Err(InitError::IncompatibleMaxSize {
+                saved: 2,
+                expected: 8
+            })
+        ));
+    }
+
+    #[test]
+    #[should_panic(expected = "index 1 out of bounds of a vector of length 1")]
+    fn setting_out_of_bounds_panics() {
+        let mut vec = StableVec::init(TestStableMemory::default()).unwrap();
+        vec.push(&1_u8).unwrap();
+        vec.set(1, &2);
+    }
+}
@@ -0,0 +1,546 @@
+//! Partitioning of stable memory into independently growable virtual memories.
+//!
+//! The stable memory of a canister is one flat address space. A [`MemoryManager`] splits it into up to 255 virtual
+//! memories, each identified by a [`MemoryId`], so that subsystems can share stable memory without coordinating
+//! offsets. A [`VirtualMemory`] implements [`StableMemory`], so [`StableWriter`](super::StableWriter),
+//! [`StableReader`](super::StableReader) and [`BufferedStableWriter`](super::BufferedStableWriter) work on it as they
+//! do on the whole stable memory.
+//!
+//! ```rust, no_run
+//! use ic_cdk::stable::memory_manager::{MemoryId, MemoryManager, VirtualMemory};
+//! use ic_cdk::stable::{CanisterStableMemory, StableReader, StableWriter};
+//! use std::io::{Read, Write};
+//!
+//! const CONFIG: MemoryId = MemoryId::new(0);
+//! const HISTORY: MemoryId = MemoryId::new(1);
+//!
+//! thread_local! {
+//!     static MEMORY_MANAGER: MemoryManager = MemoryManager::init(CanisterStableMemory::default())
+//!         .expect("the stable memory is not managed by a memory manager");
+//! }
+//!
+//! fn memory(id: MemoryId) -> VirtualMemory {
+//!     MEMORY_MANAGER.with(|manager| manager.get(id))
+//! }
+//!
+//! fn save_config(config: &[u8]) {
+//!     let mut writer = StableWriter::with_memory(memory(CONFIG), 0);
+//!     writer.write_all(&(config.len() as u64).to_le_bytes()).unwrap();
+//!     writer.write_all(config).unwrap();
+//! }
+//!
+//! fn load_config() -> Vec<u8> {
+//!     let mut reader = StableReader::with_memory(memory(CONFIG), 0);
+//!     let mut len = [0; 8];
+//!     reader.read_exact(&mut len).unwrap();
+//!     let mut config = vec![0; u64::from_le_bytes(len) as usize];
+//!     reader.read_exact(&mut config).unwrap();
+//!     config
+//! }
+//! ```
+//!
+//! # Layout
+//!
+//! The first page of the underlying memory holds a header: the size of each virtual memory, and the owner of each
+//! bucket. The rest is divided into buckets of [`DEFAULT_BUCKET_SIZE_IN_PAGES`] pages, or the size passed to
+//! [`MemoryManager::init_with_bucket_size`], which are allocated to the virtual memories as they grow. A virtual
+//! memory maps its address space onto its buckets in the order they were allocated. Buckets are never freed.
+//!
+//! The header is kept up to date on every growth, so a memory manager initialized with the same underlying memory
+//! after an upgrade finds the virtual memories as they were. The underlying memory must not be used for anything
+//! else, e.g. by [`storage::stable_save`](crate::storage::stable_save).
+
+use super::{CanisterStableMemory, StableMemory, StableMemoryError, WASM_PAGE_SIZE_IN_BYTES};
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+use thiserror::Error;
+
+/// The number of pages of a bucket, unless set with [`MemoryManager::init_with_bucket_size`].
+///
+/// With the default, the virtual memories can hold up to 256 GiB in total.
+pub const DEFAULT_BUCKET_SIZE_IN_PAGES: u16 = 128;
+
+/// The maximum number of buckets allocated to the virtual memories.
+pub const MAX_NUM_BUCKETS: u32 = 32_768;
+
+/// Identifies the underlying memory of a memory manager.
+const MAGIC: &[u8; 4] = b"CVMM";
+
+/// The version of the layout of the header.
+const LAYOUT_VERSION: u8 = 1;
+
+/// The owner of the buckets that are not allocated, which is why there are 255 memory ids and not 256.
+const UNALLOCATED: u8 = u8::MAX;
+
+/// The number of pages of the header, before the first bucket.
+const HEADER_PAGES: u64 = 1;
+
+/// The offset of the sizes of the virtual memories in the header, after the magic bytes, the version, the bucket
+/// size and the number of allocated buckets.
+const SIZES_OFFSET: u64 = 16;
+
+/// The offset of the owners of the buckets in the header, one byte per bucket, after the sizes.
+const OWNERS_OFFSET: u64 = SIZES_OFFSET + 8 * UNALLOCATED as u64;
+
+/// Identifies a virtual memory of a [`MemoryManager`], from 0 to 254.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct MemoryId(u8);
+
+impl MemoryId {
+    /// Creates a memory id.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `id` is 255, which is reserved.
+    pub const fn new(id: u8) -> Self {
+        assert!(id != UNALLOCATED, "the memory id 255 is reserved");
+        Self(id)
+    }
+
+    /// Gets the number of the memory id.
+    pub const fn get(self) -> u8 {
+        self.0
+    }
+}
+
+impl fmt::Display for MemoryId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+/// The error type of [`MemoryManager::init`].
+#[derive(Error, Debug)]
+pub enum MemoryManagerError {
+    /// The underlying memory holds data, but not the header of a memory manager.
+    #[error("the stable memory holds data that is not managed by a memory manager")]
+    UnrecognizedLayout,
+    /// The header of the memory manager was written by a newer version of this crate.
+    #[error("unsupported memory manager layout version {0}")]
+    UnsupportedVersion(u8),
+    /// The header of the memory manager is inconsistent.
+    #[error("the header of the memory manager is corrupted")]
+    CorruptedHeader,
+    /// The header could not be allocated.
+    #[error("failed to allocate the header of the memory manager: {0}")]
+    StableMemory(#[from] StableMemoryError),
+}
+
+/// Splits a [`StableMemory`] into [virtual memories](VirtualMemory).
+///
+/// Cloning a memory manager is cheap, and the clones share the same virtual memories.
+pub struct MemoryManager<M: StableMemory = CanisterStableMemory> {
+    inner: Rc<RefCell<Inner<M>>>,
+}
+
+impl<M: StableMemory> MemoryManager<M> {
+    /// Initializes a memory manager with buckets of [`DEFAULT_BUCKET_SIZE_IN_PAGES`] pages.
+    ///
+    /// If `memory` is empty, the header is written to it. Otherwise, the virtual memories are restored from its
+    /// header, with the bucket size it was written with.
+    ///
+    /// # Errors
+    ///
+    /// Fails if `memory` holds data other than the header of a memory manager, or cannot be grown to hold the header.
+    pub fn init(memory: M) -> Result<Self, MemoryManagerError> {
+        Self::init_with_bucket_size(memory, DEFAULT_BUCKET_SIZE_IN_PAGES)
+    }
+
+    /// Initializes a memory manager with buckets of `bucket_size_in_pages` pages.
+    ///
+    /// Smaller buckets waste less memory when there are many small virtual memories, but cap the total size of the
+    /// virtual memories lower, at [`MAX_NUM_BUCKETS`] buckets. The bucket size is only used if `memory` is empty;
+    /// otherwise the bucket size it was written with is kept.
+    ///
+    /// # Errors
+    ///
+    /// Fails if `memory` holds data other than the header of a memory manager, or cannot be grown to hold the header.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `bucket_size_in_pages` is 0.
+    pub fn init_with_bucket_size(
+        memory: M,
+        bucket_size_in_pages: u16,
+    ) -> Result<Self, MemoryManagerError> {
+        assert!(bucket_size_in_pages > 0, "the bucket size must not be 0");
+        let inner = if memory.stable_size() == 0 {
+            Inner::create(memory, bucket_size_in_pages)?
+        } else {
+            Inner::load(memory)?
+        };
+        Ok(Self {
+            inner: Rc::new(RefCell::new(inner)),
+        })
+    }
+
+    /// Gets the virtual memory identified by `id`.
+    ///
+    /// A virtual memory that was never grown is empty.
+    pub fn get(&self, id: MemoryId) -> VirtualMemory<M> {
+        VirtualMemory {
+            inner: self.inner.clone(),
+            id,
+        }
+    }
+
+    /// Gets the number of pages of a bucket.
+    pub fn bucket_size_in_pages(&self) -> u16 {
+        self.inner.borrow().bucket_size_in_pages
+    }
+
+    /// Gets the number of buckets allocated to the virtual memories.
+    pub fn allocated_buckets(&self) -> u32 {
+        self.inner.borrow().allocated_buckets
+    }
+}
+
+impl<M: StableMemory> Clone for MemoryManager<M> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<M: StableMemory> fmt::Debug for MemoryManager<M> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let inner = self.inner.borrow();
+        f.debug_struct("MemoryManager")
+            .field("bucket_size_in_pages", &inner.bucket_size_in_pages)
+            .field("allocated_buckets", &inner.allocated_buckets)
+            .finish()
+    }
+}
+
+/// A partition of the stable memory, obtained with [`MemoryManager::get`].
+///
+/// It grows independently of the other virtual memories, and, like the stable memory, starts empty.
+pub struct VirtualMemory<M: StableMemory = CanisterStableMemory> {
+    inner: Rc<RefCell<Inner<M>>>,
+    id: MemoryId,
+}
+
+impl<M: StableMemory> VirtualMemory<M> {
+    /// Gets the id of the virtual memory.
+    pub fn id(&self) -> MemoryId {
+        self.id
+    }
+}
+
+impl<M: StableMemory> StableMemory for VirtualMemory<M> {
+    fn stable_size(&self) -> u64 {
+        self.inner.borrow().sizes[usize::from(self.id.0)]
+    }
+
+    fn stable_grow(&self, new_pages: u64) -> Result<u64, StableMemoryError> {
+        self.inner.borrow_mut().grow(self.id, new_pages)
+    }
+
+    fn stable_write(&self, offset: u64, buf: &[u8]) {
+        let inner = self.inner.borrow();
+        inner.for_each_chunk(self.id, offset, buf.len(), |address, range| {
+            inner.memory.stable_write(address, &buf[range]);
+        });
+    }
+
+    fn stable_read(&self, offset: u64, buf: &mut [u8]) {
+        let inner = self.inner.borrow();
+        inner.for_each_chunk(self.id, offset, buf.len(), |address, range| {
+            inner.memory.stable_read(address, &mut buf[range]);
+        });
+    }
+}
+
+impl<M: StableMemory> Clone for VirtualMemory<M> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            id: self.id,
+        }
+    }
+}
+
+impl<M: StableMemory> fmt::Debug for VirtualMemory<M> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("VirtualMemory")
+            .field("id", &self.id)
+            .field("size", &self.stable_size())
+            .finish()
+    }
+}
+
+struct Inner<M> {
+    memory: M,
+    bucket_size_in_pages: u16,
+    allocated_buckets: u32,
+    /// The size of each virtual memory, in pages.
+    sizes: Vec<u64>,
+    /// The buckets of each virtual memory, in the order they were allocated.
+    buckets: Vec<Vec<u32>>,
+}
+
+impl<M: StableMemory> Inner<M> {
+    /// Writes the header of an empty memory manager to an empty memory.
+    fn create(memory: M, bucket_size_in_pages: u16) -> Result<Self, MemoryManagerError> {
+        memory.stable_grow(HEADER_PAGES)?;
+        let mut header = [0; SIZES_OFFSET as usize];
+        header[..4].copy_from_slice(MAGIC);
+        header[4] = LAYOUT_VERSION;
+        header[6..8].copy_from_slice(&bucket_size_in_pages.to_le_bytes());
+        memory.stable_write(0, &header);
+        Ok(Self {
+            memory,
+            bucket_size_in_pages,
+            allocated_buckets: 0,
+            sizes: vec![0; usize::from(UNALLOCATED)],
+            buckets: vec![Vec::new(); usize::from(UNALLOCATED)],
+        })
+    }
+
+    /// Reads the virtual memories from the header of a memory manager.
+    fn load(memory: M) -> Result<Self, MemoryManagerError> {
+        let mut header = [0; SIZES_OFFSET as usize];
+        memory.stable_read(0, &mut header);
+        if &header[..4] != MAGIC {
+            return Err(MemoryManagerError::UnrecognizedLayout);
+        }
+        if header[4] != LAYOUT_VERSION {
+            return Err(MemoryManagerError::UnsupportedVersion(header[4]));
+        }
+        let bucket_size_in_pages = u16::from_le_bytes([header[6], header[7]]);
+        let allocated_buckets = u32::from_le_bytes(header[8..12].try_into().unwrap());
+        if bucket_size_in_pages == 0 || allocated_buckets > MAX_NUM_BUCKETS {
+            return Err(MemoryManagerError::CorruptedHeader);
+        }
+
+        let mut sizes = vec![0; 8 * usize::from(UNALLOCATED)];
+        memory.stable_read(SIZES_OFFSET, &mut sizes);
+        let sizes: Vec<u64> = sizes
+            .as_chunks::<8>()
+            .0
+            .iter()
+            .map(|size| u64::from_le_bytes(*size))
+            .collect();
+        let mut owners = vec![0; allocated_buckets as usize];
+        memory.stable_read(OWNERS_OFFSET, &mut owners);
+        let mut buckets = vec![Vec::new(); usize::from(UNALLOCATED)];
+        for (bucket, owner) in (0..).zip(owners) {
+            if owner == UNALLOCATED {
+                return Err(MemoryManagerError::CorruptedHeader);
+            }
+            buckets[usize::from(owner)].push(bucket);
+        }
+        let bucket_size = u64::from(bucket_size_in_pages);
+        if sizes
+            .iter()
+            .zip(&buckets)
+            .any(|(size, buckets)| size.div_ceil(bucket_size) > buckets.len() as u64)
+        {
+            return Err(MemoryManagerError::CorruptedHeader);
+        }
+        Ok(Self {
+            memory,
+            bucket_size_in_pages,
+            allocated_buckets,
+            sizes,
+            buckets,
+        })
+    }
+
+    /// Grows a virtual memory, allocating buckets to it if needed, and returns its previous size.
+    fn grow(&mut self, id: MemoryId, new_pages: u64) -> Result<u64, StableMemoryError> {
+        let index = usize::from(id.0);
+        let previous = self.sizes[index];
+        let size = previous
+            .checked_add(new_pages)
+            .ok_or(StableMemoryError::OutOfMemory)?;
+        let bucket_size = u64::from(self.bucket_size_in_pages);
+        let owned = self.buckets[index].len() as u64;
+        let missing = size.div_ceil(bucket_size).saturating_sub(owned);
+        if missing > 0 {
+            let allocated = u64::from(self.allocated_buckets) + missing;
+            if allocated > u64::from(MAX_NUM_BUCKETS) {
+                return Err(StableMemoryError::OutOfMemory);
+            }
+            let pages = HEADER_PAGES + allocated * bucket_size;
+            let current = self.memory.stable_size();
+            if current < pages {
+                self.memory.stable_grow(pages - current)?;
+            }
+            for _ in 0..missing {
+                let bucket = self.allocated_buckets;
+                self.memory
+                    .stable_write(OWNERS_OFFSET + u64::from(bucket), &[id.0]);
+                self.buckets[index].push(bucket);
+                self.allocated_buckets += 1;
+            }
+            self.memory
+                .stable_write(8, &self.allocated_buckets.to_le_bytes());
+        }
+        self.sizes[index] = size;
+        self.memory
+            .stable_write(SIZES_OFFSET + 8 * u64::from(id.0), &size.to_le_bytes());
+        Ok(previous)
+    }
+
+    /// Calls `f` with the address in the underlying memory of each contiguous chunk of `len` bytes at `offset` in a
+    /// virtual memory, and the range of the chunk among the bytes.
+    fn for_each_chunk(
+        &self,
+        id: MemoryId,
+        offset: u64,
+        len: usize,
+        mut f: impl FnMut(u64, std::ops::Range<usize>),
+    ) {
+        let index = usize::from(id.0);
+        let size = self.sizes[index] * WASM_PAGE_SIZE_IN_BYTES;
+        assert!(
+            offset
+                .checked_add(len as u64)
+                .is_some_and(|end| end <= size),
+            "virtual memory {id} out of bounds"
+        );
+        let bucket_size = u64::from(self.bucket_size_in_pages) * WASM_PAGE_SIZE_IN_BYTES;
+        let mut done = 0;
+        while done < len {
+            let position = offset + done as u64;
+            let bucket = u64::from(self.buckets[index][(position / bucket_size) as usize]);
+            let within = position % bucket_size;
+            let chunk = (len - done).min((bucket_size - within) as usize);
+            let address = HEADER_PAGES * WASM_PAGE_SIZE_IN_BYTES + bucket * bucket_size + within;
+            f(address, done..done + chunk);
+            done += chunk;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stable::tests::TestStableMemory;
+    use crate::stable::{BufferedStableWriter, StableReader, StableWriter};
+    use std::io::{Read, Write};
+    use std::sync::Mutex;
+
+    const PAGE: usize = WASM_PAGE_SIZE_IN_BYTES as usize;
+
+    fn pattern(seed: u8, len: usize) -> Vec<u8> {
+        (0..len).map(|i| seed.wrapping_add(i as u8)).collect()
+    }
+
+    #[test]
+    fn memories_grow_independently() {
+        let memory = Rc::new(Mutex::new(Vec::new()));
+        let manager =
+            MemoryManager::init_with_bucket_size(TestStableMemory::new(memory.clone()), 1).unwrap();
+        let (a, b) = (manager.get(MemoryId::new(0)), manager.get(MemoryId::new(7)));
+        assert_eq!(a.stable_grow(1).unwrap(), 0);
+        assert_eq!(b.stable_grow(2).unwrap(), 0);
+        assert_eq!(a.stable_grow(2).unwrap(), 1);
+        assert_eq!((a.stable_size(), b.stable_size()), (3, 2));
+        assert_eq!(manager.allocated_buckets(), 5);
+        assert_eq!(
+            TestStableMemory::new(memory).stable_size(),
+            HEADER_PAGES + 5
+        );
+
+        // The buckets of `a` are interleaved with those of `b`, so these writes span non-contiguous buckets.
+        let bytes_a = pattern(1, 3 * PAGE);
+        let bytes_b = pattern(2, 2 * PAGE);
+        a.stable_write(0, &bytes_a);
+        b.stable_write(0, &bytes_b);
+        let mut read = vec![0; 2 * PAGE];
+        a.stable_read(PAGE as u64 / 2, &mut read);
+        assert_eq!(read, bytes_a[PAGE / 2..PAGE / 2 + 2 * PAGE]);
+        b.stable_read(0, &mut read);
+        assert_eq!(read, bytes_b);
+        assert_eq!(manager.get(MemoryId::new(1)).stable_size(), 0);
+    }
+
+    #[test]
+    fn memories_survive_reinitialization() {
+        let memory = Rc::new(Mutex::new(Vec::new()));
+        let manager =
+            MemoryManager::init_with_bucket_size(TestStableMemory::new(memory.clone()), 2).unwrap();
+        let a = manager.get(MemoryId::new(3));
+        a.stable_grow(3).unwrap();
+        a.stable_write(2 * PAGE as u64 + 10, b"hello");
+        drop((a, manager));
+
+        let manager = MemoryManager::init(TestStableMemory::new(memory)).unwrap();
+        assert_eq!(manager.bucket_size_in_pages(), 2);
+        let a = manager.get(MemoryId::new(3));
+        assert_eq!(a.stable_size(), 3);
+        let mut read = [0; 5];
+        a.stable_read(2 * PAGE as u64 + 10, &mut read);
+        assert_eq!(&read, b"hello");
+    }
+
+    #[test]
+    fn unmanaged_memory_is_rejected() {
+        let memory = Rc::new(Mutex::new(Vec::new()));
+        let raw = TestStableMemory::new(memory.clone());
+        raw.stable_grow(1).unwrap();
+        raw.stable_write(0, b"data");
+        assert!(matches!(
+            MemoryManager::init(TestStableMemory::new(memory.clone())),
+            Err(MemoryManagerError::UnrecognizedLayout)
+        ));
+        raw.stable_write(0, b"CVMM\x02");
+        assert!(matches!(
+            MemoryManager::init(TestStableMemory::new(memory)),
+            Err(MemoryManagerError::UnsupportedVersion(2))
+        ));
+    }
+
+    #[test]
+    fn growth_is_capped_by_the_number_of_buckets() {
+        let manager = MemoryManager::init_with_bucket_size(TestStableMemory::default(), 1).unwrap();
+        let a = manager.get(MemoryId::new(0));
+        assert!(matches!(
+            a.stable_grow(u64::from(MAX_NUM_BUCKETS) + 1),
+            Err(StableMemoryError::OutOfMemory)
+        ));
+        assert_eq!(a.stable_size(), 0);
+        assert_eq!(manager.allocated_buckets(), 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "virtual memory 0 out of bounds")]
+    fn access_beyond_the_size_panics() {
+        let manager = MemoryManager::init(TestStableMemory::default()).unwrap();
+        let a = manager.get(MemoryId::new(0));
+        a.stable_grow(1).unwrap();
+        a.stable_write(PAGE as u64 - 1, &[0, 0]);
+    }
+
+    #[test]
+    fn readers_and_writers_work_on_a_virtual_memory() {
+        let manager = MemoryManager::init_with_bucket_size(TestStableMemory::default(), 1).unwrap();
+        let (a, b) = (manager.get(MemoryId::new(0)), manager.get(MemoryId::new(1)));
+        let bytes_a = pattern(3, 2 * PAGE + 100);
+        let bytes_b = pattern(4, PAGE + 1);
+
+        // Interleave the writes, so that the buckets of both memories are interleaved too.
+        let mut writer = BufferedStableWriter::with_writer(1_000, StableWriter::with_memory(a, 0));
+        writer.write_all(&bytes_a[..PAGE + 10]).unwrap();
+        writer.flush().unwrap();
+        StableWriter::with_memory(b, 0).write_all(&bytes_b).unwrap();
+        writer.write_all(&bytes_a[PAGE + 10..]).unwrap();
+        writer.flush().unwrap();
+        drop(writer);
+
+        let mut read = vec![0; bytes_a.len()];
+        StableReader::with_memory(manager.get(MemoryId::new(0)), 0)
+            .read_exact(&mut read)
+            .unwrap();
+        assert_eq!(read, bytes_a);
+        let mut read = vec![0; bytes_b.len()];
+        StableReader::with_memory(manager.get(MemoryId::new(1)), 0)
+            .read_exact(&mut read)
+            .unwrap();
+        assert_eq!(read, bytes_b);
+    }
+}
@@ -0,0 +1,559 @@
+//! Transactions over stable memory that span several messages.
+//!
+//! The IC rolls back the changes of a message that traps, but not those of the earlier messages of the same call:
+//! the code before each `await` runs in its own message. A state migration that writes to stable memory across
+//! several messages, such as a timer making calls between its writes, can thus leave the memory half migrated.
+//!
+//! A [`TransactionalMemory`] wraps a data memory with a journal memory. The writes made with [`Transaction::write`] are
+//! appended to the journal instead of the data memory, and [`Transaction::read`] sees them over the data memory.
+//! Committing the transaction applies the writes to the data memory, and rolling it back discards them:
+//!
+//! ```rust, no_run
+//! use ic_cdk::stable::memory_manager::{MemoryId, MemoryManager, VirtualMemory};
+//! use ic_cdk::stable::transaction::TransactionalMemory;
+//! use ic_cdk::stable::{CanisterStableMemory, StableMemory};
+//!
+//! thread_local! {
+//!     static MEMORY_MANAGER: MemoryManager = MemoryManager::init(CanisterStableMemory::default())
+//!         .expect("the stable memory is not managed by a memory manager");
+//!     static STATE: TransactionalMemory<VirtualMemory, VirtualMemory> = MEMORY_MANAGER.with(|manager| {
+//!         TransactionalMemory::init(manager.get(MemoryId::new(0)), manager.get(MemoryId::new(1)))
+//!             .expect("the journal memory holds other data")
+//!     });
+//! }
+//!
+//! async fn fetch_rates() -> Vec<u8> {
+//!     // An inter-canister call, ending the message.
+//!     # unimplemented!()
+//! }
+//!
+//! async fn migrate() {
+//!     let transaction = STATE.with(|state| state.begin()).unwrap();
+//!     transaction.write(0, b"v2");
+//!     let rates = fetch_rates().await;
+//!     // If this message traps, the task is dropped, and so is the transaction, which rolls back the write above.
+//!     transaction.write(2, &rates);
+//!     transaction.commit();
+//! }
+//! ```
+//!
+//! A transaction that is dropped without being committed is rolled back, including when the task holding it is
+//! dropped because one of its messages trapped. A transaction opened by a task that is gone without dropping it, such
+//! as a timer whose message trapped, is rolled back on the next call to [`TransactionalMemory::begin`] or
+//! [`TransactionalMemory::in_transaction`]. If the canister is upgraded while a transaction is open, the transaction
+//! is rolled back by [`TransactionalMemory::init`] in the new version. The journal also records whether a transaction
+//! is being committed, so that `init` completes a commit that was interrupted.
+//!
+//! Only the writes made through the [`Transaction`] are journaled. The writes made through the memory itself, or
+//! through any of its clones, go to the data memory directly, and its reads do not see the writes of the open
+//! transaction. Growing the memory is not part of a transaction either: the pages added while a transaction is open
+//! are kept if it is rolled back.
+
+use super::{StableMemory, StableMemoryError, WASM_PAGE_SIZE_IN_BYTES, grow_to};
+use ic_cdk_executor::TaskHandle;
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+use thiserror::Error;
+
+/// Identifies a journal memory.
+const MAGIC: &[u8; 4] = b"CTXJ";
+
+/// The version of the layout of the journal.
+const LAYOUT_VERSION: u8 = 1;
+
+/// The offset of the state of the journal in the header.
+const STATE_OFFSET: u64 = 5;
+
+/// The offset of the end of the records in the header.
+const END_OFFSET: u64 = 8;
+
+/// The length of the header, before the records. Each record is the offset and the length of a write as 8 bytes each
+/// in little endian, followed by the bytes written.
+const HEADER_LEN: u64 = 16;
+
+/// The length of the offset and the length that start a record.
+const RECORD_HEADER_LEN: u64 = 16;
+
+/// No transaction is open.
+const IDLE: u8 = 0;
+/// A transaction is open, and its writes are in the journal.
+const OPEN: u8 = 1;
+/// The writes in the journal are being applied to the data memory.
+const COMMITTING: u8 = 2;
+
+/// The error type of the [`transaction`](self) module.
+#[derive(Error, Debug)]
+pub enum TransactionError {
+    /// The journal memory holds data, but not a journal.
+    #[error("the stable memory holds data that is not a journal")]
+    UnrecognizedLayout,
+    /// The journal was written by a newer version of this crate.
+    #[error("unsupported journal layout version {0}")]
+    UnsupportedVersion(u8),
+    /// The header of the journal is inconsistent.
+    #[error("the journal is corrupted")]
+    CorruptedJournal,
+    /// [`TransactionalMemory::begin`] was called while a transaction is open.
+    #[error("a transaction is already open")]
+    AlreadyOpen,
+    /// The journal could not be allocated.
+    #[error("failed to allocate the journal: {0}")]
+    StableMemory(#[from] StableMemoryError),
+}
+
+/// A [`StableMemory`] whose writes can be grouped into [transactions](Transaction).
+///
+/// Its own reads and writes go to the data memory directly, whether a transaction is open or not. Cloning a
+/// transactional memory is cheap, and at most one transaction is open across the clones.
+pub struct TransactionalMemory<M: StableMemory, J: StableMemory> {
+    inner: Rc<RefCell<Inner<M, J>>>,
+}
+
+impl<M: StableMemory, J: StableMemory> TransactionalMemory<M, J> {
+    /// Initializes a transactional memory with the journal in `journal`.
+    ///
+    /// If `journal` is empty, an empty journal is written to it. Otherwise, a transaction left open by the previous
+    /// version of the canister is rolled back, and a commit that was interrupted is completed.
+    ///
+    /// # Errors
+    ///
+    /// Fails if `journal` holds data that is not a journal, or the journal cannot be allocated.
+    pub fn init(data: M, journal: J) -> Result<Self, TransactionError> {
+        let mut inner = Inner {
+            data,
+            journal,
+            records: None,
+            owner: None,
+            id: 0,
+            end: HEADER_LEN,
+        };
+        if inner.journal.stable_size() == 0 {
+            grow_to(&inner.journal, HEADER_LEN)?;
+            let mut header = [0; HEADER_LEN as usize];
+            header[..4].copy_from_slice(MAGIC);
+            header[4] = LAYOUT_VERSION;
+            header[8..].copy_from_slice(&HEADER_LEN.to_le_bytes());
+            inner.journal.stable_write(0, &header);
+        } else {
+            let mut header = [0; HEADER_LEN as usize];
+            inner.journal.stable_read(0, &mut header);
+            if &header[..4] != MAGIC {
+                return Err(TransactionError::UnrecognizedLayout);
+            }
+            if header[4] != LAYOUT_VERSION {
+                return Err(TransactionError::UnsupportedVersion(header[4]));
+            }
+            inner.end = u64::from_le_bytes(header[8..].try_into().unwrap());
+            if inner.end < HEADER_LEN
+                || inner.end > inner.journal.stable_size() * WASM_PAGE_SIZE_IN_BYTES
+            {
+                return Err(TransactionError::CorruptedJournal);
+            }
+            match header[STATE_OFFSET as usize] {
+                IDLE => {}
+                OPEN => inner.reset(),
+                COMMITTING => inner.commit(),
+                _ => return Err(TransactionError::CorruptedJournal),
+            }
+        }
+        Ok(Self {
+            inner: Rc::new(RefCell::new(inner)),
+        })
+    }
+
+    /// Opens a transaction. The writes made with [`Transaction::write`] until it is committed or rolled back go to the
+    /// journal.
+    ///
+    /// A transaction left open by a task that is gone is rolled back first.
+    ///
+    /// # Errors
+    ///
+    /// Fails if a transaction is already open.
+    pub fn begin(&self) -> Result<Transaction<M, J>, TransactionError> {
+        let mut inner = self.inner.borrow_mut();
+        inner.recover();
+        if inner.records.is_some() {
+            return Err(TransactionError::AlreadyOpen);
+        }
+        inner.records = Some(Vec::new());
+        inner.owner = TaskHandle::current();
+        inner.id += 1;
+        inner.set_state(OPEN);
+        Ok(Transaction {
+            memory: self.clone(),
+            id: inner.id,
+        })
+    }
+
+    /// Checks whether a transaction is open, after rolling back a transaction left open by a task that is gone.
+    pub fn in_transaction(&self) -> bool {
+        let mut inner = self.inner.borrow_mut();
+        inner.recover();
+        inner.records.is_some()
+    }
+}
+
+impl<M: StableMemory, J: StableMemory> StableMemory for TransactionalMemory<M, J> {
+    fn stable_size(&self) -> u64 {
+        self.inner.borrow().data.stable_size()
+    }
+
+    fn stable_grow(&self, new_pages: u64) -> Result<u64, StableMemoryError> {
+        self.inner.borrow().data.stable_grow(new_pages)
+    }
+
+    fn stable_write(&self, offset: u64, buf: &[u8]) {
+        self.inner.borrow().data.stable_write(offset, buf);
+    }
+
+    fn stable_read(&self, offset: u64, buf: &mut [u8]) {
+        self.inner.borrow().data.stable_read(offset, buf);
+    }
+}
+
+impl<M: StableMemory, J: StableMemory> Clone for TransactionalMemory<M, J> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<M: StableMemory, J: StableMemory> fmt::Debug for TransactionalMemory<M, J> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let inner = self.inner.borrow();
+        f.debug_struct("TransactionalMemory")
+            .field("in_transaction", &inner.records.is_some())
+            .field("journal_len", &(inner.end - HEADER_LEN))
+            .finish()
+    }
+}
+
+/// An open transaction, returned by [`TransactionalMemory::begin`].
+///
+/// It is rolled back when dropped, unless it was committed. A transaction belongs to the task that opened it: once the
+/// task is gone, the transaction can be rolled back by the memory, after which using it panics.
+#[must_use = "a transaction is rolled back when dropped"]
+pub struct Transaction<M: StableMemory, J: StableMemory> {
+    memory: TransactionalMemory<M, J>,
+    /// Tells this transaction apart from the ones opened after it was rolled back.
+    id: u64,
+}
+
+impl<M: StableMemory, J: StableMemory> Transaction<M, J> {
+    /// Writes `buf` at `offset` in the transaction, to be applied to the data memory on commit.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the write is beyond the size of the data memory, if the journal memory cannot be grown to hold it, or
+    /// if the transaction was rolled back because its task is gone.
+    pub fn write(&self, offset: u64, buf: &[u8]) {
+        let mut inner = self.inner().borrow_mut();
+        let len = buf.len() as u64;
+        // Check the bounds now, rather than when the write is applied.
+        let size = inner.data.stable_size() * WASM_PAGE_SIZE_IN_BYTES;
+        assert!(
+            offset.checked_add(len).is_some_and(|end| end <= size),
+            "transactional memory out of bounds"
+        );
+        let at = inner.end + RECORD_HEADER_LEN;
+        if let Err(error) = grow_to(&inner.journal, at + len) {
+            panic!("failed to grow the journal: {error}");
+        }
+        let mut header = [0; RECORD_HEADER_LEN as usize];
+        header[..8].copy_from_slice(&offset.to_le_bytes());
+        header[8..].copy_from_slice(&len.to_le_bytes());
+        inner.journal.stable_write(inner.end, &header);
+        inner.journal.stable_write(at, buf);
+        inner.set_end(at + len);
+        inner
+            .records
+            .as_mut()
+            .unwrap()
+            .push(Record { offset, len, at });
+    }
+
+    /// Reads `buf.len()` bytes at `offset`, seeing the writes of the transaction over the data memory.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the transaction was rolled back because its task is gone.
+    pub fn read(&self, offset: u64, buf: &mut [u8]) {
+        let inner = self.inner().borrow();
+        inner.data.stable_read(offset, buf);
+        // Later writes take precedence over earlier ones.
+        for record in inner.records.iter().flatten() {
+            let start = record.offset.max(offset);
+            let end = (record.offset + record.len).min(offset + buf.len() as u64);
+            if start < end {
+                inner.journal.stable_read(
+                    record.at + (start - record.offset),
+                    &mut buf[(start - offset) as usize..(end - offset) as usize],
+                );
+            }
+        }
+    }
+
+    /// Applies the writes of the transaction to the data memory.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the transaction was rolled back because its task is gone.
+    pub fn commit(self) {
+        self.inner().borrow_mut().commit();
+    }
+
+    /// Discards the writes of the transaction.
+    pub fn rollback(self) {
+        // Dropping the transaction rolls it back.
+    }
+
+    /// Gets the state of the memory, checking that the transaction is still the open one.
+    fn inner(&self) -> &RefCell<Inner<M, J>> {
+        let inner = &self.memory.inner;
+        let open = inner.borrow().open_id();
+        assert!(open == Some(self.id), "the transaction was rolled back");
+        inner
+    }
+}
+
+impl<M: StableMemory, J: StableMemory> Drop for Transaction<M, J> {
+    fn drop(&mut self) {
+        let mut inner = self.memory.inner.borrow_mut();
+        if inner.open_id() == Some(self.id) {
+            inner.reset();
+        }
+    }
+}
+
+impl<M: StableMemory, J: StableMemory> fmt::Debug for Transaction<M, J> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Transaction").finish_non_exhaustive()
+    }
+}
+
+/// A write of the open transaction, whose bytes are at `at` in the journal.
+struct Record {
+    offset: u64,
+    len: u64,
+    at: u64,
+}
+
+struct Inner<M, J> {
+    data: M,
+    journal: J,
+    /// The writes of the open transaction, in order, or `None` if no transaction is open.
+    records: Option<Vec<Record>>,
+    /// The task that opened the transaction, if it was opened by a task.
+    owner: Option<TaskHandle>,
+    /// The identifier of the last transaction opened.
+    id: u64,
+    /// The end of the records in the journal.
+    end: u64,
+}
+
+impl<M: StableMemory, J: StableMemory> Inner<M, J> {
+    /// Applies the records of the journal to the data memory, and empties the journal.
+    ///
+    /// The journal is marked as being committed first, so that a commit interrupted by a trap is completed by
+    /// [`TransactionalMemory::init`].
+    fn commit(&mut self) {
+        self.set_state(COMMITTING);
+        let mut position = HEADER_LEN;
+        while position < self.end {
+            let mut header = [0; RECORD_HEADER_LEN as usize];
+            self.journal.stable_read(position, &mut header);
+            let offset = u64::from_le_bytes(header[..8].try_into().unwrap());
+            let len = u64::from_le_bytes(header[8..].try_into().unwrap());
+            let mut bytes = vec![0; len as usize];
+            self.journal
+                .stable_read(position + RECORD_HEADER_LEN, &mut bytes);
+            self.data.stable_write(offset, &bytes);
+            position += RECORD_HEADER_LEN + len;
+        }
+        self.reset();
+    }
+
+    /// Gets the identifier of the open transaction, if any.
+    fn open_id(&self) -> Option<u64> {
+        self.records.as_ref().map(|_| self.id)
+    }
+
+    /// Rolls back the open transaction if the task that opened it is gone without dropping it, e.g. because a message
+    /// of the task trapped and the task was not canceled.
+    fn recover(&mut self) {
+        if self.records.is_some() && self.owner.as_ref().is_some_and(|owner| !owner.is_alive()) {
+            self.reset();
+        }
+    }
+
+    /// Discards the records of the journal, and closes the transaction.
+    fn reset(&mut self) {
+        self.records = None;
+        self.owner = None;
+        self.set_end(HEADER_LEN);
+        self.set_state(IDLE);
+    }
+
+    fn set_state(&self, state: u8) {
+        self.journal.stable_write(STATE_OFFSET, &[state]);
+    }
+
+    fn set_end(&mut self, end: u64) {
+        self.end = end;
+        self.journal.stable_write(END_OFFSET, &end.to_le_bytes());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stable::tests::TestStableMemory;
+    use std::rc::Rc;
+    use std::sync::Mutex;
+
+    type Memory = TransactionalMemory<TestStableMemory, TestStableMemory>;
+
+    struct Memories {
+        data: Rc<Mutex<Vec<u8>>>,
+        journal: Rc<Mutex<Vec<u8>>>,
+    }
+
+    impl Memories {
+        fn new() -> Self {
+            let memories = Self {
+                data: Rc::new(Mutex::new(Vec::new())),
+                journal: Rc::new(Mutex::new(Vec::new())),
+            };
+            memories.data().stable_grow(1).unwrap();
+            memories
+        }
+
+        fn data(&self) -> TestStableMemory {
+            TestStableMemory::new(self.data.clone())
+        }
+
+        /// Initializes a transactional memory, as a new version of the canister would.
+        fn init(&self) -> Memory {
+            TransactionalMemory::init(self.data(), TestStableMemory::new(self.journal.clone()))
+                .unwrap()
+        }
+    }
+
+    fn read(memory: &impl StableMemory, offset: u64, len: usize) -> Vec<u8> {
+        let mut buf = vec![0; len];
+        memory.stable_read(offset, &mut buf);
+        buf
+    }
+
+    fn read_in(
+        transaction: &Transaction<TestStableMemory, TestStableMemory>,
+        offset: u64,
+        len: usize,
+    ) -> Vec<u8> {
+        let mut buf = vec![0; len];
+        transaction.read(offset, &mut buf);
+        buf
+    }
+
+    #[test]
+    fn writes_are_applied_on_commit() {
+        let memories = Memories::new();
+        let memory = memories.init();
+        memory.stable_write(0, b"old state");
+
+        let transaction = memory.begin().unwrap();
+        assert!(memory.in_transaction());
+        transaction.write(0, b"new");
+        transaction.write(2, b"xt st");
+        transaction.write(8, b"E");
+        assert_eq!(read_in(&transaction, 0, 9), b"next sttE");
+        assert_eq!(read_in(&transaction, 1, 3), b"ext");
+        // The memory and its clones do not see the writes of the transaction.
+        assert_eq!(read(&memory.clone(), 0, 9), b"old state");
+
+        transaction.commit();
+        assert!(!memory.in_transaction());
+        assert_eq!(read(&memories.data(), 0, 9), b"next sttE");
+        memory.stable_write(0, b"N");
+        assert_eq!(read(&memories.data(), 0, 9), b"Next sttE");
+    }
+
+    #[test]
+    fn writes_outside_the_transaction_are_not_journaled() {
+        let memories = Memories::new();
+        let memory = memories.init();
+        memory.stable_write(0, b"old");
+
+        let transaction = memory.begin().unwrap();
+        transaction.write(0, b"n");
+        memory.clone().stable_write(1, b"L");
+        assert_eq!(read(&memories.data(), 0, 3), b"oLd");
+        assert_eq!(read_in(&transaction, 0, 3), b"nLd");
+        drop(transaction);
+        assert_eq!(read(&memory, 0, 3), b"oLd");
+    }
+
+    #[test]
+    fn writes_are_discarded_on_rollback() {
+        let memories = Memories::new();
+        let memory = memories.init();
+        memory.stable_write(0, b"old");
+
+        let transaction = memory.begin().unwrap();
+        transaction.write(0, b"new");
+        assert!(matches!(memory.begin(), Err(TransactionError::AlreadyOpen)));
+        transaction.rollback();
+        assert_eq!(read(&memory, 0, 3), b"old");
+
+        let transaction = memory.begin().unwrap();
+        transaction.write(0, b"new");
+        drop(transaction);
+        assert_eq!(read(&memory, 0, 3), b"old");
+        assert!(!memory.in_transaction());
+    }
+
+    #[test]
+    fn interrupted_transactions_are_recovered_on_init() {
+        let memories = Memories::new();
+        let memory = memories.init();
+        memory.stable_write(0, b"old");
+
+        // The canister is upgraded while a transaction is open.
+        let transaction = memory.begin().unwrap();
+        transaction.write(0, b"new");
+        std::mem::forget(transaction);
+        let memory = memories.init();
+        assert!(!memory.in_transaction());
+        assert_eq!(read(&memory, 0, 3), b"old");
+
+        // A commit is interrupted after the journal is marked as being committed.
+        let transaction = memory.begin().unwrap();
+        transaction.write(0, b"new");
+        memory.inner.borrow().set_state(COMMITTING);
+        std::mem::forget(transaction);
+        let memory = memories.init();
+        assert_eq!(read(&memory, 0, 3), b"new");
+    }
+
+    #[test]
+    fn other_data_is_rejected() {
+        let memories = Memories::new();
+        let journal = TestStableMemory::new(memories.journal.clone());
+        journal.stable_grow(1).unwrap();
+        journal.stable_write(0, b"data");
+        assert!(matches!(
+            TransactionalMemory::init(memories.data(), journal),
+            Err(TransactionError::UnrecognizedLayout)
+        ));
+    }
+
+    #[test]
+    #[should_panic(expected = "transactional memory out of bounds")]
+    fn writes_beyond_the_size_panic() {
+        let memory = Memories::new().init();
+        let transaction = memory.begin().unwrap();
+        transaction.write(WASM_PAGE_SIZE_IN_BYTES - 1, &[0, 0]);
+    }
+}
@@ -1,6 +1,14 @@
 //! Tools for managing stable storage of data in a canister.
+//!
+//! [`stable_save`] and [`stable_restore`] store a single Candid value in stable memory. The [`snapshot`] module
+//! stores named values with a schema version and a checksum, and migrates them from older versions. The [`codec`]
+//! module saves a value with the serialization format of a [`StableCodec`](codec::StableCodec), streaming it to and
+//! from stable memory.
 use crate::stable;
 
+pub mod codec;
+pub mod snapshot;
+
 /// Saves the storage into the stable memory.
 ///
 /// This will override any value previously stored in stable memory.
@@ -0,0 +1,268 @@
+//! Pluggable serialization formats for saving a value in stable memory.
+//!
+//! [`stable_save`](super::stable_save) encodes the state with Candid, which costs many instructions for a large
+//! state, and [`stable_restore`](super::stable_restore) copies the whole stable memory to the heap before decoding
+//! it. The functions of this module take a [`StableCodec`] instead, and stream the value through a
+//! [`BufferedStableWriter`] and a [`BufferedStableReader`]:
+//!
+//! - [`CandidCodec`] encodes the value with Candid, like `stable_save`. Candid cannot be encoded or decoded
+//!   incrementally, so the encoded value is held on the heap while it is saved or restored.
+//! - [`CborCodec`], behind the `cbor` feature, encodes the value with CBOR, using `serde_cbor`.
+//! - [`BincodeCodec`], behind the `bincode` feature, encodes the value with bincode, using `bincode2`. It is the most
+//!   compact and the cheapest to encode and decode.
+//!
+//! ```rust, no_run
+//! # #[cfg(feature = "bincode")]
+//! # mod example {
+//! use ic_cdk::storage::codec::{self, BincodeCodec};
+//! use ic_cdk::{post_upgrade, pre_upgrade};
+//! use serde::{Deserialize, Serialize};
+//! use std::cell::RefCell;
+//!
+//! #[derive(Serialize, Deserialize, Default)]
+//! struct State {
+//!     balances: Vec<(candid::Principal, u64)>,
+//! }
+//!
+//! thread_local! {
+//!     static STATE: RefCell<State> = RefCell::default();
+//! }
+//!
+//! #[pre_upgrade]
+//! fn pre_upgrade() {
+//!     STATE.with_borrow(|state| codec::save(&BincodeCodec, state)).unwrap();
+//! }
+//!
+//! #[post_upgrade]
+//! fn post_upgrade() {
+//!     let state: State = codec::restore(&BincodeCodec).unwrap();
+//!     STATE.set(state);
+//! }
+//! # }
+//! ```
+//!
+//! The value is saved after a 16-byte header: the magic bytes `CCDC`, the [id](StableCodec::ID) of the codec, three
+//! reserved bytes, and the length of the encoded value as 8 bytes in little endian.
+
+use crate::stable::{
+    BufferedStableReader, BufferedStableWriter, CanisterStableMemory, StableMemory, StableReader,
+    StableWriter, WASM_PAGE_SIZE_IN_BYTES,
+};
+use candid::CandidType;
+use serde::de::DeserializeOwned;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use thiserror::Error;
+
+/// Identifies a value saved with a codec.
+const MAGIC: &[u8; 4] = b"CCDC";
+
+/// The length of the header: the magic bytes, the codec id, and the length of the encoded value.
+const HEADER_LEN: u64 = 16;
+
+/// The size of the buffers of the writer and the reader.
+const BUFFER_SIZE: usize = 1 << 20;
+
+/// The error type of the [`codec`](self) module.
+#[derive(Error, Debug)]
+pub enum CodecError {
+    /// The stable memory does not hold a value saved by this module.
+    #[error("the stable memory does not hold a value saved with a codec")]
+    NotEncoded,
+    /// The value was saved with a different codec.
+    #[error("the value was saved with the codec {saved}, not {requested}")]
+    CodecMismatch {
+        /// The id of the codec the value was saved with.
+        saved: u8,
+        /// The id of the codec the value was restored with.
+        requested: u8,
+    },
+    /// The encoded value extends beyond the end of the stable memory.
+    #[error("the encoded value is truncated")]
+    Truncated,
+    /// Candid encoding or decoding failed.
+    #[error(transparent)]
+    Candid(#[from] candid::Error),
+    /// CBOR encoding or decoding failed.
+    #[cfg(feature = "cbor")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "cbor")))]
+    #[error(transparent)]
+    Cbor(#[from] serde_cbor::Error),
+    /// Bincode encoding or decoding failed.
+    #[cfg(feature = "bincode")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "bincode")))]
+    #[error(transparent)]
+    Bincode(#[from] bincode2::Error),
+    /// Reading or writing the stable memory failed, e.g. because it could not be grown.
+    ///
+    /// Codecs implemented outside of this crate can report their errors as [`io::Error::other`].
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+/// A serialization format for values of type `T` saved in stable memory.
+///
+/// Implementations encode to a writer and decode from a reader, so that the value does not need to be held on the heap
+/// in its encoded form. [`CborCodec`] and [`BincodeCodec`] stream the value this way, but [`CandidCodec`] does not:
+/// Candid has no incremental encoder or decoder, so it holds the whole encoded value on the heap, as
+/// [`stable_save`](super::stable_save) and [`stable_restore`](super::stable_restore) do.
+pub trait StableCodec<T> {
+    /// Identifies the codec in the header of the saved value. The ids below 16 are reserved for this crate.
+    const ID: u8;
+
+    /// Encodes `value` to `writer`.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the value cannot be encoded, or `writer` fails.
+    fn encode(&self, value: &T, writer: &mut impl Write) -> Result<(), CodecError>;
+
+    /// Decodes a value from `reader`, which ends where the encoded value ends.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the value cannot be decoded, or `reader` fails.
+    fn decode(&self, reader: &mut impl Read) -> Result<T, CodecError>;
+}
+
+/// Encodes values with Candid.
+///
+/// Unlike the other codecs, it does not stream the value: the encoded value is held on the heap while it is saved or
+/// restored.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CandidCodec;
+
+impl<T: CandidType + DeserializeOwned> StableCodec<T> for CandidCodec {
+    const ID: u8 = 1;
+
+    fn encode(&self, value: &T, writer: &mut impl Write) -> Result<(), CodecError> {
+        candid::ser::IDLBuilder::new()
+            .arg(value)?
+            .serialize(writer)?;
+        Ok(())
+    }
+
+    fn decode(&self, reader: &mut impl Read) -> Result<T, CodecError> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+        Ok(candid::decode_one(&bytes)?)
+    }
+}
+
+/// Encodes values with CBOR.
+#[cfg(feature = "cbor")]
+#[cfg_attr(docsrs, doc(cfg(feature = "cbor")))]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CborCodec;
+
+#[cfg(feature = "cbor")]
+impl<T: serde::Serialize + DeserializeOwned> StableCodec<T> for CborCodec {
+    const ID: u8 = 2;
+
+    fn encode(&self, value: &T, writer: &mut impl Write) -> Result<(), CodecError> {
+        Ok(serde_cbor::to_writer(writer, value)?)
+    }
+
+    fn decode(&self, reader: &mut impl Read) -> Result<T, CodecError> {
+        Ok(serde_cbor::from_reader(reader)?)
+    }
+}
+
+/// Encodes values with bincode.
+#[cfg(feature = "bincode")]
+#[cfg_attr(docsrs, doc(cfg(feature = "bincode")))]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BincodeCodec;
+
+#[cfg(feature = "bincode")]
+impl<T: serde::Serialize + DeserializeOwned> StableCodec<T> for BincodeCodec {
+    const ID: u8 = 3;
+
+    fn encode(&self, value: &T, writer: &mut impl Write) -> Result<(), CodecError> {
+        Ok(bincode2::serialize_into(writer, value)?)
+    }
+
+    fn decode(&self, reader: &mut impl Read) -> Result<T, CodecError> {
+        Ok(bincode2::deserialize_from(reader)?)
+    }
+}
+
+/// Saves `value` in the stable memory with `codec`, overwriting it from the start.
+///
+/// # Errors
+///
+/// Fails if the value cannot be encoded, or the stable memory cannot be grown to hold it.
+pub fn save<T, C: StableCodec<T>>(codec: &C, value: &T) -> Result<(), CodecError> {
+    save_to(codec, value, CanisterStableMemory::default())
+}
+
+/// Saves `value` in `memory` with `codec`, overwriting it from the start.
+///
+/// # Errors
+///
+/// Fails if the value cannot be encoded, or `memory` cannot be grown to hold it.
+pub fn save_to<T, C: StableCodec<T>, M: StableMemory>(
+    codec: &C,
+    value: &T,
+    memory: M,
+) -> Result<(), CodecError> {
+    let mut writer = BufferedStableWriter::with_writer(
+        BUFFER_SIZE,
+        StableWriter::with_memory(memory, HEADER_LEN),
+    );
+    codec.encode(value, &mut writer)?;
+    writer.flush()?;
+    let len = writer.offset() - HEADER_LEN;
+    // The header is written last, once the length is known.
+    let mut header = [0; HEADER_LEN as usize];
+    header[..4].copy_from_slice(MAGIC);
+    header[4] = C::ID;
+    header[8..].copy_from_slice(&len.to_le_bytes());
+    writer.seek(SeekFrom::Start(0))?;
+    writer.write_all(&header)?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Restores a value saved in the stable memory with `codec`.
+///
+/// # Errors
+///
+/// Fails if the stable memory does not hold a value saved with `codec`, or the value cannot be decoded as `T`.
+pub fn restore<T, C: StableCodec<T>>(codec: &C) -> Result<T, CodecError> {
+    restore_from(codec, CanisterStableMemory::default())
+}
+
+/// Restores a value saved in `memory` with `codec`.
+///
+/// # Errors
+///
+/// Fails if `memory` does not hold a value saved with `codec`, or the value cannot be decoded as `T`.
+pub fn restore_from<T, C: StableCodec<T>, M: StableMemory>(
+    codec: &C,
+    memory: M,
+) -> Result<T, CodecError> {
+    let size = memory.stable_size() * WASM_PAGE_SIZE_IN_BYTES;
+    if size < HEADER_LEN {
+        return Err(CodecError::NotEncoded);
+    }
+    let mut header = [0; HEADER_LEN as usize];
+    memory.stable_read(0, &mut header);
+    if &header[..4] != MAGIC {
+        return Err(CodecError::NotEncoded);
+    }
+    if header[4] != C::ID {
+        return Err(CodecError::CodecMismatch {
+            saved: header[4],
+            requested: C::ID,
+        });
+    }
+    let len = u64::from_le_bytes(header[8..].try_into().unwrap());
+    if len > size - HEADER_LEN {
+        return Err(CodecError::Truncated);
+    }
+    let reader = BufferedStableReader::with_reader(
+        BUFFER_SIZE,
+        StableReader::with_memory(memory, HEADER_LEN),
+    );
+    codec.decode(&mut reader.take(len))
+}
@@ -0,0 +1,484 @@
+//! A versioned format for saving the state of a canister in stable memory across upgrades.
+//!
+//! Unlike [`stable_save`](super::stable_save), which stores a single Candid value, a [`Snapshot`] holds named slots,
+//! each with its own value, and the schema version of the state. It is saved with a header and a checksum, so that
+//! [`Snapshot::load`] detects a stable memory holding something else, or a corrupted snapshot, and reports it with a
+//! typed [`SnapshotError`].
+//!
+//! When the state changes shape, the schema version is bumped, and [`Migrations`] upgrade older snapshots to it step by
+//! step in `post_upgrade`:
+//!
+//! ```rust, no_run
+//! use candid::CandidType;
+//! use ic_cdk::storage::snapshot::{Migrations, Snapshot};
+//! use ic_cdk::{post_upgrade, pre_upgrade};
+//! use serde::Deserialize;
+//!
+//! #[derive(CandidType, Deserialize)]
+//! struct Account {
+//!     owner: candid::Principal,
+//!     balance: u64,
+//! }
+//!
+//! # fn accounts() -> Vec<Account> { vec![] }
+//! # fn restore_accounts(accounts: Vec<Account>) {}
+//! #[pre_upgrade]
+//! fn pre_upgrade() {
+//!     let mut snapshot = Snapshot::new(3);
+//!     snapshot.set("accounts", &accounts()).unwrap();
+//!     snapshot.save().unwrap();
+//! }
+//!
+//! #[post_upgrade]
+//! fn post_upgrade() {
+//!     let snapshot = Migrations::new(3)
+//!         // Version 1 kept the balances in a separate slot.
+//!         .with_migration(1, |snapshot| {
+//!             let owners: Vec<candid::Principal> = snapshot.get("owners")?;
+//!             let balances: Vec<u64> = snapshot.get("balances")?;
+//!             let accounts: Vec<Account> = owners
+//!                 .into_iter()
+//!                 .zip(balances)
+//!                 .map(|(owner, balance)| Account { owner, balance })
+//!                 .collect();
+//!             snapshot.remove("owners");
+//!             snapshot.remove("balances");
+//!             snapshot.set("accounts", &accounts)
+//!         })
+//!         // Version 2 allowed duplicate owners.
+//!         .with_migration(2, |snapshot| {
+//!             let mut accounts: Vec<Account> = snapshot.get("accounts")?;
+//!             accounts.sort_by_key(|account| account.owner);
+//!             accounts.dedup_by_key(|account| account.owner);
+//!             snapshot.set("accounts", &accounts)
+//!         })
+//!         .load()
+//!         .unwrap_or_else(|e| ic_cdk::trap(format!("failed to restore the state: {e}")));
+//!     restore_accounts(snapshot.get("accounts").unwrap());
+//! }
+//! ```
+//!
+//! # Format
+//!
+//! Integers are little endian.
+//!
+//! - The magic bytes `CSNP`, the format version (1) and three reserved bytes.
+//! - The schema version of the state, as 4 bytes, and the number of slots, as 4 bytes.
+//! - The length of the slots, as 8 bytes.
+//! - Each slot, sorted by name: the length of its name, as 4 bytes, its name in UTF-8, the length of its value, as 8
+//!   bytes, and its value.
+//! - The CRC-32 checksum of everything after the magic bytes, as 4 bytes.
+
+use crate::stable::{
+    CanisterStableMemory, StableMemory, StableMemoryError, StableWriter, WASM_PAGE_SIZE_IN_BYTES,
+};
+use candid::CandidType;
+use serde::de::DeserializeOwned;
+use std::collections::BTreeMap;
+use thiserror::Error;
+
+/// Identifies a snapshot.
+const MAGIC: &[u8; 4] = b"CSNP";
+
+/// The version of the format of the snapshot.
+const FORMAT_VERSION: u8 = 1;
+
+/// The length of the header: the magic bytes, the format version, the schema version, the number of slots and the
+/// length of the slots.
+const HEADER_LEN: u64 = 24;
+
+/// The length of the checksum after the slots.
+const CHECKSUM_LEN: u64 = 4;
+
+/// The error type of the [`snapshot`](self) module.
+#[derive(Error, Debug)]
+pub enum SnapshotError {
+    /// The stable memory does not hold a snapshot, e.g. because it was written by
+    /// [`stable_save`](super::stable_save).
+    #[error("the stable memory does not hold a snapshot")]
+    NotASnapshot,
+    /// The snapshot was saved in a format newer than this version of the crate supports.
+    #[error("unsupported snapshot format version {0}")]
+    UnsupportedFormat(u8),
+    /// The snapshot extends beyond the end of the stable memory, or its slots are malformed.
+    #[error("the snapshot is truncated or malformed")]
+    Malformed,
+    /// The checksum of the snapshot does not match its content.
+    #[error(
+        "the snapshot checksum {computed:#010x} does not match the saved checksum {saved:#010x}"
+    )]
+    ChecksumMismatch {
+        /// The checksum saved with the snapshot.
+        saved: u32,
+        /// The checksum computed from the content of the snapshot.
+        computed: u32,
+    },
+    /// The snapshot has no slot with the requested name.
+    #[error("the snapshot has no slot `{0}`")]
+    MissingSlot(String),
+    /// The value of a slot could not be encoded.
+    #[error("failed to encode the slot `{slot}`: {source}")]
+    Encode {
+        /// The name of the slot.
+        slot: String,
+        /// The Candid error.
+        source: candid::Error,
+    },
+    /// The value of a slot could not be decoded as the requested type.
+    #[error("failed to decode the slot `{slot}`: {source}")]
+    Decode {
+        /// The name of the slot.
+        slot: String,
+        /// The Candid error.
+        source: candid::Error,
+    },
+    /// The snapshot has a schema version newer than the latest known by the [`Migrations`].
+    #[error("the snapshot has schema version {found}, newer than the latest version {latest}")]
+    SchemaTooNew {
+        /// The schema version of the snapshot.
+        found: u32,
+        /// The latest schema version of the [`Migrations`].
+        latest: u32,
+    },
+    /// No migration is registered from a schema version older than the latest.
+    #[error("no migration is registered from schema version {0}")]
+    MissingMigration(u32),
+    /// A migration failed.
+    #[error("the migration from schema version {from} failed: {source}")]
+    MigrationFailed {
+        /// The schema version the migration started from.
+        from: u32,
+        /// The error returned by the migration.
+        source: Box<SnapshotError>,
+    },
+    /// The state held by the snapshot is invalid, as reported by a migration.
+    #[error("invalid state: {0}")]
+    InvalidState(String),
+    /// The stable memory could not be grown to hold the snapshot.
+    #[error("failed to write the snapshot: {0}")]
+    StableMemory(#[from] StableMemoryError),
+}
+
+/// The state of a canister as named slots, with the version of its schema.
+///
+/// Each slot holds a value encoded with Candid by [`set`](Self::set), or raw bytes set by
+/// [`set_bytes`](Self::set_bytes).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Snapshot {
+    schema_version: u32,
+    slots: BTreeMap<String, Vec<u8>>,
+}
+
+impl Snapshot {
+    /// Creates an empty snapshot of a state with the given schema version.
+    pub fn new(schema_version: u32) -> Self {
+        Self {
+            schema_version,
+            slots: BTreeMap::new(),
+        }
+    }
+
+    /// Gets the schema version of the state.
+    pub fn schema_version(&self) -> u32 {
+        self.schema_version
+    }
+
+    /// Sets the schema version of the state.
+    ///
+    /// [`Migrations`] set it after each migration, so migrations do not need to.
+    pub fn set_schema_version(&mut self, schema_version: u32) {
+        self.schema_version = schema_version;
+    }
+
+    /// Sets the slot `name` to `value` encoded with Candid, replacing its previous value.
+    ///
+    /// # Errors
+    ///
+    /// Fails with [`SnapshotError::Encode`] if the value cannot be encoded.
+    pub fn set<T: CandidType + ?Sized>(
+        &mut self,
+        name: impl Into<String>,
+        value: &T,
+    ) -> Result<(), SnapshotError> {
+        let name = name.into();
+        match candid::encode_one(value) {
+            Ok(bytes) => {
+                self.slots.insert(name, bytes);
+                Ok(())
+            }
+            Err(source) => Err(SnapshotError::Encode { slot: name, source }),
+        }
+    }
+
+    /// Decodes the value of the slot `name` with Candid.
+    ///
+    /// # Errors
+    ///
+    /// Fails with [`SnapshotError::MissingSlot`] if there is no such slot, or with [`SnapshotError::Decode`] if its
+    /// value cannot be decoded as `T`.
+    pub fn get<T: CandidType + DeserializeOwned>(&self, name: &str) -> Result<T, SnapshotError> {
+        let bytes = self
+            .get_bytes(name)
+            .ok_or_else(|| SnapshotError::MissingSlot(name.to_string()))?;
+        candid::decode_one(bytes).map_err(|source| SnapshotError::Decode {
+            slot: name.to_string(),
+            source,
+        })
+    }
+
+    /// Sets the slot `name` to raw bytes, replacing its previous value.
+    pub fn set_bytes(&mut self, name: impl Into<String>, bytes: Vec<u8>) {
+        self.slots.insert(name.into(), bytes);
+    }
+
+    /// Gets the raw bytes of the slot `name`.
+    pub fn get_bytes(&self, name: &str) -> Option<&[u8]> {
+        self.slots.get(name).map(Vec::as_slice)
+    }
+
+    /// Removes the slot `name`, returning its raw bytes.
+    pub fn remove(&mut self, name: &str) -> Option<Vec<u8>> {
+        self.slots.remove(name)
+    }
+
+    /// Renames the slot `from` to `to`, replacing the slot `to` if it exists.
+    ///
+    /// Returns `false` if there is no slot `from`.
+    pub fn rename(&mut self, from: &str, to: impl Into<String>) -> bool {
+        match self.slots.remove(from) {
+            Some(bytes) => {
+                self.slots.insert(to.into(), bytes);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Checks whether the snapshot has a slot `name`.
+    pub fn contains(&self, name: &str) -> bool {
+        self.slots.contains_key(name)
+    }
+
+    /// Gets the names of the slots, sorted.
+    pub fn slot_names(&self) -> impl Iterator<Item = &str> {
+        self.slots.keys().map(String::as_str)
+    }
+
+    /// Saves the snapshot in the stable memory, overwriting it from the start.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the stable memory cannot be grown to hold the snapshot.
+    pub fn save(&self) -> Result<(), SnapshotError> {
+        self.save_to(CanisterStableMemory::default())
+    }
+
+    /// Saves the snapshot in `memory`, overwriting it from the start.
+    ///
+    /// # Errors
+    ///
+    /// Fails if `memory` cannot be grown to hold the snapshot.
+    pub fn save_to<M: StableMemory>(&self, memory: M) -> Result<(), SnapshotError> {
+        let slots_len: u64 = self
+            .slots
+            .iter()
+            .map(|(name, bytes)| 12 + name.len() as u64 + bytes.len() as u64)
+            .sum();
+        let mut header = [0; HEADER_LEN as usize];
+        header[..4].copy_from_slice(MAGIC);
+        header[4] = FORMAT_VERSION;
+        header[8..12].copy_from_slice(&self.schema_version.to_le_bytes());
+        header[12..16].copy_from_slice(&(self.slots.len() as u32).to_le_bytes());
+        header[16..24].copy_from_slice(&slots_len.to_le_bytes());
+
+        // The magic bytes are not part of the checksum.
+        let mut hasher = crc32fast::Hasher::new();
+        hasher.update(&header[4..]);
+        let mut writer = StableWriter::with_memory(memory, 0);
+        writer.write(&header)?;
+        for (name, bytes) in &self.slots {
+            let name_len = (name.len() as u32).to_le_bytes();
+            let value_len = (bytes.len() as u64).to_le_bytes();
+            for part in [&name_len[..], name.as_bytes(), &value_len, bytes] {
+                hasher.update(part);
+                writer.write(part)?;
+            }
+        }
+        writer.write(&hasher.finalize().to_le_bytes())?;
+        Ok(())
+    }
+
+    /// Loads the snapshot saved in the stable memory.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the stable memory does not hold a valid snapshot.
+    pub fn load() -> Result<Self, SnapshotError> {
+        Self::load_from(CanisterStableMemory::default())
+    }
+
+    /// Loads the snapshot saved in `memory`.
+    ///
+    /// # Errors
+    ///
+    /// Fails if `memory` does not hold a valid snapshot.
+    pub fn load_from<M: StableMemory>(memory: M) -> Result<Self, SnapshotError> {
+        let size = memory.stable_size() * WASM_PAGE_SIZE_IN_BYTES;
+        if size < HEADER_LEN {
+            return Err(SnapshotError::NotASnapshot);
+        }
+        let mut header = [0; HEADER_LEN as usize];
+        memory.stable_read(0, &mut header);
+        if &header[..4] != MAGIC {
+            return Err(SnapshotError::NotASnapshot);
+        }
+        if header[4] != FORMAT_VERSION {
+            return Err(SnapshotError::UnsupportedFormat(header[4]));
+        }
+        let schema_version = u32::from_le_bytes(header[8..12].try_into().unwrap());
+        let count = u32::from_le_bytes(header[12..16].try_into().unwrap());
+        let slots_len = u64::from_le_bytes(header[16..24].try_into().unwrap());
+        if (HEADER_LEN + CHECKSUM_LEN)
+            .checked_add(slots_len)
+            .is_none_or(|end| end > size)
+        {
+            return Err(SnapshotError::Malformed);
+        }
+
+        let mut body = vec![0; slots_len as usize + CHECKSUM_LEN as usize];
+        memory.stable_read(HEADER_LEN, &mut body);
+        let (slots_bytes, saved) = body.split_at(slots_len as usize);
+        let saved = u32::from_le_bytes(saved.try_into().unwrap());
+        let mut hasher = crc32fast::Hasher::new();
+        hasher.update(&header[4..]);
+        hasher.update(slots_bytes);
+        let computed = hasher.finalize();
+        if computed != saved {
+            return Err(SnapshotError::ChecksumMismatch { saved, computed });
+        }
+
+        let slots = parse_slots(slots_bytes, count).ok_or(SnapshotError::Malformed)?;
+        Ok(Self {
+            schema_version,
+            slots,
+        })
+    }
+}
+
+/// Parses `count` slots filling `bytes`.
+fn parse_slots(mut bytes: &[u8], count: u32) -> Option<BTreeMap<String, Vec<u8>>> {
+    fn take<'a>(bytes: &mut &'a [u8], len: usize) -> Option<&'a [u8]> {
+        let (taken, rest) = bytes.split_at_checked(len)?;
+        *bytes = rest;
+        Some(taken)
+    }
+    let mut slots = BTreeMap::new();
+    for _ in 0..count {
+        let name_len = u32::from_le_bytes(take(&mut bytes, 4)?.try_into().ok()?);
+        let name = std::str::from_utf8(take(&mut bytes, name_len as usize)?).ok()?;
+        let value_len = u64::from_le_bytes(take(&mut bytes, 8)?.try_into().ok()?);
+        let value = take(&mut bytes, usize::try_from(value_len).ok()?)?;
+        slots.insert(name.to_string(), value.to_vec());
+    }
+    bytes.is_empty().then_some(slots)
+}
+
+/// A migration of a [`Snapshot`] from one schema version to the next.
+type Migration = Box<dyn Fn(&mut Snapshot) -> Result<(), SnapshotError>>;
+
+/// The migrations upgrading a [`Snapshot`] from older schema versions to the latest one.
+///
+/// Each migration is registered with [`with_migration`](Self::with_migration) for the schema version it upgrades
+/// from, and upgrades the snapshot to the next version. A snapshot of version 1 is upgraded to version 3 by the
+/// migration from version 1, then the migration from version 2.
+pub struct Migrations {
+    latest: u32,
+    migrations: BTreeMap<u32, Migration>,
+}
+
+impl std::fmt::Debug for Migrations {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Migrations")
+            .field("latest", &self.latest)
+            .field("from", &self.migrations.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+impl Migrations {
+    /// Creates a registry of migrations up to the `latest` schema version.
+    pub fn new(latest: u32) -> Self {
+        Self {
+            latest,
+            migrations: BTreeMap::new(),
+        }
+    }
+
+    /// Registers the migration from the schema version `from` to `from + 1`, replacing a previous one.
+    ///
+    /// The migration changes the slots of the snapshot in place. It can fail with the errors of the methods of
+    /// [`Snapshot`], or with [`SnapshotError::InvalidState`].
+    pub fn with_migration(
+        mut self,
+        from: u32,
+        migration: impl Fn(&mut Snapshot) -> Result<(), SnapshotError> + 'static,
+    ) -> Self {
+        self.migrations.insert(from, Box::new(migration));
+        self
+    }
+
+    /// Gets the latest schema version.
+    pub fn latest(&self) -> u32 {
+        self.latest
+    }
+
+    /// Upgrades `snapshot` to the latest schema version.
+    ///
+    /// # Errors
+    ///
+    /// Fails with [`SnapshotError::SchemaTooNew`] if the snapshot is newer than the latest version, with
+    /// [`SnapshotError::MissingMigration`] if a migration is missing, or with [`SnapshotError::MigrationFailed`] if a
+    /// migration fails, leaving the snapshot as that migration left it.
+    pub fn migrate(&self, snapshot: &mut Snapshot) -> Result<(), SnapshotError> {
+        if snapshot.schema_version > self.latest {
+            return Err(SnapshotError::SchemaTooNew {
+                found: snapshot.schema_version,
+                latest: self.latest,
+            });
+        }
+        while snapshot.schema_version < self.latest {
+            let from = snapshot.schema_version;
+            let migration = self
+                .migrations
+                .get(&from)
+                .ok_or(SnapshotError::MissingMigration(from))?;
+            migration(snapshot).map_err(|e| SnapshotError::MigrationFailed {
+                from,
+                source: Box::new(e),
+            })?;
+            snapshot.schema_version = from + 1;
+        }
+        Ok(())
+    }
+
+    /// Loads the snapshot saved in the stable memory and upgrades it to the latest schema version.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the stable memory does not hold a valid snapshot, or the snapshot cannot be
+    /// [migrated](Self::migrate).
+    pub fn load(&self) -> Result<Snapshot, SnapshotError> {
+        self.load_from(CanisterStableMemory::default())
+    }
+
+    /// Loads the snapshot saved in `memory` and upgrades it to the latest schema version.
+    ///
+    /// # Errors
+    ///
+    /// Fails if `memory` does not hold a valid snapshot, or the snapshot cannot be [migrated](Self::migrate).
+    pub fn load_from<M: StableMemory>(&self, memory: M) -> Result<Snapshot, SnapshotError> {
+        let mut snapshot = Snapshot::load_from(memory)?;
+        self.migrate(&mut snapshot)?;
+        Ok(snapshot)
+    }
+}
@@ -0,0 +1,127 @@
+//! Guards methods with `ic_cdk::access_control` against `ic0::host::InMemoryHost`.
+
+use candid::{Decode, Encode, Principal};
+use ic_cdk::access_control::{
+    self, ADMIN, AccessControlState, RoleAction, RoleAssignment, RoleMembers,
+};
+use ic0::host::{InMemoryHost, set_host};
+
+ic_cdk::export_role_management!();
+
+fn controller() -> Principal {
+    Principal::from_slice(&[1])
+}
+
+fn alice() -> Principal {
+    Principal::from_slice(&[2])
+}
+
+fn bob() -> Principal {
+    Principal::from_slice(&[3])
+}
+
+/// Calls `grant_role` as `caller`, returning its reply.
+fn call_grant_role(
+    host: &InMemoryHost,
+    caller: Principal,
+    role: &str,
+    principal: Principal,
+) -> Result<bool, String> {
+    host.reset_message();
+    host.set_caller(caller.as_slice());
+    let assignment = RoleAssignment {
+        role: role.to_string(),
+        principal,
+    };
+    host.set_arg_data(Encode!(&assignment).unwrap());
+    __canister_method_grant_role();
+    host.reply()
+        .unwrap()
+        .map(|bytes| Decode!(&bytes, bool).unwrap())
+}
+
+#[test]
+fn guards_check_the_roles_of_the_caller() {
+    let host = InMemoryHost::new();
+    set_host(host.clone());
+    host.add_controller(controller().as_slice());
+    host.set_time(42);
+
+    host.set_caller(controller().as_slice());
+    assert_eq!(access_control::controllers_only(), Ok(()));
+    assert_eq!(access_control::admins_only(), Ok(()));
+    // Controllers do not hold roles unless they are granted them.
+    assert!(access_control::has_role("minter")().is_err());
+
+    assert!(access_control::grant_role("minter", alice()));
+    assert!(!access_control::grant_role("minter", alice()));
+    host.set_caller(alice().as_slice());
+    assert_eq!(access_control::has_role("minter")(), Ok(()));
+    assert!(access_control::has_role("burner")().is_err());
+    assert!(access_control::controllers_only().is_err());
+    assert!(access_control::admins_only().is_err());
+
+    host.set_caller(controller().as_slice());
+    assert!(access_control::revoke_role("minter", alice()));
+    assert!(!access_control::revoke_role("minter", alice()));
+    assert_eq!(access_control::role_members("minter"), vec![]);
+
+    let log = access_control::audit_log();
+    assert_eq!(log.len(), 2);
+    assert_eq!(
+        (
+            log[0].action,
+            log[0].caller,
+            log[0].principal,
+            log[0].timestamp
+        ),
+        (RoleAction::Grant, controller(), alice(), 42)
+    );
+    assert_eq!(log[1].action, RoleAction::Revoke);
+}
+
+#[test]
+fn role_management_endpoints_are_guarded() {
+    let host = InMemoryHost::new();
+    set_host(host.clone());
+    host.add_controller(controller().as_slice());
+
+    assert!(call_grant_role(&host, alice(), ADMIN, alice()).is_err());
+    assert_eq!(
+        call_grant_role(&host, controller(), ADMIN, alice()),
+        Ok(true)
+    );
+    // Admins can manage roles too.
+    assert_eq!(call_grant_role(&host, alice(), "minter", bob()), Ok(true));
+    assert!(call_grant_role(&host, bob(), "minter", bob()).is_err());
+    assert_eq!(
+        access_control::roles(),
+        vec![
+            RoleMembers {
+                role: ADMIN.to_string(),
+                members: vec![alice()],
+            },
+            RoleMembers {
+                role: "minter".to_string(),
+                members: vec![bob()],
+            },
+        ]
+    );
+    assert_eq!(access_control::audit_log()[1].caller, alice());
+}
+
+#[test]
+fn roles_survive_an_upgrade_when_saved() {
+    let host = InMemoryHost::new();
+    set_host(host.clone());
+    access_control::grant_role("minter", alice());
+
+    let saved = access_control::save();
+    let bytes = Encode!(&saved).unwrap();
+    access_control::restore(AccessControlState::default());
+    assert!(!access_control::holds_role(&alice(), "minter"));
+
+    access_control::restore(Decode!(&bytes, AccessControlState).unwrap());
+    assert!(access_control::holds_role(&alice(), "minter"));
+    assert_eq!(access_control::audit_log().len(), 1);
+}
@@ -0,0 +1,52 @@
+//! Decodes the caller info provided by `ic0::host::InMemoryHost`.
+
+use candid::{Encode, Principal};
+use ic_cdk::caller_info::{
+    self, AttributeFormat, AttributeValue, CallerInfo, CallerInfoError, TrustedSigner,
+};
+use ic0::host::{InMemoryHost, set_host};
+
+fn issuer() -> Principal {
+    Principal::from_slice(&[7])
+}
+
+#[test]
+fn caller_info_is_checked_against_trusted_signers() {
+    let host = InMemoryHost::new();
+    set_host(host.clone());
+    assert_eq!(CallerInfo::current(), Err(CallerInfoError::NoCallerInfo));
+
+    let attributes = vec![
+        ("email".to_string(), AttributeValue::Text("a@b".into())),
+        ("verified".to_string(), AttributeValue::Bool(true)),
+    ];
+    host.set_caller_info(Encode!(&attributes).unwrap(), issuer().as_slice());
+    assert_eq!(
+        CallerInfo::current(),
+        Err(CallerInfoError::UntrustedSigner(issuer()))
+    );
+    assert!(caller_info::is_trusted_caller().is_err());
+
+    caller_info::set_trusted_signers([TrustedSigner::new(issuer(), AttributeFormat::Candid)]);
+    let info = CallerInfo::current().unwrap();
+    assert_eq!(info.signer(), issuer());
+    assert_eq!(info.attributes().len(), 2);
+    assert_eq!(info.get("email"), Some(&AttributeValue::Text("a@b".into())));
+    assert_eq!(caller_info::is_trusted_caller(), Ok(()));
+    assert_eq!(
+        caller_info::require_attribute("verified", &AttributeValue::Bool(true)),
+        Ok(())
+    );
+    assert!(caller_info::require_attribute("verified", &AttributeValue::Bool(false)).is_err());
+    assert!(caller_info::require_attribute("age", &AttributeValue::Nat(18)).is_err());
+
+    // The same signer documenting another format cannot be decoded.
+    #[cfg(feature = "cbor")]
+    {
+        caller_info::set_trusted_signers([TrustedSigner::new(issuer(), AttributeFormat::Cbor)]);
+        assert!(matches!(
+            CallerInfo::current(),
+            Err(CallerInfoError::DecodeFailed(_))
+        ));
+    }
+}
@@ -0,0 +1,106 @@
+//! Cancels in-flight calls against the call simulator of `ic0::host::InMemoryHost`.
+
+use candid::Principal;
+use ic_cdk::call::{Call, CallErrorExt, CallFailed, CancelHandle, RejectCode};
+use ic_cdk::futures::internals::in_executor_context;
+use ic_cdk::futures::{spawn, spawn_migratory};
+use ic0::host::{CallResponse, InMemoryHost, set_host};
+use std::cell::RefCell;
+use std::future::{Future, IntoFuture};
+use std::pin::Pin;
+use std::rc::Rc;
+use std::sync::Mutex;
+use std::task::{Context, Waker};
+use std::time::Duration;
+
+static LATE_RESPONSES: Mutex<Vec<(bool, u128)>> = Mutex::new(Vec::new());
+
+fn ledger() -> Principal {
+    Principal::from_slice(&[1])
+}
+
+fn install() -> InMemoryHost {
+    let host = InMemoryHost::new();
+    set_host(host.clone());
+    host.set_cycle_balance(1_000);
+    host.on_call(ledger(), "deposit", |_| {
+        CallResponse::reply(vec![])
+            .accept_cycles(30)
+            .with_latency(Duration::from_secs(5))
+    });
+    host
+}
+
+#[test]
+fn cancelled_calls_report_late_responses() {
+    let host = install();
+    LATE_RESPONSES.lock().unwrap().clear();
+    in_executor_context(|| {
+        spawn(async {
+            let mut future = Call::unbounded_wait(ledger(), "deposit")
+                .with_cycles(100)
+                .into_future();
+            // Send the call, then drop it.
+            assert!(
+                Pin::new(&mut future)
+                    .poll(&mut Context::from_waker(Waker::noop()))
+                    .is_pending()
+            );
+        });
+    });
+    host.run_until_idle();
+    assert_eq!(host.cycle_balance(), 970);
+    assert!(host.traps().is_empty());
+
+    in_executor_context(|| {
+        spawn(async {
+            let mut future = Call::unbounded_wait(ledger(), "deposit")
+                .with_cycles(100)
+                .into_future();
+            assert!(
+                Pin::new(&mut future)
+                    .poll(&mut Context::from_waker(Waker::noop()))
+                    .is_pending()
+            );
+            future.cancel_with(|late| {
+                LATE_RESPONSES
+                    .lock()
+                    .unwrap()
+                    .push((late.result.is_ok(), late.cycles_refunded));
+            });
+        });
+    });
+    assert!(LATE_RESPONSES.lock().unwrap().is_empty());
+    host.run_until_idle();
+    assert_eq!(*LATE_RESPONSES.lock().unwrap(), [(true, 70)]);
+    assert_eq!(host.cycle_balance(), 940);
+}
+
+#[test]
+fn cancel_handle_resumes_the_awaiting_task() {
+    let host = install();
+    let handle: Rc<RefCell<Option<CancelHandle<'static, 'static>>>> = Rc::default();
+    let outcome = Rc::new(RefCell::new(None));
+    let (h, out) = (handle.clone(), outcome.clone());
+    in_executor_context(|| {
+        spawn_migratory(async move {
+            let future = Call::unbounded_wait(ledger(), "deposit").into_future();
+            *h.borrow_mut() = Some(future.cancel_handle());
+            *out.borrow_mut() = Some(future.await);
+        });
+    });
+    // As a timer would, from another method.
+    in_executor_context(|| {
+        let handle = handle.borrow();
+        assert!(handle.as_ref().unwrap().cancel());
+        assert!(!handle.as_ref().unwrap().cancel());
+    });
+    let Some(Err(CallFailed::CallRejected(rejected))) = outcome.borrow_mut().take() else {
+        panic!("the task did not resume with a rejection");
+    };
+    assert_eq!(rejected.reject_code(), Ok(RejectCode::SysUnknown));
+    assert!(!rejected.is_clean_reject());
+    // The late response is ignored.
+    host.run_until_idle();
+    assert!(host.traps().is_empty());
+}
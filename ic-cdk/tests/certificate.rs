@@ -0,0 +1,86 @@
+//! Certifies data with `ic0::host::InMemoryHost` standing in for the subnet, and verifies the data certificate.
+
+use ic_cdk::api::{canister_self, certified_data_set, root_key};
+use ic_cdk::certificate::{self, Certificate, CertificateError, HashTree};
+use ic_certified_map::{labeled, leaf_hash};
+use ic0::host::{InMemoryHost, set_host};
+use sha2::{Digest, Sha256};
+
+const ROOT_KEY: [u8; 96] = [3; 96];
+
+/// The DER encoding of a BLS public key.
+fn der(key: &[u8]) -> Vec<u8> {
+    let prefix =
+        hex::decode("308182301d060d2b0601040182dc7c0503010201060c2b0601040182dc7c05030201036100")
+            .unwrap();
+    [prefix.as_slice(), key].concat()
+}
+
+/// Signs by hashing the message with the key, which stands in for a BLS signature.
+fn sign(message: &[u8], key: &[u8]) -> Vec<u8> {
+    Sha256::new()
+        .chain_update(message)
+        .chain_update(key)
+        .finalize()
+        .to_vec()
+}
+
+fn verify_bls(signature: &[u8], message: &[u8], key: &[u8]) -> bool {
+    signature == sign(message, key)
+}
+
+/// Certifies the current certified data of the canister, as the subnet would.
+fn certify(host: &InMemoryHost) -> Vec<u8> {
+    let canister_id = canister_self();
+    let tree = labeled(
+        b"canister",
+        labeled(
+            canister_id.as_slice(),
+            labeled(
+                b"certified_data",
+                HashTree::Leaf(host.certified_data().into()),
+            ),
+        ),
+    );
+    let message = [b"\x0dic-state-root".as_slice(), &tree.reconstruct()].concat();
+    let signature = sign(&message, &ROOT_KEY);
+    Certificate::new(tree, signature, None).encode()
+}
+
+#[test]
+fn certified_data_round_trips() {
+    let host = InMemoryHost::new();
+    set_host(host.clone());
+    host.set_root_key(der(&ROOT_KEY));
+
+    // In an update call, capture the root key and certify a tree.
+    let root_key = root_key();
+    let tree = labeled(b"greeting", HashTree::Leaf(b"hi".into()));
+    certified_data_set(tree.reconstruct());
+    assert_eq!(
+        certificate::verify_data_certificate_with(&tree.reconstruct(), &root_key, verify_bls),
+        Err(CertificateError::NoDataCertificate)
+    );
+
+    // In a query, verify the data certificate against the tree.
+    host.set_replicated_execution(false);
+    host.set_data_certificate(Some(certify(&host)));
+    assert_eq!(
+        certificate::verify_data_certificate_with(&tree.reconstruct(), &root_key, verify_bls),
+        Ok(())
+    );
+    let bytes = ic_cdk::api::data_certificate().unwrap();
+    let certificate = Certificate::decode(&bytes).unwrap();
+    assert_eq!(
+        certificate.certified_data(canister_self()),
+        Some(tree.reconstruct().as_slice())
+    );
+    assert_eq!(
+        certificate::verify_data_certificate_with(&leaf_hash(b"bye"), &root_key, verify_bls),
+        Err(CertificateError::CertifiedDataMismatch)
+    );
+    assert_eq!(
+        certificate::verify_data_certificate_with(&tree.reconstruct(), &der(&[4; 96]), verify_bls),
+        Err(CertificateError::InvalidSignature)
+    );
+}
@@ -0,0 +1,101 @@
+//! Saves and restores values with `ic_cdk::storage::codec` against `ic0::host::InMemoryHost`.
+
+use candid::CandidType;
+use ic_cdk::stable::memory_manager::{MemoryId, MemoryManager};
+use ic_cdk::stable::{self, CanisterStableMemory};
+use ic_cdk::storage::codec::{self, BincodeCodec, CandidCodec, CborCodec, CodecError, StableCodec};
+use ic0::host::{InMemoryHost, set_host};
+use serde::{Deserialize, Serialize};
+
+#[derive(CandidType, Serialize, Deserialize, Debug, PartialEq)]
+struct State {
+    name: String,
+    balances: Vec<(u32, u64)>,
+}
+
+fn state(accounts: u32) -> State {
+    State {
+        name: "ledger".to_string(),
+        balances: (0..accounts).map(|i| (i, u64::from(i) * 7)).collect(),
+    }
+}
+
+fn round_trip<C: StableCodec<State>>(codec: C) {
+    set_host(InMemoryHost::new());
+    let saved = state(10);
+    codec::save(&codec, &saved).unwrap();
+    assert_eq!(codec::restore::<State, _>(&codec).unwrap(), saved);
+}
+
+#[test]
+fn values_round_trip() {
+    round_trip(CandidCodec);
+    round_trip(CborCodec);
+    round_trip(BincodeCodec);
+}
+
+#[test]
+fn large_values_are_streamed() {
+    set_host(InMemoryHost::new());
+    // Spans several buffers of the writer and the reader.
+    let saved = state(300_000);
+    codec::save(&BincodeCodec, &saved).unwrap();
+    assert_eq!(codec::restore::<State, _>(&BincodeCodec).unwrap(), saved);
+
+    // A smaller value saved afterwards is restored without the leftover bytes.
+    let saved = state(3);
+    codec::save(&CborCodec, &saved).unwrap();
+    assert_eq!(codec::restore::<State, _>(&CborCodec).unwrap(), saved);
+}
+
+#[test]
+fn values_are_saved_in_virtual_memories() {
+    set_host(InMemoryHost::new());
+    let manager = MemoryManager::init(CanisterStableMemory::default()).unwrap();
+    let (a, b) = (manager.get(MemoryId::new(0)), manager.get(MemoryId::new(1)));
+    codec::save_to(&BincodeCodec, &state(2), a.clone()).unwrap();
+    codec::save_to(&CandidCodec, &state(5), b.clone()).unwrap();
+    assert_eq!(
+        codec::restore_from::<State, _, _>(&BincodeCodec, a).unwrap(),
+        state(2)
+    );
+    assert_eq!(
+        codec::restore_from::<State, _, _>(&CandidCodec, b).unwrap(),
+        state(5)
+    );
+}
+
+#[test]
+fn invalid_stable_memory_is_rejected() {
+    set_host(InMemoryHost::new());
+    assert!(matches!(
+        codec::restore::<State, _>(&CborCodec),
+        Err(CodecError::NotEncoded)
+    ));
+
+    ic_cdk::storage::stable_save((42_u64,)).unwrap();
+    assert!(matches!(
+        codec::restore::<State, _>(&CborCodec),
+        Err(CodecError::NotEncoded)
+    ));
+
+    codec::save(&CborCodec, &state(1)).unwrap();
+    assert!(matches!(
+        codec::restore::<State, _>(&BincodeCodec),
+        Err(CodecError::CodecMismatch {
+            saved: 2,
+            requested: 3
+        })
+    ));
+    assert!(matches!(
+        codec::restore::<u64, _>(&CborCodec),
+        Err(CodecError::Cbor(_))
+    ));
+
+    // The length of the value runs past the end of the stable memory.
+    stable::stable_write(8, &u64::MAX.to_le_bytes());
+    assert!(matches!(
+        codec::restore::<State, _>(&CborCodec),
+        Err(CodecError::Truncated)
+    ));
+}
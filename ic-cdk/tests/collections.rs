@@ -0,0 +1,52 @@
+//! Keeps `ic_cdk::stable::collections` in the virtual memories of a memory manager against `ic0::host::InMemoryHost`.
+
+use candid::Principal;
+use ic_cdk::stable::CanisterStableMemory;
+use ic_cdk::stable::collections::{InitError, StableBTreeMap, StableCell, StableLog, StableVec};
+use ic_cdk::stable::memory_manager::{MemoryId, MemoryManager, VirtualMemory};
+use ic0::host::{InMemoryHost, set_host};
+
+type Balances = StableBTreeMap<Principal, u64, VirtualMemory>;
+
+fn memories() -> impl Fn(u8) -> VirtualMemory {
+    let manager = MemoryManager::init(CanisterStableMemory::default()).unwrap();
+    move |id| manager.get(MemoryId::new(id))
+}
+
+#[test]
+fn collections_survive_an_upgrade() {
+    set_host(InMemoryHost::new());
+    let alice = Principal::from_slice(&[1]);
+    let bob = Principal::from_slice(&[2]);
+
+    let memory = memories();
+    let mut balances = Balances::init(memory(0)).unwrap();
+    balances.insert(alice, 10).unwrap();
+    balances.insert(bob, 20).unwrap();
+    let mut history = StableVec::init(memory(1)).unwrap();
+    history.push(&(alice, 10_u64)).unwrap();
+    let mut log = StableLog::init(memory(2), memory(3)).unwrap();
+    log.append(&"opened".to_string()).unwrap();
+    let mut version = StableCell::init(memory(4), 1_u32).unwrap();
+    version.set(2).unwrap();
+    drop((balances, history, log, version, memory));
+
+    // After an upgrade, the heap is empty, and the collections are found in stable memory.
+    let memory = memories();
+    let balances = Balances::init(memory(0)).unwrap();
+    assert_eq!(
+        balances.iter().collect::<Vec<_>>(),
+        [(alice, 10), (bob, 20)]
+    );
+    let history = StableVec::<(Principal, u64), _>::init(memory(1)).unwrap();
+    assert_eq!(history.get(0), Some((alice, 10)));
+    let log = StableLog::<String, _, _>::init(memory(2), memory(3)).unwrap();
+    assert_eq!(log.get(0).unwrap(), "opened");
+    let version = StableCell::init(memory(4), 1_u32).unwrap();
+    assert_eq!(*version.get(), 2);
+
+    assert!(matches!(
+        StableVec::<u8, _>::init(memory(0)),
+        Err(InitError::UnrecognizedLayout)
+    ));
+}
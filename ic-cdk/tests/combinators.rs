@@ -0,0 +1,112 @@
+//! Runs the call combinators against the call simulator of `ic0::host::InMemoryHost`.
+
+use candid::{Encode, Principal};
+use ic_cdk::call::{Call, CallFailed, RejectCode, join_all, select};
+use ic_cdk::futures::internals::in_executor_context;
+use ic_cdk::futures::spawn;
+use ic0::host::{CallResponse, InMemoryHost, set_host};
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::time::Duration;
+
+fn callee() -> Principal {
+    Principal::from_slice(&[1])
+}
+
+fn install() -> InMemoryHost {
+    let host = InMemoryHost::new();
+    set_host(host.clone());
+    host.on_call(callee(), "echo", |request| {
+        CallResponse::reply(request.arg.clone()).with_latency(Duration::from_secs(1))
+    });
+    host.on_call(callee(), "slow", |_| {
+        CallResponse::reply(Encode!().unwrap()).with_latency(Duration::from_secs(10))
+    });
+    host
+}
+
+#[test]
+fn join_all_limits_concurrency() {
+    let host = install();
+    let started = Rc::new(RefCell::new(vec![]));
+    let log = started.clone();
+    host.on_call(callee(), "echo", move |request| {
+        log.borrow_mut().push(ic0::time() / 1_000_000_000);
+        CallResponse::reply(request.arg.clone()).with_latency(Duration::from_secs(1))
+    });
+    let results = Rc::new(RefCell::new(vec![]));
+    let out = results.clone();
+    in_executor_context(|| {
+        spawn(async move {
+            let calls = (0..5u32).map(|n| Call::bounded_wait(callee(), "echo").with_arg(n));
+            for result in join_all(calls, 2).await.unwrap() {
+                out.borrow_mut()
+                    .push(result.unwrap().candid::<u32>().unwrap());
+            }
+        });
+    });
+    host.run_until_idle();
+    assert_eq!(*results.borrow(), [0, 1, 2, 3, 4]);
+    // Two calls at a time, each taking a second.
+    assert_eq!(*started.borrow(), [0, 0, 1, 1, 2]);
+}
+
+#[test]
+fn join_all_checks_cycles_up_front() {
+    let host = install();
+    host.set_call_cost(1_000, 0);
+    host.set_cycle_balance(2_500);
+    let error = Rc::new(RefCell::new(None));
+    let out = error.clone();
+    in_executor_context(|| {
+        spawn(async move {
+            let calls = (0..3).map(|_| Call::bounded_wait(callee(), "echo"));
+            *out.borrow_mut() = join_all(calls, 3).await.err();
+        });
+    });
+    let error = error.borrow_mut().take().unwrap();
+    assert_eq!((error.available, error.required), (2_500, 3_000));
+    assert!(host.is_idle());
+}
+
+#[test]
+fn select_returns_first_completion() {
+    let host = install();
+    let results = Rc::new(RefCell::new(vec![]));
+    let out = results.clone();
+    in_executor_context(|| {
+        spawn(async move {
+            let calls = [
+                Call::bounded_wait(callee(), "slow"),
+                Call::bounded_wait(callee(), "echo"),
+            ];
+            let (index, result) = select(calls, 60).await.unwrap();
+            out.borrow_mut().push((index, result.is_ok()));
+
+            // Both bounded-wait calls time out after 5 seconds, and the first one is reported.
+            let calls = [
+                Call::bounded_wait(callee(), "slow"),
+                Call::bounded_wait(callee(), "slow").change_timeout(3_600),
+            ];
+            let (index, result) = select(calls, 5).await.unwrap();
+            let Err(CallFailed::CallRejected(rejected)) = result else {
+                panic!("expected a reject");
+            };
+            assert_eq!(rejected.reject_code(), Ok(RejectCode::SysUnknown));
+            out.borrow_mut().push((index, false));
+        });
+    });
+    host.run_until_idle();
+    assert_eq!(*results.borrow(), [(1, true), (0, false)]);
+}
+
+#[test]
+#[should_panic(expected = "select requires bounded-wait calls")]
+fn select_rejects_unbounded_wait_calls() {
+    install();
+    let calls = [
+        Call::bounded_wait(callee(), "echo"),
+        Call::unbounded_wait(callee(), "slow"),
+    ];
+    let _ = futures::executor::block_on(select(calls, 5));
+}
@@ -0,0 +1,104 @@
+//! Charges callers and budgets calls with `ic_cdk::cycles` against `ic0::host::InMemoryHost`.
+
+use candid::Principal;
+use ic_cdk::api::SignCostError;
+use ic_cdk::call::Call;
+use ic_cdk::cycles::{
+    BudgetedCallError, CostPlan, CostPlanError, CyclesBudget, InsufficientCyclesAttached, charge,
+};
+use ic_cdk::futures::internals::in_executor_context;
+use ic_cdk::futures::spawn;
+use ic0::host::{CallResponse, InMemoryHost, set_host};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+#[test]
+fn charge_accepts_or_refuses_the_attached_cycles() {
+    let host = InMemoryHost::new();
+    set_host(host.clone());
+    host.set_cycles_available(1_000);
+    assert_eq!(
+        charge(2_000, 2_000),
+        Err(InsufficientCyclesAttached {
+            available: 1_000,
+            required: 2_000
+        })
+    );
+    assert_eq!(host.cycles_available(), 1_000);
+    assert_eq!(charge(300, 300), Ok(300));
+    assert_eq!(charge(0, u128::MAX), Ok(700));
+    assert_eq!(host.cycles_available(), 0);
+}
+
+#[test]
+fn budget_tracks_cycles_spent_on_calls() {
+    let host = InMemoryHost::new();
+    set_host(host.clone());
+    host.set_cycle_balance(1_000_000);
+    host.set_call_cost(100, 0);
+    let callee = Principal::from_slice(&[1]);
+    host.on_call(callee, "work", |_| {
+        CallResponse::reply(vec![]).accept_cycles(400)
+    });
+
+    let budget = Rc::new(CyclesBudget::new(2_000));
+    let errors = Rc::new(RefCell::new(vec![]));
+    let (task_budget, out) = (budget.clone(), errors.clone());
+    in_executor_context(|| {
+        spawn(async move {
+            let call = || Call::bounded_wait(callee, "work").with_cycles(1_000);
+            task_budget.call(call()).await.unwrap();
+            // 1_000 attached and 100 for the call, minus the 600 refunded.
+            assert_eq!(task_budget.spent(), 500);
+            task_budget.call(call()).await.unwrap();
+            assert_eq!(task_budget.remaining(), 1_000);
+            let Err(e) = task_budget
+                .call(Call::bounded_wait(callee, "work").with_cycles(1_000))
+                .await
+            else {
+                panic!("the budget is exceeded");
+            };
+            out.borrow_mut().push(e);
+        });
+    });
+    host.run_until_idle();
+    assert!(matches!(
+        errors.borrow()[..],
+        [BudgetedCallError::BudgetExceeded {
+            cost: 1_100,
+            remaining: 1_000
+        }]
+    ));
+    assert_eq!(budget.spent(), 1_000);
+}
+
+#[test]
+fn plan_is_checked_against_the_liquid_cycle_balance() {
+    let host = InMemoryHost::new();
+    set_host(host.clone());
+    host.set_cycle_balance(3_000);
+    host.set_call_cost(100, 0);
+    let callee = Principal::from_slice(&[1]);
+
+    let calls = [
+        Call::bounded_wait(callee, "a").with_cycles(1_000),
+        Call::bounded_wait(callee, "b").with_cycles(1_000),
+    ];
+    let mut plan = CostPlan::new().with(&calls[..]);
+    assert_eq!(plan.check_balance().unwrap(), 2_200);
+
+    plan.add(&1_000_u128);
+    let Err(CostPlanError::InsufficientLiquidCycleBalance(e)) = plan.check_balance() else {
+        panic!("the plan costs more than the balance");
+    };
+    assert_eq!(
+        (e.available, e.required, e.shortfall()),
+        (3_000, 3_200, 200)
+    );
+
+    plan.add(&Err::<u128, _>(SignCostError::InvalidKeyName));
+    assert!(matches!(
+        plan.total(),
+        Err(CostPlanError::CostUnavailable { index: 2, .. })
+    ));
+}
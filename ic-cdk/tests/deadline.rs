@@ -0,0 +1,110 @@
+//! Propagates the deadline of the caller into nested calls made against `ic0::host::InMemoryHost`.
+
+use candid::Principal;
+use ic_cdk::api::Deadline;
+use ic_cdk::call::Call;
+use ic_cdk::futures::internals::{in_executor_context, with_message_deadline};
+use ic_cdk::futures::spawn;
+use ic0::host::{CallResponse, InMemoryHost, set_host};
+use std::cell::RefCell;
+use std::num::NonZeroU64;
+use std::rc::Rc;
+use std::time::Duration;
+
+const SECOND: u64 = 1_000_000_000;
+
+#[test]
+fn deadline_reports_the_remaining_time() {
+    let host = InMemoryHost::new();
+    set_host(host.clone());
+    host.set_time(100 * SECOND);
+    assert!(!Deadline::current().is_bounded_wait());
+    assert_eq!(Deadline::current().remaining(), None);
+
+    host.set_deadline(142 * SECOND + SECOND / 2);
+    let deadline = Deadline::current();
+    assert!(deadline.is_bounded_wait());
+    assert_eq!(deadline.get(), NonZeroU64::new(142 * SECOND + SECOND / 2));
+    assert_eq!(deadline.remaining(), Some(Duration::from_millis(42_500)));
+    assert!(!deadline.has_passed());
+    host.set_time(200 * SECOND);
+    assert_eq!(deadline.remaining(), Some(Duration::ZERO));
+    assert!(deadline.has_passed());
+}
+
+#[test]
+fn nested_calls_stay_within_the_deadline() {
+    let host = InMemoryHost::new();
+    set_host(host.clone());
+    let callee = Principal::from_slice(&[1]);
+    let deadlines = Rc::new(RefCell::new(vec![]));
+    let log = deadlines.clone();
+    host.on_call(callee, "nested", move |request| {
+        log.borrow_mut().push(request.deadline);
+        CallResponse::reply(vec![])
+    });
+    host.set_time(100 * SECOND);
+    host.set_deadline(142 * SECOND + SECOND / 2);
+    in_executor_context(|| {
+        spawn(async move {
+            let deadline = Deadline::current();
+            for call in [
+                Call::bounded_wait(callee, "nested"),
+                Call::bounded_wait(callee, "nested").change_timeout(10),
+                Call::unbounded_wait(callee, "nested"),
+            ] {
+                call.within_deadline(deadline).await.unwrap();
+            }
+            // Without a deadline, the timeout stays as it is.
+            Call::bounded_wait(callee, "nested")
+                .within_deadline(Deadline::new(None))
+                .await
+                .unwrap();
+        });
+    });
+    host.run_until_idle();
+    assert_eq!(
+        *deadlines.borrow(),
+        [142 * SECOND, 110 * SECOND, 0, 400 * SECOND]
+    );
+}
+
+#[test]
+fn tasks_keep_the_deadline_of_their_message() {
+    let host = InMemoryHost::new();
+    set_host(host.clone());
+    let callee = Principal::from_slice(&[1]);
+    let deadlines = Rc::new(RefCell::new(vec![]));
+    let log = deadlines.clone();
+    host.on_call(callee, "nested", move |request| {
+        log.borrow_mut().push(request.deadline);
+        CallResponse::reply(vec![])
+    });
+    host.set_time(100 * SECOND);
+    host.set_deadline(142 * SECOND + SECOND / 2);
+    in_executor_context(|| {
+        spawn(with_message_deadline(async move {
+            Call::bounded_wait(callee, "nested").await.unwrap();
+            // The callback runs in another message, but the task keeps its deadline.
+            assert_eq!(
+                Deadline::current().get(),
+                NonZeroU64::new(142 * SECOND + SECOND / 2)
+            );
+            Call::bounded_wait(callee, "nested")
+                .change_timeout(100)
+                .await
+                .unwrap();
+            // A task spawned within the task inherits its deadline.
+            spawn(async move {
+                Call::bounded_wait(callee, "nested").await.unwrap();
+            });
+            Call::unbounded_wait(callee, "nested").await.unwrap();
+        }));
+    });
+    host.run_until_idle();
+    assert_eq!(
+        *deadlines.borrow(),
+        // The spawned task runs once the task awaits its last call.
+        [142 * SECOND, 142 * SECOND, 0, 142 * SECOND]
+    );
+}
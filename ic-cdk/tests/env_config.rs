@@ -0,0 +1,58 @@
+//! Loads configurations from the environment variables of `ic0::host::InMemoryHost`.
+
+use candid::Principal;
+use ic_cdk::env_config::{EnvConfig, EnvConfigError, FromEnvVar};
+use ic0::host::{InMemoryHost, set_host};
+use std::time::Duration;
+
+#[derive(Debug, PartialEq, FromEnvVar)]
+enum Network {
+    Mainnet,
+    #[env(rename = "local")]
+    Testnet,
+}
+
+#[derive(Debug, EnvConfig)]
+#[env(prefix = "APP_")]
+struct Config {
+    ledger: Principal,
+    #[env(name = "NETWORK")]
+    network: Network,
+    #[env(default)]
+    max_batch: u32,
+    #[env(default = "30s")]
+    timeout: Duration,
+    admin: Option<Principal>,
+}
+
+#[test]
+fn config_is_loaded_and_cached() {
+    let host = InMemoryHost::new();
+    set_host(host.clone());
+    assert_eq!(
+        Config::load().unwrap_err(),
+        EnvConfigError::Missing("APP_LEDGER".into())
+    );
+
+    let ledger = "ryjl3-tyaaa-aaaaa-aaaba-cai";
+    host.set_env_var("APP_LEDGER", ledger);
+    host.set_env_var("NETWORK", "LOCAL");
+    let config = Config::get();
+    assert_eq!(config.ledger, Principal::from_text(ledger).unwrap());
+    assert_eq!(config.network, Network::Testnet);
+    assert_eq!(config.max_batch, 0);
+    assert_eq!(config.timeout, Duration::from_secs(30));
+    assert_eq!(config.admin, None);
+
+    // The configuration is cached until it is reloaded.
+    host.set_env_var("APP_TIMEOUT", "5m");
+    assert_eq!(Config::get().timeout, Duration::from_secs(30));
+    assert_eq!(Config::reload().unwrap().timeout, Duration::from_secs(300));
+
+    host.set_env_var("NETWORK", "moon");
+    assert_eq!(
+        Config::reload().unwrap_err().to_string(),
+        "environment variable `NETWORK` has an invalid value `moon`: expected one of: mainnet, local"
+    );
+    assert_eq!(Config::get().network, Network::Testnet);
+}
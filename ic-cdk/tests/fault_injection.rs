@@ -0,0 +1,204 @@
+//! Drives inter-canister calls through the failures that `ic0::host::InMemoryHost` can inject.
+
+use candid::Principal;
+use ic_cdk::call::{Call, CallFailed, RejectCode};
+use ic_cdk::futures::internals::in_executor_context;
+use ic_cdk::futures::{is_recovering_from_trap, spawn};
+use ic0::host::{CallResponse, Fault, FaultExplorer, InMemoryHost, set_host};
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use std::time::Duration;
+
+thread_local! {
+    static BALANCE: Cell<u64> = const { Cell::new(0) };
+    static SENT: Cell<u64> = const { Cell::new(0) };
+    static RECOVERED: Cell<u32> = const { Cell::new(0) };
+}
+
+fn ledger() -> Principal {
+    Principal::from_slice(&[1])
+}
+
+#[test]
+fn each_fault_surfaces_as_call_failed() {
+    let host = InMemoryHost::new();
+    set_host(host.clone());
+    host.on_call(ledger(), "deposit", |_| CallResponse::reply(vec![]));
+    let outcomes = Rc::new(RefCell::new(vec![]));
+    for fault in Fault::all() {
+        host.inject_fault(fault);
+        let outcomes = outcomes.clone();
+        in_executor_context(|| {
+            spawn(async move {
+                let outcome = match Call::bounded_wait(ledger(), "deposit").await {
+                    Ok(_) => Err("replied"),
+                    Err(CallFailed::CallPerformFailed(_)) => Err("perform failed"),
+                    Err(CallFailed::CallRejected(rejected)) => Ok(rejected.reject_code().unwrap()),
+                    Err(CallFailed::InsufficientLiquidCycleBalance(_)) => {
+                        Err("insufficient cycles")
+                    }
+                };
+                outcomes.borrow_mut().push(outcome);
+            });
+        });
+        host.run_until_idle();
+    }
+    assert_eq!(
+        *outcomes.borrow(),
+        [
+            Err("perform failed"),
+            Ok(RejectCode::SysFatal),
+            Ok(RejectCode::SysTransient),
+            Ok(RejectCode::DestinationInvalid),
+            Ok(RejectCode::SysUnknown),
+            // The timeout.
+            Ok(RejectCode::SysUnknown),
+        ]
+    );
+    // The trap in the callback cancelled the task instead of letting it record an outcome.
+    assert_eq!(
+        host.traps(),
+        ["canister trapped: injected trap in callback"]
+    );
+}
+
+/// Returns reserved funds to the balance unless the transfer went through, including when the task is cancelled
+/// because a callback trapped.
+struct Reservation(u64);
+
+impl Drop for Reservation {
+    fn drop(&mut self) {
+        if is_recovering_from_trap() {
+            RECOVERED.set(RECOVERED.get() + 1);
+        }
+        BALANCE.set(BALANCE.get() + self.0);
+    }
+}
+
+#[test]
+fn concurrent_transfers_stay_consistent() {
+    RECOVERED.set(0);
+    FaultExplorer::new().explore(|host| {
+        BALANCE.set(100);
+        SENT.set(0);
+        host.on_call(ledger(), "deposit", |_| CallResponse::reply(vec![]));
+        in_executor_context(|| {
+            for bounded_wait in [true, false] {
+                spawn(async move {
+                    BALANCE.set(BALANCE.get() - 10);
+                    let reservation = Reservation(10);
+                    let call = if bounded_wait {
+                        Call::bounded_wait(ledger(), "deposit")
+                    } else {
+                        Call::unbounded_wait(ledger(), "deposit")
+                    };
+                    if call.await.is_ok() {
+                        std::mem::forget(reservation);
+                        SENT.set(SENT.get() + 10);
+                    }
+                });
+            }
+        });
+        host.run_until_idle();
+        assert_eq!(BALANCE.get() + SENT.get(), 100);
+    });
+    assert!(RECOVERED.get() > 0);
+}
+
+#[test]
+fn retries_follow_the_policy() {
+    use ic_cdk::call::RetryPolicy;
+
+    let host = InMemoryHost::new();
+    set_host(host.clone());
+    host.set_cycle_balance(1_000_000);
+    host.set_call_cost(1_000, 1);
+    let attempts = Rc::new(Cell::new(0));
+    let counter = attempts.clone();
+    host.on_call(ledger(), "deposit", move |_| {
+        counter.set(counter.get() + 1);
+        CallResponse::reply(vec![])
+    });
+    let retry = |policy: RetryPolicy, faults: &[Fault]| {
+        attempts.set(0);
+        for fault in faults {
+            host.inject_fault(*fault);
+        }
+        let outcome = Rc::new(Cell::new(None));
+        let result = outcome.clone();
+        in_executor_context(|| {
+            spawn(async move {
+                let response = Call::bounded_wait(ledger(), "deposit")
+                    .with_retry(policy)
+                    .await;
+                result.set(Some(response.is_ok()));
+            });
+        });
+        host.run_until_idle();
+        (outcome.get().unwrap(), attempts.get())
+    };
+    let transient = Fault::Reject(RejectCode::SysTransient as u32);
+
+    // Clean, retryable rejects are retried up to the maximum number of attempts.
+    assert_eq!(
+        retry(RetryPolicy::new(3), &[transient, transient]),
+        (true, 1)
+    );
+    assert_eq!(
+        retry(RetryPolicy::new(2), &[transient, transient]),
+        (false, 0)
+    );
+    // Other rejects are not retried.
+    let reject = Fault::Reject(RejectCode::SysFatal as u32);
+    assert_eq!(retry(RetryPolicy::new(3), &[reject]), (false, 0));
+    // A timeout is not a clean reject: the callee may have executed the call.
+    assert_eq!(retry(RetryPolicy::new(3), &[Fault::Timeout]), (false, 1));
+    assert_eq!(
+        retry(RetryPolicy::new(3).with_idempotent(true), &[Fault::Timeout]),
+        (true, 2)
+    );
+    // No attempt is made past the deadline or the cycle budget.
+    host.set_time(100);
+    assert_eq!(
+        retry(RetryPolicy::new(3).with_deadline(100), &[transient]),
+        (false, 0)
+    );
+    let cost = Call::bounded_wait(ledger(), "deposit").get_cost();
+    assert_eq!(
+        retry(
+            RetryPolicy::new(3).with_cycle_budget(2 * cost),
+            &[transient, transient]
+        ),
+        (false, 0)
+    );
+
+    // With a backoff, the delay doubles before each new attempt, up to the maximum delay.
+    let delays = Rc::new(RefCell::new(vec![]));
+    let log = delays.clone();
+    let backoff = RetryPolicy::new(4).with_backoff(
+        Duration::from_secs(1),
+        Duration::from_secs(3),
+        move |delay| {
+            log.borrow_mut().push(delay);
+            std::future::ready(())
+        },
+    );
+    assert_eq!(
+        retry(backoff.clone(), &[transient, transient, transient]),
+        (true, 1)
+    );
+    assert_eq!(*delays.borrow(), [1, 2, 3].map(Duration::from_secs));
+    // No attempt is made if the deadline is reached by the end of the delay.
+    delays.borrow_mut().clear();
+    assert_eq!(
+        retry(backoff.with_deadline(100 + 1_000_000_000), &[transient]),
+        (false, 0)
+    );
+    assert!(delays.borrow().is_empty());
+}
+
+#[test]
+#[should_panic(expected = "a retry policy makes at least one attempt")]
+fn retry_policies_make_at_least_one_attempt() {
+    let _ = ic_cdk::call::RetryPolicy::new(0);
+}
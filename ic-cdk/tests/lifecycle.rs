@@ -0,0 +1,88 @@
+//! Observes stopping and drains outstanding calls with `ic_cdk::lifecycle` against `ic0::host::InMemoryHost`.
+
+use candid::Principal;
+use ic_cdk::call::Call;
+use ic_cdk::futures::internals::in_executor_context;
+use ic_cdk::futures::{spawn, spawn_migratory};
+use ic_cdk::lifecycle::{self, drained, outstanding_calls};
+use ic0::host::{CallResponse, InMemoryHost, set_host};
+use std::cell::Cell;
+use std::rc::Rc;
+use std::time::Duration;
+
+#[test]
+fn draining_is_observed() {
+    let host = InMemoryHost::new();
+    set_host(host.clone());
+    assert!(!lifecycle::is_draining());
+    assert_eq!(lifecycle::reject_while_draining(), Ok(()));
+
+    host.set_status(2);
+    assert!(lifecycle::is_stopping());
+    assert!(lifecycle::is_draining());
+    assert!(lifecycle::reject_while_draining().is_err());
+
+    host.set_status(1);
+    lifecycle::begin_drain();
+    assert!(!lifecycle::is_stopping());
+    assert!(lifecycle::is_draining());
+    lifecycle::end_drain();
+    assert!(!lifecycle::is_draining());
+}
+
+#[test]
+fn drained_waits_for_outstanding_calls() {
+    let host = InMemoryHost::new();
+    set_host(host.clone());
+    let callee = Principal::from_slice(&[1]);
+    host.on_call(callee, "fast", |_| {
+        CallResponse::reply(vec![]).with_latency(Duration::from_secs(1))
+    });
+    host.on_call(callee, "slow", |_| {
+        CallResponse::reply(vec![]).with_latency(Duration::from_secs(5))
+    });
+
+    let done = Rc::new(Cell::new(false));
+    let flag = done.clone();
+    in_executor_context(|| {
+        spawn(async move {
+            Call::bounded_wait(callee, "fast").await.unwrap();
+        });
+        spawn(async move {
+            Call::bounded_wait(callee, "slow").await.unwrap();
+        });
+        spawn_migratory(async move {
+            drained().await;
+            flag.set(true);
+        });
+    });
+    assert_eq!(outstanding_calls(), 2);
+
+    while outstanding_calls() == 2 {
+        assert!(host.run_next());
+    }
+    assert_eq!(outstanding_calls(), 1);
+    assert!(!done.get());
+    host.run_until_idle();
+    assert_eq!(outstanding_calls(), 0);
+    assert!(done.get());
+}
+
+#[test]
+fn a_trapping_callback_finishes_its_call() {
+    let host = InMemoryHost::new();
+    set_host(host.clone());
+    let callee = Principal::from_slice(&[1]);
+    host.on_call(callee, "m", |_| CallResponse::reply(vec![]));
+
+    in_executor_context(|| {
+        spawn(async move {
+            Call::bounded_wait(callee, "m").await.unwrap();
+            panic!("trap after the response");
+        });
+    });
+    assert_eq!(outstanding_calls(), 1);
+    host.run_until_idle();
+    assert_eq!(host.traps().len(), 1);
+    assert_eq!(outstanding_calls(), 0);
+}
@@ -0,0 +1,79 @@
+//! Logs with the `log` crate into the buffer of `ic_cdk::logging`, against `ic0::host::InMemoryHost`.
+
+use ic_cdk::logging::{CanisterLogger, LogLevel, LogRequest, get_logs};
+use ic_cdk::stable::CanisterStableMemory;
+use ic0::host::{InMemoryHost, set_host};
+use log::LevelFilter;
+
+fn messages(request: LogRequest) -> (Vec<String>, Option<u64>) {
+    let page = get_logs(&request);
+    let messages = page
+        .entries
+        .into_iter()
+        .map(|entry| entry.message)
+        .collect();
+    (messages, page.next)
+}
+
+#[test]
+fn logs_are_buffered_and_persisted() {
+    let host = InMemoryHost::new();
+    set_host(host.clone());
+    host.set_time(42);
+    let logger = || {
+        CanisterLogger::new()
+            .with_level(LevelFilter::Info)
+            .with_capacity(3)
+            .with_stable_memory(CanisterStableMemory::default(), 0, 4096)
+    };
+    logger().init().unwrap();
+    log::debug!("filtered out");
+    for i in 0..4 {
+        log::info!("info {i}");
+    }
+    log::error!("failed");
+    assert_eq!(
+        messages(LogRequest::default()),
+        (
+            vec!["failed".into(), "info 3".into(), "info 2".into()],
+            None
+        )
+    );
+    assert_eq!(
+        messages(LogRequest {
+            max_entries: Some(2),
+            ..Default::default()
+        }),
+        (vec!["failed".into(), "info 3".into()], Some(3))
+    );
+    assert_eq!(
+        messages(LogRequest {
+            before: Some(3),
+            ..Default::default()
+        }),
+        (vec!["info 2".into()], None)
+    );
+    assert_eq!(
+        messages(LogRequest {
+            min_level: Some(LogLevel::Warn),
+            ..Default::default()
+        }),
+        (vec!["failed".into()], None)
+    );
+    let entry = &get_logs(&LogRequest::default()).entries[0];
+    assert_eq!((entry.index, entry.timestamp), (4, 42));
+    assert_eq!(entry.target, module_path!());
+    assert!(
+        host.debug_prints()
+            .contains(&format!("[ERROR {}] failed", module_path!()))
+    );
+
+    // As after an upgrade, the entries are restored from stable memory.
+    logger().with_capacity(10).init().unwrap();
+    log::warn!("upgraded");
+    assert_eq!(
+        messages(LogRequest::default()).0,
+        ["upgraded", "failed", "info 3", "info 2", "info 1", "info 0"]
+    );
+    assert_eq!(get_logs(&LogRequest::default()).entries[0].index, 5);
+}
@@ -0,0 +1,49 @@
+//! Renders the metrics of `ic_cdk::metrics` against `ic0::host::InMemoryHost`.
+
+use ic_cdk::metrics::{self, internals::record_method_instructions};
+use ic0::host::{InMemoryHost, set_host};
+
+#[test]
+fn renders_standard_and_registered_metrics() {
+    let host = InMemoryHost::new();
+    set_host(host.clone());
+    host.set_cycle_balance(5_000_000_000_000);
+    host.set_version(3);
+    assert_eq!(ic_cdk::api::stable_grow(2), 0);
+
+    host.set_instruction_counter(20_000);
+    record_method_instructions("transfer");
+    host.set_instruction_counter(3_000_000);
+    record_method_instructions("transfer");
+    record_method_instructions("canister_init");
+    metrics::gauge("queue_length", "Length of the queue.").set(7.0);
+
+    let text = metrics::render();
+    for line in [
+        "# TYPE canister_cycle_balance gauge",
+        "canister_cycle_balance 5000000000000",
+        "canister_version 3",
+        "canister_stable_memory_bytes 131072",
+        "# TYPE canister_method_instructions histogram",
+        "canister_method_instructions_bucket{method=\"transfer\",le=\"10000\"} 0",
+        "canister_method_instructions_bucket{method=\"transfer\",le=\"100000\"} 1",
+        "canister_method_instructions_bucket{method=\"transfer\",le=\"+Inf\"} 2",
+        "canister_method_instructions_sum{method=\"transfer\"} 3020000",
+        "canister_method_instructions_count{method=\"transfer\"} 2",
+        "canister_method_instructions_count{method=\"canister_init\"} 1",
+        "queue_length 7",
+    ] {
+        assert!(
+            text.lines().any(|l| l == line),
+            "missing `{line}` in:\n{text}"
+        );
+    }
+
+    // The standard metrics are sampled again on every render.
+    host.set_cycle_balance(1_000);
+    assert!(
+        metrics::render()
+            .lines()
+            .any(|l| l == "canister_cycle_balance 1000")
+    );
+}
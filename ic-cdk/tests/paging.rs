@@ -0,0 +1,84 @@
+//! Fetches paged payloads from a callee simulated by `ic0::host::InMemoryHost`.
+
+use candid::{Encode, Principal, decode_one};
+use ic_cdk::call::Call;
+use ic_cdk::call::paging::{Page, PageRequest, PagedPayload, PagingError};
+use ic_cdk::futures::internals::in_executor_context;
+use ic_cdk::futures::spawn;
+use ic0::host::{CallResponse, InMemoryHost, set_host};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+fn callee() -> Principal {
+    Principal::from_slice(&[1])
+}
+
+/// Serves `payload` in pages of 100 bytes, passing each page through `tamper`.
+fn install(payload: Vec<u8>, tamper: impl Fn(&mut Page) + 'static) -> InMemoryHost {
+    let host = InMemoryHost::new();
+    set_host(host.clone());
+    let payload = PagedPayload::new(payload);
+    host.on_call(callee(), "get", move |request| {
+        let request: PageRequest = decode_one(&request.arg).unwrap();
+        let mut page = payload.serve_with_page_size(&request, 100);
+        tamper(&mut page);
+        CallResponse::reply(Encode!(&page).unwrap())
+    });
+    host
+}
+
+fn fetch(host: &InMemoryHost) -> Result<Vec<u8>, PagingError> {
+    let result = Rc::new(RefCell::new(None));
+    let out = result.clone();
+    in_executor_context(|| {
+        spawn(async move {
+            let response = Call::bounded_wait(callee(), "get").paged().await;
+            *out.borrow_mut() = Some(response.map(|response| response.into_bytes()));
+        });
+    });
+    host.run_until_idle();
+    result.take().unwrap()
+}
+
+#[test]
+fn pages_are_assembled() {
+    let payload: Vec<u8> = (0..1_000).map(|n| n as u8).collect();
+    let host = install(payload.clone(), |_| {});
+    assert_eq!(fetch(&host).unwrap(), payload);
+
+    let accounts: Vec<(String, u64)> = (0..50).map(|n| (format!("account {n}"), n)).collect();
+    let host = install(Encode!(&accounts).unwrap(), |_| {});
+    let result = Rc::new(RefCell::new(None));
+    let out = result.clone();
+    in_executor_context(|| {
+        spawn(async move {
+            let response = Call::bounded_wait(callee(), "get").paged().await.unwrap();
+            *out.borrow_mut() = Some(response.candid::<Vec<(String, u64)>>().unwrap());
+        });
+    });
+    host.run_until_idle();
+    assert_eq!(result.take().unwrap(), accounts);
+}
+
+#[test]
+fn tampered_pages_fail_the_integrity_check() {
+    let payload = vec![7; 250];
+    let host = install(payload.clone(), |page| {
+        if page.next.is_none() {
+            page.data[0] ^= 1;
+        }
+    });
+    assert!(matches!(
+        fetch(&host),
+        Err(PagingError::IntegrityCheckFailed)
+    ));
+    let host = install(payload, |page| {
+        if page.next.is_some() {
+            page.sha256[0] ^= 1;
+        }
+    });
+    assert!(matches!(
+        fetch(&host),
+        Err(PagingError::IntegrityCheckFailed)
+    ));
+}
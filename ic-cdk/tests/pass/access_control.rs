@@ -0,0 +1,15 @@
+use ic_cdk::access_control::{self, has_role};
+use ic_cdk::{query, update};
+
+#[update(guard = "access_control::controllers_only")]
+fn controllers() {}
+
+#[update(guard = "has_role(\"minter\")")]
+fn minters() {}
+
+#[query(guard = "access_control::admins_only", guard = "has_role(\"auditor\")")]
+fn admin_auditors() {}
+
+ic_cdk::export_role_management!();
+
+fn main() {}
@@ -0,0 +1,59 @@
+//! Profiles methods with `ic_cdk::profiling` against the call simulator of `ic0::host::InMemoryHost`.
+
+use candid::Principal;
+use ic_cdk::call::Call;
+use ic_cdk::futures::internals::in_executor_context;
+use ic_cdk::futures::spawn;
+use ic_cdk::metrics;
+use ic_cdk::profiling::internals::{profile, profile_sync};
+use ic_cdk::profiling::{MethodProfile, profiles};
+use ic0::host::{CallResponse, InMemoryHost, set_host};
+
+fn profile_of(method: &str) -> MethodProfile {
+    profiles()
+        .into_iter()
+        .find(|profile| profile.method == method)
+        .unwrap()
+}
+
+#[test]
+fn profiles_segments_and_call_cycles() {
+    let host = InMemoryHost::new();
+    set_host(host.clone());
+    host.set_cycle_balance(1_000_000);
+    host.set_call_cost(1_000, 0);
+    host.set_instruction_counter(50_000);
+    let callee = Principal::from_slice(&[1]);
+    host.on_call(callee, "ping", |_| {
+        CallResponse::reply(vec![]).accept_cycles(200)
+    });
+
+    in_executor_context(|| {
+        spawn(profile("transfer", async move {
+            Call::bounded_wait(callee, "ping")
+                .with_cycles(500)
+                .await
+                .unwrap();
+            Call::bounded_wait(callee, "ping").await.unwrap();
+        }));
+    });
+    host.run_until_idle();
+    in_executor_context(|| profile_sync("canister_init", || ()));
+
+    let transfer = profile_of("transfer");
+    assert_eq!(transfer.instructions.count, 1);
+    assert_eq!(transfer.instructions.sum, 50_000.0);
+    // Started, resumed after the first call, and resumed after the second one.
+    assert_eq!(transfer.segments.sum, 3.0);
+    assert_eq!(transfer.segment_instructions.count, 3);
+    // 500 attached and 1_000 for each call, minus the 300 refunded.
+    assert_eq!(transfer.call_cycles.sum, 2_200.0);
+    let init = profile_of("canister_init");
+    assert_eq!((init.segments.sum, init.call_cycles.sum), (1.0, 0.0));
+
+    let text = metrics::render();
+    assert!(
+        text.lines()
+            .any(|l| l == "canister_method_call_cycles_sum{method=\"transfer\"} 2200")
+    );
+}
@@ -0,0 +1,69 @@
+//! Carries the finer-grained error code of a rejection, and refines the classification of rejections with it.
+
+use ic_cdk::call::{CallErrorExt, CallRejected, ErrorCode, RejectCode};
+
+#[test]
+fn error_codes_are_carried() {
+    let stopped = CallRejected::with_error_code(
+        RejectCode::CanisterError as u32,
+        ErrorCode::CanisterStopped as u32,
+        "rejected".into(),
+    );
+    assert_eq!(stopped.error_code(), Some(ErrorCode::CanisterStopped));
+    assert_eq!(
+        stopped.raw_error_code(),
+        Some(ErrorCode::CanisterStopped as u32)
+    );
+    let dropped = CallRejected::with_error_code(6, 602, "rejected".into());
+    assert_eq!(dropped.error_code(), Some(ErrorCode::ResponseDropped));
+
+    let unknown = CallRejected::with_error_code(5, u32::MAX, "rejected".into());
+    assert_eq!(unknown.error_code(), None);
+    assert_eq!(unknown.raw_error_code(), Some(u32::MAX));
+    let without = CallRejected::with_rejection(RejectCode::CanisterError as u32, "rejected".into());
+    assert_eq!(without.error_code(), None);
+    assert_eq!(without.raw_error_code(), None);
+}
+
+#[test]
+fn error_codes_refine_the_classification() {
+    for error_code in [
+        ErrorCode::CanisterOutOfCycles,
+        ErrorCode::CanisterStopped,
+        ErrorCode::CanisterStopping,
+    ] {
+        for reject_code in [RejectCode::SysTransient, RejectCode::CanisterError] {
+            let rejected = CallRejected::with_error_code(
+                reject_code as u32,
+                error_code as u32,
+                "rejected".into(),
+            );
+            assert!(rejected.is_clean_reject(), "{error_code:?}");
+            assert!(!rejected.is_immediately_retryable(), "{error_code:?}");
+        }
+    }
+
+    // Other error codes, and rejections without an error code, are classified by their reject code.
+    let queue_full = CallRejected::with_error_code(
+        RejectCode::SysTransient as u32,
+        ErrorCode::CanisterQueueFull as u32,
+        "rejected".into(),
+    );
+    assert!(queue_full.is_clean_reject());
+    assert!(queue_full.is_immediately_retryable());
+    let trapped = CallRejected::with_error_code(
+        RejectCode::CanisterError as u32,
+        ErrorCode::CanisterTrapped as u32,
+        "rejected".into(),
+    );
+    assert!(!trapped.is_clean_reject());
+    assert!(!trapped.is_immediately_retryable());
+    let transient =
+        CallRejected::with_rejection(RejectCode::SysTransient as u32, "rejected".into());
+    assert!(transient.is_clean_reject());
+    assert!(transient.is_immediately_retryable());
+    let canister_error =
+        CallRejected::with_rejection(RejectCode::CanisterError as u32, "rejected".into());
+    assert!(!canister_error.is_clean_reject());
+    assert!(!canister_error.is_immediately_retryable());
+}
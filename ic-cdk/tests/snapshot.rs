@@ -0,0 +1,124 @@
+//! Saves, loads and migrates `ic_cdk::storage::snapshot` against `ic0::host::InMemoryHost`.
+
+use ic_cdk::stable::{self, CanisterStableMemory, StableMemory};
+use ic_cdk::storage::snapshot::{Migrations, Snapshot, SnapshotError};
+use ic0::host::{InMemoryHost, set_host};
+
+fn v1() -> Snapshot {
+    let mut snapshot = Snapshot::new(1);
+    snapshot.set("names", &vec!["alice", "bob"]).unwrap();
+    snapshot.set("balances", &vec![10_u64, 20]).unwrap();
+    snapshot
+}
+
+fn migrations() -> Migrations {
+    Migrations::new(3)
+        .with_migration(1, |snapshot| {
+            let names: Vec<String> = snapshot.get("names")?;
+            let balances: Vec<u64> = snapshot.get("balances")?;
+            snapshot.remove("names");
+            snapshot.remove("balances");
+            let accounts: Vec<(String, u64)> = names.into_iter().zip(balances).collect();
+            snapshot.set("accounts", &accounts)
+        })
+        .with_migration(2, |snapshot| {
+            let accounts: Vec<(String, u64)> = snapshot.get("accounts")?;
+            if accounts.is_empty() {
+                return Err(SnapshotError::InvalidState("no accounts".to_string()));
+            }
+            snapshot.set("total", &accounts.iter().map(|(_, b)| b).sum::<u64>())
+        })
+}
+
+#[test]
+fn snapshot_round_trips() {
+    set_host(InMemoryHost::new());
+    let mut snapshot = v1();
+    snapshot.set_bytes("raw", vec![1, 2, 3]);
+    snapshot.save().unwrap();
+
+    let loaded = Snapshot::load().unwrap();
+    assert_eq!(loaded, snapshot);
+    assert_eq!(loaded.schema_version(), 1);
+    assert_eq!(
+        loaded.slot_names().collect::<Vec<_>>(),
+        ["balances", "names", "raw"]
+    );
+    assert_eq!(loaded.get::<Vec<u64>>("balances").unwrap(), [10, 20]);
+    assert!(matches!(
+        loaded.get::<Vec<u64>>("names"),
+        Err(SnapshotError::Decode { slot, .. }) if slot == "names"
+    ));
+    assert!(matches!(
+        loaded.get::<u64>("missing"),
+        Err(SnapshotError::MissingSlot(slot)) if slot == "missing"
+    ));
+}
+
+#[test]
+fn migrations_run_in_order() {
+    set_host(InMemoryHost::new());
+    v1().save().unwrap();
+
+    let snapshot = migrations().load().unwrap();
+    assert_eq!(snapshot.schema_version(), 3);
+    assert_eq!(
+        snapshot.get::<Vec<(String, u64)>>("accounts").unwrap(),
+        [("alice".to_string(), 10), ("bob".to_string(), 20)]
+    );
+    assert_eq!(snapshot.get::<u64>("total").unwrap(), 30);
+    assert!(!snapshot.contains("names"));
+
+    // A snapshot of the latest version is loaded as is.
+    snapshot.save().unwrap();
+    assert_eq!(migrations().load().unwrap(), snapshot);
+}
+
+#[test]
+fn migration_failures_are_reported() {
+    set_host(InMemoryHost::new());
+    let mut snapshot = Snapshot::new(1);
+    snapshot.set("names", &Vec::<String>::new()).unwrap();
+    snapshot.set("balances", &Vec::<u64>::new()).unwrap();
+    assert!(matches!(
+        migrations().migrate(&mut snapshot),
+        Err(SnapshotError::MigrationFailed { from: 2, source })
+            if matches!(*source, SnapshotError::InvalidState(_))
+    ));
+    assert_eq!(snapshot.schema_version(), 2);
+
+    assert!(matches!(
+        migrations().migrate(&mut Snapshot::new(0)),
+        Err(SnapshotError::MissingMigration(0))
+    ));
+    assert!(matches!(
+        migrations().migrate(&mut Snapshot::new(4)),
+        Err(SnapshotError::SchemaTooNew {
+            found: 4,
+            latest: 3
+        })
+    ));
+}
+
+#[test]
+fn invalid_stable_memory_is_rejected() {
+    set_host(InMemoryHost::new());
+    assert!(matches!(Snapshot::load(), Err(SnapshotError::NotASnapshot)));
+
+    ic_cdk::storage::stable_save((42_u64,)).unwrap();
+    assert!(matches!(Snapshot::load(), Err(SnapshotError::NotASnapshot)));
+
+    v1().save().unwrap();
+    let mut byte = [0];
+    stable::stable_read(40, &mut byte);
+    stable::stable_write(40, &[byte[0] ^ 1]);
+    assert!(matches!(
+        Snapshot::load(),
+        Err(SnapshotError::ChecksumMismatch { .. })
+    ));
+
+    // The length of the slots runs past the end of the stable memory.
+    let size = CanisterStableMemory::default().stable_size() * stable::WASM_PAGE_SIZE_IN_BYTES;
+    stable::stable_write(16, &size.to_le_bytes());
+    assert!(matches!(Snapshot::load(), Err(SnapshotError::Malformed)));
+}
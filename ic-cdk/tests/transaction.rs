@@ -0,0 +1,111 @@
+//! Spans transactions of `ic_cdk::stable::transaction` over several messages against `ic0::host::InMemoryHost`.
+
+use candid::Principal;
+use ic_cdk::call::Call;
+use ic_cdk::futures::internals::in_executor_context;
+use ic_cdk::futures::spawn;
+use ic_cdk::stable::memory_manager::{MemoryId, MemoryManager, VirtualMemory};
+use ic_cdk::stable::transaction::TransactionalMemory;
+use ic_cdk::stable::{CanisterStableMemory, StableMemory};
+use ic0::host::{CallResponse, InMemoryHost, set_host};
+
+type Memory = TransactionalMemory<VirtualMemory, VirtualMemory>;
+
+fn init() -> Memory {
+    let manager = MemoryManager::init(CanisterStableMemory::default()).unwrap();
+    TransactionalMemory::init(manager.get(MemoryId::new(0)), manager.get(MemoryId::new(1))).unwrap()
+}
+
+fn read(memory: &Memory) -> [u8; 4] {
+    let mut buf = [0; 4];
+    memory.stable_read(0, &mut buf);
+    buf
+}
+
+/// Migrates `memory` from `v1` to `v2` in two messages, separated by a call to `method`.
+fn migrate(host: &InMemoryHost, memory: &Memory, method: &'static str, trap: bool) {
+    let callee = Principal::from_slice(&[1]);
+    host.on_call(callee, method, |_| CallResponse::reply(vec![]));
+    let memory = memory.clone();
+    in_executor_context(|| {
+        spawn(async move {
+            let transaction = memory.begin().unwrap();
+            transaction.write(0, b"v2");
+            Call::bounded_wait(callee, method).await.unwrap();
+            transaction.write(2, b"!!");
+            if trap {
+                panic!("trap in the second message");
+            }
+            transaction.commit();
+        });
+    });
+    host.run_until_idle();
+}
+
+#[test]
+fn a_trap_rolls_back_the_earlier_messages() {
+    let host = InMemoryHost::new();
+    set_host(host.clone());
+    let memory = init();
+    memory.stable_grow(1).unwrap();
+    memory.stable_write(0, b"v1..");
+
+    migrate(&host, &memory, "trapping", true);
+    assert_eq!(host.traps().len(), 1);
+    assert!(!memory.in_transaction());
+    assert_eq!(read(&memory), *b"v1..");
+
+    migrate(&host, &memory, "succeeding", false);
+    assert_eq!(read(&memory), *b"v2!!");
+    // The migration survives an upgrade.
+    drop(memory);
+    assert_eq!(read(&init()), *b"v2!!");
+}
+
+#[test]
+fn an_upgrade_rolls_back_an_open_transaction() {
+    set_host(InMemoryHost::new());
+    let memory = init();
+    memory.stable_grow(1).unwrap();
+    memory.stable_write(0, b"v1..");
+    let transaction = memory.begin().unwrap();
+    transaction.write(0, b"v2");
+    // The heap, and the transaction with it, is lost in the upgrade.
+    std::mem::forget(transaction);
+    drop(memory);
+
+    let memory = init();
+    assert!(!memory.in_transaction());
+    assert_eq!(read(&memory), *b"v1..");
+}
+
+#[test]
+fn a_transaction_left_open_by_a_task_that_is_gone_is_rolled_back() {
+    let host = InMemoryHost::new();
+    set_host(host.clone());
+    let callee = Principal::from_slice(&[1]);
+    host.on_call(callee, "rates", |_| CallResponse::reply(vec![]));
+    let memory = init();
+    memory.stable_grow(1).unwrap();
+    memory.stable_write(0, b"v1..");
+
+    let task_memory = memory.clone();
+    in_executor_context(|| {
+        spawn(async move {
+            let transaction = task_memory.begin().unwrap();
+            transaction.write(0, b"v2");
+            Call::bounded_wait(callee, "rates").await.unwrap();
+            // The task ends without dropping the transaction, as when its destructors do not run after a trap.
+            std::mem::forget(transaction);
+        });
+    });
+    // The transaction is open while the task is waiting for the call.
+    assert!(memory.in_transaction());
+    host.run_until_idle();
+
+    assert!(!memory.in_transaction());
+    let transaction = memory.begin().unwrap();
+    let mut buf = [0; 4];
+    transaction.read(0, &mut buf);
+    assert_eq!(buf, *b"v1..");
+}
@@ -0,0 +1,1274 @@
+//! Native backend for the System API.
+//!
+//! Outside of Wasm there is no replica to import the System API from. Instead, every function in [`sys`](crate::sys)
+//! dispatches to a [`Host`] installed on the current thread. If no host is installed, the functions panic, as they
+//! always have.
+//!
+//! [`InMemoryHost`] is a ready-made implementation that models a single canister executing a single message. It is
+//! enough to unit test code that calls `msg_caller`, `time`, `stable_write`, etc. with a plain `cargo test`:
+//!
+//! ```
+//! use ic0::host::{InMemoryHost, set_host};
+//!
+//! let host = InMemoryHost::new();
+//! host.set_time(1_700_000_000_000_000_000);
+//! set_host(host.clone());
+//!
+//! assert_eq!(ic0::time(), 1_700_000_000_000_000_000);
+//! ic0::debug_print(b"hello");
+//! assert_eq!(host.debug_prints(), vec!["hello".to_string()]);
+//! ```
+//!
+//! Because the host is per thread, tests running in parallel do not observe each other's state.
+
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::rc::Rc;
+
+mod faults;
+mod network;
+
+#[doc(inline)]
+pub use crate::sys::host_api::Host;
+pub use faults::{Fault, FaultExplorer};
+pub use network::{CallRequest, CallResponse};
+
+thread_local! {
+    static HOST: RefCell<Option<Rc<dyn Host>>> = const { RefCell::new(None) };
+}
+
+/// Installs `host` as the System API backend of the current thread.
+///
+/// Returns the previously installed host, if any.
+pub fn set_host(host: impl Host + 'static) -> Option<Rc<dyn Host>> {
+    HOST.with_borrow_mut(|slot| slot.replace(Rc::new(host)))
+}
+
+/// Uninstalls the System API backend of the current thread and returns it.
+pub fn take_host() -> Option<Rc<dyn Host>> {
+    HOST.with_borrow_mut(Option::take)
+}
+
+/// Calls `f` with the host installed on the current thread.
+///
+/// The host is not borrowed while `f` runs, so a host may re-enter the System API.
+///
+/// # Panics
+///
+/// Panics if no host is installed.
+pub(crate) fn with_host<R>(name: &str, f: impl FnOnce(&dyn Host) -> R) -> R {
+    let Some(host) = HOST.with_borrow(Clone::clone) else {
+        panic!("{name} should only be called inside canisters.");
+    };
+    f(&*host)
+}
+
+/// Reinterprets a System API `(src, size)` pair as a slice.
+///
+/// # Safety
+///
+/// `src` must be a pointer to a readable sequence of bytes with size `size`, valid for `'a`.
+pub unsafe fn src_slice<'a>(src: usize, size: usize) -> &'a [u8] {
+    if size == 0 {
+        return &[];
+    }
+    // SAFETY: guaranteed by the caller.
+    unsafe { std::slice::from_raw_parts(src as *const u8, size) }
+}
+
+/// Reinterprets a System API `(dst, size)` pair as a mutable slice.
+///
+/// # Safety
+///
+/// `dst` must be a pointer to a writable sequence of bytes with size `size`, valid for `'a` and not aliased.
+pub unsafe fn dst_slice<'a>(dst: usize, size: usize) -> &'a mut [u8] {
+    if size == 0 {
+        return &mut [];
+    }
+    // SAFETY: guaranteed by the caller.
+    unsafe { std::slice::from_raw_parts_mut(dst as *mut u8, size) }
+}
+
+/// Implements the `*_copy(dst, offset, size)` family of functions: copies `data[offset..offset + size]` to `dst`.
+///
+/// # Safety
+///
+/// `dst` must be a pointer to a writable sequence of bytes with size `size`.
+pub unsafe fn copy_to_dst(name: &str, data: &[u8], dst: usize, offset: usize, size: usize) {
+    let Some(src) = offset
+        .checked_add(size)
+        .and_then(|end| data.get(offset..end))
+    else {
+        trap(&format!(
+            "{name}: out of bounds (offset {offset}, size {size}, length {})",
+            data.len()
+        ));
+    };
+    // SAFETY: guaranteed by the caller.
+    unsafe { dst_slice(dst, size) }.copy_from_slice(src);
+}
+
+/// Writes a `u128` to a `dst` pointer in the little-endian format used by the `*128` functions.
+///
+/// # Safety
+///
+/// `dst` must be a pointer to a writable sequence of 16 bytes.
+pub unsafe fn write_u128(dst: usize, value: u128) {
+    // SAFETY: guaranteed by the caller.
+    unsafe { dst_slice(dst, 16) }.copy_from_slice(&value.to_le_bytes());
+}
+
+/// How [`InMemoryHost`] traps: by panicking with the trap message.
+fn trap(message: &str) -> ! {
+    panic!("canister trapped: {message}")
+}
+
+/// WASM page size in bytes.
+const WASM_PAGE_SIZE_IN_BYTES: u64 = 64 * 1024;
+
+/// The maximum size of the stable memory of a canister on the IC, 500 GiB, in pages.
+const MAX_STABLE_MEMORY_IN_PAGES: u64 = 500 * 1024 * 1024 * 1024 / WASM_PAGE_SIZE_IN_BYTES;
+
+/// The valid `ecdsa_curve` of `cost_sign_with_ecdsa`: secp256k1.
+const ECDSA_CURVES: [u32; 1] = [0];
+
+/// The valid `algorithm` of `cost_sign_with_schnorr`: bip340secp256k1 and ed25519.
+const SCHNORR_ALGORITHMS: [u32; 2] = [0, 1];
+
+/// The valid `vetkd_curve` of `cost_vetkd_derive_key`: bls12_381_g2.
+const VETKD_CURVES: [u32; 1] = [0];
+
+/// The principal of the first canister on a local replica, `rrkah-fqaaa-aaaaa-aaaaq-cai`.
+const DEFAULT_CANISTER_ID: &[u8] = &[0, 0, 0, 0, 0, 0, 0, 1, 1, 1];
+
+/// The anonymous principal, `2vxsx-fae`.
+const ANONYMOUS_PRINCIPAL: &[u8] = &[4];
+
+/// The call context of the top-level message, which is driven by the test rather than by the host.
+const ROOT_CONTEXT: u64 = 0;
+
+/// A [`Host`] that models a single canister in memory.
+///
+/// The modelled state covers the message (argument, caller, method name, attached cycles, reply), the canister (id,
+/// cycle balance, status, version, controllers, environment variables), stable memory, certified data, time, and
+/// `debug_print` output. Traps are turned into panics with the message `canister trapped: <message>`.
+///
+/// `InMemoryHost` is a cheap handle to shared state: install a clone with [`set_host`], and keep the original to
+/// configure the canister and inspect its effects.
+///
+/// # Inter-canister calls
+///
+/// Calls are delivered over a simulated network. Register what answers them with [`on_call`](Self::on_call) (a
+/// closure producing a [`CallResponse`]) or [`register_method`](Self::register_method) (a canister entry point, such
+/// as the function generated by `#[update]`), then drive delivery with [`run_until_idle`](Self::run_until_idle):
+///
+/// ```
+/// use ic0::host::{CallResponse, InMemoryHost, set_host};
+///
+/// let host = InMemoryHost::new();
+/// set_host(host.clone());
+/// host.on_call([1], "greet", |request| {
+///     CallResponse::reply([b"hello, ".as_slice(), &request.arg].concat())
+/// });
+/// unsafe extern "C" fn callback(_env: usize) {
+///     let mut reply = vec![0; ic0::msg_arg_data_size()];
+///     ic0::msg_arg_data_copy(&mut reply, 0);
+///     ic0::msg_reply_data_append(&reply);
+///     ic0::msg_reply();
+/// }
+/// // SAFETY: `callback` ignores its environment.
+/// unsafe { ic0::call_new(&[1], "greet", callback, 0, callback, 0) };
+/// ic0::call_data_append(b"world");
+/// assert_eq!(ic0::call_perform(), 0);
+///
+/// host.run_until_idle();
+/// assert_eq!(host.reply(), Some(Ok(b"hello, world".to_vec())));
+/// ```
+///
+/// Responses are delivered in order of simulated time, which advances as they are delivered. A trap in a callback is
+/// caught and followed by the cleanup callback, as on the IC; since a native trap does not roll back memory, only the
+/// state kept by the host and by `ic-cdk` is restored, and the local variables of the code that trapped are dropped as
+/// the panic unwinds. Traps are panics, so only callbacks declared `extern "C-unwind"` can trap without aborting the
+/// process.
+///
+/// Canisters reached through [`register_method`](Self::register_method) run in the same process, and share everything
+/// but their message and call context with the canister under test.
+///
+/// Failures can be forced with [`inject_fault`](Self::inject_fault), or enumerated exhaustively, together with every
+/// order of delivery, with a [`FaultExplorer`].
+#[derive(Clone, Debug, Default)]
+pub struct InMemoryHost {
+    state: Rc<RefCell<State>>,
+}
+
+#[derive(Debug)]
+struct State {
+    message: Message,
+    contexts: BTreeMap<u64, CallContext>,
+    next_context_id: u64,
+    caller_info_data: Vec<u8>,
+    caller_info_signer: Vec<u8>,
+    subnet_self: Vec<u8>,
+    cycle_balance: u128,
+    status: u32,
+    version: u64,
+    controllers: Vec<Vec<u8>>,
+    env_vars: BTreeMap<String, String>,
+    stable_memory: Vec<u8>,
+    max_stable_pages: u64,
+    certified_data: Vec<u8>,
+    data_certificate: Option<Vec<u8>>,
+    root_key: Vec<u8>,
+    time: u64,
+    global_timer: u64,
+    instruction_counter: u64,
+    replicated: bool,
+    debug_prints: Vec<String>,
+    call_base_fee: u128,
+    call_fee_per_byte: u128,
+    create_canister_fee: u128,
+    http_request_base_fee: u128,
+    http_request_fee_per_request_byte: u128,
+    http_request_fee_per_response_byte: u128,
+    threshold_key_fees: BTreeMap<(ThresholdKeyKind, u32, String), u128>,
+    network: network::Network,
+}
+
+/// The management canister methods using a threshold key, whose fees depend on the key.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum ThresholdKeyKind {
+    Ecdsa,
+    Schnorr,
+    VetKd,
+}
+
+/// The message currently executing: the top-level message, a call to a registered method, or a callback.
+#[derive(Debug, Default)]
+struct Message {
+    context: u64,
+    arg_data: Vec<u8>,
+    method_name: String,
+    deadline: u64,
+    reply_data: Vec<u8>,
+    message_accepted: bool,
+    cycles_refunded: u128,
+    reject_code: u32,
+    reject_message: String,
+    outgoing: Option<network::OutgoingCall>,
+}
+
+/// A call context, shared by a message and the callbacks of the calls it makes.
+#[derive(Debug)]
+struct CallContext {
+    caller: Vec<u8>,
+    canister_self: Vec<u8>,
+    cycles_available: u128,
+    reply: Option<Result<Vec<u8>, String>>,
+    /// The simulated call that created this context, if any.
+    origin: Option<u64>,
+    outstanding_calls: usize,
+}
+
+impl Default for State {
+    fn default() -> Self {
+        let root = CallContext {
+            caller: ANONYMOUS_PRINCIPAL.to_vec(),
+            canister_self: DEFAULT_CANISTER_ID.to_vec(),
+            cycles_available: 0,
+            reply: None,
+            origin: None,
+            outstanding_calls: 0,
+        };
+        Self {
+            message: Message::default(),
+            contexts: BTreeMap::from([(ROOT_CONTEXT, root)]),
+            next_context_id: ROOT_CONTEXT + 1,
+            caller_info_data: vec![],
+            caller_info_signer: vec![],
+            subnet_self: vec![],
+            cycle_balance: 0,
+            // Running
+            status: 1,
+            version: 0,
+            controllers: vec![],
+            env_vars: BTreeMap::new(),
+            stable_memory: vec![],
+            max_stable_pages: MAX_STABLE_MEMORY_IN_PAGES,
+            certified_data: vec![],
+            data_certificate: None,
+            root_key: vec![],
+            time: 0,
+            global_timer: 0,
+            instruction_counter: 0,
+            replicated: true,
+            debug_prints: vec![],
+            call_base_fee: 0,
+            call_fee_per_byte: 0,
+            create_canister_fee: 0,
+            http_request_base_fee: 0,
+            http_request_fee_per_request_byte: 0,
+            http_request_fee_per_response_byte: 0,
+            threshold_key_fees: BTreeMap::new(),
+            network: network::Network::default(),
+        }
+    }
+}
+
+impl State {
+    fn context(&self) -> &CallContext {
+        &self.contexts[&self.message.context]
+    }
+
+    fn context_mut(&mut self) -> &mut CallContext {
+        self.contexts
+            .get_mut(&self.message.context)
+            .expect("the call context of the current message is alive")
+    }
+
+    fn root_mut(&mut self) -> &mut CallContext {
+        self.contexts
+            .get_mut(&ROOT_CONTEXT)
+            .expect("the root call context is never removed")
+    }
+}
+
+// Configuration
+impl InMemoryHost {
+    /// Creates a host for a running canister `rrkah-fqaaa-aaaaa-aaaaq-cai`, called by the anonymous principal at time 0,
+    /// with no cycles and empty stable memory.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the argument data of the current message.
+    pub fn set_arg_data(&self, arg_data: impl Into<Vec<u8>>) {
+        self.state.borrow_mut().message.arg_data = arg_data.into();
+    }
+
+    /// Sets the caller of the top-level message.
+    pub fn set_caller(&self, caller: impl AsRef<[u8]>) {
+        self.state.borrow_mut().root_mut().caller = caller.as_ref().to_vec();
+    }
+
+    /// Sets the caller info data and the canister that signed it.
+    pub fn set_caller_info(&self, data: impl Into<Vec<u8>>, signer: impl AsRef<[u8]>) {
+        let mut state = self.state.borrow_mut();
+        state.caller_info_data = data.into();
+        state.caller_info_signer = signer.as_ref().to_vec();
+    }
+
+    /// Sets the name of the method being called.
+    pub fn set_method_name(&self, method_name: impl Into<String>) {
+        self.state.borrow_mut().message.method_name = method_name.into();
+    }
+
+    /// Sets the best-effort response deadline of the current message. Zero means no deadline.
+    pub fn set_deadline(&self, deadline: u64) {
+        self.state.borrow_mut().message.deadline = deadline;
+    }
+
+    /// Sets the cycles attached to the top-level message.
+    pub fn set_cycles_available(&self, cycles: u128) {
+        self.state.borrow_mut().root_mut().cycles_available = cycles;
+    }
+
+    /// Sets the id of the canister.
+    pub fn set_canister_self(&self, canister_id: impl AsRef<[u8]>) {
+        self.state.borrow_mut().root_mut().canister_self = canister_id.as_ref().to_vec();
+    }
+
+    /// Sets the id of the subnet the canister is running on.
+    pub fn set_subnet_self(&self, subnet_id: impl AsRef<[u8]>) {
+        self.state.borrow_mut().subnet_self = subnet_id.as_ref().to_vec();
+    }
+
+    /// Sets the cycle balance of the canister.
+    pub fn set_cycle_balance(&self, cycles: u128) {
+        self.state.borrow_mut().cycle_balance = cycles;
+    }
+
+    /// Sets the raw status code of the canister (1: running, 2: stopping, 3: stopped).
+    pub fn set_status(&self, status: u32) {
+        self.state.borrow_mut().status = status;
+    }
+
+    /// Sets the version of the canister.
+    pub fn set_version(&self, version: u64) {
+        self.state.borrow_mut().version = version;
+    }
+
+    /// Adds a controller of the canister.
+    pub fn add_controller(&self, controller: impl AsRef<[u8]>) {
+        self.state
+            .borrow_mut()
+            .controllers
+            .push(controller.as_ref().to_vec());
+    }
+
+    /// Sets an environment variable of the canister.
+    pub fn set_env_var(&self, name: impl Into<String>, value: impl Into<String>) {
+        self.state
+            .borrow_mut()
+            .env_vars
+            .insert(name.into(), value.into());
+    }
+
+    /// Sets the data certificate returned in query calls. `None` means the certificate is not present.
+    pub fn set_data_certificate(&self, certificate: Option<Vec<u8>>) {
+        self.state.borrow_mut().data_certificate = certificate;
+    }
+
+    /// Sets the root key of the Internet Computer instance.
+    pub fn set_root_key(&self, root_key: impl Into<Vec<u8>>) {
+        self.state.borrow_mut().root_key = root_key.into();
+    }
+
+    /// Sets the current time, in nanoseconds since the epoch.
+    pub fn set_time(&self, time: u64) {
+        self.state.borrow_mut().time = time;
+    }
+
+    /// Advances the current time by `nanos` nanoseconds.
+    pub fn advance_time(&self, nanos: u64) {
+        let mut state = self.state.borrow_mut();
+        state.time = state.time.saturating_add(nanos);
+    }
+
+    /// Sets the value returned by the instruction counters.
+    pub fn set_instruction_counter(&self, instructions: u64) {
+        self.state.borrow_mut().instruction_counter = instructions;
+    }
+
+    /// Sets the fee charged by `cost_call`: `base_fee + fee_per_byte * (method name size + payload size)`.
+    pub fn set_call_cost(&self, base_fee: u128, fee_per_byte: u128) {
+        let mut state = self.state.borrow_mut();
+        state.call_base_fee = base_fee;
+        state.call_fee_per_byte = fee_per_byte;
+    }
+
+    /// Sets the fee charged by `cost_create_canister`.
+    pub fn set_create_canister_cost(&self, fee: u128) {
+        self.state.borrow_mut().create_canister_fee = fee;
+    }
+
+    /// Sets the fee charged by `cost_http_request`:
+    /// `base_fee + fee_per_request_byte * request size + fee_per_response_byte * max response bytes`.
+    pub fn set_http_request_cost(
+        &self,
+        base_fee: u128,
+        fee_per_request_byte: u128,
+        fee_per_response_byte: u128,
+    ) {
+        let mut state = self.state.borrow_mut();
+        state.http_request_base_fee = base_fee;
+        state.http_request_fee_per_request_byte = fee_per_request_byte;
+        state.http_request_fee_per_response_byte = fee_per_response_byte;
+    }
+
+    /// Sets the fee charged by `cost_sign_with_ecdsa` for the key `key_name` on `ecdsa_curve`.
+    ///
+    /// The cost functions of the threshold keys report the keys that were not given a fee as invalid key names.
+    pub fn set_sign_with_ecdsa_cost(
+        &self,
+        key_name: impl Into<String>,
+        ecdsa_curve: u32,
+        fee: u128,
+    ) {
+        self.set_threshold_key_fee(ThresholdKeyKind::Ecdsa, key_name.into(), ecdsa_curve, fee);
+    }
+
+    /// Sets the fee charged by `cost_sign_with_schnorr` for the key `key_name` with `algorithm`.
+    pub fn set_sign_with_schnorr_cost(
+        &self,
+        key_name: impl Into<String>,
+        algorithm: u32,
+        fee: u128,
+    ) {
+        self.set_threshold_key_fee(ThresholdKeyKind::Schnorr, key_name.into(), algorithm, fee);
+    }
+
+    /// Sets the fee charged by `cost_vetkd_derive_key` for the key `key_name` on `vetkd_curve`.
+    pub fn set_vetkd_derive_key_cost(
+        &self,
+        key_name: impl Into<String>,
+        vetkd_curve: u32,
+        fee: u128,
+    ) {
+        self.set_threshold_key_fee(ThresholdKeyKind::VetKd, key_name.into(), vetkd_curve, fee);
+    }
+
+    fn set_threshold_key_fee(
+        &self,
+        kind: ThresholdKeyKind,
+        key_name: String,
+        curve: u32,
+        fee: u128,
+    ) {
+        self.state
+            .borrow_mut()
+            .threshold_key_fees
+            .insert((kind, curve, key_name), fee);
+    }
+
+    /// Sets the number of pages beyond which `stable64_grow` fails, which defaults to the limit of the IC, 500 GiB.
+    pub fn set_max_stable_pages(&self, pages: u64) {
+        self.state.borrow_mut().max_stable_pages = pages;
+    }
+
+    /// Sets whether the canister is executing in replicated mode.
+    pub fn set_replicated_execution(&self, replicated: bool) {
+        self.state.borrow_mut().replicated = replicated;
+    }
+
+    /// Starts a new top-level message: clears the argument, the reply, the attached cycles and the reject details,
+    /// leaving the canister state and calls in flight untouched.
+    pub fn reset_message(&self) {
+        let mut state = self.state.borrow_mut();
+        state.message = Message::default();
+        let root = state.root_mut();
+        root.reply = None;
+        root.cycles_available = 0;
+    }
+}
+
+// Inspection
+impl InMemoryHost {
+    /// Gets the reply to the top-level message, if any: `Ok` with the reply data, or `Err` with the reject message.
+    pub fn reply(&self) -> Option<Result<Vec<u8>, String>> {
+        self.state.borrow().contexts[&ROOT_CONTEXT].reply.clone()
+    }
+
+    /// Gets the cycles attached to the top-level message that were not accepted.
+    pub fn cycles_available(&self) -> u128 {
+        self.state.borrow().contexts[&ROOT_CONTEXT].cycles_available
+    }
+
+    /// Gets the cycle balance of the canister.
+    pub fn cycle_balance(&self) -> u128 {
+        self.state.borrow().cycle_balance
+    }
+
+    /// Gets the content of the stable memory.
+    pub fn stable_memory(&self) -> Vec<u8> {
+        self.state.borrow().stable_memory.clone()
+    }
+
+    /// Gets the certified data of the canister.
+    pub fn certified_data(&self) -> Vec<u8> {
+        self.state.borrow().certified_data.clone()
+    }
+
+    /// Gets the deadline of the global timer. Zero means the timer is not set.
+    pub fn global_timer(&self) -> u64 {
+        self.state.borrow().global_timer
+    }
+
+    /// Gets all messages printed with `debug_print`.
+    pub fn debug_prints(&self) -> Vec<String> {
+        self.state.borrow().debug_prints.clone()
+    }
+}
+
+impl InMemoryHost {
+    fn stable_bounds_check(&self, offset: u64, size: u64) {
+        let len = self.state.borrow().stable_memory.len() as u64;
+        if offset.checked_add(size).is_none_or(|end| end > len) {
+            trap("stable memory out of bounds");
+        }
+    }
+
+    /// Writes the fee of the threshold key named by `src` and `size` to `dst`, and returns 0, or returns 1 if `curve`
+    /// is not a valid curve or algorithm, and 2 if the key was not given a fee.
+    ///
+    /// # Safety
+    ///
+    /// `src` must be a pointer to a readable sequence of bytes of size `size`, and `dst` to a writable sequence of 16
+    /// bytes.
+    unsafe fn threshold_key_cost(
+        &self,
+        kind: ThresholdKeyKind,
+        src: usize,
+        size: usize,
+        curve: u32,
+        dst: usize,
+    ) -> u32 {
+        let valid_curves: &[u32] = match kind {
+            ThresholdKeyKind::Ecdsa => &ECDSA_CURVES,
+            ThresholdKeyKind::Schnorr => &SCHNORR_ALGORITHMS,
+            ThresholdKeyKind::VetKd => &VETKD_CURVES,
+        };
+        if !valid_curves.contains(&curve) {
+            return 1;
+        }
+        // SAFETY: the caller guarantees that `src` is readable for `size` bytes.
+        let key_name = String::from_utf8_lossy(unsafe { src_slice(src, size) }).into_owned();
+        let Some(fee) = self
+            .state
+            .borrow()
+            .threshold_key_fees
+            .get(&(kind, curve, key_name))
+            .copied()
+        else {
+            return 2;
+        };
+        // SAFETY: the caller guarantees that `dst` is writable for 16 bytes.
+        unsafe { write_u128(dst, fee) };
+        0
+    }
+
+    fn reply_once(&self, reply: Result<Vec<u8>, String>) {
+        let mut state = self.state.borrow_mut();
+        let context = state.context_mut();
+        if context.reply.is_some() {
+            drop(state);
+            trap("the message has already been replied to");
+        }
+        context.reply = Some(reply.clone());
+        if let Some(call_id) = context.origin {
+            let refund = std::mem::take(&mut context.cycles_available);
+            state.respond(call_id, reply.map_err(network::canister_reject), refund, 0);
+        }
+    }
+}
+
+impl Host for InMemoryHost {
+    unsafe fn msg_arg_data_size(&self) -> usize {
+        self.state.borrow().message.arg_data.len()
+    }
+    unsafe fn msg_arg_data_copy(&self, dst: usize, offset: usize, size: usize) {
+        let state = self.state.borrow();
+        // SAFETY: the caller upholds the contract of ic0.msg_arg_data_copy.
+        unsafe {
+            copy_to_dst(
+                "msg_arg_data_copy",
+                &state.message.arg_data,
+                dst,
+                offset,
+                size,
+            )
+        }
+    }
+    unsafe fn msg_caller_size(&self) -> usize {
+        self.state.borrow().context().caller.len()
+    }
+    unsafe fn msg_caller_copy(&self, dst: usize, offset: usize, size: usize) {
+        let state = self.state.borrow();
+        // SAFETY: the caller upholds the contract of ic0.msg_caller_copy.
+        unsafe {
+            copy_to_dst(
+                "msg_caller_copy",
+                &state.context().caller,
+                dst,
+                offset,
+                size,
+            )
+        }
+    }
+    unsafe fn msg_caller_info_data_size(&self) -> usize {
+        self.state.borrow().caller_info_data.len()
+    }
+    unsafe fn msg_caller_info_data_copy(&self, dst: usize, offset: usize, size: usize) {
+        let state = self.state.borrow();
+        // SAFETY: the caller upholds the contract of ic0.msg_caller_info_data_copy.
+        unsafe {
+            copy_to_dst(
+                "msg_caller_info_data_copy",
+                &state.caller_info_data,
+                dst,
+                offset,
+                size,
+            )
+        }
+    }
+    unsafe fn msg_caller_info_signer_size(&self) -> usize {
+        self.state.borrow().caller_info_signer.len()
+    }
+    unsafe fn msg_caller_info_signer_copy(&self, dst: usize, offset: usize, size: usize) {
+        let state = self.state.borrow();
+        // SAFETY: the caller upholds the contract of ic0.msg_caller_info_signer_copy.
+        unsafe {
+            copy_to_dst(
+                "msg_caller_info_signer_copy",
+                &state.caller_info_signer,
+                dst,
+                offset,
+                size,
+            )
+        }
+    }
+    unsafe fn msg_reject_code(&self) -> u32 {
+        self.state.borrow().message.reject_code
+    }
+    unsafe fn msg_reject_msg_size(&self) -> usize {
+        let state = self.state.borrow();
+        if state.message.reject_code == 0 {
+            drop(state);
+            trap("msg_reject_msg_size called outside of a reject callback");
+        }
+        state.message.reject_message.len()
+    }
+    unsafe fn msg_reject_msg_copy(&self, dst: usize, offset: usize, size: usize) {
+        let state = self.state.borrow();
+        if state.message.reject_code == 0 {
+            drop(state);
+            trap("msg_reject_msg_copy called outside of a reject callback");
+        }
+        // SAFETY: the caller upholds the contract of ic0.msg_reject_msg_copy.
+        unsafe {
+            copy_to_dst(
+                "msg_reject_msg_copy",
+                state.message.reject_message.as_bytes(),
+                dst,
+                offset,
+                size,
+            )
+        }
+    }
+    unsafe fn msg_deadline(&self) -> u64 {
+        self.state.borrow().message.deadline
+    }
+    unsafe fn msg_reply_data_append(&self, src: usize, size: usize) {
+        // SAFETY: the caller upholds the contract of ic0.msg_reply_data_append.
+        let data = unsafe { src_slice(src, size) };
+        self.state
+            .borrow_mut()
+            .message
+            .reply_data
+            .extend_from_slice(data);
+    }
+    unsafe fn msg_reply(&self) {
+        let data = std::mem::take(&mut self.state.borrow_mut().message.reply_data);
+        self.reply_once(Ok(data));
+    }
+    unsafe fn msg_reject(&self, src: usize, size: usize) {
+        // SAFETY: the caller upholds the contract of ic0.msg_reject.
+        let message = String::from_utf8_lossy(unsafe { src_slice(src, size) }).into_owned();
+        self.reply_once(Err(message));
+    }
+    unsafe fn msg_cycles_available128(&self, dst: usize) {
+        let cycles = self.state.borrow().context().cycles_available;
+        // SAFETY: the caller upholds the contract of ic0.msg_cycles_available128.
+        unsafe { write_u128(dst, cycles) }
+    }
+    unsafe fn msg_cycles_refunded128(&self, dst: usize) {
+        let cycles = self.state.borrow().message.cycles_refunded;
+        // SAFETY: the caller upholds the contract of ic0.msg_cycles_refunded128.
+        unsafe { write_u128(dst, cycles) }
+    }
+    unsafe fn msg_cycles_accept128(&self, max_amount_high: u64, max_amount_low: u64, dst: usize) {
+        let max_amount = (u128::from(max_amount_high) << 64) | u128::from(max_amount_low);
+        let accepted = {
+            let mut state = self.state.borrow_mut();
+            let context = state.context_mut();
+            let accepted = max_amount.min(context.cycles_available);
+            context.cycles_available -= accepted;
+            state.cycle_balance = state.cycle_balance.saturating_add(accepted);
+            accepted
+        };
+        // SAFETY: the caller upholds the contract of ic0.msg_cycles_accept128.
+        unsafe { write_u128(dst, accepted) }
+    }
+    unsafe fn cycles_burn128(&self, amount_high: u64, amount_low: u64, dst: usize) {
+        let amount = (u128::from(amount_high) << 64) | u128::from(amount_low);
+        let burned = {
+            let mut state = self.state.borrow_mut();
+            let burned = amount.min(state.cycle_balance);
+            state.cycle_balance -= burned;
+            burned
+        };
+        // SAFETY: the caller upholds the contract of ic0.cycles_burn128.
+        unsafe { write_u128(dst, burned) }
+    }
+    unsafe fn canister_self_size(&self) -> usize {
+        self.state.borrow().context().canister_self.len()
+    }
+    unsafe fn canister_self_copy(&self, dst: usize, offset: usize, size: usize) {
+        let state = self.state.borrow();
+        // SAFETY: the caller upholds the contract of ic0.canister_self_copy.
+        unsafe {
+            copy_to_dst(
+                "canister_self_copy",
+                &state.context().canister_self,
+                dst,
+                offset,
+                size,
+            )
+        }
+    }
+    unsafe fn canister_cycle_balance128(&self, dst: usize) {
+        let cycles = self.state.borrow().cycle_balance;
+        // SAFETY: the caller upholds the contract of ic0.canister_cycle_balance128.
+        unsafe { write_u128(dst, cycles) }
+    }
+    unsafe fn canister_liquid_cycle_balance128(&self, dst: usize) {
+        // The freezing threshold is not modelled, so all cycles are liquid.
+        let cycles = self.state.borrow().cycle_balance;
+        // SAFETY: the caller upholds the contract of ic0.canister_liquid_cycle_balance128.
+        unsafe { write_u128(dst, cycles) }
+    }
+    unsafe fn canister_status(&self) -> u32 {
+        self.state.borrow().status
+    }
+    unsafe fn canister_version(&self) -> u64 {
+        self.state.borrow().version
+    }
+    unsafe fn subnet_self_size(&self) -> usize {
+        self.state.borrow().subnet_self.len()
+    }
+    unsafe fn subnet_self_copy(&self, dst: usize, offset: usize, size: usize) {
+        let state = self.state.borrow();
+        // SAFETY: the caller upholds the contract of ic0.subnet_self_copy.
+        unsafe { copy_to_dst("subnet_self_copy", &state.subnet_self, dst, offset, size) }
+    }
+    unsafe fn msg_method_name_size(&self) -> usize {
+        self.state.borrow().message.method_name.len()
+    }
+    unsafe fn msg_method_name_copy(&self, dst: usize, offset: usize, size: usize) {
+        let state = self.state.borrow();
+        // SAFETY: the caller upholds the contract of ic0.msg_method_name_copy.
+        unsafe {
+            copy_to_dst(
+                "msg_method_name_copy",
+                state.message.method_name.as_bytes(),
+                dst,
+                offset,
+                size,
+            )
+        }
+    }
+    unsafe fn accept_message(&self) {
+        let mut state = self.state.borrow_mut();
+        if state.message.message_accepted {
+            drop(state);
+            trap("accept_message called twice");
+        }
+        state.message.message_accepted = true;
+    }
+    unsafe fn call_new(
+        &self,
+        callee_src: usize,
+        callee_size: usize,
+        name_src: usize,
+        name_size: usize,
+        reply_fun: usize,
+        reply_env: usize,
+        reject_fun: usize,
+        reject_env: usize,
+    ) {
+        // SAFETY: the caller upholds the contract of ic0.call_new.
+        let (callee, name) = unsafe {
+            (
+                src_slice(callee_src, callee_size),
+                src_slice(name_src, name_size),
+            )
+        };
+        let call = network::OutgoingCall {
+            callee: callee.to_vec(),
+            method: String::from_utf8_lossy(name).into_owned(),
+            reply: network::Callback::new(reply_fun, reply_env),
+            reject: network::Callback::new(reject_fun, reject_env),
+            cleanup: None,
+            arg: vec![],
+            cycles: 0,
+            timeout_seconds: None,
+        };
+        let mut state = self.state.borrow_mut();
+        // A call that was never performed is discarded, returning its cycles.
+        if let Some(discarded) = state.message.outgoing.replace(call) {
+            state.cycle_balance += discarded.cycles;
+        }
+    }
+    unsafe fn call_on_cleanup(&self, fun: usize, env: usize) {
+        self.with_outgoing_call("call_on_cleanup", |call| {
+            call.cleanup = Some(network::Callback::new(fun, env));
+        });
+    }
+    unsafe fn call_data_append(&self, src: usize, size: usize) {
+        // SAFETY: the caller upholds the contract of ic0.call_data_append.
+        let data = unsafe { src_slice(src, size) };
+        self.with_outgoing_call("call_data_append", |call| call.arg.extend_from_slice(data));
+    }
+    unsafe fn call_with_best_effort_response(&self, timeout_seconds: u32) {
+        self.with_outgoing_call("call_with_best_effort_response", |call| {
+            call.timeout_seconds = Some(timeout_seconds);
+        });
+    }
+    unsafe fn call_cycles_add128(&self, amount_high: u64, amount_low: u64) {
+        let amount = (u128::from(amount_high) << 64) | u128::from(amount_low);
+        let mut state = self.state.borrow_mut();
+        if state.cycle_balance < amount {
+            drop(state);
+            trap("call_cycles_add128: insufficient cycles balance");
+        }
+        state.cycle_balance -= amount;
+        drop(state);
+        self.with_outgoing_call("call_cycles_add128", |call| call.cycles += amount);
+    }
+    unsafe fn call_perform(&self) -> u32 {
+        self.perform_call()
+    }
+    unsafe fn cost_call(&self, method_name_size: u64, payload_size: u64, dst: usize) {
+        let state = self.state.borrow();
+        let bytes = u128::from(method_name_size) + u128::from(payload_size);
+        let cost = state.call_base_fee + state.call_fee_per_byte * bytes;
+        // SAFETY: the caller upholds the contract of ic0.cost_call.
+        unsafe { write_u128(dst, cost) }
+    }
+    unsafe fn cost_create_canister(&self, dst: usize) {
+        let cost = self.state.borrow().create_canister_fee;
+        // SAFETY: the caller upholds the contract of ic0.cost_create_canister.
+        unsafe { write_u128(dst, cost) }
+    }
+    unsafe fn cost_http_request(&self, request_size: u64, max_res_bytes: u64, dst: usize) {
+        let state = self.state.borrow();
+        let cost = state.http_request_base_fee
+            + state.http_request_fee_per_request_byte * u128::from(request_size)
+            + state.http_request_fee_per_response_byte * u128::from(max_res_bytes);
+        // SAFETY: the caller upholds the contract of ic0.cost_http_request.
+        unsafe { write_u128(dst, cost) }
+    }
+    unsafe fn cost_sign_with_ecdsa(
+        &self,
+        src: usize,
+        size: usize,
+        ecdsa_curve: u32,
+        dst: usize,
+    ) -> u32 {
+        // SAFETY: the caller upholds the contract of ic0.cost_sign_with_ecdsa.
+        unsafe { self.threshold_key_cost(ThresholdKeyKind::Ecdsa, src, size, ecdsa_curve, dst) }
+    }
+    unsafe fn cost_sign_with_schnorr(
+        &self,
+        src: usize,
+        size: usize,
+        algorithm: u32,
+        dst: usize,
+    ) -> u32 {
+        // SAFETY: the caller upholds the contract of ic0.cost_sign_with_schnorr.
+        unsafe { self.threshold_key_cost(ThresholdKeyKind::Schnorr, src, size, algorithm, dst) }
+    }
+    unsafe fn cost_vetkd_derive_key(
+        &self,
+        src: usize,
+        size: usize,
+        vetkd_curve: u32,
+        dst: usize,
+    ) -> u32 {
+        // SAFETY: the caller upholds the contract of ic0.cost_vetkd_derive_key.
+        unsafe { self.threshold_key_cost(ThresholdKeyKind::VetKd, src, size, vetkd_curve, dst) }
+    }
+    unsafe fn stable64_size(&self) -> u64 {
+        self.state.borrow().stable_memory.len() as u64 / WASM_PAGE_SIZE_IN_BYTES
+    }
+    unsafe fn stable64_grow(&self, new_pages: u64) -> u64 {
+        let mut state = self.state.borrow_mut();
+        let old_pages = state.stable_memory.len() as u64 / WASM_PAGE_SIZE_IN_BYTES;
+        let Some(new_len) = old_pages
+            .checked_add(new_pages)
+            .filter(|pages| *pages <= state.max_stable_pages)
+            .and_then(|pages| pages.checked_mul(WASM_PAGE_SIZE_IN_BYTES))
+            .and_then(|len| usize::try_from(len).ok())
+        else {
+            return u64::MAX;
+        };
+        // Fail like the IC rather than abort if the memory cannot be allocated.
+        let additional = new_len - state.stable_memory.len();
+        if state.stable_memory.try_reserve_exact(additional).is_err() {
+            return u64::MAX;
+        }
+        state.stable_memory.resize(new_len, 0);
+        old_pages
+    }
+    unsafe fn stable64_write(&self, offset: u64, src: u64, size: u64) {
+        self.stable_bounds_check(offset, size);
+        // SAFETY: the caller upholds the contract of ic0.stable64_write.
+        let data = unsafe { src_slice(src as usize, size as usize) };
+        let offset = offset as usize;
+        self.state.borrow_mut().stable_memory[offset..offset + data.len()].copy_from_slice(data);
+    }
+    unsafe fn stable64_read(&self, dst: u64, offset: u64, size: u64) {
+        self.stable_bounds_check(offset, size);
+        let state = self.state.borrow();
+        // SAFETY: the caller upholds the contract of ic0.stable64_read.
+        unsafe {
+            copy_to_dst(
+                "stable64_read",
+                &state.stable_memory,
+                dst as usize,
+                offset as usize,
+                size as usize,
+            )
+        }
+    }
+    unsafe fn root_key_size(&self) -> usize {
+        self.state.borrow().root_key.len()
+    }
+    unsafe fn root_key_copy(&self, dst: usize, offset: usize, size: usize) {
+        let state = self.state.borrow();
+        // SAFETY: the caller upholds the contract of ic0.root_key_copy.
+        unsafe { copy_to_dst("root_key_copy", &state.root_key, dst, offset, size) }
+    }
+    unsafe fn certified_data_set(&self, src: usize, size: usize) {
+        if size > 32 {
+            trap("certified_data_set: data is longer than 32 bytes");
+        }
+        // SAFETY: the caller upholds the contract of ic0.certified_data_set.
+        let data = unsafe { src_slice(src, size) };
+        self.state.borrow_mut().certified_data = data.to_vec();
+    }
+    unsafe fn data_certificate_present(&self) -> u32 {
+        u32::from(self.state.borrow().data_certificate.is_some())
+    }
+    unsafe fn data_certificate_size(&self) -> usize {
+        let state = self.state.borrow();
+        match &state.data_certificate {
+            Some(certificate) => certificate.len(),
+            None => {
+                drop(state);
+                trap("data_certificate_size: no data certificate is present")
+            }
+        }
+    }
+    unsafe fn data_certificate_copy(&self, dst: usize, offset: usize, size: usize) {
+        let state = self.state.borrow();
+        let Some(certificate) = &state.data_certificate else {
+            drop(state);
+            trap("data_certificate_copy: no data certificate is present");
+        };
+        // SAFETY: the caller upholds the contract of ic0.data_certificate_copy.
+        unsafe { copy_to_dst("data_certificate_copy", certificate, dst, offset, size) }
+    }
+    unsafe fn time(&self) -> u64 {
+        self.state.borrow().time
+    }
+    unsafe fn global_timer_set(&self, timestamp: u64) -> u64 {
+        std::mem::replace(&mut self.state.borrow_mut().global_timer, timestamp)
+    }
+    unsafe fn performance_counter(&self, counter_type: u32) -> u64 {
+        match counter_type {
+            0 | 1 => self.state.borrow().instruction_counter,
+            _ => trap(&format!(
+                "performance_counter: unknown counter type {counter_type}"
+            )),
+        }
+    }
+    unsafe fn is_controller(&self, src: usize, size: usize) -> u32 {
+        // SAFETY: the caller upholds the contract of ic0.is_controller.
+        let principal = unsafe { src_slice(src, size) };
+        u32::from(
+            self.state
+                .borrow()
+                .controllers
+                .iter()
+                .any(|controller| controller == principal),
+        )
+    }
+    unsafe fn in_replicated_execution(&self) -> u32 {
+        u32::from(self.state.borrow().replicated)
+    }
+    unsafe fn env_var_count(&self) -> usize {
+        self.state.borrow().env_vars.len()
+    }
+    unsafe fn env_var_name_size(&self, index: usize) -> usize {
+        let state = self.state.borrow();
+        match state.env_vars.keys().nth(index) {
+            Some(name) => name.len(),
+            None => {
+                drop(state);
+                trap("env_var_name_size: index out of bounds")
+            }
+        }
+    }
+    unsafe fn env_var_name_copy(&self, index: usize, dst: usize, offset: usize, size: usize) {
+        let state = self.state.borrow();
+        let Some(name) = state.env_vars.keys().nth(index) else {
+            drop(state);
+            trap("env_var_name_copy: index out of bounds");
+        };
+        // SAFETY: the caller upholds the contract of ic0.env_var_name_copy.
+        unsafe { copy_to_dst("env_var_name_copy", name.as_bytes(), dst, offset, size) }
+    }
+    unsafe fn env_var_name_exists(&self, name_src: usize, name_size: usize) -> u32 {
+        // SAFETY: the caller upholds the contract of ic0.env_var_name_exists.
+        let name = String::from_utf8_lossy(unsafe { src_slice(name_src, name_size) });
+        u32::from(self.state.borrow().env_vars.contains_key(&*name))
+    }
+    unsafe fn env_var_value_size(&self, name_src: usize, name_size: usize) -> usize {
+        // SAFETY: the caller upholds the contract of ic0.env_var_value_size.
+        let name = String::from_utf8_lossy(unsafe { src_slice(name_src, name_size) });
+        let state = self.state.borrow();
+        match state.env_vars.get(&*name) {
+            Some(value) => value.len(),
+            None => {
+                drop(state);
+                trap(&format!("env_var_value_size: no variable named {name}"))
+            }
+        }
+    }
+    unsafe fn env_var_value_copy(
+        &self,
+        name_src: usize,
+        name_size: usize,
+        dst: usize,
+        offset: usize,
+        size: usize,
+    ) {
+        // SAFETY: the caller upholds the contract of ic0.env_var_value_copy.
+        let name = String::from_utf8_lossy(unsafe { src_slice(name_src, name_size) });
+        let state = self.state.borrow();
+        let Some(value) = state.env_vars.get(&*name) else {
+            drop(state);
+            trap(&format!("env_var_value_copy: no variable named {name}"));
+        };
+        // SAFETY: the caller upholds the contract of ic0.env_var_value_copy.
+        unsafe { copy_to_dst("env_var_value_copy", value.as_bytes(), dst, offset, size) }
+    }
+    unsafe fn debug_print(&self, src: usize, size: usize) {
+        // SAFETY: the caller upholds the contract of ic0.debug_print.
+        let message = String::from_utf8_lossy(unsafe { src_slice(src, size) }).into_owned();
+        self.state.borrow_mut().debug_prints.push(message);
+    }
+    unsafe fn trap(&self, src: usize, size: usize) {
+        // SAFETY: the caller upholds the contract of ic0.trap.
+        let message = String::from_utf8_lossy(unsafe { src_slice(src, size) }).into_owned();
+        trap(&message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn install() -> InMemoryHost {
+        let host = InMemoryHost::new();
+        set_host(host.clone());
+        host
+    }
+
+    #[test]
+    #[should_panic(expected = "time should only be called inside canisters.")]
+    fn panics_without_host() {
+        take_host();
+        crate::time();
+    }
+
+    #[test]
+    fn message_and_canister() {
+        let host = install();
+        host.set_arg_data(b"DIDL\x00\x00".to_vec());
+        host.set_caller([1, 2, 3]);
+        host.set_time(42);
+        host.set_env_var("B", "2");
+        host.set_env_var("A", "1");
+
+        let mut arg = vec![0; crate::msg_arg_data_size()];
+        crate::msg_arg_data_copy(&mut arg, 0);
+        assert_eq!(arg, b"DIDL\x00\x00");
+        let mut caller = vec![0; crate::msg_caller_size()];
+        crate::msg_caller_copy(&mut caller, 0);
+        assert_eq!(caller, [1, 2, 3]);
+        assert_eq!(crate::time(), 42);
+        assert_eq!(crate::canister_status(), 1);
+
+        assert_eq!(crate::env_var_count(), 2);
+        let mut name = vec![0; crate::env_var_name_size(0)];
+        crate::env_var_name_copy(0, &mut name, 0);
+        assert_eq!(name, b"A");
+        assert_eq!(crate::env_var_name_exists("B"), 1);
+        assert_eq!(crate::env_var_name_exists("C"), 0);
+
+        crate::msg_reply_data_append(b"ok");
+        crate::msg_reply();
+        assert_eq!(host.reply(), Some(Ok(b"ok".to_vec())));
+    }
+
+    #[test]
+    fn cycles() {
+        let host = install();
+        host.set_cycle_balance(1_000);
+        host.set_cycles_available(300);
+        assert_eq!(crate::msg_cycles_accept128(200), 200);
+        assert_eq!(crate::msg_cycles_accept128(200), 100);
+        assert_eq!(crate::canister_cycle_balance128(), 1_300);
+        assert_eq!(crate::cycles_burn128(2_000), 1_300);
+        assert_eq!(host.cycle_balance(), 0);
+    }
+
+    #[test]
+    fn stable_memory() {
+        let host = install();
+        assert_eq!(crate::stable64_size(), 0);
+        assert_eq!(crate::stable64_grow(2), 0);
+        assert_eq!(crate::stable64_size(), 2);
+        crate::stable64_write(b"hello", WASM_PAGE_SIZE_IN_BYTES - 2);
+        let mut buf = [0; 5];
+        crate::stable64_read(&mut buf, WASM_PAGE_SIZE_IN_BYTES - 2);
+        assert_eq!(&buf, b"hello");
+        assert_eq!(
+            host.stable_memory().len() as u64,
+            2 * WASM_PAGE_SIZE_IN_BYTES
+        );
+    }
+
+    #[test]
+    fn stable_memory_limit() {
+        let host = install();
+        assert_eq!(crate::stable64_grow(u64::MAX), u64::MAX);
+        assert_eq!(
+            crate::stable64_grow(MAX_STABLE_MEMORY_IN_PAGES + 1),
+            u64::MAX
+        );
+        host.set_max_stable_pages(3);
+        assert_eq!(crate::stable64_grow(2), 0);
+        assert_eq!(crate::stable64_grow(2), u64::MAX);
+        assert_eq!(crate::stable64_grow(1), 2);
+        assert_eq!(crate::stable64_size(), 3);
+    }
+
+    #[test]
+    fn costs() {
+        let host = install();
+        host.set_call_cost(10, 1);
+        host.set_create_canister_cost(500);
+        host.set_http_request_cost(1_000, 2, 3);
+        host.set_sign_with_ecdsa_cost("key_1", 0, 20);
+        host.set_sign_with_schnorr_cost("key_1", 1, 30);
+        host.set_vetkd_derive_key_cost("key_1", 0, 40);
+
+        assert_eq!(crate::cost_call(3, 7), 20);
+        assert_eq!(crate::cost_create_canister(), 500);
+        assert_eq!(crate::cost_http_request(100, 1_000), 1_000 + 200 + 3_000);
+        assert_eq!(crate::cost_sign_with_ecdsa("key_1", 0), (20, 0));
+        assert_eq!(crate::cost_sign_with_schnorr("key_1", 1), (30, 0));
+        assert_eq!(crate::cost_vetkd_derive_key("key_1", 0), (40, 0));
+        // An invalid curve, then a key without a fee.
+        assert_eq!(crate::cost_sign_with_ecdsa("key_1", 1).1, 1);
+        assert_eq!(crate::cost_sign_with_schnorr("key_1", 0).1, 2);
+        assert_eq!(crate::cost_vetkd_derive_key("test_key_1", 0).1, 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "canister trapped: stable memory out of bounds")]
+    fn stable_memory_out_of_bounds() {
+        install();
+        crate::stable64_write(b"hello", 0);
+    }
+
+    #[test]
+    fn debug_print_and_certified_data() {
+        let host = install();
+        crate::debug_print(b"first");
+        crate::debug_print(b"second");
+        crate::certified_data_set(&[7; 32]);
+        assert_eq!(host.debug_prints(), ["first", "second"]);
+        assert_eq!(host.certified_data(), [7; 32]);
+        assert_eq!(crate::data_certificate_present(), 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "canister trapped: goodbye")]
+    fn trap_panics() {
+        install();
+        crate::trap(b"goodbye");
+    }
+}
@@ -0,0 +1,411 @@
+//! Fault injection and systematic exploration for [`InMemoryHost`]'s simulated network.
+
+use super::network::{DESTINATION_INVALID, SYS_FATAL, SYS_TRANSIENT, SYS_UNKNOWN, panic_message};
+use super::{HOST, InMemoryHost, set_host};
+use std::panic::{AssertUnwindSafe, catch_unwind};
+
+/// A way for an inter-canister call to fail, injected with [`InMemoryHost::inject_fault`] or by a
+/// [`FaultExplorer`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Fault {
+    /// `ic0.call_perform` returns a non-zero code. The call is not made, and its cycles are returned to the balance.
+    PerformFailed,
+    /// The system rejects the call with the raw reject code `code` (as returned by `ic0.msg_reject_code`) before it
+    /// reaches the callee, and refunds all attached cycles.
+    ///
+    /// The code is one of the system reject codes: `SYS_FATAL` (1), `SYS_TRANSIENT` (2), `DESTINATION_INVALID` (3), or
+    /// `SYS_UNKNOWN` (6), which is only possible for bounded-wait calls. A `CANISTER_REJECT` (4) or `CANISTER_ERROR` (5)
+    /// comes from the callee, so make the callee reject the call instead.
+    Reject(u32),
+    /// The callee executes the call, but its response is lost: the caller sees a `SYS_UNKNOWN` reject once the
+    /// deadline expires, and gets no cycles back.
+    ///
+    /// Only possible for bounded-wait calls.
+    Timeout,
+    /// The callee's response is delivered, but the callback traps before doing anything, so the cleanup callback runs
+    /// instead.
+    TrapInCallback,
+}
+
+impl Fault {
+    /// Gets every fault: a failed `call_perform`, a reject with each system reject code, a timeout, and a trap in the
+    /// callback.
+    pub fn all() -> Vec<Fault> {
+        let rejects =
+            [SYS_FATAL, SYS_TRANSIENT, DESTINATION_INVALID, SYS_UNKNOWN].map(Fault::Reject);
+        [Fault::PerformFailed]
+            .into_iter()
+            .chain(rejects)
+            .chain([Fault::Timeout, Fault::TrapInCallback])
+            .collect()
+    }
+
+    /// Whether the fault can happen to a call, given whether it is bounded-wait and whether it is one-way.
+    fn is_possible(self, bounded_wait: bool, oneway: bool) -> bool {
+        match self {
+            Fault::PerformFailed => true,
+            Fault::Reject(SYS_FATAL | SYS_TRANSIENT | DESTINATION_INVALID) => true,
+            Fault::Reject(SYS_UNKNOWN) => bounded_wait,
+            Fault::Reject(_) => false,
+            // A one-way call has no callback to observe these.
+            Fault::Timeout => bounded_wait && !oneway,
+            Fault::TrapInCallback => !oneway,
+        }
+    }
+}
+
+/// Runs a scenario once for every way its inter-canister calls can fail and be delivered.
+///
+/// Each run installs a fresh [`InMemoryHost`] on the current thread and hands it to the scenario, which sets up its
+/// canister, makes its calls, drives the network with [`run_until_idle`](InMemoryHost::run_until_idle), and asserts
+/// that the canister's state is consistent. Along the way, the host makes a choice at two kinds of points:
+///
+/// - when a call is performed: whether it succeeds, or fails with one of the configured [`Fault`]s that is possible
+///   for it;
+/// - when [`run_next`](InMemoryHost::run_next) delivers an event, if interleavings are explored: which of the pending
+///   requests, responses and timeouts goes first. Requests from one canister to another are still delivered in the
+///   order they were made, as on the IC.
+///
+/// Exploration is stateless: every run replays the choices of the previous one up to the last choice with an untried
+/// alternative, takes that alternative, and takes the first alternative of any later choice. The scenario must
+/// therefore be deterministic, and must reset any state it keeps outside of the host, such as the canister's
+/// thread-local state, at the start of each run.
+///
+/// If the scenario panics in some run, exploration stops and panics with the choices that led to the failure.
+///
+/// ```
+/// use ic0::host::{CallResponse, FaultExplorer};
+/// use std::cell::Cell;
+///
+/// thread_local! {
+///     static PENDING: Cell<u32> = const { Cell::new(0) };
+/// }
+///
+/// unsafe extern "C" fn done(_env: usize) {
+///     PENDING.set(PENDING.get() - 1);
+/// }
+///
+/// let runs = FaultExplorer::new().explore(|host| {
+///     PENDING.set(0);
+///     host.on_call([1], "ping", |_| CallResponse::reply(vec![]));
+///     for _ in 0..2 {
+///         // SAFETY: `done` ignores its environment.
+///         unsafe {
+///             ic0::call_new(&[1], "ping", done, 0, done, 0);
+///             ic0::call_on_cleanup(done, 0);
+///         }
+///         if ic0::call_perform() == 0 {
+///             PENDING.set(PENDING.get() + 1);
+///         }
+///     }
+///     host.run_until_idle();
+///     // Every performed call got exactly one callback, whether it succeeded or not.
+///     assert_eq!(PENDING.get(), 0);
+/// });
+/// assert!(runs > 64);
+/// ```
+#[derive(Clone, Debug)]
+pub struct FaultExplorer {
+    faults: Vec<Fault>,
+    interleavings: bool,
+    max_runs: usize,
+}
+
+impl Default for FaultExplorer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FaultExplorer {
+    /// Creates an explorer that injects every [`Fault`], explores interleavings, and stops after 10,000 runs.
+    pub fn new() -> Self {
+        Self {
+            faults: Fault::all(),
+            interleavings: true,
+            max_runs: 10_000,
+        }
+    }
+
+    /// Only injects `faults`. With no faults, every call succeeds and only interleavings are explored.
+    pub fn with_faults(mut self, faults: impl IntoIterator<Item = Fault>) -> Self {
+        self.faults = faults.into_iter().collect();
+        self
+    }
+
+    /// Sets whether to explore the order in which pending events are delivered. If not, events are delivered in order
+    /// of simulated time, as without an explorer.
+    pub fn with_interleavings(mut self, interleavings: bool) -> Self {
+        self.interleavings = interleavings;
+        self
+    }
+
+    /// Stops exploring after `max_runs` runs, even if some choices remain untried.
+    pub fn with_max_runs(mut self, max_runs: usize) -> Self {
+        self.max_runs = max_runs;
+        self
+    }
+
+    /// Runs `scenario` until every combination of choices has been tried, or the maximum number of runs is reached.
+    ///
+    /// Returns the number of runs. The host installed on the current thread before exploring is reinstalled afterwards.
+    ///
+    /// # Panics
+    ///
+    /// If `scenario` panics, with the panic message and the choices of the failing run.
+    pub fn explore(&self, mut scenario: impl FnMut(&InMemoryHost)) -> usize {
+        let previous = HOST.with_borrow_mut(Option::take);
+        let mut replay = vec![];
+        let mut runs = 0;
+        loop {
+            let host = InMemoryHost::new();
+            host.state.borrow_mut().network.exploration = Some(Exploration {
+                faults: self.faults.clone(),
+                interleavings: self.interleavings,
+                replay,
+                choices: vec![],
+                trace: vec![],
+            });
+            set_host(host.clone());
+            let result = catch_unwind(AssertUnwindSafe(|| scenario(&host)));
+            runs += 1;
+            let exploration = host
+                .state
+                .borrow_mut()
+                .network
+                .exploration
+                .take()
+                .expect("the exploration stays installed while the scenario runs");
+            if let Err(payload) = result {
+                HOST.with_borrow_mut(|slot| *slot = previous);
+                panic!(
+                    "scenario failed in run {runs}: {}\nchoices:\n{}",
+                    panic_message(payload),
+                    exploration.trace.join("\n"),
+                );
+            }
+            match exploration.next_replay() {
+                Some(next) if runs < self.max_runs => replay = next,
+                _ => break,
+            }
+        }
+        HOST.with_borrow_mut(|slot| *slot = previous);
+        runs
+    }
+}
+
+/// The choices of one run of a [`FaultExplorer`].
+#[derive(Debug)]
+pub(super) struct Exploration {
+    faults: Vec<Fault>,
+    interleavings: bool,
+    /// The alternatives to take at the first choices of this run.
+    replay: Vec<usize>,
+    /// The alternative taken at each choice so far, and how many there were.
+    choices: Vec<(usize, usize)>,
+    /// A description of each choice so far.
+    trace: Vec<String>,
+}
+
+impl Exploration {
+    fn choose(&mut self, alternatives: usize) -> usize {
+        let taken = self.replay.get(self.choices.len()).copied().unwrap_or(0);
+        assert!(
+            taken < alternatives,
+            "the scenario made different choices when replayed; it must be deterministic"
+        );
+        self.choices.push((taken, alternatives));
+        taken
+    }
+
+    /// Chooses whether `call` fails, and how.
+    pub(super) fn choose_fault(
+        &mut self,
+        call: &str,
+        bounded_wait: bool,
+        oneway: bool,
+    ) -> Option<Fault> {
+        let possible = self
+            .faults
+            .iter()
+            .copied()
+            .filter(|fault| fault.is_possible(bounded_wait, oneway))
+            .collect::<Vec<_>>();
+        if possible.is_empty() {
+            return None;
+        }
+        let fault = match self.choose(possible.len() + 1) {
+            0 => None,
+            taken => Some(possible[taken - 1]),
+        };
+        self.trace.push(match fault {
+            Some(fault) => format!("- {call} fails with {fault:?}"),
+            None => format!("- {call} succeeds"),
+        });
+        fault
+    }
+
+    /// Chooses which of the pending events described by `candidates` is delivered next.
+    pub(super) fn choose_event(&mut self, candidates: &[String]) -> usize {
+        if !self.interleavings || candidates.len() < 2 {
+            return 0;
+        }
+        let taken = self.choose(candidates.len());
+        self.trace.push(format!(
+            "- {} is delivered before {}",
+            candidates[taken],
+            candidates
+                .iter()
+                .enumerate()
+                .filter(|&(i, _)| i != taken)
+                .map(|(_, candidate)| candidate.as_str())
+                .collect::<Vec<_>>()
+                .join(", "),
+        ));
+        taken
+    }
+
+    pub(super) fn interleavings(&self) -> bool {
+        self.interleavings
+    }
+
+    /// Gets the choices to replay in the next run, or `None` if every alternative has been tried.
+    fn next_replay(&self) -> Option<Vec<usize>> {
+        let last = self
+            .choices
+            .iter()
+            .rposition(|&(taken, alternatives)| taken + 1 < alternatives)?;
+        let mut replay = self.choices[..last]
+            .iter()
+            .map(|&(taken, _)| taken)
+            .collect::<Vec<_>>();
+        replay.push(self.choices[last].0 + 1);
+        Some(replay)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::host::CallResponse;
+    use std::cell::RefCell;
+
+    thread_local! {
+        static LOG: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
+    }
+
+    unsafe extern "C" fn record(env: usize) {
+        let entry = match crate::msg_reject_code() {
+            0 => format!("{env}: reply"),
+            code => format!("{env}: reject {code}"),
+        };
+        LOG.with_borrow_mut(|log| log.push(entry));
+    }
+
+    unsafe extern "C" fn cleanup(env: usize) {
+        LOG.with_borrow_mut(|log| log.push(format!("{env}: cleanup")));
+    }
+
+    fn call(env: usize, timeout_seconds: Option<u32>, cycles: u128) -> u32 {
+        // SAFETY: `record` and `cleanup` are safe to call with any environment.
+        unsafe {
+            crate::call_new(&[1], "ping", record, env, record, env);
+            crate::call_on_cleanup(cleanup, env);
+        }
+        if let Some(timeout_seconds) = timeout_seconds {
+            crate::call_with_best_effort_response(timeout_seconds);
+        }
+        crate::call_cycles_add128(cycles);
+        crate::call_perform()
+    }
+
+    #[test]
+    fn injected_faults() {
+        let host = InMemoryHost::new();
+        set_host(host.clone());
+        host.set_cycle_balance(100);
+        host.on_call([1], "ping", |_| {
+            LOG.with_borrow_mut(|log| log.push("callee".to_string()));
+            CallResponse::reply(vec![]).accept_cycles(10)
+        });
+        for fault in [
+            Fault::PerformFailed,
+            Fault::Reject(2),
+            Fault::Timeout,
+            Fault::TrapInCallback,
+        ] {
+            host.inject_fault(fault);
+        }
+        assert_eq!(call(1, None, 10), 2);
+        assert_eq!(call(2, None, 10), 0);
+        assert_eq!(call(3, Some(10), 10), 0);
+        assert_eq!(call(4, None, 10), 0);
+        assert_eq!(host.cycle_balance(), 70);
+
+        host.run_until_idle();
+        assert_eq!(
+            LOG.take(),
+            [
+                "2: reject 2",
+                "callee",
+                "callee",
+                "4: cleanup",
+                "3: reject 6",
+            ]
+        );
+        assert_eq!(
+            host.traps(),
+            ["canister trapped: injected trap in callback"]
+        );
+        // The rejected call was refunded, the callee kept the cycles of the others.
+        assert_eq!(host.cycle_balance(), 80);
+    }
+
+    #[test]
+    fn explores_every_fault_and_interleaving() {
+        let mut outcomes = Vec::new();
+        let runs = FaultExplorer::new().explore(|host| {
+            host.on_call([1], "ping", |_| CallResponse::reply(vec![]));
+            call(1, Some(10), 0);
+            call(2, None, 0);
+            host.run_until_idle();
+            outcomes.push(LOG.take().join(", "));
+        });
+        assert_eq!(runs, outcomes.len());
+        // 7 possible faults or success for the bounded-wait call, 5 for the other, and up to 3 orders of delivery.
+        assert!(runs > 8 * 6);
+        for expected in [
+            "1: reply, 2: reply",
+            "2: reply, 1: reply",
+            "1: cleanup, 2: reject 3",
+            "2: reply",
+            "2: reject 1, 1: reject 6",
+        ] {
+            assert!(
+                outcomes.iter().any(|outcome| outcome == expected),
+                "{expected}"
+            );
+        }
+    }
+
+    #[test]
+    fn reports_failing_choices() {
+        let result = catch_unwind(|| {
+            FaultExplorer::new()
+                .with_faults([Fault::Reject(3)])
+                .with_interleavings(false)
+                .explore(|host| {
+                    host.on_call([1], "ping", |_| CallResponse::reply(vec![]));
+                    call(1, None, 0);
+                    call(2, None, 0);
+                    host.run_until_idle();
+                    assert!(!LOG.take().contains(&"2: reject 3".to_string()), "rejected");
+                })
+        });
+        let message = panic_message(result.unwrap_err());
+        assert_eq!(
+            message,
+            "scenario failed in run 2: rejected\nchoices:\n- call 0 to `ping` succeeds\n- call 1 to `ping` fails with Reject(3)"
+        );
+    }
+}
@@ -0,0 +1,791 @@
+//! The simulated network behind [`InMemoryHost`]'s inter-canister calls.
+
+use super::faults::{Exploration, Fault};
+use super::{CallContext, InMemoryHost, Message, ROOT_CONTEXT, State, trap};
+use std::any::Any;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::fmt;
+use std::panic::{AssertUnwindSafe, catch_unwind};
+use std::rc::Rc;
+use std::time::Duration;
+
+// Reject codes, as returned by `ic0.msg_reject_code`. Only the callee produces `CANISTER_REJECT` and `CANISTER_ERROR`.
+pub(super) const SYS_FATAL: u32 = 1;
+pub(super) const SYS_TRANSIENT: u32 = 2;
+pub(super) const DESTINATION_INVALID: u32 = 3;
+const CANISTER_REJECT: u32 = 4;
+const CANISTER_ERROR: u32 = 5;
+pub(super) const SYS_UNKNOWN: u32 = 6;
+
+/// The function pointer `ic0::call_new_oneway` passes for callbacks that must never be called.
+const NO_CALLBACK: usize = usize::MAX;
+
+/// An inter-canister call, as seen by a handler registered with [`InMemoryHost::on_call`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CallRequest {
+    /// The canister making the call.
+    pub caller: Vec<u8>,
+    /// The canister being called.
+    pub callee: Vec<u8>,
+    /// The name of the method being called.
+    pub method: String,
+    /// The argument data.
+    pub arg: Vec<u8>,
+    /// The cycles attached to the call.
+    pub cycles: u128,
+    /// The deadline of a bounded-wait call in nanoseconds since the epoch, or zero for an unbounded-wait call.
+    pub deadline: u64,
+}
+
+/// The response of a handler registered with [`InMemoryHost::on_call`].
+///
+/// By default, a response keeps none of the attached cycles and is delivered immediately.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CallResponse {
+    result: Result<Vec<u8>, (u32, String)>,
+    cycles_accepted: u128,
+    latency: Duration,
+}
+
+impl CallResponse {
+    /// Replies with `data`.
+    pub fn reply(data: impl Into<Vec<u8>>) -> Self {
+        Self {
+            result: Ok(data.into()),
+            cycles_accepted: 0,
+            latency: Duration::ZERO,
+        }
+    }
+
+    /// Rejects with the raw reject code `code` (as returned by `ic0.msg_reject_code`) and `message`.
+    pub fn reject(code: u32, message: impl Into<String>) -> Self {
+        Self {
+            result: Err((code, message.into())),
+            cycles_accepted: 0,
+            latency: Duration::ZERO,
+        }
+    }
+
+    /// Accepts up to `cycles` of the cycles attached to the call. The rest are refunded to the caller.
+    pub fn accept_cycles(mut self, cycles: u128) -> Self {
+        self.cycles_accepted = cycles;
+        self
+    }
+
+    /// Delivers the response `latency` after the call is received, in simulated time.
+    ///
+    /// If this is past the deadline of a bounded-wait call, the caller sees a `SYS_UNKNOWN` reject instead.
+    pub fn with_latency(mut self, latency: Duration) -> Self {
+        self.latency = latency;
+        self
+    }
+}
+
+/// Maps a reject message from `ic0.msg_reject` to the result seen by the caller.
+pub(super) fn canister_reject(message: String) -> (u32, String) {
+    (CANISTER_REJECT, message)
+}
+
+type Handler = Rc<dyn Fn(&CallRequest) -> CallResponse>;
+
+/// What answers calls to a method.
+#[derive(Clone)]
+enum Target {
+    Handler(Handler),
+    Method(fn()),
+}
+
+impl fmt::Debug for Target {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Handler(_) => f.write_str("Handler(..)"),
+            Self::Method(entry) => f.debug_tuple("Method").field(entry).finish(),
+        }
+    }
+}
+
+/// A function pointer and environment passed to `ic0.call_new` or `ic0.call_on_cleanup`.
+#[derive(Clone, Copy, Debug)]
+pub(super) struct Callback {
+    fun: usize,
+    env: usize,
+}
+
+impl Callback {
+    pub(super) fn new(fun: usize, env: usize) -> Self {
+        Self { fun, env }
+    }
+
+    /// Calls the callback.
+    ///
+    /// # Safety
+    ///
+    /// The callback must have been passed to `ic0.call_new` or `ic0.call_on_cleanup`, whose contracts make it safe to
+    /// call with its environment, and must not have been called before, except for a cleanup after a trap.
+    unsafe fn invoke(self) {
+        // SAFETY: outside of Wasm, `ic0.call_new` and `ic0.call_on_cleanup` receive function pointers cast to `usize`.
+        // Calling an `extern "C"` function through a `"C-unwind"` pointer is sound as long as it does not unwind, which
+        // it cannot: a panic aborts at its boundary instead.
+        let fun =
+            unsafe { std::mem::transmute::<usize, unsafe extern "C-unwind" fn(usize)>(self.fun) };
+        // SAFETY: guaranteed by the caller.
+        unsafe { fun(self.env) }
+    }
+}
+
+/// A call being assembled with `ic0.call_new` and friends, not yet performed.
+#[derive(Debug)]
+pub(super) struct OutgoingCall {
+    pub(super) callee: Vec<u8>,
+    pub(super) method: String,
+    pub(super) reply: Callback,
+    pub(super) reject: Callback,
+    pub(super) cleanup: Option<Callback>,
+    pub(super) arg: Vec<u8>,
+    pub(super) cycles: u128,
+    pub(super) timeout_seconds: Option<u32>,
+}
+
+/// A call that has been performed and not yet responded to.
+#[derive(Debug)]
+struct InFlightCall {
+    context: u64,
+    reply: Callback,
+    reject: Callback,
+    cleanup: Option<Callback>,
+    fault: Option<Fault>,
+}
+
+#[derive(Debug)]
+enum Event {
+    Request {
+        call_id: u64,
+        request: CallRequest,
+    },
+    Response {
+        call_id: u64,
+        result: Result<Vec<u8>, (u32, String)>,
+        refund: u128,
+    },
+    Timeout {
+        call_id: u64,
+    },
+}
+
+impl Event {
+    fn describe(&self) -> String {
+        match self {
+            Event::Request { call_id, .. } => format!("the request of call {call_id}"),
+            Event::Response { call_id, .. } => format!("the response to call {call_id}"),
+            Event::Timeout { call_id } => format!("the timeout of call {call_id}"),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub(super) struct Network {
+    targets: HashMap<(Vec<u8>, String), Target>,
+    /// Pending events, ordered by delivery time and then by the order they were scheduled in.
+    events: BTreeMap<(u64, u64), Event>,
+    next_seq: u64,
+    calls: BTreeMap<u64, InFlightCall>,
+    next_call_id: u64,
+    traps: Vec<String>,
+    /// Faults to inject into the next performed calls.
+    faults: VecDeque<Fault>,
+    pub(super) exploration: Option<Exploration>,
+}
+
+impl Network {
+    fn schedule(&mut self, time: u64, event: Event) {
+        self.events.insert((time, self.next_seq), event);
+        self.next_seq += 1;
+    }
+
+    /// Whether delivering `event` has any effect. Responses to calls that have timed out, or whose response is lost,
+    /// are dropped, and so are timeouts of calls that got a response.
+    fn is_live(&self, event: &Event) -> bool {
+        match event {
+            Event::Request { .. } => true,
+            Event::Response { call_id, .. } => self
+                .calls
+                .get(call_id)
+                .is_some_and(|call| call.fault != Some(Fault::Timeout)),
+            Event::Timeout { call_id } => self.calls.contains_key(call_id),
+        }
+    }
+
+    /// Picks the next event to deliver: the earliest one, unless an exploration chooses another.
+    fn next_event(&mut self) -> Option<(u64, u64)> {
+        let earliest = *self.events.keys().next()?;
+        if !self
+            .exploration
+            .as_ref()
+            .is_some_and(Exploration::interleavings)
+        {
+            return Some(earliest);
+        }
+        let mut streams = HashSet::new();
+        let (keys, candidates): (Vec<_>, Vec<_>) = self
+            .events
+            .iter()
+            .filter(|(_, event)| self.is_live(event))
+            .filter(|(_, event)| match event {
+                // Requests between two canisters are delivered in order.
+                Event::Request { request, .. } => {
+                    streams.insert((request.caller.clone(), request.callee.clone()))
+                }
+                _ => true,
+            })
+            .map(|(key, event)| (*key, event.describe()))
+            .unzip();
+        if keys.is_empty() {
+            return Some(earliest);
+        }
+        let exploration = self.exploration.as_mut()?;
+        Some(keys[exploration.choose_event(&candidates)])
+    }
+}
+
+impl State {
+    /// Schedules the response to the simulated call `call_id`, `latency` nanoseconds from now.
+    pub(super) fn respond(
+        &mut self,
+        call_id: u64,
+        result: Result<Vec<u8>, (u32, String)>,
+        refund: u128,
+        latency: u64,
+    ) {
+        let time = self.time.saturating_add(latency);
+        self.network.schedule(
+            time,
+            Event::Response {
+                call_id,
+                result,
+                refund,
+            },
+        );
+    }
+}
+
+// Configuration and driving
+impl InMemoryHost {
+    /// Answers calls to `method` of `callee` with `handler`.
+    ///
+    /// Replaces any previous handler or method registered for the same method.
+    pub fn on_call(
+        &self,
+        callee: impl AsRef<[u8]>,
+        method: impl Into<String>,
+        handler: impl Fn(&CallRequest) -> CallResponse + 'static,
+    ) {
+        self.state.borrow_mut().network.targets.insert(
+            (callee.as_ref().to_vec(), method.into()),
+            Target::Handler(Rc::new(handler)),
+        );
+    }
+
+    /// Answers calls to `method` of `callee` by running the canister entry point `entry`, such as the
+    /// `__canister_method_<name>` function generated by `#[update]`.
+    ///
+    /// The entry point runs with its own message and call context: `msg_caller` is the calling canister, `canister_self`
+    /// is `callee`, and replying or rejecting responds to the call. Everything else is shared with the calling canister.
+    ///
+    /// Replaces any previous handler or method registered for the same method.
+    pub fn register_method(
+        &self,
+        callee: impl AsRef<[u8]>,
+        method: impl Into<String>,
+        entry: fn(),
+    ) {
+        self.state.borrow_mut().network.targets.insert(
+            (callee.as_ref().to_vec(), method.into()),
+            Target::Method(entry),
+        );
+    }
+
+    /// Makes the next performed call fail with `fault`. Faults injected repeatedly apply to successive calls.
+    ///
+    /// Unlike a [`FaultExplorer`](super::FaultExplorer), this does not check whether the fault is possible for the
+    /// call: a [`Fault::Timeout`] injected into an unbounded-wait call leaves it without a response forever.
+    ///
+    /// # Panics
+    ///
+    /// If `fault` is a [`Fault::Reject`] with a code that is not a system reject code.
+    pub fn inject_fault(&self, fault: Fault) {
+        if let Fault::Reject(code) = fault {
+            assert!(
+                matches!(
+                    code,
+                    SYS_FATAL | SYS_TRANSIENT | DESTINATION_INVALID | SYS_UNKNOWN
+                ),
+                "reject code {code} is not a system reject code"
+            );
+        }
+        self.state.borrow_mut().network.faults.push_back(fault);
+    }
+
+    /// Delivers the next pending request, response or timeout, advancing the time to when it is due.
+    ///
+    /// Returns `false` if nothing was pending.
+    pub fn run_next(&self) -> bool {
+        let event = {
+            let mut state = self.state.borrow_mut();
+            let Some(key) = state.network.next_event() else {
+                return false;
+            };
+            let event = state.network.events.remove(&key).unwrap();
+            state.time = state.time.max(key.0);
+            if !state.network.is_live(&event) {
+                return true;
+            }
+            event
+        };
+        match event {
+            Event::Request { call_id, request } => self.deliver_request(call_id, request),
+            Event::Response {
+                call_id,
+                result,
+                refund,
+            } => self.deliver_response(call_id, result, refund),
+            Event::Timeout { call_id } => self.deliver_response(
+                call_id,
+                Err((SYS_UNKNOWN, "call deadline has expired".to_string())),
+                0,
+            ),
+        }
+        true
+    }
+
+    /// Delivers pending requests, responses and timeouts until there are none left.
+    pub fn run_until_idle(&self) {
+        while self.run_next() {}
+    }
+
+    /// Returns whether no requests, responses or timeouts are pending.
+    pub fn is_idle(&self) -> bool {
+        self.state.borrow().network.events.is_empty()
+    }
+
+    /// Gets the messages of all traps caught while running registered methods and callbacks.
+    pub fn traps(&self) -> Vec<String> {
+        self.state.borrow().network.traps.clone()
+    }
+}
+
+// System API
+impl InMemoryHost {
+    pub(super) fn with_outgoing_call(&self, name: &str, f: impl FnOnce(&mut OutgoingCall)) {
+        let mut state = self.state.borrow_mut();
+        let Some(call) = &mut state.message.outgoing else {
+            drop(state);
+            trap(&format!("{name} called without a call being constructed"));
+        };
+        f(call);
+    }
+
+    pub(super) fn perform_call(&self) -> u32 {
+        let mut state = self.state.borrow_mut();
+        let Some(call) = state.message.outgoing.take() else {
+            drop(state);
+            trap("call_perform called without a call being constructed");
+        };
+        let call_id = state.network.next_call_id;
+        state.network.next_call_id += 1;
+        let now = state.time;
+        let deadline = call.timeout_seconds.map_or(0, |timeout| {
+            now.saturating_add(u64::from(timeout) * 1_000_000_000)
+        });
+        let oneway = call.reply.fun == NO_CALLBACK;
+        let fault = match state.network.faults.pop_front() {
+            Some(fault) => Some(fault),
+            None => {
+                let description = format!("call {call_id} to `{}`", call.method);
+                state.network.exploration.as_mut().and_then(|exploration| {
+                    exploration.choose_fault(&description, deadline != 0, oneway)
+                })
+            }
+        };
+        if fault == Some(Fault::PerformFailed) {
+            state.cycle_balance += call.cycles;
+            return SYS_TRANSIENT;
+        }
+        let context = state.message.context;
+        let request = CallRequest {
+            caller: state.context().canister_self.clone(),
+            callee: call.callee,
+            method: call.method,
+            arg: call.arg,
+            cycles: call.cycles,
+            deadline,
+        };
+        if !oneway {
+            state.context_mut().outstanding_calls += 1;
+        }
+        state.network.calls.insert(
+            call_id,
+            InFlightCall {
+                context,
+                reply: call.reply,
+                reject: call.reject,
+                cleanup: call.cleanup,
+                fault,
+            },
+        );
+        if let Some(Fault::Reject(code)) = fault {
+            let refund = request.cycles;
+            state.respond(
+                call_id,
+                Err((code, "injected fault".to_string())),
+                refund,
+                0,
+            );
+        } else {
+            state
+                .network
+                .schedule(now, Event::Request { call_id, request });
+        }
+        if deadline != 0 {
+            state.network.schedule(deadline, Event::Timeout { call_id });
+        }
+        0
+    }
+}
+
+// Delivery
+impl InMemoryHost {
+    fn deliver_request(&self, call_id: u64, request: CallRequest) {
+        let target = self
+            .state
+            .borrow()
+            .network
+            .targets
+            .get(&(request.callee.clone(), request.method.clone()))
+            .cloned();
+        match target {
+            None => {
+                let message = format!(
+                    "no method `{}` is registered for canister {:02x?}",
+                    request.method, request.callee
+                );
+                self.state.borrow_mut().respond(
+                    call_id,
+                    Err((DESTINATION_INVALID, message)),
+                    request.cycles,
+                    0,
+                );
+            }
+            Some(Target::Handler(handler)) => {
+                let response = handler(&request);
+                let refund = request.cycles - response.cycles_accepted.min(request.cycles);
+                let latency = u64::try_from(response.latency.as_nanos()).unwrap_or(u64::MAX);
+                self.state
+                    .borrow_mut()
+                    .respond(call_id, response.result, refund, latency);
+            }
+            Some(Target::Method(entry)) => {
+                let context = {
+                    let mut state = self.state.borrow_mut();
+                    let context = state.next_context_id;
+                    state.next_context_id += 1;
+                    state.contexts.insert(
+                        context,
+                        CallContext {
+                            caller: request.caller,
+                            canister_self: request.callee,
+                            cycles_available: request.cycles,
+                            reply: None,
+                            origin: Some(call_id),
+                            outstanding_calls: 0,
+                        },
+                    );
+                    context
+                };
+                let message = Message {
+                    context,
+                    arg_data: request.arg,
+                    method_name: request.method,
+                    deadline: request.deadline,
+                    ..Message::default()
+                };
+                let trapped = self.execute(message, entry);
+                self.finish_context(context, trapped);
+            }
+        }
+    }
+
+    fn deliver_response(&self, call_id: u64, result: Result<Vec<u8>, (u32, String)>, refund: u128) {
+        let call = {
+            let mut state = self.state.borrow_mut();
+            // A response to a call that has already timed out is dropped.
+            let Some(call) = state.network.calls.remove(&call_id) else {
+                return;
+            };
+            state.cycle_balance += refund;
+            call
+        };
+        if call.reply.fun == NO_CALLBACK {
+            return;
+        }
+        let mut message = Message {
+            context: call.context,
+            cycles_refunded: refund,
+            ..Message::default()
+        };
+        let callback = match result {
+            Ok(data) => {
+                message.arg_data = data;
+                call.reply
+            }
+            Err((code, reject_message)) => {
+                message.reject_code = code;
+                message.reject_message = reject_message;
+                call.reject
+            }
+        };
+        let mut trapped = if call.fault == Some(Fault::TrapInCallback) {
+            self.execute(message, || trap("injected trap in callback"))
+        } else {
+            // SAFETY: each performed call gets exactly one response, and the calls to callbacks that cannot be called
+            // were filtered out above.
+            self.execute(message, || unsafe { callback.invoke() })
+        };
+        if trapped.is_some()
+            && let Some(cleanup) = call.cleanup
+        {
+            let message = Message {
+                context: call.context,
+                ..Message::default()
+            };
+            // SAFETY: the callback trapped, which is when the cleanup callback is called.
+            if let Some(cleanup_trap) = self.execute(message, || unsafe { cleanup.invoke() }) {
+                trapped = Some(cleanup_trap);
+            }
+        }
+        self.state
+            .borrow_mut()
+            .contexts
+            .get_mut(&call.context)
+            .expect("a call context is alive while it has outstanding calls")
+            .outstanding_calls -= 1;
+        self.finish_context(call.context, trapped);
+    }
+
+    /// Runs `f` as `message`, catching traps and panics. Returns the trap message, if any.
+    fn execute(&self, message: Message, f: impl FnOnce()) -> Option<String> {
+        let previous = std::mem::replace(&mut self.state.borrow_mut().message, message);
+        let result = catch_unwind(AssertUnwindSafe(f));
+        let mut state = self.state.borrow_mut();
+        let message = std::mem::replace(&mut state.message, previous);
+        if let Some(discarded) = message.outgoing {
+            state.cycle_balance += discarded.cycles;
+        }
+        let trap_message = result.err().map(panic_message)?;
+        state.network.traps.push(trap_message.clone());
+        Some(trap_message)
+    }
+
+    /// Called after each message executed in `context`. Once nothing can reply anymore, rejects the call that created the
+    /// context if it was not answered, and forgets the context.
+    fn finish_context(&self, context: u64, trapped: Option<String>) {
+        let mut state = self.state.borrow_mut();
+        let call_context = &state.contexts[&context];
+        if call_context.outstanding_calls > 0 {
+            return;
+        }
+        if call_context.reply.is_none()
+            && let Some(call_id) = call_context.origin
+        {
+            let message =
+                trapped.unwrap_or_else(|| "canister did not reply to the call".to_string());
+            let call_context = state.contexts.get_mut(&context).unwrap();
+            call_context.reply = Some(Err(message.clone()));
+            let refund = std::mem::take(&mut call_context.cycles_available);
+            state.respond(call_id, Err((CANISTER_ERROR, message)), refund, 0);
+        }
+        if context != ROOT_CONTEXT {
+            state.contexts.remove(&context);
+        }
+    }
+}
+
+pub(super) fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    match payload.downcast::<String>() {
+        Ok(message) => *message,
+        Err(payload) => match payload.downcast::<&'static str>() {
+            Ok(message) => message.to_string(),
+            Err(_) => "Box<dyn Any>".to_string(),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::host::set_host;
+    use std::cell::RefCell;
+
+    thread_local! {
+        static LOG: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
+    }
+
+    fn log() -> Vec<String> {
+        LOG.take()
+    }
+
+    fn install() -> InMemoryHost {
+        let host = InMemoryHost::new();
+        set_host(host.clone());
+        LOG.take();
+        host
+    }
+
+    unsafe extern "C" fn record(env: usize) {
+        let entry = match crate::msg_reject_code() {
+            0 => {
+                let mut data = vec![0; crate::msg_arg_data_size()];
+                crate::msg_arg_data_copy(&mut data, 0);
+                format!(
+                    "{env}: reply {} refunded {}",
+                    String::from_utf8_lossy(&data),
+                    crate::msg_cycles_refunded128()
+                )
+            }
+            code => {
+                let mut message = vec![0; crate::msg_reject_msg_size()];
+                crate::msg_reject_msg_copy(&mut message, 0);
+                format!("{env}: reject {code} {}", String::from_utf8_lossy(&message))
+            }
+        };
+        LOG.with_borrow_mut(|log| log.push(entry));
+    }
+
+    unsafe extern "C-unwind" fn trapping(_env: usize) {
+        crate::trap(b"oops");
+    }
+
+    unsafe extern "C-unwind" fn cleanup(env: usize) {
+        LOG.with_borrow_mut(|log| log.push(format!("{env}: cleanup")));
+    }
+
+    fn call(callee: &[u8], method: &str, env: usize, arg: &[u8]) {
+        // SAFETY: `record` is safe to call with any environment.
+        unsafe { crate::call_new(callee, method, record, env, record, env) };
+        crate::call_data_append(arg);
+    }
+
+    #[test]
+    fn reply_in_order_of_latency() {
+        let host = install();
+        host.set_cycle_balance(1_000);
+        host.on_call([1], "echo", |request| {
+            let latency = Duration::from_secs(u64::from(request.arg[0] - b'0'));
+            CallResponse::reply(request.arg.clone())
+                .accept_cycles(10)
+                .with_latency(latency)
+        });
+        call(&[1], "echo", 1, b"2");
+        crate::call_cycles_add128(100);
+        assert_eq!(crate::call_perform(), 0);
+        call(&[1], "echo", 2, b"1");
+        assert_eq!(crate::call_perform(), 0);
+        assert_eq!(host.cycle_balance(), 900);
+
+        host.run_until_idle();
+        assert_eq!(log(), ["2: reply 1 refunded 0", "1: reply 2 refunded 90"]);
+        assert_eq!(host.cycle_balance(), 990);
+        assert_eq!(crate::time(), 2_000_000_000);
+    }
+
+    #[test]
+    fn rejects() {
+        let host = install();
+        host.on_call([1], "reject", |_| {
+            CallResponse::reject(CANISTER_REJECT, "no")
+        });
+        host.on_call([1], "slow", |_| {
+            CallResponse::reply(vec![]).with_latency(Duration::from_secs(20))
+        });
+        call(&[1], "reject", 1, b"");
+        crate::call_perform();
+        call(&[2], "missing", 2, b"");
+        crate::call_perform();
+        call(&[1], "slow", 3, b"");
+        crate::call_with_best_effort_response(10);
+        crate::call_perform();
+
+        host.run_until_idle();
+        assert_eq!(
+            log(),
+            [
+                "1: reject 4 no",
+                "2: reject 3 no method `missing` is registered for canister [02]",
+                "3: reject 6 call deadline has expired",
+            ]
+        );
+        assert!(host.is_idle());
+    }
+
+    #[test]
+    fn trap_in_callback_runs_cleanup() {
+        let host = install();
+        host.on_call([1], "ok", |_| CallResponse::reply(vec![]));
+        // SAFETY: the callee and method name are readable, and `trapping` and `cleanup` are safe to call with any
+        // environment.
+        unsafe {
+            let fun = trapping as unsafe extern "C-unwind" fn(usize) as usize;
+            crate::sys::call_new(
+                [1u8].as_ptr() as usize,
+                1,
+                b"ok".as_ptr() as usize,
+                2,
+                fun,
+                1,
+                fun,
+                1,
+            );
+            crate::sys::call_on_cleanup(cleanup as unsafe extern "C-unwind" fn(usize) as usize, 1);
+        }
+        crate::call_perform();
+
+        host.run_until_idle();
+        assert_eq!(log(), ["1: cleanup"]);
+        assert_eq!(host.traps(), ["canister trapped: oops"]);
+    }
+
+    #[test]
+    fn registered_method() {
+        fn greet() {
+            let mut caller = vec![0; crate::msg_caller_size()];
+            crate::msg_caller_copy(&mut caller, 0);
+            let mut canister = vec![0; crate::canister_self_size()];
+            crate::canister_self_copy(&mut canister, 0);
+            crate::msg_cycles_accept128(5);
+            crate::msg_reply_data_append(format!("{caller:?} -> {canister:?}").as_bytes());
+            crate::msg_reply();
+        }
+        fn silent() {}
+
+        let host = install();
+        host.set_canister_self([7]);
+        host.set_cycle_balance(100);
+        host.register_method([1], "greet", greet);
+        host.register_method([1], "silent", silent);
+        call(&[1], "greet", 1, b"");
+        crate::call_cycles_add128(20);
+        crate::call_perform();
+        call(&[1], "silent", 2, b"");
+        crate::call_perform();
+
+        host.run_until_idle();
+        assert_eq!(
+            log(),
+            [
+                "1: reply [7] -> [1] refunded 15",
+                "2: reject 5 canister did not reply to the call",
+            ]
+        );
+        // The callee shares the canister state, so it received the accepted cycles.
+        assert_eq!(host.cycle_balance(), 100);
+    }
+}
@@ -7,6 +7,9 @@
 //!
 //! Any function `ic0.foo` that would write to a user buffer has two versions, `foo` which takes `&mut [u8]` and
 //! `foo_uninit` which takes `&mut [MaybeUninit<u8>]`.
+//!
+//! Outside of Wasm, the System API is provided by a [`host::Host`] installed on the current thread, which allows canister
+//! code to be unit tested natively. See the [`host`] module.
 
 #![warn(
     elided_lifetimes_in_paths,
@@ -20,6 +23,9 @@ use std::mem::MaybeUninit;
 
 pub mod sys;
 
+#[cfg(not(target_family = "wasm"))]
+pub mod host;
+
 #[inline]
 pub fn msg_arg_data_size() -> usize {
     // SAFETY: ic0.msg_arg_data_size is always safe to call
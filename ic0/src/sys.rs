@@ -151,129 +151,175 @@ unsafe extern "C" {
 }
 
 #[cfg(not(target_family = "wasm"))]
-#[allow(unused_variables)]
 #[allow(clippy::missing_safety_doc)]
 #[allow(clippy::too_many_arguments)]
+#[allow(clippy::undocumented_unsafe_blocks)]
 mod non_wasm {
     #[doc = "# Safety\n\nAlways safe to call"]
     pub unsafe fn msg_arg_data_size() -> usize {
-        panic!("msg_arg_data_size should only be called inside canisters.");
+        crate::host::with_host("msg_arg_data_size", |host| unsafe {
+            host.msg_arg_data_size()
+        })
     }
     #[doc = "# Safety\n\n`dst` must be a pointer to a writable sequence of bytes with size `size`. The `offset` parameter does not affect safety."]
     pub unsafe fn msg_arg_data_copy(dst: usize, offset: usize, size: usize) {
-        panic!("msg_arg_data_copy should only be called inside canisters.");
+        crate::host::with_host("msg_arg_data_copy", |host| unsafe {
+            host.msg_arg_data_copy(dst, offset, size)
+        })
     }
     #[doc = "# Safety\n\nAlways safe to call"]
     pub unsafe fn msg_caller_size() -> usize {
-        panic!("msg_caller_size should only be called inside canisters.");
+        crate::host::with_host("msg_caller_size", |host| unsafe { host.msg_caller_size() })
     }
     #[doc = "# Safety\n\n`dst` must be a pointer to a writable sequence of bytes with size `size`. The `offset` parameter does not affect safety."]
     pub unsafe fn msg_caller_copy(dst: usize, offset: usize, size: usize) {
-        panic!("msg_caller_copy should only be called inside canisters.");
+        crate::host::with_host("msg_caller_copy", |host| unsafe {
+            host.msg_caller_copy(dst, offset, size)
+        })
     }
     #[doc = "# Safety\n\nAlways safe to call"]
     pub unsafe fn msg_caller_info_data_size() -> usize {
-        panic!("msg_caller_info_data_size should only be called inside canisters.");
+        crate::host::with_host("msg_caller_info_data_size", |host| unsafe {
+            host.msg_caller_info_data_size()
+        })
     }
     #[doc = "# Safety\n\n`dst` must be a pointer to a writable sequence of bytes with size `size`. The `offset` parameter does not affect safety."]
     pub unsafe fn msg_caller_info_data_copy(dst: usize, offset: usize, size: usize) {
-        panic!("msg_caller_info_data_copy should only be called inside canisters.");
+        crate::host::with_host("msg_caller_info_data_copy", |host| unsafe {
+            host.msg_caller_info_data_copy(dst, offset, size)
+        })
     }
     #[doc = "# Safety\n\nAlways safe to call"]
     pub unsafe fn msg_caller_info_signer_size() -> usize {
-        panic!("msg_caller_info_signer_size should only be called inside canisters.");
+        crate::host::with_host("msg_caller_info_signer_size", |host| unsafe {
+            host.msg_caller_info_signer_size()
+        })
     }
     #[doc = "# Safety\n\n`dst` must be a pointer to a writable sequence of bytes with size `size`. The `offset` parameter does not affect safety."]
     pub unsafe fn msg_caller_info_signer_copy(dst: usize, offset: usize, size: usize) {
-        panic!("msg_caller_info_signer_copy should only be called inside canisters.");
+        crate::host::with_host("msg_caller_info_signer_copy", |host| unsafe {
+            host.msg_caller_info_signer_copy(dst, offset, size)
+        })
     }
     #[doc = "# Safety\n\nAlways safe to call"]
     pub unsafe fn msg_reject_code() -> u32 {
-        panic!("msg_reject_code should only be called inside canisters.");
+        crate::host::with_host("msg_reject_code", |host| unsafe { host.msg_reject_code() })
     }
     #[doc = "# Safety\n\nAlways safe to call"]
     pub unsafe fn msg_reject_msg_size() -> usize {
-        panic!("msg_reject_msg_size should only be called inside canisters.");
+        crate::host::with_host("msg_reject_msg_size", |host| unsafe {
+            host.msg_reject_msg_size()
+        })
     }
     #[doc = "# Safety\n\n`dst` must be a pointer to a writable sequence of bytes with size `size`. The `offset` parameter does not affect safety."]
     pub unsafe fn msg_reject_msg_copy(dst: usize, offset: usize, size: usize) {
-        panic!("msg_reject_msg_copy should only be called inside canisters.");
+        crate::host::with_host("msg_reject_msg_copy", |host| unsafe {
+            host.msg_reject_msg_copy(dst, offset, size)
+        })
     }
     #[doc = "# Safety\n\nAlways safe to call"]
     pub unsafe fn msg_deadline() -> u64 {
-        panic!("msg_deadline should only be called inside canisters.");
+        crate::host::with_host("msg_deadline", |host| unsafe { host.msg_deadline() })
     }
     #[doc = "# Safety\n\n`src` must be a pointer to a readable sequence of bytes with size `size`."]
     pub unsafe fn msg_reply_data_append(src: usize, size: usize) {
-        panic!("msg_reply_data_append should only be called inside canisters.");
+        crate::host::with_host("msg_reply_data_append", |host| unsafe {
+            host.msg_reply_data_append(src, size)
+        })
     }
     #[doc = "# Safety\n\nAlways safe to call"]
     pub unsafe fn msg_reply() {
-        panic!("msg_reply should only be called inside canisters.");
+        crate::host::with_host("msg_reply", |host| unsafe { host.msg_reply() })
     }
     #[doc = "# Safety\n\n`src` must be a pointer to a readable sequence of bytes with size `size`"]
     pub unsafe fn msg_reject(src: usize, size: usize) {
-        panic!("msg_reject should only be called inside canisters.");
+        crate::host::with_host("msg_reject", |host| unsafe { host.msg_reject(src, size) })
     }
     #[doc = "# Safety\n\n`dst` must be a pointer to a writable sequence of 16 bytes (LE u128)"]
     pub unsafe fn msg_cycles_available128(dst: usize) {
-        panic!("msg_cycles_available128 should only be called inside canisters.");
+        crate::host::with_host("msg_cycles_available128", |host| unsafe {
+            host.msg_cycles_available128(dst)
+        })
     }
     #[doc = "# Safety\n\n`dst` must be a pointer to a writable sequence of 16 bytes (LE u128)"]
     pub unsafe fn msg_cycles_refunded128(dst: usize) {
-        panic!("msg_cycles_refunded128 should only be called inside canisters.");
+        crate::host::with_host("msg_cycles_refunded128", |host| unsafe {
+            host.msg_cycles_refunded128(dst)
+        })
     }
     #[doc = "# Safety\n\n`dst` must be a pointer to a writable sequence of 16 bytes (LE u128). The `max_amount_high` and `max_amount_low` parameters do not affect safety."]
     pub unsafe fn msg_cycles_accept128(max_amount_high: u64, max_amount_low: u64, dst: usize) {
-        panic!("msg_cycles_accept128 should only be called inside canisters.");
+        crate::host::with_host("msg_cycles_accept128", |host| unsafe {
+            host.msg_cycles_accept128(max_amount_high, max_amount_low, dst)
+        })
     }
     #[doc = "# Safety\n\n`dst` must be a pointer to a writable sequence of 16 bytes (LE u128). The `amount_high` and `amount_low` parameters do not affect safety."]
     pub unsafe fn cycles_burn128(amount_high: u64, amount_low: u64, dst: usize) {
-        panic!("cycles_burn128 should only be called inside canisters.");
+        crate::host::with_host("cycles_burn128", |host| unsafe {
+            host.cycles_burn128(amount_high, amount_low, dst)
+        })
     }
     #[doc = "# Safety\n\nAlways safe to call"]
     pub unsafe fn canister_self_size() -> usize {
-        panic!("canister_self_size should only be called inside canisters.");
+        crate::host::with_host("canister_self_size", |host| unsafe {
+            host.canister_self_size()
+        })
     }
     #[doc = "# Safety\n\n`dst` must be a pointer to a writable sequence of bytes with size `size`. The `offset` parameter does not affect safety."]
     pub unsafe fn canister_self_copy(dst: usize, offset: usize, size: usize) {
-        panic!("canister_self_copy should only be called inside canisters.");
+        crate::host::with_host("canister_self_copy", |host| unsafe {
+            host.canister_self_copy(dst, offset, size)
+        })
     }
     #[doc = "# Safety\n\n`dst` must be a pointer to a writable sequence of 16 bytes (LE u128)"]
     pub unsafe fn canister_cycle_balance128(dst: usize) {
-        panic!("canister_cycle_balance128 should only be called inside canisters.");
+        crate::host::with_host("canister_cycle_balance128", |host| unsafe {
+            host.canister_cycle_balance128(dst)
+        })
     }
     #[doc = "# Safety\n\n`dst` must be a pointer to a writable sequence of 16 bytes (LE u128)"]
     pub unsafe fn canister_liquid_cycle_balance128(dst: usize) {
-        panic!("canister_liquid_cycle_balance128 should only be called inside canisters.");
+        crate::host::with_host("canister_liquid_cycle_balance128", |host| unsafe {
+            host.canister_liquid_cycle_balance128(dst)
+        })
     }
     #[doc = "# Safety\n\nAlways safe to call"]
     pub unsafe fn canister_status() -> u32 {
-        panic!("canister_status should only be called inside canisters.");
+        crate::host::with_host("canister_status", |host| unsafe { host.canister_status() })
     }
     #[doc = "# Safety\n\nAlways safe to call"]
     pub unsafe fn canister_version() -> u64 {
-        panic!("canister_version should only be called inside canisters.");
+        crate::host::with_host("canister_version", |host| unsafe {
+            host.canister_version()
+        })
     }
     #[doc = "# Safety\n\nAlways safe to call"]
     pub unsafe fn subnet_self_size() -> usize {
-        panic!("subnet_self_size should only be called inside canisters.");
+        crate::host::with_host("subnet_self_size", |host| unsafe {
+            host.subnet_self_size()
+        })
     }
     #[doc = "# Safety\n\n`dst` must be a pointer to a writable sequence of bytes with size `size`. The `offset` parameter does not affect safety."]
     pub unsafe fn subnet_self_copy(dst: usize, offset: usize, size: usize) {
-        panic!("subnet_self_copy should only be called inside canisters.");
+        crate::host::with_host("subnet_self_copy", |host| unsafe {
+            host.subnet_self_copy(dst, offset, size)
+        })
     }
     #[doc = "# Safety\n\nAlways safe to call"]
     pub unsafe fn msg_method_name_size() -> usize {
-        panic!("msg_method_name_size should only be called inside canisters.");
+        crate::host::with_host("msg_method_name_size", |host| unsafe {
+            host.msg_method_name_size()
+        })
     }
     #[doc = "# Safety\n\n`dst` must be a pointer to a writable sequence of bytes with size `size`. The `offset` parameter does not affect safety."]
     pub unsafe fn msg_method_name_copy(dst: usize, offset: usize, size: usize) {
-        panic!("msg_method_name_copy should only be called inside canisters.");
+        crate::host::with_host("msg_method_name_copy", |host| unsafe {
+            host.msg_method_name_copy(dst, offset, size)
+        })
     }
     #[doc = "# Safety\n\nAlways safe to call"]
     pub unsafe fn accept_message() {
-        panic!("accept_message should only be called inside canisters.");
+        crate::host::with_host("accept_message", |host| unsafe { host.accept_message() })
     }
     #[doc = "# Safety\n\n- `callee_src` must be a pointer to a readable sequence of bytes with size `callee_size`\n- `name_src` must be a pointer to a readable UTF-8 string with size `name_size`\n- `reply_fun` must be a function pointer with signature (env : usize) -> (), safely callable as an entrypoint with `reply_env`\n- `reject_fun` must be a function pointer with signature (env : usize) -> (), safely callable as an entrypoint with `reject_env`\n- This function takes ownership of `reply_env` and `reject_env`\n- If called, `reply_fun` will take ownership of `reply_env`, `reject_env`, and the `ic0.call_on_cleanup` `env`\n- If called, `reject_fun` will take ownership of `reply_env`, `reject_env`, and the `ic0.call_on_cleanup` `env`"]
     pub unsafe fn call_new(
@@ -286,99 +332,148 @@ mod non_wasm {
         reject_fun: usize,
         reject_env: usize,
     ) {
-        panic!("call_new should only be called inside canisters.");
+        crate::host::with_host("call_new", |host| unsafe {
+            host.call_new(
+                callee_src,
+                callee_size,
+                name_src,
+                name_size,
+                reply_fun,
+                reply_env,
+                reject_fun,
+                reject_env,
+            )
+        })
     }
     #[doc = "# Safety\n\n- `fun` must be a function pointer with signature (env : usize) -> (), safely callable as an entrypoint with `env`\n- This function takes ownership of `env`\n- If called, `fun` will take ownership of `env`, `reply_env`, and `reject_env`"]
     pub unsafe fn call_on_cleanup(fun: usize, env: usize) {
-        panic!("call_on_cleanup should only be called inside canisters.");
+        crate::host::with_host("call_on_cleanup", |host| unsafe {
+            host.call_on_cleanup(fun, env)
+        })
     }
     #[doc = "# Safety\n\n`src` must be a pointer to a readable sequence of bytes with size `size`"]
     pub unsafe fn call_data_append(src: usize, size: usize) {
-        panic!("call_data_append should only be called inside canisters.");
+        crate::host::with_host("call_data_append", |host| unsafe {
+            host.call_data_append(src, size)
+        })
     }
     #[doc = "# Safety\n\nAlways safe to call"]
     pub unsafe fn call_with_best_effort_response(timeout_seconds: u32) {
-        panic!("call_with_best_effort_response should only be called inside canisters.");
+        crate::host::with_host("call_with_best_effort_response", |host| unsafe {
+            host.call_with_best_effort_response(timeout_seconds)
+        })
     }
     #[doc = "# Safety\n\nAlways safe to call"]
     pub unsafe fn call_cycles_add128(amount_high: u64, amount_low: u64) {
-        panic!("call_cycles_add128 should only be called inside canisters.");
+        crate::host::with_host("call_cycles_add128", |host| unsafe {
+            host.call_cycles_add128(amount_high, amount_low)
+        })
     }
     #[doc = "# Safety\n\nAlways safe to call.\n- If this function returns a nonzero value, ownership of `reply_env`, `reject_env`, and the `ic0.call_on_cleanup` `env` is released to the caller\n- If this function returns 0, then (from the perspective of safety, *not* semantics) exactly one of `reply_fun`, `reject_fun`, or the `ic0.call_on_cleanup` `fun` will be called, exactly once."]
     pub unsafe fn call_perform() -> u32 {
-        panic!("call_perform should only be called inside canisters.");
+        crate::host::with_host("call_perform", |host| unsafe { host.call_perform() })
     }
     #[doc = "# Safety\n\nAlways safe to call"]
     pub unsafe fn stable64_size() -> u64 {
-        panic!("stable64_size should only be called inside canisters.");
+        crate::host::with_host("stable64_size", |host| unsafe { host.stable64_size() })
     }
     #[doc = "# Safety\n\nAlways safe to call"]
     pub unsafe fn stable64_grow(new_pages: u64) -> u64 {
-        panic!("stable64_grow should only be called inside canisters.");
+        crate::host::with_host("stable64_grow", |host| unsafe {
+            host.stable64_grow(new_pages)
+        })
     }
     #[doc = "# Safety\n\n`src` must be a pointer to a readable sequence of bytes with size `size`. The `offset` parameter does not affect safety."]
     pub unsafe fn stable64_write(offset: u64, src: u64, size: u64) {
-        panic!("stable64_write should only be called inside canisters.");
+        crate::host::with_host("stable64_write", |host| unsafe {
+            host.stable64_write(offset, src, size)
+        })
     }
     #[doc = "# Safety\n\n`dst` must be a pointer to a writable sequence of bytes with size `size`. The `offset` parameter does not affect safety."]
     pub unsafe fn stable64_read(dst: u64, offset: u64, size: u64) {
-        panic!("stable64_read should only be called inside canisters.");
+        crate::host::with_host("stable64_read", |host| unsafe {
+            host.stable64_read(dst, offset, size)
+        })
     }
     #[doc = "# Safety\n\nAlways safe to call"]
     pub unsafe fn root_key_size() -> usize {
-        panic!("root_key_size should only be called inside canisters.");
+        crate::host::with_host("root_key_size", |host| unsafe { host.root_key_size() })
     }
     #[doc = "# Safety\n\n`dst` must be a pointer to a writable sequence of bytes with size `size`. The `offset` parameter does not affect safety."]
     pub unsafe fn root_key_copy(dst: usize, offset: usize, size: usize) {
-        panic!("root_key_copy should only be called inside canisters.");
+        crate::host::with_host("root_key_copy", |host| unsafe {
+            host.root_key_copy(dst, offset, size)
+        })
     }
     #[doc = "# Safety\n\n`src` must be a pointer to a readable sequence of bytes with size `size`"]
     pub unsafe fn certified_data_set(src: usize, size: usize) {
-        panic!("certified_data_set should only be called inside canisters.");
+        crate::host::with_host("certified_data_set", |host| unsafe {
+            host.certified_data_set(src, size)
+        })
     }
     #[doc = "# Safety\n\nAlways safe to call"]
     pub unsafe fn data_certificate_present() -> u32 {
-        panic!("data_certificate_present should only be called inside canisters.");
+        crate::host::with_host("data_certificate_present", |host| unsafe {
+            host.data_certificate_present()
+        })
     }
     #[doc = "# Safety\n\nAlways safe to call"]
     pub unsafe fn data_certificate_size() -> usize {
-        panic!("data_certificate_size should only be called inside canisters.");
+        crate::host::with_host("data_certificate_size", |host| unsafe {
+            host.data_certificate_size()
+        })
     }
     #[doc = "# Safety\n\n`dst` must be a pointer to a writable sequence of bytes with size `size`. The `offset` parameter does not affect safety."]
     pub unsafe fn data_certificate_copy(dst: usize, offset: usize, size: usize) {
-        panic!("data_certificate_copy should only be called inside canisters.");
+        crate::host::with_host("data_certificate_copy", |host| unsafe {
+            host.data_certificate_copy(dst, offset, size)
+        })
     }
     #[doc = "# Safety\n\nAlways safe to call"]
     pub unsafe fn time() -> u64 {
-        panic!("time should only be called inside canisters.");
+        crate::host::with_host("time", |host| unsafe { host.time() })
     }
     #[doc = "# Safety\n\nAlways safe to call"]
     pub unsafe fn global_timer_set(timestamp: u64) -> u64 {
-        panic!("global_timer_set should only be called inside canisters.");
+        crate::host::with_host("global_timer_set", |host| unsafe {
+            host.global_timer_set(timestamp)
+        })
     }
     #[doc = "# Safety\n\nAlways safe to call"]
     pub unsafe fn performance_counter(counter_type: u32) -> u64 {
-        panic!("performance_counter should only be called inside canisters.");
+        crate::host::with_host("performance_counter", |host| unsafe {
+            host.performance_counter(counter_type)
+        })
     }
     #[doc = "# Safety\n\n`src` must be a pointer to a readable sequence of bytes with size `size`"]
     pub unsafe fn is_controller(src: usize, size: usize) -> u32 {
-        panic!("is_controller should only be called inside canisters.");
+        crate::host::with_host("is_controller", |host| unsafe {
+            host.is_controller(src, size)
+        })
     }
     #[doc = "# Safety\n\nAlways safe to call"]
     pub unsafe fn in_replicated_execution() -> u32 {
-        panic!("in_replicated_execution should only be called inside canisters.");
+        crate::host::with_host("in_replicated_execution", |host| unsafe {
+            host.in_replicated_execution()
+        })
     }
     #[doc = "# Safety\n\n`dst` must be a pointer to a writable sequence of 16 bytes (LE u128). The `method_name_size` and `payload_size` parameters do not affect safety."]
     pub unsafe fn cost_call(method_name_size: u64, payload_size: u64, dst: usize) {
-        panic!("cost_call should only be called inside canisters.");
+        crate::host::with_host("cost_call", |host| unsafe {
+            host.cost_call(method_name_size, payload_size, dst)
+        })
     }
     #[doc = "# Safety\n\n`dst` must be a pointer to a writable sequence of 16 bytes (LE u128)"]
     pub unsafe fn cost_create_canister(dst: usize) {
-        panic!("cost_create_canister should only be called inside canisters.");
+        crate::host::with_host("cost_create_canister", |host| unsafe {
+            host.cost_create_canister(dst)
+        })
     }
     #[doc = "# Safety\n\n`dst` must be a pointer to a writable sequence of 16 bytes (LE u128). The `request_size` and `max_res_bytes` parameters do not affect safety"]
     pub unsafe fn cost_http_request(request_size: u64, max_res_bytes: u64, dst: usize) {
-        panic!("cost_http_request should only be called inside canisters.");
+        crate::host::with_host("cost_http_request", |host| unsafe {
+            host.cost_http_request(request_size, max_res_bytes, dst)
+        })
     }
     #[doc = "# Safety\n\n- `src` must be a pointer to a readable UTF-8 string with size `size`\n- `dst` must be a pointer to a writable sequence of 16 bytes (LE u128)\n- The `ecdsa_curve` parameter does not affect safety"]
     pub unsafe fn cost_sign_with_ecdsa(
@@ -387,7 +482,9 @@ mod non_wasm {
         ecdsa_curve: u32,
         dst: usize,
     ) -> u32 {
-        panic!("cost_sign_with_ecdsa should only be called inside canisters.");
+        crate::host::with_host("cost_sign_with_ecdsa", |host| unsafe {
+            host.cost_sign_with_ecdsa(src, size, ecdsa_curve, dst)
+        })
     }
     #[doc = "# Safety\n\n- `src` must be a pointer to a readable UTF-8 string with size `size`\n- `dst` must be a pointer to a writable sequence of 16 bytes (LE u128)\n- The `algorithm` parameter does not affect safety"]
     pub unsafe fn cost_sign_with_schnorr(
@@ -396,7 +493,9 @@ mod non_wasm {
         algorithm: u32,
         dst: usize,
     ) -> u32 {
-        panic!("cost_sign_with_schnorr should only be called inside canisters.");
+        crate::host::with_host("cost_sign_with_schnorr", |host| unsafe {
+            host.cost_sign_with_schnorr(src, size, algorithm, dst)
+        })
     }
     #[doc = "# Safety\n\n- `src` must be a pointer to a readable UTF-8 string with size `size`\n- `dst` must be a pointer to a writable sequence of 16 bytes (LE u128)\n- The `vetkd_curve` parameter does not affect safety"]
     pub unsafe fn cost_vetkd_derive_key(
@@ -405,27 +504,37 @@ mod non_wasm {
         vetkd_curve: u32,
         dst: usize,
     ) -> u32 {
-        panic!("cost_vetkd_derive_key should only be called inside canisters.");
+        crate::host::with_host("cost_vetkd_derive_key", |host| unsafe {
+            host.cost_vetkd_derive_key(src, size, vetkd_curve, dst)
+        })
     }
     #[doc = "# Safety\n\nAlways safe to call"]
     pub unsafe fn env_var_count() -> usize {
-        panic!("env_var_count should only be called inside canisters.");
+        crate::host::with_host("env_var_count", |host| unsafe { host.env_var_count() })
     }
     #[doc = "# Safety\n\nAlways safe to call"]
     pub unsafe fn env_var_name_size(index: usize) -> usize {
-        panic!("env_var_name_size should only be called inside canisters.");
+        crate::host::with_host("env_var_name_size", |host| unsafe {
+            host.env_var_name_size(index)
+        })
     }
     #[doc = "# Safety\n\n- The `index` parameter does not affect safety\n- `dst` must be a pointer to a writable sequence of bytes with size `size`\n- The `offset` parameter does not affect safety"]
     pub unsafe fn env_var_name_copy(index: usize, dst: usize, offset: usize, size: usize) {
-        panic!("env_var_name_copy should only be called inside canisters.");
+        crate::host::with_host("env_var_name_copy", |host| unsafe {
+            host.env_var_name_copy(index, dst, offset, size)
+        })
     }
     #[doc = "# Safety\n\n`name_src` must be a pointer to a readable UTF-8 string with size `name_size`"]
     pub unsafe fn env_var_name_exists(name_src: usize, name_size: usize) -> u32 {
-        panic!("env_var_name_exists should only be called inside canisters.");
+        crate::host::with_host("env_var_name_exists", |host| unsafe {
+            host.env_var_name_exists(name_src, name_size)
+        })
     }
     #[doc = "# Safety\n\n`name_src` must be a pointer to a readable UTF-8 string with size `name_size`"]
     pub unsafe fn env_var_value_size(name_src: usize, name_size: usize) -> usize {
-        panic!("env_var_value_size should only be called inside canisters.");
+        crate::host::with_host("env_var_value_size", |host| unsafe {
+            host.env_var_value_size(name_src, name_size)
+        })
     }
     #[doc = "# Safety\n\n- `name_src` must be a pointer to a readable UTF-8 string with size `name_size`\n- `dst` must be a pointer to a writable sequence of bytes with size `size`\n- The `offset` parameter does not affect safety"]
     pub unsafe fn env_var_value_copy(
@@ -435,17 +544,336 @@ mod non_wasm {
         offset: usize,
         size: usize,
     ) {
-        panic!("env_var_value_copy should only be called inside canisters.");
+        crate::host::with_host("env_var_value_copy", |host| unsafe {
+            host.env_var_value_copy(name_src, name_size, dst, offset, size)
+        })
     }
     #[doc = "# Safety\n\n`src` must be a pointer to a readable sequence of bytes with size `size`"]
     pub unsafe fn debug_print(src: usize, size: usize) {
-        panic!("debug_print should only be called inside canisters.");
+        crate::host::with_host("debug_print", |host| unsafe { host.debug_print(src, size) })
     }
     #[doc = "# Safety\n\n`src` must be a pointer to a readable sequence of bytes with size `size`"]
     pub unsafe fn trap(src: usize, size: usize) {
-        panic!("trap should only be called inside canisters.");
+        crate::host::with_host("trap", |host| unsafe { host.trap(src, size) })
     }
 }
 
 #[cfg(not(target_family = "wasm"))]
 pub use non_wasm::*;
+
+#[cfg(not(target_family = "wasm"))]
+#[allow(unused_variables)]
+#[allow(clippy::missing_safety_doc)]
+#[allow(clippy::too_many_arguments)]
+pub(crate) mod host_api {
+    /// A native implementation of the System API.
+    ///
+    /// Outside of Wasm, every function in [`ic0::sys`](crate::sys) dispatches to the [`Host`] installed on the current
+    /// thread with [`set_host`](crate::host::set_host). Each method has the same signature and safety contract as the
+    /// System API function it is named after, with pointers referring to the native address space.
+    ///
+    /// All methods have a default implementation that panics, so an implementor only needs to provide the functions its
+    /// tests actually exercise. Implementations of `trap` must not return.
+    pub trait Host {
+        #[doc = "# Safety\n\nAlways safe to call"]
+        unsafe fn msg_arg_data_size(&self) -> usize {
+            panic!("msg_arg_data_size is not supported by this host.");
+        }
+        #[doc = "# Safety\n\n`dst` must be a pointer to a writable sequence of bytes with size `size`. The `offset` parameter does not affect safety."]
+        unsafe fn msg_arg_data_copy(&self, dst: usize, offset: usize, size: usize) {
+            panic!("msg_arg_data_copy is not supported by this host.");
+        }
+        #[doc = "# Safety\n\nAlways safe to call"]
+        unsafe fn msg_caller_size(&self) -> usize {
+            panic!("msg_caller_size is not supported by this host.");
+        }
+        #[doc = "# Safety\n\n`dst` must be a pointer to a writable sequence of bytes with size `size`. The `offset` parameter does not affect safety."]
+        unsafe fn msg_caller_copy(&self, dst: usize, offset: usize, size: usize) {
+            panic!("msg_caller_copy is not supported by this host.");
+        }
+        #[doc = "# Safety\n\nAlways safe to call"]
+        unsafe fn msg_caller_info_data_size(&self) -> usize {
+            panic!("msg_caller_info_data_size is not supported by this host.");
+        }
+        #[doc = "# Safety\n\n`dst` must be a pointer to a writable sequence of bytes with size `size`. The `offset` parameter does not affect safety."]
+        unsafe fn msg_caller_info_data_copy(&self, dst: usize, offset: usize, size: usize) {
+            panic!("msg_caller_info_data_copy is not supported by this host.");
+        }
+        #[doc = "# Safety\n\nAlways safe to call"]
+        unsafe fn msg_caller_info_signer_size(&self) -> usize {
+            panic!("msg_caller_info_signer_size is not supported by this host.");
+        }
+        #[doc = "# Safety\n\n`dst` must be a pointer to a writable sequence of bytes with size `size`. The `offset` parameter does not affect safety."]
+        unsafe fn msg_caller_info_signer_copy(&self, dst: usize, offset: usize, size: usize) {
+            panic!("msg_caller_info_signer_copy is not supported by this host.");
+        }
+        #[doc = "# Safety\n\nAlways safe to call"]
+        unsafe fn msg_reject_code(&self) -> u32 {
+            panic!("msg_reject_code is not supported by this host.");
+        }
+        #[doc = "# Safety\n\nAlways safe to call"]
+        unsafe fn msg_reject_msg_size(&self) -> usize {
+            panic!("msg_reject_msg_size is not supported by this host.");
+        }
+        #[doc = "# Safety\n\n`dst` must be a pointer to a writable sequence of bytes with size `size`. The `offset` parameter does not affect safety."]
+        unsafe fn msg_reject_msg_copy(&self, dst: usize, offset: usize, size: usize) {
+            panic!("msg_reject_msg_copy is not supported by this host.");
+        }
+        #[doc = "# Safety\n\nAlways safe to call"]
+        unsafe fn msg_deadline(&self) -> u64 {
+            panic!("msg_deadline is not supported by this host.");
+        }
+        #[doc = "# Safety\n\n`src` must be a pointer to a readable sequence of bytes with size `size`."]
+        unsafe fn msg_reply_data_append(&self, src: usize, size: usize) {
+            panic!("msg_reply_data_append is not supported by this host.");
+        }
+        #[doc = "# Safety\n\nAlways safe to call"]
+        unsafe fn msg_reply(&self) {
+            panic!("msg_reply is not supported by this host.");
+        }
+        #[doc = "# Safety\n\n`src` must be a pointer to a readable sequence of bytes with size `size`"]
+        unsafe fn msg_reject(&self, src: usize, size: usize) {
+            panic!("msg_reject is not supported by this host.");
+        }
+        #[doc = "# Safety\n\n`dst` must be a pointer to a writable sequence of 16 bytes (LE u128)"]
+        unsafe fn msg_cycles_available128(&self, dst: usize) {
+            panic!("msg_cycles_available128 is not supported by this host.");
+        }
+        #[doc = "# Safety\n\n`dst` must be a pointer to a writable sequence of 16 bytes (LE u128)"]
+        unsafe fn msg_cycles_refunded128(&self, dst: usize) {
+            panic!("msg_cycles_refunded128 is not supported by this host.");
+        }
+        #[doc = "# Safety\n\n`dst` must be a pointer to a writable sequence of 16 bytes (LE u128). The `max_amount_high` and `max_amount_low` parameters do not affect safety."]
+        unsafe fn msg_cycles_accept128(
+            &self,
+            max_amount_high: u64,
+            max_amount_low: u64,
+            dst: usize,
+        ) {
+            panic!("msg_cycles_accept128 is not supported by this host.");
+        }
+        #[doc = "# Safety\n\n`dst` must be a pointer to a writable sequence of 16 bytes (LE u128). The `amount_high` and `amount_low` parameters do not affect safety."]
+        unsafe fn cycles_burn128(&self, amount_high: u64, amount_low: u64, dst: usize) {
+            panic!("cycles_burn128 is not supported by this host.");
+        }
+        #[doc = "# Safety\n\nAlways safe to call"]
+        unsafe fn canister_self_size(&self) -> usize {
+            panic!("canister_self_size is not supported by this host.");
+        }
+        #[doc = "# Safety\n\n`dst` must be a pointer to a writable sequence of bytes with size `size`. The `offset` parameter does not affect safety."]
+        unsafe fn canister_self_copy(&self, dst: usize, offset: usize, size: usize) {
+            panic!("canister_self_copy is not supported by this host.");
+        }
+        #[doc = "# Safety\n\n`dst` must be a pointer to a writable sequence of 16 bytes (LE u128)"]
+        unsafe fn canister_cycle_balance128(&self, dst: usize) {
+            panic!("canister_cycle_balance128 is not supported by this host.");
+        }
+        #[doc = "# Safety\n\n`dst` must be a pointer to a writable sequence of 16 bytes (LE u128)"]
+        unsafe fn canister_liquid_cycle_balance128(&self, dst: usize) {
+            panic!("canister_liquid_cycle_balance128 is not supported by this host.");
+        }
+        #[doc = "# Safety\n\nAlways safe to call"]
+        unsafe fn canister_status(&self) -> u32 {
+            panic!("canister_status is not supported by this host.");
+        }
+        #[doc = "# Safety\n\nAlways safe to call"]
+        unsafe fn canister_version(&self) -> u64 {
+            panic!("canister_version is not supported by this host.");
+        }
+        #[doc = "# Safety\n\nAlways safe to call"]
+        unsafe fn subnet_self_size(&self) -> usize {
+            panic!("subnet_self_size is not supported by this host.");
+        }
+        #[doc = "# Safety\n\n`dst` must be a pointer to a writable sequence of bytes with size `size`. The `offset` parameter does not affect safety."]
+        unsafe fn subnet_self_copy(&self, dst: usize, offset: usize, size: usize) {
+            panic!("subnet_self_copy is not supported by this host.");
+        }
+        #[doc = "# Safety\n\nAlways safe to call"]
+        unsafe fn msg_method_name_size(&self) -> usize {
+            panic!("msg_method_name_size is not supported by this host.");
+        }
+        #[doc = "# Safety\n\n`dst` must be a pointer to a writable sequence of bytes with size `size`. The `offset` parameter does not affect safety."]
+        unsafe fn msg_method_name_copy(&self, dst: usize, offset: usize, size: usize) {
+            panic!("msg_method_name_copy is not supported by this host.");
+        }
+        #[doc = "# Safety\n\nAlways safe to call"]
+        unsafe fn accept_message(&self) {
+            panic!("accept_message is not supported by this host.");
+        }
+        #[doc = "# Safety\n\n- `callee_src` must be a pointer to a readable sequence of bytes with size `callee_size`\n- `name_src` must be a pointer to a readable UTF-8 string with size `name_size`\n- `reply_fun` must be a function pointer with signature (env : usize) -> (), safely callable as an entrypoint with `reply_env`\n- `reject_fun` must be a function pointer with signature (env : usize) -> (), safely callable as an entrypoint with `reject_env`\n- This function takes ownership of `reply_env` and `reject_env`\n- If called, `reply_fun` will take ownership of `reply_env`, `reject_env`, and the `ic0.call_on_cleanup` `env`\n- If called, `reject_fun` will take ownership of `reply_env`, `reject_env`, and the `ic0.call_on_cleanup` `env`"]
+        unsafe fn call_new(
+            &self,
+            callee_src: usize,
+            callee_size: usize,
+            name_src: usize,
+            name_size: usize,
+            reply_fun: usize,
+            reply_env: usize,
+            reject_fun: usize,
+            reject_env: usize,
+        ) {
+            panic!("call_new is not supported by this host.");
+        }
+        #[doc = "# Safety\n\n- `fun` must be a function pointer with signature (env : usize) -> (), safely callable as an entrypoint with `env`\n- This function takes ownership of `env`\n- If called, `fun` will take ownership of `env`, `reply_env`, and `reject_env`"]
+        unsafe fn call_on_cleanup(&self, fun: usize, env: usize) {
+            panic!("call_on_cleanup is not supported by this host.");
+        }
+        #[doc = "# Safety\n\n`src` must be a pointer to a readable sequence of bytes with size `size`"]
+        unsafe fn call_data_append(&self, src: usize, size: usize) {
+            panic!("call_data_append is not supported by this host.");
+        }
+        #[doc = "# Safety\n\nAlways safe to call"]
+        unsafe fn call_with_best_effort_response(&self, timeout_seconds: u32) {
+            panic!("call_with_best_effort_response is not supported by this host.");
+        }
+        #[doc = "# Safety\n\nAlways safe to call"]
+        unsafe fn call_cycles_add128(&self, amount_high: u64, amount_low: u64) {
+            panic!("call_cycles_add128 is not supported by this host.");
+        }
+        #[doc = "# Safety\n\nAlways safe to call.\n- If this function returns a nonzero value, ownership of `reply_env`, `reject_env`, and the `ic0.call_on_cleanup` `env` is released to the caller\n- If this function returns 0, then (from the perspective of safety, *not* semantics) exactly one of `reply_fun`, `reject_fun`, or the `ic0.call_on_cleanup` `fun` will be called, exactly once."]
+        unsafe fn call_perform(&self) -> u32 {
+            panic!("call_perform is not supported by this host.");
+        }
+        #[doc = "# Safety\n\nAlways safe to call"]
+        unsafe fn stable64_size(&self) -> u64 {
+            panic!("stable64_size is not supported by this host.");
+        }
+        #[doc = "# Safety\n\nAlways safe to call"]
+        unsafe fn stable64_grow(&self, new_pages: u64) -> u64 {
+            panic!("stable64_grow is not supported by this host.");
+        }
+        #[doc = "# Safety\n\n`src` must be a pointer to a readable sequence of bytes with size `size`. The `offset` parameter does not affect safety."]
+        unsafe fn stable64_write(&self, offset: u64, src: u64, size: u64) {
+            panic!("stable64_write is not supported by this host.");
+        }
+        #[doc = "# Safety\n\n`dst` must be a pointer to a writable sequence of bytes with size `size`. The `offset` parameter does not affect safety."]
+        unsafe fn stable64_read(&self, dst: u64, offset: u64, size: u64) {
+            panic!("stable64_read is not supported by this host.");
+        }
+        #[doc = "# Safety\n\nAlways safe to call"]
+        unsafe fn root_key_size(&self) -> usize {
+            panic!("root_key_size is not supported by this host.");
+        }
+        #[doc = "# Safety\n\n`dst` must be a pointer to a writable sequence of bytes with size `size`. The `offset` parameter does not affect safety."]
+        unsafe fn root_key_copy(&self, dst: usize, offset: usize, size: usize) {
+            panic!("root_key_copy is not supported by this host.");
+        }
+        #[doc = "# Safety\n\n`src` must be a pointer to a readable sequence of bytes with size `size`"]
+        unsafe fn certified_data_set(&self, src: usize, size: usize) {
+            panic!("certified_data_set is not supported by this host.");
+        }
+        #[doc = "# Safety\n\nAlways safe to call"]
+        unsafe fn data_certificate_present(&self) -> u32 {
+            panic!("data_certificate_present is not supported by this host.");
+        }
+        #[doc = "# Safety\n\nAlways safe to call"]
+        unsafe fn data_certificate_size(&self) -> usize {
+            panic!("data_certificate_size is not supported by this host.");
+        }
+        #[doc = "# Safety\n\n`dst` must be a pointer to a writable sequence of bytes with size `size`. The `offset` parameter does not affect safety."]
+        unsafe fn data_certificate_copy(&self, dst: usize, offset: usize, size: usize) {
+            panic!("data_certificate_copy is not supported by this host.");
+        }
+        #[doc = "# Safety\n\nAlways safe to call"]
+        unsafe fn time(&self) -> u64 {
+            panic!("time is not supported by this host.");
+        }
+        #[doc = "# Safety\n\nAlways safe to call"]
+        unsafe fn global_timer_set(&self, timestamp: u64) -> u64 {
+            panic!("global_timer_set is not supported by this host.");
+        }
+        #[doc = "# Safety\n\nAlways safe to call"]
+        unsafe fn performance_counter(&self, counter_type: u32) -> u64 {
+            panic!("performance_counter is not supported by this host.");
+        }
+        #[doc = "# Safety\n\n`src` must be a pointer to a readable sequence of bytes with size `size`"]
+        unsafe fn is_controller(&self, src: usize, size: usize) -> u32 {
+            panic!("is_controller is not supported by this host.");
+        }
+        #[doc = "# Safety\n\nAlways safe to call"]
+        unsafe fn in_replicated_execution(&self) -> u32 {
+            panic!("in_replicated_execution is not supported by this host.");
+        }
+        #[doc = "# Safety\n\n`dst` must be a pointer to a writable sequence of 16 bytes (LE u128). The `method_name_size` and `payload_size` parameters do not affect safety."]
+        unsafe fn cost_call(&self, method_name_size: u64, payload_size: u64, dst: usize) {
+            panic!("cost_call is not supported by this host.");
+        }
+        #[doc = "# Safety\n\n`dst` must be a pointer to a writable sequence of 16 bytes (LE u128)"]
+        unsafe fn cost_create_canister(&self, dst: usize) {
+            panic!("cost_create_canister is not supported by this host.");
+        }
+        #[doc = "# Safety\n\n`dst` must be a pointer to a writable sequence of 16 bytes (LE u128). The `request_size` and `max_res_bytes` parameters do not affect safety"]
+        unsafe fn cost_http_request(&self, request_size: u64, max_res_bytes: u64, dst: usize) {
+            panic!("cost_http_request is not supported by this host.");
+        }
+        #[doc = "# Safety\n\n- `src` must be a pointer to a readable UTF-8 string with size `size`\n- `dst` must be a pointer to a writable sequence of 16 bytes (LE u128)\n- The `ecdsa_curve` parameter does not affect safety"]
+        unsafe fn cost_sign_with_ecdsa(
+            &self,
+            src: usize,
+            size: usize,
+            ecdsa_curve: u32,
+            dst: usize,
+        ) -> u32 {
+            panic!("cost_sign_with_ecdsa is not supported by this host.");
+        }
+        #[doc = "# Safety\n\n- `src` must be a pointer to a readable UTF-8 string with size `size`\n- `dst` must be a pointer to a writable sequence of 16 bytes (LE u128)\n- The `algorithm` parameter does not affect safety"]
+        unsafe fn cost_sign_with_schnorr(
+            &self,
+            src: usize,
+            size: usize,
+            algorithm: u32,
+            dst: usize,
+        ) -> u32 {
+            panic!("cost_sign_with_schnorr is not supported by this host.");
+        }
+        #[doc = "# Safety\n\n- `src` must be a pointer to a readable UTF-8 string with size `size`\n- `dst` must be a pointer to a writable sequence of 16 bytes (LE u128)\n- The `vetkd_curve` parameter does not affect safety"]
+        unsafe fn cost_vetkd_derive_key(
+            &self,
+            src: usize,
+            size: usize,
+            vetkd_curve: u32,
+            dst: usize,
+        ) -> u32 {
+            panic!("cost_vetkd_derive_key is not supported by this host.");
+        }
+        #[doc = "# Safety\n\nAlways safe to call"]
+        unsafe fn env_var_count(&self) -> usize {
+            panic!("env_var_count is not supported by this host.");
+        }
+        #[doc = "# Safety\n\nAlways safe to call"]
+        unsafe fn env_var_name_size(&self, index: usize) -> usize {
+            panic!("env_var_name_size is not supported by this host.");
+        }
+        #[doc = "# Safety\n\n- The `index` parameter does not affect safety\n- `dst` must be a pointer to a writable sequence of bytes with size `size`\n- The `offset` parameter does not affect safety"]
+        unsafe fn env_var_name_copy(&self, index: usize, dst: usize, offset: usize, size: usize) {
+            panic!("env_var_name_copy is not supported by this host.");
+        }
+        #[doc = "# Safety\n\n`name_src` must be a pointer to a readable UTF-8 string with size `name_size`"]
+        unsafe fn env_var_name_exists(&self, name_src: usize, name_size: usize) -> u32 {
+            panic!("env_var_name_exists is not supported by this host.");
+        }
+        #[doc = "# Safety\n\n`name_src` must be a pointer to a readable UTF-8 string with size `name_size`"]
+        unsafe fn env_var_value_size(&self, name_src: usize, name_size: usize) -> usize {
+            panic!("env_var_value_size is not supported by this host.");
+        }
+        #[doc = "# Safety\n\n- `name_src` must be a pointer to a readable UTF-8 string with size `name_size`\n- `dst` must be a pointer to a writable sequence of bytes with size `size`\n- The `offset` parameter does not affect safety"]
+        unsafe fn env_var_value_copy(
+            &self,
+            name_src: usize,
+            name_size: usize,
+            dst: usize,
+            offset: usize,
+            size: usize,
+        ) {
+            panic!("env_var_value_copy is not supported by this host.");
+        }
+        #[doc = "# Safety\n\n`src` must be a pointer to a readable sequence of bytes with size `size`"]
+        unsafe fn debug_print(&self, src: usize, size: usize) {
+            panic!("debug_print is not supported by this host.");
+        }
+        #[doc = "# Safety\n\n`src` must be a pointer to a readable sequence of bytes with size `size`"]
+        unsafe fn trap(&self, src: usize, size: usize) {
+            panic!("trap is not supported by this host.");
+        }
+    }
+}
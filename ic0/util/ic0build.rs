@@ -217,9 +217,9 @@ unsafe extern "C" {{"#,
         f,
         r#"
 #[cfg(not(target_family = "wasm"))]
-#[allow(unused_variables)]
 #[allow(clippy::missing_safety_doc)]
 #[allow(clippy::too_many_arguments)]
+#[allow(clippy::undocumented_unsafe_blocks)]
 mod non_wasm{{"#,
     )
     .unwrap();
@@ -227,6 +227,10 @@ mod non_wasm{{"#,
     for api in &ic0.apis {
         let fn_name = &api.name;
         let args = &api.args;
+        let arg_names = api.args.iter().map(|arg| match arg {
+            FnArg::Typed(pat_type) => &pat_type.pat,
+            FnArg::Receiver(_) => unreachable!("receivers are rejected while parsing"),
+        });
 
         let mut r = quote! {
             pub unsafe fn #fn_name(#(#args),*)
@@ -238,15 +242,15 @@ mod non_wasm{{"#,
             }
         }
 
-        let panic_str = format!("{fn_name} should only be called inside canisters.");
-        let Some(comment) = safety_comments.get(&fn_name.to_string()) else {
+        let fn_name_str = fn_name.to_string();
+        let Some(comment) = safety_comments.get(&fn_name_str) else {
             panic!("missing safety comment for {fn_name}")
         };
 
         r = quote! {
             #[doc = #comment]
             #r {
-                panic!(#panic_str);
+                crate::host::with_host(#fn_name_str, |host| unsafe { host.#fn_name(#(#arg_names),*) })
             }
         };
         writeln!(f, "{r}").unwrap();
@@ -258,6 +262,56 @@ mod non_wasm{{"#,
 
 #[cfg(not(target_family = "wasm"))]
 pub use non_wasm::*;
+
+#[cfg(not(target_family = "wasm"))]
+#[allow(unused_variables)]
+#[allow(clippy::missing_safety_doc)]
+#[allow(clippy::too_many_arguments)]
+pub(crate) mod host_api {{
+    /// A native implementation of the System API.
+    ///
+    /// Outside of Wasm, every function in [`ic0::sys`](crate::sys) dispatches to the [`Host`] installed on the current
+    /// thread with [`set_host`](crate::host::set_host). Each method has the same signature and safety contract as the
+    /// System API function it is named after, with pointers referring to the native address space.
+    ///
+    /// All methods have a default implementation that panics, so an implementor only needs to provide the functions its
+    /// tests actually exercise. Implementations of `trap` must not return.
+    pub trait Host {{"#,
+    )
+    .unwrap();
+
+    for api in &ic0.apis {
+        let fn_name = &api.name;
+        let args = &api.args;
+
+        let mut r = quote! {
+            unsafe fn #fn_name(&self, #(#args),*)
+        };
+
+        if let Some(output) = &api.output {
+            r = quote! {
+                #r -> #output
+            }
+        }
+
+        let panic_str = format!("{fn_name} is not supported by this host.");
+        let Some(comment) = safety_comments.get(&fn_name.to_string()) else {
+            panic!("missing safety comment for {fn_name}")
+        };
+
+        r = quote! {
+            #[doc = #comment]
+            #r {
+                panic!(#panic_str);
+            }
+        };
+        writeln!(f, "{r}").unwrap();
+    }
+
+    writeln!(
+        f,
+        r#"    }}
+}}
 "#
     )
     .unwrap();